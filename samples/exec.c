@@ -0,0 +1,17 @@
+#include <stdio.h>
+#include <unistd.h>
+
+void before_exec(const char *path) {
+    printf("exec: about to run %s\n", path);
+}
+
+int main(int argc, char *argv[]) {
+    if (argc < 2) {
+        printf("usage: %s <program> [args...]\n", argv[0]);
+        return 1;
+    }
+    before_exec(argv[1]);
+    execv(argv[1], &argv[1]);
+    perror("execv");
+    return 1;
+}
@@ -0,0 +1,24 @@
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+void in_parent(pid_t child) {
+    printf("parent: forked child %d\n", child);
+}
+
+void in_child(void) {
+    printf("child: running as %d\n", getpid());
+}
+
+int main() {
+    pid_t pid = fork();
+    if (pid == 0) {
+        in_child();
+        return 7;
+    }
+    in_parent(pid);
+    int status;
+    waitpid(pid, &status, 0);
+    printf("parent: child exited with %d\n", WEXITSTATUS(status));
+    return 0;
+}
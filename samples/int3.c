@@ -0,0 +1,23 @@
+#include <signal.h>
+#include <stdio.h>
+
+// a trap of the program's own, not a breakpoint deet set
+void trap() {
+    printf("Trapping\n");
+#if defined(__x86_64__)
+    __asm__ volatile("int3");
+#else
+    raise(SIGTRAP);
+#endif
+    printf("Trapped\n");
+}
+
+void after() {
+    printf("After the trap\n");
+}
+
+int main() {
+    trap();
+    after();
+    return 0;
+}
@@ -0,0 +1,30 @@
+#include <stdio.h>
+
+namespace shapes {
+
+struct Square {
+    int side;
+    int area() const;
+};
+
+int Square::area() const {
+    return side * side;
+}
+
+}
+
+static int scale(int a) {
+    return a * 2;
+}
+
+static double scale(double a) {
+    return a * 2.0;
+}
+
+int main() {
+    shapes::Square sq = {3};
+    printf("area = %d\n", sq.area());
+    printf("scale(int) = %d\n", scale(21));
+    printf("scale(double) = %f\n", scale(1.5));
+    return 0;
+}
@@ -0,0 +1,16 @@
+#include <stdio.h>
+
+// Recurses 100000 frames deep and then crashes, for deep backtraces.
+
+int dive(int depth) {
+    if (depth == 0) {
+        volatile int *nowhere = 0;
+        return *nowhere;
+    }
+    return dive(depth - 1) + 1;
+}
+
+int main() {
+    printf("%d\n", dive(100000));
+    return 0;
+}
@@ -0,0 +1,19 @@
+#include <stdio.h>
+#include <string.h>
+
+// Built with -static (see the Makefile): no dynamic loader, the C library linked in.
+
+int total = 0;
+
+void add(int n) {
+    total += n;
+    printf("total = %d\n", total);
+}
+
+int main() {
+    const char *words[] = {"static", "linking", "works"};
+    for (int i = 0; i < 3; i++) {
+        add(strlen(words[i]));
+    }
+    return 0;
+}
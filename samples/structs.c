@@ -0,0 +1,36 @@
+#include <stdio.h>
+
+typedef enum { RED, GREEN = 5, BLUE } color_t;
+
+struct node {
+    int value;
+    struct node *next;
+};
+
+typedef struct {
+    char name[16];
+    double coords[2][3];
+    color_t color;
+    union {
+        int as_int;
+        float as_float;
+    } tag;
+    const char *label;
+} record_t;
+
+struct node tail = {3, NULL};
+struct node head = {1, &tail};
+record_t record = {"origin", {{0}}, GREEN, {7}, "first"};
+
+int sum_list(struct node *list) {
+    int total = 0;
+    for (struct node *cur = list; cur != NULL; cur = cur->next) {
+        total += cur->value;
+    }
+    return total;
+}
+
+int main() {
+    printf("sum = %d, record = %s\n", sum_list(&head), record.name);
+    return 0;
+}
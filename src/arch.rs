@@ -0,0 +1,526 @@
+//! What differs between the machines deet runs on: the breakpoint instruction, how the
+//! registers are read and written, which of them are the program counter, stack pointer and
+//! frame pointer, and how syscalls pass their arguments. One module per `target_arch`; the rest
+//! of deet only uses what they have in common.
+//!
+//! On both, a frame's frame pointer points at the saved frame pointer of its caller, with the
+//! return address right above it (rbp/rip on x86_64, x29/x30 on aarch64).
+
+use crate::tracer::ptrace;
+use nix::unistd::Pid;
+
+/// The register set of a thread, as ptrace and the NT_PRSTATUS note of a core file have it.
+pub type Registers = libc::user_regs_struct;
+
+#[cfg(target_arch = "x86_64")]
+mod machine {
+    use super::Registers;
+    use crate::tracer::ptrace;
+    use nix::unistd::Pid;
+
+    /// int3
+    pub const BREAKPOINT: [u8; 1] = [0xcc];
+
+    /// How far past the breakpoint instruction the pc is once it traps.
+    pub const PC_AFTER_BREAKPOINT: usize = 1;
+
+    /// The si_code of the SIGTRAP a breakpoint instruction raises. Single steps have TRAP_TRACE,
+    /// and raise(SIGTRAP) SI_TKILL.
+    pub const BREAKPOINT_TRAP_CODE: libc::c_int = 0x80; // SI_KERNEL
+
+    /// The si_codes of the SIGTRAP a single step ends with: TRAP_TRACE, or TRAP_BRKPT for a step
+    /// over a syscall instruction.
+    pub const STEP_TRAP_CODES: &[libc::c_int] = &[2, 1]; // TRAP_TRACE, TRAP_BRKPT
+
+    /// e_machine of the core files we write
+    pub const ELF_MACHINE: u16 = 62;
+
+    /// Whether 32-bit programs run here too, as i386 ones do on x86_64. Their registers come
+    /// zero-extended in the 64-bit layout, eip in rip and so on.
+    pub const RUNS_32_BIT: bool = true;
+
+    pub fn get_registers(tid: Pid) -> nix::Result<Registers> {
+        ptrace::getregs(tid)
+    }
+
+    pub fn set_registers(tid: Pid, regs: Registers) -> nix::Result<()> {
+        ptrace::setregs(tid, regs)
+    }
+
+    pub fn pc(regs: &Registers) -> usize {
+        regs.rip as usize
+    }
+
+    pub fn set_pc(regs: &mut Registers, pc: usize) {
+        regs.rip = pc as u64;
+    }
+
+    pub fn frame_pointer(regs: &Registers) -> usize {
+        regs.rbp as usize
+    }
+
+    /// The registers call frame information describes, by their DWARF numbers. Column 16, the
+    /// return address, is rip's.
+    pub const DWARF_REGISTERS: &[&str] = &[
+        "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15",
+    ];
+
+    pub const DWARF_SP: u16 = 7;
+    pub const DWARF_FP: u16 = 6;
+
+    /// The caller's stack pointer, given the frame pointer of a frame: above its saved frame
+    /// pointer and the return address its call pushed.
+    pub fn caller_sp(fp: usize, word_size: usize) -> Option<usize> {
+        Some(fp + 2 * word_size)
+    }
+
+    /// If the instruction that took a thread from registers `before` to `after` was a call,
+    /// where it returns to and the stack pointer once it has. A call pushes the address of the
+    /// instruction after it, which is at most 15 bytes on.
+    pub fn called(
+        before: &Registers,
+        after: &Registers,
+        word_size: usize,
+        read_word: impl Fn(usize) -> nix::Result<usize>,
+    ) -> Option<(usize, usize)> {
+        let sp = before.rsp as usize;
+        if after.rsp as usize != sp.wrapping_sub(word_size) {
+            return None;
+        }
+        let return_addr = match word_size {
+            4 => read_word(after.rsp as usize).ok()? & 0xffff_ffff,
+            _ => read_word(after.rsp as usize).ok()?,
+        };
+        let after_call = pc(before) + 1..=pc(before) + 15;
+        (after_call.contains(&return_addr) && pc(after) != return_addr).then_some((return_addr, sp))
+    }
+
+    /// The registers of a `g` packet of the GDB remote protocol, in order, with their sizes in
+    /// bytes. The floating point and vector registers that follow are left out.
+    pub const REMOTE_REGISTERS: &[(&str, usize)] = &[
+        ("rax", 8),
+        ("rbx", 8),
+        ("rcx", 8),
+        ("rdx", 8),
+        ("rsi", 8),
+        ("rdi", 8),
+        ("rbp", 8),
+        ("rsp", 8),
+        ("r8", 8),
+        ("r9", 8),
+        ("r10", 8),
+        ("r11", 8),
+        ("r12", 8),
+        ("r13", 8),
+        ("r14", 8),
+        ("r15", 8),
+        ("rip", 8),
+        ("eflags", 4),
+        ("cs", 4),
+        ("ss", 4),
+        ("ds", 4),
+        ("es", 4),
+        ("fs", 4),
+        ("gs", 4),
+    ];
+
+    /// The register called `name`, for `print $name`.
+    pub fn register(regs: &Registers, name: &str) -> Option<u64> {
+        match name {
+            // the 32-bit halves, which are all a 32-bit program has
+            "eax" | "ebx" | "ecx" | "edx" | "esi" | "edi" | "ebp" | "esp" | "eip" => {
+                Some(register(regs, &format!("r{}", &name[1..]))? & 0xffff_ffff)
+            }
+            _ => register_mut(&mut regs.clone(), name).map(|value| *value),
+        }
+    }
+
+    /// The field of the register called `name`.
+    pub fn register_mut<'a>(regs: &'a mut Registers, name: &str) -> Option<&'a mut u64> {
+        let field = match name {
+            "rax" => &mut regs.rax,
+            "rbx" => &mut regs.rbx,
+            "rcx" => &mut regs.rcx,
+            "rdx" => &mut regs.rdx,
+            "rsi" => &mut regs.rsi,
+            "rdi" => &mut regs.rdi,
+            "rbp" | "fp" => &mut regs.rbp,
+            "rsp" | "sp" => &mut regs.rsp,
+            "r8" => &mut regs.r8,
+            "r9" => &mut regs.r9,
+            "r10" => &mut regs.r10,
+            "r11" => &mut regs.r11,
+            "r12" => &mut regs.r12,
+            "r13" => &mut regs.r13,
+            "r14" => &mut regs.r14,
+            "r15" => &mut regs.r15,
+            "rip" | "pc" => &mut regs.rip,
+            "eflags" => &mut regs.eflags,
+            "cs" => &mut regs.cs,
+            "ss" => &mut regs.ss,
+            "ds" => &mut regs.ds,
+            "es" => &mut regs.es,
+            "fs" => &mut regs.fs,
+            "gs" => &mut regs.gs,
+            "fs_base" => &mut regs.fs_base,
+            "gs_base" => &mut regs.gs_base,
+            "orig_rax" => &mut regs.orig_rax,
+            _ => return None,
+        };
+        Some(field)
+    }
+
+    /// The program counter and the flags register by name, with the flags of the latter, for
+    /// `info registers`
+    pub const PC_REGISTER: &str = "rip";
+    pub const FLAGS_REGISTER: &str = "eflags";
+    pub const FLAGS: &[(u32, &str)] = &[
+        (0, "CF"),
+        (2, "PF"),
+        (4, "AF"),
+        (6, "ZF"),
+        (7, "SF"),
+        (8, "TF"),
+        (9, "IF"),
+        (10, "DF"),
+        (11, "OF"),
+    ];
+
+    /// Whether register `name` holds an address, so `print` shows it as a pointer.
+    pub fn is_address_register(name: &str) -> bool {
+        matches!(
+            name,
+            "pc" | "rip" | "sp" | "rsp" | "fp" | "rbp" | "eip" | "esp" | "ebp"
+        )
+    }
+
+    /// The number and arguments of the syscall a thread has stopped on entering.
+    pub fn syscall(regs: &Registers) -> (u64, [u64; 6]) {
+        let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+        (regs.orig_rax, args)
+    }
+
+    /// What the syscall a thread has stopped on leaving returned.
+    pub fn syscall_return(regs: &Registers) -> u64 {
+        regs.rax
+    }
+
+    /// syscall, for making one on the program's behalf
+    pub const SYSCALL_INSTRUCTION: [u8; 2] = [0x0f, 0x05];
+
+    /// Sets up the registers for SYSCALL_INSTRUCTION to make syscall `number`. orig_rax is -1 so
+    /// the kernel doesn't take the thread for one interrupted in a syscall and restart it.
+    pub fn set_syscall(regs: &mut Registers, number: u64, args: [u64; 3]) {
+        regs.rax = number;
+        regs.orig_rax = u64::MAX;
+        [regs.rdi, regs.rsi, regs.rdx] = args;
+    }
+
+    /// How many watchpoints the debug registers hold at once: dr0-dr3 take the addresses, dr6
+    /// says which one trapped and dr7 turns them on.
+    pub const WATCHPOINT_SLOTS: usize = 4;
+
+    fn debug_register(n: usize) -> ptrace::AddressType {
+        (std::mem::offset_of!(libc::user, u_debugreg) + 8 * n) as ptrace::AddressType
+    }
+
+    /// Has debug register `slot` of thread `tid` trap writes to the `len` bytes at `addr`, or
+    /// turns it off with None. `len` is 1, 2, 4 or 8, and `addr` a multiple of it. The trap comes
+    /// after the writing instruction.
+    pub fn set_watchpoint(tid: Pid, slot: usize, watch: Option<(usize, usize)>) -> nix::Result<()> {
+        let dr7 = ptrace::read_user(tid, debug_register(7))? as u64;
+        // the local enable bit, then the condition (01: writes) and length bits of the slot
+        let mask = 0b11 << (2 * slot) | 0b1111 << (16 + 4 * slot);
+        let dr7 = match watch {
+            Some((addr, len)) => {
+                let len_bits = match len {
+                    1 => 0b00,
+                    2 => 0b01,
+                    8 => 0b10,
+                    _ => 0b11,
+                };
+                unsafe {
+                    ptrace::write_user(tid, debug_register(slot), addr as *mut libc::c_void)?;
+                }
+                dr7 & !mask | 1 << (2 * slot) | (0b01 | len_bits << 2) << (16 + 4 * slot)
+            }
+            None => dr7 & !mask,
+        };
+        unsafe { ptrace::write_user(tid, debug_register(7), dr7 as *mut libc::c_void) }
+    }
+
+    /// Which slot's watchpoint thread `tid` has trapped on since `clear_watchpoint_hit`, if any.
+    /// A single step that triggers one traps as a step, but this still tells.
+    pub fn watchpoint_hit(tid: Pid) -> nix::Result<Option<usize>> {
+        let dr6 = ptrace::read_user(tid, debug_register(6))?;
+        Ok((0..WATCHPOINT_SLOTS).find(|slot| dr6 & 1 << slot != 0))
+    }
+
+    /// Clears dr6, which the processor never does, so the next trap doesn't look like the last.
+    pub fn clear_watchpoint_hit(tid: Pid) -> nix::Result<()> {
+        unsafe { ptrace::write_user(tid, debug_register(6), std::ptr::null_mut()) }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod machine {
+    use super::Registers;
+    use crate::tracer::ptrace;
+    use nix::errno::Errno;
+    use nix::unistd::Pid;
+    use std::mem::MaybeUninit;
+
+    /// brk #0. Unlike int3 it leaves the pc at the instruction.
+    pub const BREAKPOINT: [u8; 4] = [0x00, 0x00, 0x20, 0xd4];
+
+    /// How far past the breakpoint instruction the pc is once it traps.
+    pub const PC_AFTER_BREAKPOINT: usize = 0;
+
+    /// The si_code of the SIGTRAP a breakpoint instruction raises. Single steps have TRAP_TRACE,
+    /// and raise(SIGTRAP) SI_TKILL.
+    pub const BREAKPOINT_TRAP_CODE: libc::c_int = 1; // TRAP_BRKPT
+
+    /// The si_codes of the SIGTRAP a single step ends with: TRAP_TRACE, or SI_USER for a step
+    /// over a syscall instruction.
+    pub const STEP_TRAP_CODES: &[libc::c_int] = &[2, 0]; // TRAP_TRACE, SI_USER
+
+    /// e_machine of the core files we write
+    pub const ELF_MACHINE: u16 = 183;
+
+    /// Whether 32-bit programs run here too. Not arm ones: aarch64 has its own regsets for
+    /// them, which deet doesn't read.
+    pub const RUNS_32_BIT: bool = false;
+
+    /// The regset of the general registers, for PTRACE_GETREGSET
+    const NT_PRSTATUS: libc::c_int = 1;
+
+    /// aarch64 has no PTRACE_GETREGS; the registers are read and written as a regset.
+    fn regset(request: libc::c_uint, tid: Pid, regs: *mut Registers) -> nix::Result<()> {
+        let mut iov = libc::iovec {
+            iov_base: regs as *mut libc::c_void,
+            iov_len: std::mem::size_of::<Registers>(),
+        };
+        unsafe {
+            ptrace::request(
+                request,
+                tid,
+                NT_PRSTATUS as usize as *mut libc::c_void,
+                &mut iov as *mut libc::iovec as *mut libc::c_void,
+            )
+        }
+        .map(drop)
+    }
+
+    pub fn get_registers(tid: Pid) -> nix::Result<Registers> {
+        let mut regs = MaybeUninit::<Registers>::uninit();
+        regset(libc::PTRACE_GETREGSET as _, tid, regs.as_mut_ptr())?;
+        Ok(unsafe { regs.assume_init() })
+    }
+
+    pub fn set_registers(tid: Pid, mut regs: Registers) -> nix::Result<()> {
+        regset(libc::PTRACE_SETREGSET as _, tid, &mut regs)
+    }
+
+    pub fn pc(regs: &Registers) -> usize {
+        regs.pc as usize
+    }
+
+    pub fn set_pc(regs: &mut Registers, pc: usize) {
+        regs.pc = pc as u64;
+    }
+
+    pub fn frame_pointer(regs: &Registers) -> usize {
+        regs.regs[29] as usize
+    }
+
+    /// The registers call frame information describes, by their DWARF numbers. The return
+    /// address is in x30.
+    pub const DWARF_REGISTERS: &[&str] = &[
+        "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13",
+        "x14", "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26",
+        "x27", "x28", "x29", "x30", "sp",
+    ];
+
+    pub const DWARF_SP: u16 = 31;
+    pub const DWARF_FP: u16 = 29;
+
+    /// The caller's stack pointer, given the frame pointer of a frame. Unknown: the frame record
+    /// can be anywhere in the frame.
+    pub fn caller_sp(_fp: usize, _word_size: usize) -> Option<usize> {
+        None
+    }
+
+    /// If the instruction that took a thread from registers `before` to `after` was a call,
+    /// where it returns to and the stack pointer once it has. A branch with link puts the
+    /// address of the instruction after it in x30, and leaves the stack pointer alone.
+    pub fn called(
+        before: &Registers,
+        after: &Registers,
+        _word_size: usize,
+        _read_word: impl Fn(usize) -> nix::Result<usize>,
+    ) -> Option<(usize, usize)> {
+        let return_addr = pc(before) + 4;
+        (after.regs[30] as usize == return_addr && pc(after) != return_addr)
+            .then_some((return_addr, after.sp as usize))
+    }
+
+    /// The registers of a `g` packet of the GDB remote protocol, in order, with their sizes in
+    /// bytes. The floating point and vector registers that follow are left out.
+    pub const REMOTE_REGISTERS: &[(&str, usize)] = &[
+        ("x0", 8),
+        ("x1", 8),
+        ("x2", 8),
+        ("x3", 8),
+        ("x4", 8),
+        ("x5", 8),
+        ("x6", 8),
+        ("x7", 8),
+        ("x8", 8),
+        ("x9", 8),
+        ("x10", 8),
+        ("x11", 8),
+        ("x12", 8),
+        ("x13", 8),
+        ("x14", 8),
+        ("x15", 8),
+        ("x16", 8),
+        ("x17", 8),
+        ("x18", 8),
+        ("x19", 8),
+        ("x20", 8),
+        ("x21", 8),
+        ("x22", 8),
+        ("x23", 8),
+        ("x24", 8),
+        ("x25", 8),
+        ("x26", 8),
+        ("x27", 8),
+        ("x28", 8),
+        ("x29", 8),
+        ("x30", 8),
+        ("sp", 8),
+        ("pc", 8),
+        ("pstate", 4),
+    ];
+
+    /// The register called `name`, for `print $name`.
+    pub fn register(regs: &Registers, name: &str) -> Option<u64> {
+        register_mut(&mut regs.clone(), name).map(|value| *value)
+    }
+
+    /// The field of the register called `name`.
+    pub fn register_mut<'a>(regs: &'a mut Registers, name: &str) -> Option<&'a mut u64> {
+        let field = match name {
+            "fp" => &mut regs.regs[29],
+            "lr" => &mut regs.regs[30],
+            "sp" => &mut regs.sp,
+            "pc" => &mut regs.pc,
+            "pstate" => &mut regs.pstate,
+            _ => {
+                let number: usize = name.strip_prefix('x')?.parse().ok()?;
+                regs.regs.get_mut(number)?
+            }
+        };
+        Some(field)
+    }
+
+    /// The program counter and the flags register by name, with the flags of the latter, for
+    /// `info registers`
+    pub const PC_REGISTER: &str = "pc";
+    pub const FLAGS_REGISTER: &str = "pstate";
+    pub const FLAGS: &[(u32, &str)] = &[(28, "V"), (29, "C"), (30, "Z"), (31, "N")];
+
+    /// Whether register `name` holds an address, so `print` shows it as a pointer.
+    pub fn is_address_register(name: &str) -> bool {
+        matches!(name, "pc" | "sp" | "fp" | "x29" | "lr" | "x30")
+    }
+
+    /// The number and arguments of the syscall a thread has stopped on entering.
+    pub fn syscall(regs: &Registers) -> (u64, [u64; 6]) {
+        let args = [
+            regs.regs[0],
+            regs.regs[1],
+            regs.regs[2],
+            regs.regs[3],
+            regs.regs[4],
+            regs.regs[5],
+        ];
+        (regs.regs[8], args)
+    }
+
+    /// What the syscall a thread has stopped on leaving returned.
+    pub fn syscall_return(regs: &Registers) -> u64 {
+        regs.regs[0]
+    }
+
+    /// svc #0, for making a syscall on the program's behalf
+    pub const SYSCALL_INSTRUCTION: [u8; 4] = [0x01, 0x00, 0x00, 0xd4];
+
+    /// Sets up the registers for SYSCALL_INSTRUCTION to make syscall `number`.
+    pub fn set_syscall(regs: &mut Registers, number: u64, args: [u64; 3]) {
+        regs.regs[8] = number;
+        regs.regs[..3].copy_from_slice(&args);
+    }
+
+    /// None: the watchpoint registers trap before the write, which would need stepping over
+    /// with the watchpoint off, and deet doesn't set them.
+    pub const WATCHPOINT_SLOTS: usize = 0;
+
+    pub fn set_watchpoint(
+        _tid: Pid,
+        _slot: usize,
+        _watch: Option<(usize, usize)>,
+    ) -> nix::Result<()> {
+        Err(Errno::ENOTSUP)
+    }
+
+    pub fn watchpoint_hit(_tid: Pid) -> nix::Result<Option<usize>> {
+        Ok(None)
+    }
+
+    pub fn clear_watchpoint_hit(_tid: Pid) -> nix::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("deet only supports x86_64 and aarch64");
+
+pub use machine::*;
+
+/// The si_code of the SIGTRAP a hardware breakpoint or watchpoint raises
+pub const HARDWARE_TRAP_CODE: libc::c_int = 4; // TRAP_HWBKPT
+
+/// Rewinds a thread that has just trapped on a breakpoint to the breakpoint's address, so the
+/// replaced instruction runs when it's resumed. Nothing to do where the trap doesn't advance the
+/// pc.
+pub fn rewind_breakpoint(tid: Pid) -> nix::Result<()> {
+    if PC_AFTER_BREAKPOINT != 0 {
+        let mut regs = get_registers(tid)?;
+        let addr = pc(&regs) - PC_AFTER_BREAKPOINT;
+        set_pc(&mut regs, addr);
+        set_registers(tid, regs)?;
+    }
+    Ok(())
+}
+
+/// Reads a register set of a thread other than the general registers, by its NT_* note type:
+/// as much of it as the kernel has, up to `size` bytes.
+pub fn read_regset(tid: Pid, note: u32, size: usize) -> nix::Result<Vec<u8>> {
+    let mut bytes = vec![0u8; size];
+    let mut iov = libc::iovec {
+        iov_base: bytes.as_mut_ptr() as *mut libc::c_void,
+        iov_len: size,
+    };
+    unsafe {
+        ptrace::request(
+            libc::PTRACE_GETREGSET as _,
+            tid,
+            note as usize as *mut libc::c_void,
+            &mut iov as *mut libc::iovec as *mut libc::c_void,
+        )
+    }?;
+    bytes.truncate(iov.iov_len);
+    Ok(bytes)
+}
@@ -0,0 +1,125 @@
+use crate::output;
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::unistd;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long `flush` waits for the inferior's pipes to drain, in case something keeps writing
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Tees the inferior's stdout and stderr into a log file (`set logging-child`). The inferior
+/// writes into pipes, and a pump thread per pipe copies everything to deet's own stream and to
+/// the log. Without a log file, it just captures the output, so that it can be sent on as DAP
+/// output events rather than mixing with the protocol.
+///
+/// The pipes are inherited by the inferior's children, so their output ends up in the log too.
+/// Since the inferior's stdout is then no longer a terminal, stdio buffers it fully instead of
+/// by line.
+pub struct OutputLog {
+    file: Arc<Mutex<Option<BufWriter<File>>>>,
+    /// Read ends of the pipes, shared with the pump threads so `flush` can see what's unread
+    pipes: Mutex<Vec<Arc<File>>>,
+}
+
+impl OutputLog {
+    /// Starts a new log at `path`. A log left by a previous run is kept as `path.1`.
+    pub fn create(path: &Path) -> Result<OutputLog, String> {
+        if path.exists() {
+            let mut old = path.as_os_str().to_owned();
+            old.push(".1");
+            std::fs::rename(path, &old).map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+        let file = File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(OutputLog {
+            file: Arc::new(Mutex::new(Some(BufWriter::new(file)))),
+            pipes: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Captures the output without logging it.
+    pub fn capture() -> OutputLog {
+        OutputLog {
+            file: Arc::new(Mutex::new(None)),
+            pipes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Creates a pipe for one of the inferior's streams and starts pumping it, to deet's stderr
+    /// if `is_stderr` and its stdout otherwise. Returns the write end for the inferior.
+    pub fn pipe(&self, is_stderr: bool) -> Result<Stdio, String> {
+        let (read_fd, write_fd) =
+            unistd::pipe2(OFlag::O_CLOEXEC).map_err(|err| format!("pipe: {}", err))?;
+        let (reader, writer) = unsafe { (File::from_raw_fd(read_fd), File::from_raw_fd(write_fd)) };
+        let reader = Arc::new(reader);
+        self.pipes.lock().unwrap().push(reader.clone());
+        let file = self.file.clone();
+        thread::spawn(move || pump(&reader, &file, is_stderr));
+        Ok(Stdio::from(writer))
+    }
+
+    /// Waits until everything the inferior wrote so far has been copied, then flushes the log,
+    /// so that output shows up before deet reports a stop.
+    pub fn flush(&self) {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        loop {
+            // the pumps only read while holding the lock, so with it held, data that's no longer
+            // in a pipe has been written out
+            let mut file = self.file.lock().unwrap();
+            let drained = self
+                .pipes
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|pipe| unread_bytes(pipe) == 0);
+            if drained || Instant::now() >= deadline {
+                if let Some(file) = file.as_mut() {
+                    let _ = file.flush();
+                }
+                return;
+            }
+            drop(file);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// Copies `pipe` to deet's stdout or stderr and to the log until the last writer closes it.
+fn pump(pipe: &File, file: &Mutex<Option<BufWriter<File>>>, is_stderr: bool) {
+    let mut buf = [0u8; 4096];
+    loop {
+        let mut fds = [PollFd::new(pipe.as_raw_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, -1) {
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(_) => return,
+        }
+        let mut file = file.lock().unwrap();
+        let count = match (&*pipe).read(&mut buf) {
+            Ok(0) | Err(_) => {
+                if let Some(file) = file.as_mut() {
+                    let _ = file.flush();
+                }
+                return;
+            }
+            Ok(count) => count,
+        };
+        output::write_inferior(&buf[..count], is_stderr);
+        if let Some(file) = file.as_mut() {
+            let _ = file.write_all(&buf[..count]);
+        }
+    }
+}
+
+fn unread_bytes(pipe: &File) -> libc::c_int {
+    let mut count: libc::c_int = 0;
+    unsafe { libc::ioctl(pipe.as_raw_fd(), libc::FIONREAD, &mut count) };
+    count
+}
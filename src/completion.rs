@@ -0,0 +1,177 @@
+use crate::debugger_command::{self, ArgumentCompletion};
+use crate::dwarf_data::DwarfData;
+use crate::style::{self, paint, Style};
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hinter, HistoryHinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+
+/// The rustyline helper for deet's prompt. Tab completes command names at the start of the line,
+/// and then whatever the command's arguments are: subcommands and settings from the command
+/// table, symbols of the target, or file names. With hints on, the rest of the last command in
+/// the history that starts like the line is shown dimmed after it.
+pub struct DeetHelper {
+    filenames: FilenameCompleter,
+    symbols: SymbolIndex,
+    /// The command prompt, plain (as given to readline) and styled
+    prompt: (String, String),
+    hints: bool,
+}
+
+impl DeetHelper {
+    pub fn new(debug_data: &DwarfData) -> DeetHelper {
+        DeetHelper {
+            filenames: FilenameCompleter::new(),
+            symbols: SymbolIndex::new(debug_data),
+            prompt: (String::new(), String::new()),
+            hints: true,
+        }
+    }
+
+    pub fn set_hints(&mut self, on: bool) {
+        self.hints = on;
+    }
+
+    /// Styles `plain` as `styled` when it's the prompt. Readline measures the prompt as given,
+    /// so the escape sequences can only be added here.
+    pub fn set_prompt(&mut self, plain: String, styled: String) {
+        self.prompt = (plain, styled);
+    }
+
+    /// Switches to the symbols of a newly loaded program.
+    pub fn set_symbols(&mut self, debug_data: &DwarfData) {
+        self.symbols = SymbolIndex::new(debug_data);
+    }
+}
+
+/// Sorted snapshots of the target's symbol names, so completing a prefix is a binary search even
+/// in programs with many thousands of symbols.
+struct SymbolIndex {
+    functions: Vec<String>,
+    files: Vec<String>,
+    globals: Vec<String>,
+}
+
+impl SymbolIndex {
+    fn new(debug_data: &DwarfData) -> SymbolIndex {
+        let sorted = |mut names: Vec<String>| {
+            names.sort();
+            names.dedup();
+            names
+        };
+        SymbolIndex {
+            functions: sorted(debug_data.function_names()),
+            files: sorted(debug_data.source_file_names()),
+            globals: sorted(debug_data.global_variable_names()),
+        }
+    }
+}
+
+/// The names in sorted `names` that start with `prefix`.
+fn with_prefix<'a>(names: &'a [String], prefix: &str) -> &'a [String] {
+    let start = names.partition_point(|name| name.as_str() < prefix);
+    let len = names[start..].partition_point(|name| name.starts_with(prefix));
+    &names[start..start + len]
+}
+
+fn word(word: &str) -> Pair {
+    Pair {
+        display: word.to_string(),
+        replacement: format!("{} ", word),
+    }
+}
+
+impl Completer for DeetHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let partial = &before[start..];
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+        let candidates = match words.split_first() {
+            None => debugger_command::complete_command(partial)
+                .into_iter()
+                .map(word)
+                .collect(),
+            Some((command, args)) => {
+                match debugger_command::complete_argument(command, args, partial) {
+                    Some(ArgumentCompletion::Words(words)) => words.into_iter().map(word).collect(),
+                    Some(ArgumentCompletion::Files) => {
+                        return self.filenames.complete(line, pos, ctx)
+                    }
+                    Some(ArgumentCompletion::Locations) => {
+                        let functions = with_prefix(&self.symbols.functions, partial);
+                        // a file is followed by the line, so no space after the colon
+                        let files = with_prefix(&self.symbols.files, partial)
+                            .iter()
+                            .map(|file| Pair {
+                                display: format!("{}:", file),
+                                replacement: format!("{}:", file),
+                            });
+                        functions
+                            .iter()
+                            .map(|name| word(name))
+                            .chain(files)
+                            .collect()
+                    }
+                    Some(ArgumentCompletion::Variables) => {
+                        with_prefix(&self.symbols.globals, partial)
+                            .iter()
+                            .map(|name| word(name))
+                            .collect()
+                    }
+                    None => Vec::new(),
+                }
+            }
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for DeetHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        // not for blank lines, which repeat the last command rather than start it
+        if !self.hints || line.trim().is_empty() {
+            return None;
+        }
+        HistoryHinter {}.hint(line, pos, ctx)
+    }
+}
+
+impl Highlighter for DeetHelper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        _default: bool,
+    ) -> Cow<'b, str> {
+        if prompt == self.prompt.0 {
+            Cow::Borrowed(&self.prompt.1)
+        } else if style::enabled() {
+            Cow::Owned(paint(Style::Prompt, prompt).to_string())
+        } else {
+            Cow::Borrowed(prompt)
+        }
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if style::enabled() {
+            Cow::Owned(paint(Style::Hint, hint).to_string())
+        } else {
+            Cow::Borrowed(hint)
+        }
+    }
+}
+
+impl Validator for DeetHelper {}
+
+impl Helper for DeetHelper {}
@@ -0,0 +1,440 @@
+use crate::arch;
+use crate::inferior::{read_memory_maps, read_stat};
+use crate::registers;
+use crate::tracer::ptrace;
+use nix::sys::uio::{process_vm_readv, RemoteIoVec};
+use nix::unistd::Pid;
+use object::elf::FileHeader64;
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::Endianness;
+use std::fs;
+use std::io::{self, IoSliceMut, Write};
+use std::os::unix::fs::MetadataExt;
+
+const PAGE_SIZE: u64 = 4096;
+const ELF_HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+
+/// Offset of pr_reg within struct elf_prstatus, the same on x86_64 and aarch64
+const PRSTATUS_REGS_OFFSET: usize = 112;
+/// Offsets of pr_fname and pr_psargs within struct elf_prpsinfo
+const PRPSINFO_FNAME: std::ops::Range<usize> = 40..56;
+const PRPSINFO_PSARGS: std::ops::Range<usize> = 56..136;
+
+/// A core file opened for post-mortem debugging: the dead process's memory and registers.
+pub struct CoreFile {
+    data: memmap2::Mmap,
+    /// PT_LOAD segments as (vaddr, file offset, file size)
+    segments: Vec<(u64, u64, u64)>,
+    registers: arch::Registers,
+    /// The other register sets of the thread that crashed, such as the floating point
+    /// registers, by note type
+    regsets: Vec<(u32, Vec<u8>)>,
+    /// The signal that killed the process, if recorded
+    signal: i32,
+    pid: i32,
+    /// Command line the process was started with
+    command: String,
+}
+
+impl CoreFile {
+    pub fn open(path: &str) -> Result<CoreFile, String> {
+        let file = fs::File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+        let data =
+            unsafe { memmap2::Mmap::map(&file) }.map_err(|err| format!("{}: {}", path, err))?;
+        let format_error = |err: object::Error| format!("{}: not a valid core file: {}", path, err);
+
+        let header = FileHeader64::<Endianness>::parse(&*data).map_err(format_error)?;
+        let endian = header.endian().map_err(format_error)?;
+        if header.e_type(endian) != ET_CORE {
+            return Err(format!("{}: not a core file", path));
+        }
+        let mut segments = Vec::new();
+        let mut prstatus = None;
+        let mut prpsinfo = None;
+        let mut regsets = Vec::new();
+        let mut threads = 0;
+        for phdr in header
+            .program_headers(endian, &*data)
+            .map_err(format_error)?
+        {
+            match phdr.p_type(endian) {
+                PT_LOAD => segments.push((
+                    phdr.p_vaddr(endian),
+                    phdr.p_offset(endian),
+                    phdr.p_filesz(endian),
+                )),
+                PT_NOTE => {
+                    let mut notes = match phdr.notes(endian, &*data).map_err(format_error)? {
+                        Some(notes) => notes,
+                        None => continue,
+                    };
+                    while let Some(note) = notes.next().map_err(format_error)? {
+                        match note.n_type(endian) {
+                            // only the first thread's status, which is the one that crashed
+                            NT_PRSTATUS => {
+                                prstatus = prstatus.or(Some(note.desc()));
+                                threads += 1;
+                            }
+                            NT_PRPSINFO => prpsinfo = Some(note.desc()),
+                            // a thread's other register sets follow its status
+                            note_type if threads == 1 => {
+                                regsets.push((note_type, note.desc().to_vec()))
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let regs_size = std::mem::size_of::<arch::Registers>();
+        let prstatus = match prstatus {
+            Some(desc) if desc.len() >= PRSTATUS_REGS_OFFSET + regs_size => desc,
+            _ => return Err(format!("{}: core file has no register state", path)),
+        };
+        let read_i32 =
+            |offset: usize| i32::from_le_bytes(prstatus[offset..offset + 4].try_into().unwrap());
+        let registers = unsafe {
+            std::ptr::read_unaligned(
+                prstatus[PRSTATUS_REGS_OFFSET..].as_ptr() as *const arch::Registers
+            )
+        };
+        let c_string = |bytes: &[u8]| {
+            let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).into_owned()
+        };
+        let command = match prpsinfo {
+            Some(desc) if desc.len() >= PRPSINFO_PSARGS.end => {
+                let psargs = c_string(&desc[PRPSINFO_PSARGS]);
+                if psargs.is_empty() {
+                    c_string(&desc[PRPSINFO_FNAME])
+                } else {
+                    psargs
+                }
+            }
+            _ => String::new(),
+        };
+
+        let signal = i16::from_le_bytes([prstatus[12], prstatus[13]]) as i32;
+        let pid = read_i32(32);
+        Ok(CoreFile {
+            data,
+            segments,
+            registers,
+            regsets,
+            signal,
+            pid,
+            command,
+        })
+    }
+
+    pub fn registers(&self) -> &arch::Registers {
+        &self.registers
+    }
+
+    /// The register set of note type `note` of the thread that crashed, if the core file has it.
+    pub fn regset(&self, note: u32) -> Option<&[u8]> {
+        self.regsets
+            .iter()
+            .find(|(note_type, _)| *note_type == note)
+            .map(|(_, desc)| desc.as_slice())
+    }
+
+    pub fn signal(&self) -> i32 {
+        self.signal
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Copies `buf.len()` bytes of the dead process's memory at `addr`. Memory that isn't in the
+    /// core (unmapped, or a mapping that couldn't be dumped) fails with EIO, like reading it from a
+    /// live process would.
+    pub fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<(), nix::Error> {
+        let addr = addr as u64;
+        for (vaddr, offset, file_size) in &self.segments {
+            if addr >= *vaddr && addr + buf.len() as u64 <= vaddr + file_size {
+                let start = (offset + addr - vaddr) as usize;
+                buf.copy_from_slice(&self.data[start..start + buf.len()]);
+                return Ok(());
+            }
+        }
+        Err(nix::Error::EIO)
+    }
+
+    /// Reads a word of memory, like ptrace::read does for a live process.
+    pub fn read_word(&self, addr: usize) -> Result<usize, nix::Error> {
+        let mut buf = [0; std::mem::size_of::<usize>()];
+        self.read_memory(addr, &mut buf)?;
+        Ok(usize::from_le_bytes(buf))
+    }
+}
+
+/// A mapping to dump, with its ELF segment flags.
+struct Mapping {
+    start: u64,
+    end: u64,
+    flags: u32,
+}
+
+/// Writes an ELF core file of the stopped process `pid` to `path`, in the same layout the kernel
+/// uses: a PT_NOTE segment with the process status, registers (the floating point ones too)
+/// and auxv, then a PT_LOAD segment
+/// per mapping holding its contents. `breakpoints` lists (address, original byte) pairs so the
+/// dump shows the program's own code rather than our breakpoint instructions.
+pub fn write_core_file(pid: Pid, breakpoints: &[(usize, u8)], path: &str) -> io::Result<()> {
+    let mappings = read_mappings(pid)?;
+    let notes = build_notes(pid)?;
+
+    let phnum = mappings.len() as u64 + 1;
+    let notes_offset = ELF_HEADER_SIZE + phnum * PROGRAM_HEADER_SIZE;
+    let mut data_offset = align_up(notes_offset + notes.len() as u64, PAGE_SIZE);
+
+    // read everything first, so the headers can say how much of each mapping we got
+    let mut segments = Vec::new();
+    for mapping in &mappings {
+        let mut contents = read_memory(pid, mapping);
+        if let Some(contents) = &mut contents {
+            for (addr, orig_byte) in breakpoints {
+                let addr = *addr as u64;
+                if addr >= mapping.start && addr < mapping.end {
+                    contents[(addr - mapping.start) as usize] = *orig_byte;
+                }
+            }
+        }
+        let offset = data_offset;
+        let file_size = contents.as_ref().map_or(0, |c| c.len() as u64);
+        data_offset += align_up(file_size, PAGE_SIZE);
+        segments.push((offset, contents));
+    }
+
+    let mut out = Vec::new();
+    write_elf_header(&mut out, phnum as u16);
+    write_program_header(
+        &mut out,
+        PT_NOTE,
+        0,
+        notes_offset,
+        0,
+        notes.len() as u64,
+        0,
+        1,
+    );
+    for (mapping, (offset, contents)) in mappings.iter().zip(&segments) {
+        let file_size = contents.as_ref().map_or(0, |c| c.len() as u64);
+        write_program_header(
+            &mut out,
+            PT_LOAD,
+            mapping.flags,
+            *offset,
+            mapping.start,
+            file_size,
+            mapping.end - mapping.start,
+            PAGE_SIZE,
+        );
+    }
+    out.extend_from_slice(&notes);
+
+    let mut file = fs::File::create(path)?;
+    file.write_all(&out)?;
+    let mut written = out.len() as u64;
+    for (offset, contents) in segments {
+        if let Some(contents) = contents {
+            file.write_all(&vec![0; (offset - written) as usize])?;
+            file.write_all(&contents)?;
+            written = offset + contents.len() as u64;
+        }
+    }
+    Ok(())
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+/// The readable mappings of the process, which are the ones that can go in the core.
+fn read_mappings(pid: Pid) -> io::Result<Vec<Mapping>> {
+    let mut mappings = Vec::new();
+    for mapping in read_memory_maps(pid)? {
+        // [vsyscall] lives above the user address space and can't be read
+        if !mapping.perms.starts_with('r') || mapping.name == "[vsyscall]" {
+            continue;
+        }
+        let mut flags = PF_R;
+        if mapping.perms.as_bytes().get(1) == Some(&b'w') {
+            flags |= PF_W;
+        }
+        if mapping.perms.as_bytes().get(2) == Some(&b'x') {
+            flags |= PF_X;
+        }
+        mappings.push(Mapping {
+            start: mapping.start as u64,
+            end: mapping.end as u64,
+            flags,
+        });
+    }
+    Ok(mappings)
+}
+
+/// Reads a whole mapping, or returns None if the kernel won't let us (e.g. [vvar]).
+fn read_memory(pid: Pid, mapping: &Mapping) -> Option<Vec<u8>> {
+    let len = (mapping.end - mapping.start) as usize;
+    let mut contents = vec![0; len];
+    let mut done = 0;
+    while done < len {
+        let remote = [RemoteIoVec {
+            base: mapping.start as usize + done,
+            len: len - done,
+        }];
+        let mut local = [IoSliceMut::new(&mut contents[done..])];
+        match process_vm_readv(pid, &mut local, &remote) {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => done += read,
+        }
+    }
+    Some(contents)
+}
+
+fn write_elf_header(out: &mut Vec<u8>, phnum: u16) {
+    out.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&ET_CORE.to_le_bytes());
+    out.extend_from_slice(&arch::ELF_MACHINE.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    out.extend_from_slice(&ELF_HEADER_SIZE.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&phnum.to_le_bytes());
+    out.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_program_header(
+    out: &mut Vec<u8>,
+    p_type: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    file_size: u64,
+    mem_size: u64,
+    align: u64,
+) {
+    out.extend_from_slice(&p_type.to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&offset.to_le_bytes());
+    out.extend_from_slice(&vaddr.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes()); // p_paddr
+    out.extend_from_slice(&file_size.to_le_bytes());
+    out.extend_from_slice(&mem_size.to_le_bytes());
+    out.extend_from_slice(&align.to_le_bytes());
+}
+
+fn write_note(out: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    write_named_note(out, "CORE", note_type, desc)
+}
+
+fn write_named_note(out: &mut Vec<u8>, name: &str, note_type: u32, desc: &[u8]) {
+    out.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+    out.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    out.extend_from_slice(&note_type.to_le_bytes());
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.resize(align_up(out.len() as u64, 4) as usize, 0);
+    out.extend_from_slice(desc);
+    out.resize(align_up(out.len() as u64, 4) as usize, 0);
+}
+
+fn build_notes(pid: Pid) -> io::Result<Vec<u8>> {
+    let regs = arch::get_registers(pid).map_err(io::Error::from)?;
+    let signal = ptrace::getsiginfo(pid).map_or(0, |info| info.si_signo);
+    let (state, ppid, pgrp, sid) = read_stat(pid)?;
+    let mut notes = Vec::new();
+
+    // struct elf_prstatus
+    let mut prstatus = Vec::new();
+    prstatus.extend_from_slice(&signal.to_le_bytes()); // pr_info.si_signo
+    prstatus.extend_from_slice(&[0; 8]); // si_code, si_errno
+    prstatus.extend_from_slice(&(signal as u16).to_le_bytes()); // pr_cursig
+    prstatus.extend_from_slice(&[0; 2]);
+    prstatus.extend_from_slice(&[0; 16]); // pr_sigpend, pr_sighold
+    for id in [pid.as_raw(), ppid, pgrp, sid] {
+        prstatus.extend_from_slice(&id.to_le_bytes());
+    }
+    prstatus.extend_from_slice(&[0; 64]); // pr_utime, pr_stime, pr_cutime, pr_cstime
+                                          // pr_reg has the same layout as user_regs_struct
+    let regs_bytes = unsafe {
+        std::slice::from_raw_parts(
+            &regs as *const arch::Registers as *const u8,
+            std::mem::size_of::<arch::Registers>(),
+        )
+    };
+    prstatus.extend_from_slice(regs_bytes);
+    prstatus.extend_from_slice(&0i32.to_le_bytes()); // pr_fpvalid
+    prstatus.extend_from_slice(&[0; 4]);
+    write_note(&mut notes, NT_PRSTATUS, &prstatus);
+    // the other register sets follow the status they belong with, each left out if the kernel
+    // or CPU doesn't have it
+    for (note_type, size, name) in registers::REGSETS {
+        if let Ok(regset) = arch::read_regset(pid, *note_type, *size) {
+            write_named_note(&mut notes, name, *note_type, &regset);
+        }
+    }
+
+    // struct elf_prpsinfo
+    let metadata = fs::metadata(format!("/proc/{}", pid))?;
+    let mut prpsinfo = Vec::new();
+    let state_num = "RSDTZW".find(state).unwrap_or(0) as u8;
+    prpsinfo.extend_from_slice(&[state_num, state as u8, (state == 'Z') as u8, 0]);
+    prpsinfo.extend_from_slice(&[0; 4]);
+    prpsinfo.extend_from_slice(&0u64.to_le_bytes()); // pr_flag
+    prpsinfo.extend_from_slice(&metadata.uid().to_le_bytes());
+    prpsinfo.extend_from_slice(&metadata.gid().to_le_bytes());
+    for id in [pid.as_raw(), ppid, pgrp, sid] {
+        prpsinfo.extend_from_slice(&id.to_le_bytes());
+    }
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+    let mut fname = [0u8; 16];
+    for (dst, src) in fname.iter_mut().zip(comm.trim_end().bytes().take(15)) {
+        *dst = src;
+    }
+    prpsinfo.extend_from_slice(&fname);
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let mut psargs = [0u8; 80];
+    for (dst, src) in psargs.iter_mut().zip(cmdline.iter().take(79)) {
+        *dst = if *src == 0 { b' ' } else { *src };
+    }
+    let args_len = cmdline.len().min(79);
+    if args_len > 0 && psargs[args_len - 1] == b' ' {
+        psargs[args_len - 1] = 0;
+    }
+    prpsinfo.extend_from_slice(&psargs);
+    write_note(&mut notes, NT_PRPSINFO, &prpsinfo);
+
+    if let Ok(auxv) = fs::read(format!("/proc/{}/auxv", pid)) {
+        write_note(&mut notes, NT_AUXV, &auxv);
+    }
+    Ok(notes)
+}
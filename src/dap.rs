@@ -0,0 +1,809 @@
+//! The Debug Adapter Protocol (`--dap`), as spoken with editors like VS Code over stdin and
+//! stdout: messages are JSON with a Content-Length header. Requests are answered here, with what
+//! they need of the debugger through `Session`, or mapped onto deet commands for the debugger to
+//! run; this module also frames the messages and keeps track of the request being answered.
+//!
+//! Requests are read by a thread of their own, which handles `pause` while the program runs:
+//! deet is waiting for it then, not reading requests.
+//!
+//! Like an MI command (see `mi`), a request may be answered early, e.g. `continue` before the
+//! inferior runs or `stackTrace` by the backtrace it asked for; otherwise it's answered once the
+//! next request is read.
+
+use crate::json::{self, Value};
+#[cfg(not(test))]
+use crate::output;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Mutex, OnceLock};
+
+struct Pending {
+    request_seq: usize,
+    command: String,
+    answered: bool,
+    /// Why the request failed, if it wasn't answered: a default message, and the first console
+    /// line since it started, which is usually the more telling one
+    error: Option<&'static str>,
+    first_console: Option<String>,
+    /// The body of the response if it succeeds without being answered
+    body: Option<Value>,
+}
+
+static PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+
+/// Sequence number of the last message sent
+static SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// The requests from the thread reading them, which the first `read_request` starts
+static REQUESTS: OnceLock<Mutex<Receiver<Result<Request, String>>>> = OnceLock::new();
+
+/// The process that's running, for `pause` to interrupt; 0 while it's stopped
+static RUNNING: AtomicI32 = AtomicI32::new(0);
+
+/// What answering requests takes of the debugger.
+pub trait Session {
+    /// Loads `program`, unless deet was started with one, and has it run with `args`. Fails if
+    /// deet was started to debug another program.
+    fn launch(&mut self, program: Option<&str>, args: &[String]) -> Result<(), String>;
+    /// Sets breakpoints at `lines` of the source file at `path`, returning a DAP Breakpoint for
+    /// each.
+    fn set_breakpoints(&mut self, path: &str, lines: &[usize]) -> Vec<Value>;
+    /// The name of the program, if it's running
+    fn running(&self) -> Option<String>;
+    /// The locals and parameters in frame `level`, the innermost being 0: their names, types and
+    /// values.
+    fn locals(&self, level: usize) -> Result<Vec<(String, String, String)>, String>;
+    /// The value of `expression` in frame `level`, formatted as `print` does.
+    fn evaluate(&self, expression: &str, level: usize) -> Result<String, String>;
+}
+
+pub struct Request {
+    pub seq: usize,
+    pub command: String,
+    pub arguments: Value,
+}
+
+/// Notes that the process `pid` is running, or with None that it has stopped, for `pause`.
+pub fn set_running(pid: Option<Pid>) {
+    RUNNING.store(pid.map_or(0, Pid::as_raw), Ordering::Relaxed);
+}
+
+/// The next request, None at the end of input. A `pause` while the program runs is answered by
+/// the reading thread, which interrupts the program as ctrl+c would.
+fn read_request() -> Option<Result<Request, String>> {
+    let requests = REQUESTS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let stdin = std::io::stdin();
+            while let Some(request) = read_message(&mut stdin.lock()) {
+                if let Ok(request) = &request {
+                    let pid = RUNNING.load(Ordering::Relaxed);
+                    if request.command == "pause"
+                        && pid != 0
+                        && signal::kill(Pid::from_raw(pid), Signal::SIGINT).is_ok()
+                    {
+                        send(response(request.seq, &request.command, Ok(None)));
+                        continue;
+                    }
+                }
+                if sender.send(request).is_err() {
+                    return;
+                }
+            }
+        });
+        Mutex::new(receiver)
+    });
+    requests.lock().unwrap().recv().ok()
+}
+
+/// Reads the next request from `input`. None at the end of input; an error for a message that
+/// isn't a request, which can be skipped.
+fn read_message(input: &mut impl BufRead) -> Option<Result<Request, String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut content = vec![0; length?];
+    input.read_exact(&mut content).ok()?;
+    let message = match json::parse(&String::from_utf8_lossy(&content)) {
+        Ok(message) => message,
+        Err(err) => return Some(Err(err)),
+    };
+    let (Some(seq), Some("request"), Some(command)) = (
+        message.get("seq").as_usize(),
+        message.get("type").as_str(),
+        message.get("command").as_str(),
+    ) else {
+        return Some(Err(format!("Not a request: {}", message)));
+    };
+    Some(Ok(Request {
+        seq,
+        command: command.to_string(),
+        arguments: message.get("arguments").clone(),
+    }))
+}
+
+/// Frames `message`, giving it the next sequence number, and writes it to stdout.
+fn send(message: Value) {
+    let Value::Object(mut members) = message else {
+        return;
+    };
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed) + 1;
+    members.insert(0, ("seq".to_string(), seq.into()));
+    let content = Value::Object(members).to_string();
+    write(&format!(
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    ));
+}
+
+#[cfg(not(test))]
+fn write(message: &str) {
+    output::write_raw(message);
+}
+
+/// Tests look at the messages instead.
+#[cfg(test)]
+fn write(message: &str) {
+    tests::SENT.with(|sent| sent.borrow_mut().push(message.to_string()));
+}
+
+pub fn event(name: &str, body: Value) {
+    send(Value::object([
+        ("type", "event".into()),
+        ("event", name.into()),
+        ("body", body),
+    ]));
+}
+
+/// Output of deet (category "console") or of the inferior ("stdout" or "stderr").
+pub fn output(category: &str, text: &str) {
+    event(
+        "output",
+        Value::object([("category", category.into()), ("output", text.into())]),
+    );
+}
+
+/// Console output: an output event, and a possible error message for the request.
+pub fn console(line: &str) {
+    if let Some(pending) = PENDING.lock().unwrap().as_mut() {
+        let line = line.trim_end();
+        if !line.is_empty() && pending.first_console.is_none() {
+            pending.first_console = Some(line.to_string());
+        }
+    }
+    output("console", line);
+}
+
+/// Starts answering `request`. If it isn't answered by the time the next request is read, it
+/// fails with `error`, or if that's None, succeeds with `body`.
+pub fn begin(request: &Request, error: Option<&'static str>, body: Option<Value>) {
+    *PENDING.lock().unwrap() = Some(Pending {
+        request_seq: request.seq,
+        command: request.command.clone(),
+        answered: false,
+        error,
+        first_console: None,
+        body,
+    });
+}
+
+fn response(request_seq: usize, command: &str, result: Result<Option<Value>, &str>) -> Value {
+    let mut members = vec![
+        ("type".to_string(), "response".into()),
+        ("request_seq".to_string(), request_seq.into()),
+        ("success".to_string(), result.is_ok().into()),
+        ("command".to_string(), command.into()),
+    ];
+    match result {
+        Ok(Some(body)) => members.push(("body".to_string(), body)),
+        Ok(None) => {}
+        Err(message) => members.push(("message".to_string(), message.into())),
+    }
+    Value::Object(members)
+}
+
+/// Answers the request successfully with `body`. Later answers are dropped: a request has one
+/// response.
+pub fn respond(body: Value) {
+    answer(Ok(Some(body)));
+}
+
+pub fn fail(message: &str) {
+    answer(Err(message));
+}
+
+fn answer(result: Result<Option<Value>, &str>) {
+    let mut pending = PENDING.lock().unwrap();
+    if let Some(pending) = pending.as_mut().filter(|pending| !pending.answered) {
+        pending.answered = true;
+        send(response(pending.request_seq, &pending.command, result));
+    }
+}
+
+/// Answers the last request, if it hasn't been answered yet.
+pub fn finish() {
+    let Some(pending) = PENDING.lock().unwrap().take() else {
+        return;
+    };
+    if pending.answered {
+        return;
+    }
+    let result = match pending.error {
+        Some(default) => Err(pending.first_console.as_deref().unwrap_or(default)),
+        None => Ok(pending.body.clone()),
+    };
+    send(response(pending.request_seq, &pending.command, result));
+}
+
+/// Answers the previous request, then answers requests until one maps onto a deet command line,
+/// which is returned for the debugger to run. None at the end of input.
+pub fn next_command(session: &mut impl Session) -> Option<String> {
+    loop {
+        finish();
+        let request = match read_request()? {
+            Ok(request) => request,
+            Err(message) => {
+                outln!("{}", message);
+                continue;
+            }
+        };
+        begin(&request, None, None);
+        if let Some(line) = handle(session, &request) {
+            return Some(line);
+        }
+    }
+}
+
+/// Answers `request`, or returns the deet command line that does.
+fn handle(session: &mut impl Session, request: &Request) -> Option<String> {
+    let args = &request.arguments;
+    let command = request.command.as_str();
+    match command {
+        "initialize" => {
+            respond(Value::object([(
+                "supportsConfigurationDoneRequest",
+                true.into(),
+            )]));
+            event("initialized", Value::object([]));
+        }
+        "launch" => {
+            let program_args: Vec<String> = args
+                .get("args")
+                .as_array()
+                .iter()
+                .filter_map(|arg| arg.as_str().map(str::to_string))
+                .collect();
+            if let Err(message) = session.launch(args.get("program").as_str(), &program_args) {
+                fail(&message);
+            }
+        }
+        "setBreakpoints" => {
+            let path = args.get("source").get("path").as_str().unwrap_or("");
+            let lines: Vec<usize> = args
+                .get("breakpoints")
+                .as_array()
+                .iter()
+                .filter_map(|breakpoint| breakpoint.get("line").as_usize())
+                .collect();
+            let breakpoints = session.set_breakpoints(path, &lines);
+            respond(Value::object([("breakpoints", breakpoints.into())]));
+        }
+        "setExceptionBreakpoints" => respond(Value::object([("breakpoints", Vec::new().into())])),
+        "configurationDone" => {
+            finish();
+            return Some("run".to_string());
+        }
+        "threads" => {
+            let threads = match session.running() {
+                Some(name) => vec![Value::object([("id", 1.into()), ("name", name.into())])],
+                None => Vec::new(),
+            };
+            respond(Value::object([("threads", threads.into())]));
+        }
+        "continue" | "next" | "stepIn" | "stepOut" if session.running().is_none() => {
+            fail("The program is not being run.")
+        }
+        "continue" => {
+            respond(Value::object([("allThreadsContinued", true.into())]));
+            return Some("continue".to_string());
+        }
+        // answered before the program runs, like continue; the stop is an event
+        "next" | "stepIn" | "stepOut" => {
+            answer(Ok(None));
+            let line = match command {
+                "next" => "next",
+                "stepIn" => "step",
+                _ => "finish",
+            };
+            return Some(line.to_string());
+        }
+        // the program is stopped, as requests are being read
+        "pause" => {}
+        "stackTrace" => {
+            begin(request, Some("No stack."), None);
+            return Some("backtrace".to_string());
+        }
+        // a frame's scope is its locals, whose reference is the frame's id (its level) + 1, as
+        // 0 means there are no variables
+        "scopes" => {
+            let level = args.get("frameId").as_usize().unwrap_or(0);
+            let scope = Value::object([
+                ("name", "Locals".into()),
+                ("presentationHint", "locals".into()),
+                ("variablesReference", (level + 1).into()),
+                ("expensive", false.into()),
+            ]);
+            respond(Value::object([("scopes", vec![scope].into())]));
+        }
+        "variables" => {
+            let reference = args.get("variablesReference").as_usize().unwrap_or(0);
+            match reference.checked_sub(1).map(|level| session.locals(level)) {
+                Some(Ok(locals)) => {
+                    let variables: Vec<Value> = locals
+                        .into_iter()
+                        .map(|(name, type_name, value)| {
+                            Value::object([
+                                ("name", name.into()),
+                                ("value", value.into()),
+                                ("type", type_name.into()),
+                                ("variablesReference", 0.into()),
+                            ])
+                        })
+                        .collect();
+                    respond(Value::object([("variables", variables.into())]));
+                }
+                Some(Err(message)) => fail(&message),
+                None => respond(Value::object([("variables", Vec::new().into())])),
+            }
+        }
+        // the debug console takes deet commands
+        "evaluate" if args.get("context").as_str() == Some("repl") => {
+            let result = Value::object([("result", "".into()), ("variablesReference", 0.into())]);
+            begin(request, None, Some(result));
+            let line = args.get("expression").as_str().unwrap_or("");
+            if !line.trim().is_empty() {
+                return Some(line.to_string());
+            }
+        }
+        // hovers and watches are expressions
+        "evaluate" => {
+            let expression = args.get("expression").as_str().unwrap_or("");
+            let level = args.get("frameId").as_usize().unwrap_or(0);
+            match session.evaluate(expression, level) {
+                Ok(value) => respond(Value::object([
+                    ("result", value.into()),
+                    ("variablesReference", 0.into()),
+                ])),
+                Err(message) => fail(&message),
+            }
+        }
+        "disconnect" | "terminate" => {
+            finish();
+            return Some("quit".to_string());
+        }
+        other => fail(&format!("Unsupported request: {}", other)),
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// What `send` wrote, framed
+        pub static SENT: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// The pending request is global, so the tests take turns
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    struct MockSession {
+        program: Option<String>,
+        args: Vec<String>,
+        /// Lines with code in `main.c`
+        code_lines: Vec<usize>,
+        running: bool,
+    }
+
+    impl MockSession {
+        fn new() -> MockSession {
+            MockSession {
+                program: None,
+                args: Vec::new(),
+                code_lines: vec![3, 4, 7],
+                running: false,
+            }
+        }
+    }
+
+    impl Session for MockSession {
+        fn launch(&mut self, program: Option<&str>, args: &[String]) -> Result<(), String> {
+            match program {
+                Some("samples/other") => {
+                    Err("deet was started to debug samples/hello, not samples/other.".to_string())
+                }
+                _ => {
+                    self.program = program.map(str::to_string);
+                    self.args = args.to_vec();
+                    Ok(())
+                }
+            }
+        }
+
+        fn set_breakpoints(&mut self, path: &str, lines: &[usize]) -> Vec<Value> {
+            lines
+                .iter()
+                .map(|&line| {
+                    let verified = path.ends_with("main.c") && self.code_lines.contains(&line);
+                    Value::object([("verified", verified.into()), ("line", line.into())])
+                })
+                .collect()
+        }
+
+        fn running(&self) -> Option<String> {
+            self.running.then(|| "hello".to_string())
+        }
+
+        fn locals(&self, level: usize) -> Result<Vec<(String, String, String)>, String> {
+            match level {
+                0 => Ok(vec![
+                    ("argc".to_string(), "int".to_string(), "1".to_string()),
+                    (
+                        "name".to_string(),
+                        "char *".to_string(),
+                        "0x4020 \"deet\"".to_string(),
+                    ),
+                ]),
+                1 => Ok(Vec::new()),
+                _ => Err(format!("No frame at level {}.", level)),
+            }
+        }
+
+        fn evaluate(&self, expression: &str, level: usize) -> Result<String, String> {
+            match (expression, level) {
+                ("argc + 1", 0) => Ok("2".to_string()),
+                _ => Err(format!("No symbol \"{}\" in current context.", expression)),
+            }
+        }
+    }
+
+    fn frame(content: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+    }
+
+    /// Reads the framed `requests` and answers them as `next_command` does, returning the command
+    /// lines they mapped onto and the messages sent, without their sequence numbers.
+    fn exchange(session: &mut MockSession, requests: &[&str]) -> (Vec<String>, Vec<Value>) {
+        let input: String = requests.iter().map(|request| frame(request)).collect();
+        let mut input = input.as_bytes();
+        let mut lines = Vec::new();
+        SENT.with(|sent| sent.borrow_mut().clear());
+        while let Some(request) = read_message(&mut input) {
+            finish();
+            let request = request.unwrap();
+            begin(&request, None, None);
+            lines.extend(handle(session, &request));
+        }
+        finish();
+        let sent = SENT.with(|sent| sent.take());
+        let messages = sent
+            .iter()
+            .map(|message| {
+                let (header, content) = message.split_once("\r\n\r\n").unwrap();
+                assert_eq!(header, format!("Content-Length: {}", content.len()));
+                match json::parse(content).unwrap() {
+                    Value::Object(members) => Value::Object(
+                        members
+                            .into_iter()
+                            .filter(|(key, _)| key != "seq")
+                            .collect(),
+                    ),
+                    other => panic!("not an object: {}", other),
+                }
+            })
+            .collect();
+        (lines, messages)
+    }
+
+    fn expect(messages: &[Value], expected: &[&str]) {
+        let expected: Vec<Value> = expected.iter().map(|e| json::parse(e).unwrap()).collect();
+        assert_eq!(messages, expected);
+    }
+
+    #[test]
+    fn initialize_and_launch() {
+        let _lock = LOCK.lock().unwrap();
+        let mut session = MockSession::new();
+        let (lines, messages) = exchange(
+            &mut session,
+            &[
+                r#"{"seq":1,"type":"request","command":"initialize","arguments":{"adapterID":"deet"}}"#,
+                r#"{"seq":2,"type":"request","command":"launch","arguments":{"program":"samples/hello","args":["a b","c"]}}"#,
+                r#"{"seq":3,"type":"request","command":"configurationDone"}"#,
+            ],
+        );
+        assert_eq!(lines, ["run"]);
+        assert_eq!(session.program.as_deref(), Some("samples/hello"));
+        assert_eq!(session.args, ["a b", "c"]);
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":true,"command":"initialize","body":{"supportsConfigurationDoneRequest":true}}"#,
+                r#"{"type":"event","event":"initialized","body":{}}"#,
+                r#"{"type":"response","request_seq":2,"success":true,"command":"launch"}"#,
+                r#"{"type":"response","request_seq":3,"success":true,"command":"configurationDone"}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn launch_of_another_program_fails() {
+        let _lock = LOCK.lock().unwrap();
+        let (lines, messages) = exchange(
+            &mut MockSession::new(),
+            &[
+                r#"{"seq":1,"type":"request","command":"launch","arguments":{"program":"samples/other"}}"#,
+            ],
+        );
+        assert!(lines.is_empty());
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":false,"command":"launch","message":"deet was started to debug samples/hello, not samples/other."}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn set_breakpoints() {
+        let _lock = LOCK.lock().unwrap();
+        let (_, messages) = exchange(
+            &mut MockSession::new(),
+            &[
+                r#"{"seq":4,"type":"request","command":"setBreakpoints","arguments":{"source":{"path":"/src/main.c"},"breakpoints":[{"line":4},{"line":5}]}}"#,
+                r#"{"seq":5,"type":"request","command":"setExceptionBreakpoints","arguments":{"filters":[]}}"#,
+            ],
+        );
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":4,"success":true,"command":"setBreakpoints","body":{"breakpoints":[{"verified":true,"line":4},{"verified":false,"line":5}]}}"#,
+                r#"{"type":"response","request_seq":5,"success":true,"command":"setExceptionBreakpoints","body":{"breakpoints":[]}}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn threads_and_continue() {
+        let _lock = LOCK.lock().unwrap();
+        let mut session = MockSession::new();
+        let (lines, messages) = exchange(
+            &mut session,
+            &[
+                r#"{"seq":1,"type":"request","command":"threads"}"#,
+                r#"{"seq":2,"type":"request","command":"continue","arguments":{"threadId":1}}"#,
+            ],
+        );
+        assert!(lines.is_empty());
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":true,"command":"threads","body":{"threads":[]}}"#,
+                r#"{"type":"response","request_seq":2,"success":false,"command":"continue","message":"The program is not being run."}"#,
+            ],
+        );
+
+        session.running = true;
+        let (lines, messages) = exchange(
+            &mut session,
+            &[
+                r#"{"seq":3,"type":"request","command":"threads"}"#,
+                r#"{"seq":4,"type":"request","command":"continue","arguments":{"threadId":1}}"#,
+            ],
+        );
+        assert_eq!(lines, ["continue"]);
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":3,"success":true,"command":"threads","body":{"threads":[{"id":1,"name":"hello"}]}}"#,
+                r#"{"type":"response","request_seq":4,"success":true,"command":"continue","body":{"allThreadsContinued":true}}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn stepping_runs_next_step_and_finish() {
+        let _lock = LOCK.lock().unwrap();
+        let mut session = MockSession::new();
+        let (lines, messages) = exchange(
+            &mut session,
+            &[r#"{"seq":1,"type":"request","command":"next","arguments":{"threadId":1}}"#],
+        );
+        assert!(lines.is_empty());
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":false,"command":"next","message":"The program is not being run."}"#,
+            ],
+        );
+
+        session.running = true;
+        for (command, line) in [("next", "next"), ("stepIn", "step"), ("stepOut", "finish")] {
+            let request = format!(
+                r#"{{"seq":2,"type":"request","command":"{}","arguments":{{"threadId":1}}}}"#,
+                command
+            );
+            let (lines, messages) = exchange(&mut session, &[&request]);
+            assert_eq!(lines, [line]);
+            expect(
+                &messages,
+                &[&format!(
+                    r#"{{"type":"response","request_seq":2,"success":true,"command":"{}"}}"#,
+                    command
+                )],
+            );
+        }
+    }
+
+    #[test]
+    fn stack_trace_fails_with_the_console_output() {
+        let _lock = LOCK.lock().unwrap();
+        let input = frame(
+            r#"{"seq":7,"type":"request","command":"stackTrace","arguments":{"threadId":1}}"#,
+        );
+        SENT.with(|sent| sent.borrow_mut().clear());
+        let request = read_message(&mut input.as_bytes()).unwrap().unwrap();
+        begin(&request, None, None);
+        assert_eq!(
+            handle(&mut MockSession::new(), &request).as_deref(),
+            Some("backtrace")
+        );
+        // what the backtrace printed instead of frames
+        console("The program is not being run.\n");
+        finish();
+        let sent = SENT.with(|sent| sent.take());
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].contains(r#""event":"output""#));
+        assert!(sent[1].ends_with(
+            r#""type":"response","request_seq":7,"success":false,"command":"stackTrace","message":"The program is not being run."}"#
+        ));
+    }
+
+    #[test]
+    fn scopes_and_variables() {
+        let _lock = LOCK.lock().unwrap();
+        let (_, messages) = exchange(
+            &mut MockSession::new(),
+            &[
+                r#"{"seq":1,"type":"request","command":"scopes","arguments":{"frameId":0}}"#,
+                r#"{"seq":2,"type":"request","command":"variables","arguments":{"variablesReference":1}}"#,
+                r#"{"seq":3,"type":"request","command":"variables","arguments":{"variablesReference":2}}"#,
+                r#"{"seq":4,"type":"request","command":"variables","arguments":{"variablesReference":5}}"#,
+                r#"{"seq":5,"type":"request","command":"variables","arguments":{"variablesReference":0}}"#,
+            ],
+        );
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":true,"command":"scopes","body":{"scopes":[{"name":"Locals","presentationHint":"locals","variablesReference":1,"expensive":false}]}}"#,
+                r#"{"type":"response","request_seq":2,"success":true,"command":"variables","body":{"variables":[{"name":"argc","value":"1","type":"int","variablesReference":0},{"name":"name","value":"0x4020 \"deet\"","type":"char *","variablesReference":0}]}}"#,
+                r#"{"type":"response","request_seq":3,"success":true,"command":"variables","body":{"variables":[]}}"#,
+                r#"{"type":"response","request_seq":4,"success":false,"command":"variables","message":"No frame at level 4."}"#,
+                r#"{"type":"response","request_seq":5,"success":true,"command":"variables","body":{"variables":[]}}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn evaluate_outside_the_console_evaluates_expressions() {
+        let _lock = LOCK.lock().unwrap();
+        let (lines, messages) = exchange(
+            &mut MockSession::new(),
+            &[
+                r#"{"seq":1,"type":"request","command":"evaluate","arguments":{"expression":"argc + 1","frameId":0,"context":"hover"}}"#,
+                r#"{"seq":2,"type":"request","command":"evaluate","arguments":{"expression":"nope","context":"watch"}}"#,
+            ],
+        );
+        assert!(lines.is_empty());
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":true,"command":"evaluate","body":{"result":"2","variablesReference":0}}"#,
+                r#"{"type":"response","request_seq":2,"success":false,"command":"evaluate","message":"No symbol \"nope\" in current context."}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn evaluate_in_the_console_runs_commands() {
+        let _lock = LOCK.lock().unwrap();
+        let (lines, messages) = exchange(
+            &mut MockSession::new(),
+            &[
+                r#"{"seq":1,"type":"request","command":"evaluate","arguments":{"expression":"info breakpoints","context":"repl"}}"#,
+                r#"{"seq":2,"type":"request","command":"evaluate","arguments":{"expression":"   ","context":"repl"}}"#,
+            ],
+        );
+        assert_eq!(lines, ["info breakpoints"]);
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":true,"command":"evaluate","body":{"result":"","variablesReference":0}}"#,
+                r#"{"type":"response","request_seq":2,"success":true,"command":"evaluate","body":{"result":"","variablesReference":0}}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn unsupported_requests_fail() {
+        let _lock = LOCK.lock().unwrap();
+        let (_, messages) = exchange(
+            &mut MockSession::new(),
+            &[r#"{"seq":1,"type":"request","command":"restartFrame","arguments":{"frameId":0}}"#],
+        );
+        expect(
+            &messages,
+            &[
+                r#"{"type":"response","request_seq":1,"success":false,"command":"restartFrame","message":"Unsupported request: restartFrame"}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn disconnect_quits() {
+        let _lock = LOCK.lock().unwrap();
+        let (lines, messages) = exchange(
+            &mut MockSession::new(),
+            &[r#"{"seq":9,"type":"request","command":"disconnect","arguments":{}}"#],
+        );
+        assert_eq!(lines, ["quit"]);
+        expect(
+            &messages,
+            &[r#"{"type":"response","request_seq":9,"success":true,"command":"disconnect"}"#],
+        );
+    }
+
+    #[test]
+    fn messages_that_are_not_requests() {
+        let input = [
+            frame(r#"{"seq":1,"type":"event","event":"stopped"}"#),
+            frame("{not json"),
+            // headers other than Content-Length are skipped
+            format!(
+                "Content-Type: application/json\r\n{}",
+                frame(r#"{"seq":2,"type":"request","command":"threads"}"#)
+            ),
+        ]
+        .concat();
+        let mut input = input.as_bytes();
+        assert_eq!(
+            read_message(&mut input).unwrap().err().as_deref(),
+            Some(r#"Not a request: {"seq":1,"type":"event","event":"stopped"}"#)
+        );
+        assert!(read_message(&mut input).unwrap().is_err());
+        let request = read_message(&mut input).unwrap().unwrap();
+        assert_eq!((request.seq, request.command.as_str()), (2, "threads"));
+        assert_eq!(request.arguments, Value::Null);
+        assert!(read_message(&mut input).is_none());
+    }
+}
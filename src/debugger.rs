@@ -1,217 +1,5541 @@
-use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::{Inferior, Status};
+use crate::arch;
+use crate::completion::DeetHelper;
+use crate::core_file::{self, CoreFile};
+use crate::dap;
+use crate::debugger_command::{self, DebuggerCommand, SkipCommand};
+use crate::dwarf_data::{
+    self, format_symbol_name, DebugDataCache, DwarfData, Error as DwarfError, TypeTable,
+};
+use crate::error::DeetError;
+use crate::event::{self, Event, Location, StopReason};
+use crate::expr::{self, Expr};
+use crate::inferior::{
+    self, Counting, Inferior, LaunchOptions, ResourceUsage, Status, SyscallStop,
+};
+use crate::json::Value as JsonValue;
+use crate::location::{Location as BreakpointLocation, Resolved};
+use crate::mi::{self, Request};
+use crate::output::{self, Format};
+use crate::printf::{self, Printf};
+use crate::registers::{self, RegisterSet};
+use crate::script::Interpreter;
+use crate::settings::{Settings, Value};
+use crate::signals;
+use crate::skip::SkipList;
+use crate::source::SourceFiles;
+use crate::style::{self, paint, Style};
+use crate::syscalls::SyscallTracer;
+use crate::terminal::{Interrupts, Terminal};
+use crate::unwind;
 // use libc::getaddrinfo;
 // use nix::sys::ptrace;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use regex::Regex;
+use rustyline::config::{BellStyle, CompletionType, Configurer, EditMode};
 use rustyline::error::ReadlineError;
-use rustyline::history::FileHistory;
-use rustyline::Editor;
-use std::collections::HashMap;
+use rustyline::history::{FileHistory, History};
+use rustyline::{Config, Editor};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+/// How many aliases may expand into each other, so an alias loop errors out instead of hanging
+const MAX_ALIAS_DEPTH: usize = 8;
+
+/// How deeply user-defined commands may call each other, so recursion errors out
+const MAX_USER_CALL_DEPTH: usize = 16;
+
+/// How many frames `backtrace` shows without -full-depth
+const BACKTRACE_LIMIT: usize = 1000;
+
+/// How many frames of a backtrace are shown at a time, as text
+const BACKTRACE_BATCH: usize = 64;
+
+/// How long new history entries may wait before they're written to the history file. They're
+/// also written on quitting.
+const HISTORY_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What commands that need a program say without one
+const NO_EXECUTABLE: &str = "No executable file specified.\nUse the \"file\" command.";
+
+/// Options from deet's command line.
+#[derive(Default)]
+pub struct Options {
+    /// Run non-interactively; see `Debugger::batch`
+    pub batch: bool,
+    /// Where to keep the command history, instead of the default location
+    pub history_file: Option<PathBuf>,
+    /// The program's arguments from --args, as the shell split them, for `run` to start it
+    /// with until `set args` or a `run` with arguments changes them
+    pub args: Vec<String>,
+}
+
+/// Something to run at startup, in the order given.
+enum Startup {
+    InitFile(PathBuf),
+    /// A command from -ex
+    Command(String),
+}
+
+/// The commands followed by a block of lines up to an "end"
+const BLOCK_COMMANDS: &[&str] = &["define"];
+
+/// Environment variables as name and value, which needn't be UTF-8
+type Environment = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Why `read_block` got no block.
+enum BlockError {
+    /// The user pressed ctrl+c or ctrl+d instead of finishing it
+    Abandoned,
+    /// The script ended first
+    MissingEnd,
+}
+
+/// A command line from a script or user-defined command, waiting its turn.
+struct PendingLine {
+    text: String,
+    /// How many scripts and user-defined commands it's nested in
+    depth: usize,
+    /// The script and line number it came from, for error messages. Lines of user-defined
+    /// commands get the location of the line that called them.
+    origin: Option<(Rc<str>, usize)>,
+}
+
+/// What the user asked to break on, kept so the breakpoint can be resolved again when the
+/// inferior execs a new program.
 #[derive(Clone)]
-pub struct Breakpoint {
-    pub addr: usize,
-    pub orig_byte: u8,
+enum BreakpointSpec {
+    /// `break *addr`
+    Address,
+    /// `break [<file>:]<line>`
+    Line(Option<String>, usize),
+    /// `break <function>`; holds the raw (linkage) name of the chosen function so overloads
+    /// resolve to the same one
+    Function(String),
+}
+
+/// An entry in the debugger's breakpoint list, shared by all inferiors.
+struct UserBreakpoint {
+    addr: usize,
+    spec: BreakpointSpec,
+    /// Disabled breakpoints aren't installed in any inferior
+    enabled: bool,
+    /// Why an inferior couldn't install it when it last tried, for `info breakpoints`
+    install_error: Option<nix::Error>,
+    /// Set for a tracepoint, which collects values instead of stopping
+    trace: Option<Tracepoint>,
+    timing: BreakpointTiming,
+    /// With count-instructions on, how many instructions the program ran since its previous
+    /// breakpoint stop the last time it got to this one
+    instructions: Option<u64>,
+}
+
+/// What breakpoint-timing measured of a breakpoint: how often the program got to it, and how
+/// long it ran each time since its previous breakpoint stop, all told.
+#[derive(Default)]
+struct BreakpointTiming {
+    hits: u32,
+    total: Duration,
+}
+
+/// What a tracepoint (`trace`) collects, and how often it has this run.
+struct Tracepoint {
+    /// The expressions as typed, and parsed
+    exprs: Vec<(String, Expr)>,
+    hits: usize,
+}
+
+/// What a tracepoint collected when the program got to it once, for `tdump` and `tsave`.
+struct TraceRecord {
+    tracepoint: usize,
+    /// Which hit of the tracepoint, from 1
+    hit: usize,
+    /// Since the program started
+    time: Duration,
+    /// The values of the tracepoint's expressions, formatted, or why they couldn't be
+    values: Vec<Result<String, String>>,
+}
+
+/// Memory whose writes stop the program (`watch-range`), numbered apart from the breakpoints.
+/// Its pages are made read-only in every inferior before it's resumed.
+struct WatchRange {
+    addr: usize,
+    len: usize,
+    /// Why its pages couldn't be made read-only when last tried, so that's only reported once
+    protect_error: Option<nix::Error>,
+}
+
+/// How many bytes of a watched range a hit shows, from the address written to
+const WATCH_BYTES_SHOWN: usize = 8;
+
+/// A variable whose changes stop the program (`watch`), watched by a debug register of the
+/// current inferior. Numbered apart from the breakpoints and the range watchpoints.
+struct Watchpoint {
+    number: usize,
+    name: String,
+    entity_type: dwarf_data::TypeId,
+    /// Where the variable is in the current inferior, and its size
+    addr: usize,
+    len: usize,
+    /// A global's address as the debug info has it, to find it again in the process of a new
+    /// run
+    static_addr: Option<usize>,
+    /// The slot it has in the current inferior, None if it isn't set in one
+    slot: Option<usize>,
+    /// Its value when last looked at, to tell a change from a write of the same value
+    value: Option<Vec<u8>>,
+    /// The frame a local is in
+    frame: Option<WatchFrame>,
+}
+
+/// The frame of a watched local, or of a function a step runs until it returns: where the stack
+/// pointer is once it has returned (its CFA), and the return address, which gets an internal
+/// breakpoint to catch that.
+#[derive(Clone, Copy)]
+struct WatchFrame {
+    cfa: usize,
+    return_addr: usize,
+}
+
+impl Watchpoint {
+    /// Sets the watchpoint in `inferior` and reads the value to compare with. A local's frame
+    /// gets its breakpoint too.
+    fn arm(&mut self, inferior: &mut Inferior) -> Result<(), nix::Error> {
+        let (start, len) = hardware_span(self.addr, self.len).ok_or(nix::Error::EINVAL)?;
+        let slot = inferior.set_watchpoint(start, len)?;
+        if let Some(frame) = self.frame {
+            if let Err(err) = inferior.install_breakpoint(frame.return_addr) {
+                inferior.clear_watchpoint(slot)?;
+                return Err(err);
+            }
+        }
+        self.slot = Some(slot);
+        self.value = inferior.read_memory(self.addr, self.len).ok();
+        Ok(())
+    }
+}
+
+/// Which process to keep debugging when the inferior forks.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FollowForkMode {
+    Parent,
+    Child,
 }
 
 pub struct Debugger {
-    target: String,
-    history_path: String,
-    readline: Editor<(), FileHistory>,
+    /// The program to debug, from the command line or `file`
+    target: Option<String>,
+    /// Everything `set` can change
+    settings: Settings,
+    /// The line editor, or None in batch mode, where commands are read from stdin as they are
+    readline: Option<Editor<DeetHelper, FileHistory>>,
+    /// Whether deet runs non-interactively (`--batch`): no prompts or questions, and exiting
+    /// once the inferior terminates
+    batch: bool,
+    /// Whether deet speaks GDB/MI (`--interpreter=mi`) rather than the CLI: commands are read
+    /// without an editor, as in batch mode, and answered with MI records
+    mi: bool,
+    /// Whether deet speaks the Debug Adapter Protocol (`--dap`) rather than the CLI; see `dap`
+    dap: bool,
+    /// How many values `print` has printed, to number them like gdb's value history
+    value_history: usize,
+    /// When the history file was last written
+    history_saved: Instant,
+    /// Whether writing the history file has failed already, so it's only reported once
+    history_warned: bool,
+    /// The signal the current inferior last stopped with, if not a breakpoint's, so batch mode
+    /// can exit nonzero after a crash
+    stop_signal: Option<Signal>,
+    /// The time limit `run --timeout` gave its run, for the continue that starts it
+    run_timeout: Option<Duration>,
+    /// With breakpoint-timing on, how long the program has run since its last breakpoint stop,
+    /// not counting the time it was stopped
+    run_since_hit: Duration,
+    /// With count-instructions on, the instructions the program has run since it started or
+    /// `count reset`, and of those, since its last breakpoint stop
+    instructions: u64,
+    instructions_since_hit: u64,
+    /// With count-instructions on, the source lines `step` and `next` have stepped since the
+    /// program started or `count reset`
+    line_steps: u64,
+    /// The frame whose return `step`, `next` or `finish` is running the program until
+    step_return: Option<WatchFrame>,
+    /// The last command line, if an empty line should run it again
+    repeat_line: Option<String>,
+    /// User-defined aliases (`alias NAME = COMMAND...`), by name
+    aliases: BTreeMap<String, Vec<String>>,
+    /// User-defined commands (`define NAME`), by name
+    user_commands: BTreeMap<String, Vec<String>>,
+    /// Lines of running scripts and user-defined commands, to run before prompting again
+    pending_lines: VecDeque<PendingLine>,
+    /// How deeply the command being run is nested in scripts and user-defined commands, and the
+    /// script line it came from
+    command_depth: usize,
+    command_origin: Option<(Rc<str>, usize)>,
+    /// Init files and -ex commands still to run before the first prompt
+    startup: VecDeque<Startup>,
+    /// The inferior that commands act on
     inferior: Option<Inferior>,
-    debug_data: DwarfData,
-    breakpoints: HashMap<usize, Option<Breakpoint>>,
+    /// Number of the current inferior, as shown by `info inferiors`
+    inferior_num: usize,
+    /// Other inferiors that are kept stopped, keyed by number
+    other_inferiors: BTreeMap<usize, Inferior>,
+    /// With follow-fork-mode child, the parent of the vfork child being followed, kept until the
+    /// child execs or exits: they share memory until then, breakpoints and all
+    vfork_parent: Option<Inferior>,
+    next_inferior_num: usize,
+    debug_data: Rc<DwarfData>,
+    /// The debug info of programs loaded before, to switch back to without parsing it again
+    debug_data_cache: DebugDataCache,
+    /// All breakpoints, installed in every inferior. A breakpoint's number is its index.
+    breakpoints: Vec<UserBreakpoint>,
+    watch_ranges: Vec<WatchRange>,
+    watchpoints: Vec<Watchpoint>,
+    /// The number the next `watch` gets; those of deleted watchpoints aren't used again
+    next_watchpoint_num: usize,
+    /// What the tracepoints collected in the last run
+    trace_records: Vec<TraceRecord>,
+    /// The functions and files `skip` has stepping go over
+    skips: SkipList,
+    source_files: SourceFiles,
+    /// How to start inferiors, apart from what's in `settings`
+    launch_options: LaunchOptions,
+    terminal: Terminal,
+    /// Core file being examined instead of a live process. Only one of `core` and `inferior` is
+    /// set at a time.
+    core: Option<CoreFile>,
+    /// Set while `set trace-syscalls` is on
+    syscall_tracer: Option<SyscallTracer>,
+    /// How the last inferior ended, as a shell would report it: its exit code, or 128 plus the
+    /// signal that killed it
+    last_exit_status: Option<i32>,
+    /// The process of the current inferior once it has exited, which `info inferiors` lists
+    /// until there's a new one
+    exited_inferior: Option<Pid>,
+    /// The variables, functions and hooks of `script` and `eval`. Taken out while a script runs.
+    script: Option<Interpreter>,
 }
 
 impl Debugger {
-    /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
-        let debug_data = match DwarfData::from_file(target) {
-            Ok(val) => val,
-            Err(DwarfError::ErrorOpeningFile) => {
-                println!("Could not open file {}", target);
-                std::process::exit(1);
-            }
-            Err(DwarfError::DwarfFormatError(err)) => {
-                println!("Could not debugging symbols from {}: {:?}", target, err);
-                std::process::exit(1);
-            }
-        };
-        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
-        let mut readline = Editor::<(), FileHistory>::new().expect("Create Editor fail");
-        // Attempt to load history from ~/.deet_history if it exists
-        let _ = readline.load_history(&history_path);
-        debug_data.print();
-
-        Debugger {
-            target: target.to_string(),
-            history_path,
+    /// Initializes the debugger, with the debug info of `target` if there is one. Fails if that
+    /// can't be loaded.
+    pub fn new(target: Option<&str>, options: Options) -> Result<Debugger, DeetError> {
+        let batch = options.batch;
+        let mi = output::format() == Format::Mi;
+        let dap = output::format() == Format::Dap;
+        let mut debug_data_cache = DebugDataCache::default();
+        let debug_data = match target {
+            Some(target) => {
+                let debug_data = load_debug_data(&mut debug_data_cache, target)?;
+                if !mi {
+                    debug_data.print();
+                }
+                debug_data
+            }
+            None => Rc::new(DwarfData::empty()),
+        };
+        let mut settings = Settings::new();
+        settings.set_value("style enabled", Value::Bool(style::enabled()));
+        settings.set_value(
+            "output-format",
+            Value::Enum(match output::format() {
+                Format::Json => "json",
+                _ => "text",
+            }),
+        );
+        if !options.args.is_empty() {
+            let args: Vec<String> = options
+                .args
+                .iter()
+                .map(|arg| debugger_command::quote_arg(arg))
+                .collect();
+            settings.set_value("args", Value::String(args.join(" ")));
+        }
+        let history_path = options.history_file.or_else(default_history_path);
+        settings.set_value("history filename", Value::Path(history_path.clone()));
+        if history_path.is_none() && !batch && !mi && !dap {
+            outln!(
+                "warning: neither XDG_STATE_HOME, HOME nor the current directory is usable; command history won't be saved."
+            );
+        }
+        let readline = (!batch && !mi && !dap)
+            .then(|| {
+                let config = Config::builder()
+                    .max_history_size(settings.int("history size"))
+                    .and_then(|builder| builder.history_ignore_dups(true))
+                    .expect("Invalid history config")
+                    .edit_mode(edit_mode(&settings))
+                    .bell_style(bell_style(&settings))
+                    .completion_type(completion_type(&settings))
+                    .build();
+                // without a usable terminal, lines are read from stdin as they come
+                let mut readline = match Editor::<DeetHelper, FileHistory>::with_config(config) {
+                    Ok(readline) => readline,
+                    Err(err) => {
+                        outln!("warning: no line editing: {}", err);
+                        return None;
+                    }
+                };
+                readline.set_helper(Some(DeetHelper::new(&debug_data)));
+                // Attempt to load the history if it exists
+                if let Some(path) = &history_path {
+                    let _ = readline.load_history(path);
+                }
+                Some(readline)
+            })
+            .flatten();
+        for unit in debug_data.broken_units() {
+            outln!("warning: skipped {}", unit);
+        }
+        let terminal = Terminal::new();
+
+        Ok(Debugger {
+            target: target.map(str::to_string),
+            settings,
             readline,
+            batch,
+            mi,
+            dap,
+            value_history: 0,
+            history_saved: Instant::now(),
+            history_warned: false,
+            stop_signal: None,
+            run_since_hit: Duration::ZERO,
+            instructions: 0,
+            instructions_since_hit: 0,
+            line_steps: 0,
+            step_return: None,
+            run_timeout: None,
+            repeat_line: None,
+            aliases: BTreeMap::new(),
+            user_commands: BTreeMap::new(),
+            pending_lines: VecDeque::new(),
+            command_depth: 0,
+            command_origin: None,
+            startup: VecDeque::new(),
             inferior: None,
+            inferior_num: 1,
+            other_inferiors: BTreeMap::new(),
+            vfork_parent: None,
+            next_inferior_num: 2,
             debug_data,
-            breakpoints: HashMap::new(),
-        }
+            debug_data_cache,
+            breakpoints: Vec::new(),
+            watch_ranges: Vec::new(),
+            watchpoints: Vec::new(),
+            next_watchpoint_num: 0,
+            trace_records: Vec::new(),
+            skips: SkipList::new(),
+            source_files: SourceFiles::new(target.unwrap_or("")),
+            launch_options: LaunchOptions {
+                own_process_group: terminal.is_managed(),
+                ..LaunchOptions::default()
+            },
+            terminal,
+            core: None,
+            syscall_tracer: None,
+            last_exit_status: None,
+            exited_inferior: None,
+            script: Some(Interpreter::new()),
+        })
     }
 
-    fn parse_address(addr: &str) -> Option<usize> {
-        let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
-            &addr[2..]
-        } else {
-            &addr
+    /// Opens a core file for post-mortem debugging, replacing any previous one, and shows where
+    /// the process died.
+    pub fn load_core(&mut self, path: &str) {
+        if self.inferior.is_some() {
+            outln!("A program is being debugged already; kill it before opening a core file.");
+            return;
+        }
+        let core = match CoreFile::open(path) {
+            Ok(core) => core,
+            Err(err) => {
+                outln!("{}", err);
+                return;
+            }
         };
-        // println!("addr = {}", addr);
-        usize::from_str_radix(addr_without_0x, 16).ok()
+        if !core.command().is_empty() {
+            outln!("Core was generated by `{}'.", core.command());
+        }
+        match Signal::try_from(core.signal()) {
+            Ok(signal) if core.signal() != 0 => {
+                outln!(
+                    "Program terminated with signal {}, {}.",
+                    signals::name(signal),
+                    signals::meaning(signal)
+                )
+            }
+            _ => outln!("Process {} was not terminated by a signal.", core.pid()),
+        }
+        let rip = arch::pc(core.registers());
+        let signal = Signal::try_from(core.signal()).ok();
+        self.core = Some(core);
+        self.print_stop_location(rip, StopReason::Core(signal));
+    }
+
+    /// The program counter of the process being examined, live or dead.
+    fn current_pc(&self) -> Option<usize> {
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) => inferior.instruction_pointer().ok(),
+            (None, Some(core)) => Some(arch::pc(core.registers())),
+            (None, None) => None,
+        }
+    }
+
+    /// The radix of bare digits in an address, from `set address-radix`.
+    fn address_radix(&self) -> u32 {
+        match self.settings.choice("address-radix") {
+            "10" => 10,
+            _ => 16,
+        }
     }
 
-    pub fn run(&mut self) {
+    /// Reads and runs commands until the user quits. Returns the status deet should exit with.
+    pub fn run(&mut self) -> i32 {
         loop {
-            match self.get_next_command() {
-                DebuggerCommand::Run(args) => {
-                    // If type run when there exists inferior, kill the child process.
-                    if let Some(inferior) = &mut self.inferior {
-                        inferior.kill().expect("inferior.kill wasn't running");
-                    }
-                    if let Some(inferior) =
-                        Inferior::new(&self.target, &args, &mut self.breakpoints)
-                    {
+            let command = self.get_next_command();
+            if let Some(status) = self.execute(command) {
+                return status;
+            }
+            self.run_stop_hooks();
+        }
+    }
+
+    /// Runs one command. Returns the status deet should exit with once it has quit.
+    fn execute(&mut self, command: DebuggerCommand) -> Option<i32> {
+        match command {
+            DebuggerCommand::Run(line) => {
+                if self.core.is_some() {
+                    outln!(
+                        "Can't run while examining a core file; use \"core-file\" without \
+                         arguments to close it first."
+                    );
+                    return None;
+                }
+                let Some(target) = self.target.clone() else {
+                    outln!("{}", NO_EXECUTABLE);
+                    return None;
+                };
+                if self.inferior.is_some()
+                    && self.settings.bool("detach-on-fork")
+                    && !self.confirm("The program is running. Kill it and start a new one?")
+                {
+                    return None;
+                }
+                let (timeout, line) = match debugger_command::split_run_timeout(&line) {
+                    Ok((timeout, line)) => (timeout, line.to_string()),
+                    Err(err) => {
+                        outln!("{}", err);
+                        return None;
+                    }
+                };
+                if !line.is_empty() {
+                    self.settings.set_value("args", Value::String(line));
+                }
+                let (args, redirections) =
+                    match debugger_command::parse_run_args(self.settings.string("args")) {
+                        Ok(parsed) => parsed,
+                        Err(err) => {
+                            outln!("{}", err);
+                            return None;
+                        }
+                    };
+                // If type run when there exists inferior, kill the child process, unless
+                // we're keeping every process around
+                if let Some(mut inferior) = self.inferior.take() {
+                    if self.settings.bool("detach-on-fork") {
+                        if let Err(err) = inferior.kill() {
+                            outln!("Error killing inferior {}: {}", inferior.pid(), err);
+                        }
+                        self.release_vfork_parent();
+                    } else {
+                        self.other_inferiors.insert(self.inferior_num, inferior);
+                        self.inferior_num = self.next_inferior_num;
+                        self.next_inferior_num += 1;
+                        outln!("[New inferior {}]", self.inferior_num);
+                    }
+                }
+                if self.settings.bool("auto-reload-symbols") && self.debug_data.has_changed(&target)
+                {
+                    self.reload_symbols(&target);
+                }
+                self.print_modified_environment();
+                self.terminal.reset_inferior_modes();
+                match Inferior::new(&target, &args, &redirections, &self.launch_options()) {
+                    Ok(inferior) => {
+                        // a new trace, and no time run yet toward the first breakpoint
+                        self.trace_records.clear();
+                        self.run_since_hit = Duration::ZERO;
+                        self.instructions = 0;
+                        self.instructions_since_hit = 0;
+                        self.line_steps = 0;
+                        for trace in self
+                            .breakpoints
+                            .iter_mut()
+                            .filter_map(|bp| bp.trace.as_mut())
+                        {
+                            trace.hits = 0;
+                        }
+                        if inferior.has_stdin_pipe() {
+                            outln!(
+                            "The program's stdin is a pipe from deet: write to it with \"input\", \
+                             and close it with \"input --eof\"."
+                        );
+                        }
                         // Create the inferior
                         self.inferior = Some(inferior);
-                        self.continue_exec(&mut self.breakpoints.clone());
-                    } else {
-                        println!("Error starting subprocess");
+                        self.install_breakpoints();
+                        self.install_watchpoints();
+                        self.run_timeout = timeout;
+                        self.continue_exec();
                     }
+                    Err(err) => outln!(
+                        "{}",
+                        paint(Style::Error, format!("Error starting subprocess: {}", err))
+                    ),
                 }
+            }
 
-                DebuggerCommand::Continue => {
-                    if let Some(_) = &self.inferior {
-                        self.continue_exec(&mut self.breakpoints.clone());
-                    } else {
-                        // continue when there is no inferior
-                        println!("There is no inferior running");
+            DebuggerCommand::Input(text) => match &self.inferior {
+                Some(inferior) => {
+                    let line = printf::unescape(&text) + "\n";
+                    if let Err(err) = inferior.write_stdin(line.as_bytes()) {
+                        outln!("{}", err);
                     }
                 }
+                None => outln!("The program is not being run."),
+            },
 
-                DebuggerCommand::Backtrace => {
-                    if let Some(inferior) = &self.inferior {
-                        inferior.print_backtrace(&self.debug_data).unwrap();
+            DebuggerCommand::InputEof => match &self.inferior {
+                Some(inferior) => {
+                    if let Err(err) = inferior.close_stdin() {
+                        outln!("{}", err);
                     }
                 }
+                None => outln!("The program is not being run."),
+            },
 
-                DebuggerCommand::Quit => {
-                    // if there exists inferior, kill the child process
-                    if let Some(inferior) = &mut self.inferior {
-                        inferior.kill().expect("inferior.kill wasn't running");
-                    }
-                    return;
+            DebuggerCommand::Continue => {
+                if self.core.is_some() {
+                    outln!(
+                        "The program is not being run: a core file is a snapshot of a dead \
+                         process."
+                    );
+                } else if self.inferior.is_some() {
+                    self.continue_exec();
+                } else {
+                    // continue when there is no inferior
+                    outln!("There is no inferior running");
                 }
+            }
 
-                DebuggerCommand::Breakpoint(bp_target) => {
-                    let addr: usize;
-                    if bp_target.starts_with("*") {
-                        addr = Self::parse_address(&bp_target[1..]).unwrap();
-                    } else if let Ok(line_number) = bp_target.parse::<usize>() {
-                        if let Some(address) = self.debug_data.get_addr_for_line(None, line_number)
-                        {
-                            addr = address;
-                        } else {
-                            println!("line number can't find the corresponding address");
-                            continue;
-                        }
-                    } else if let Some(address) =
-                        self.debug_data.get_addr_for_function(None, &bp_target)
-                    {
-                        addr = address;
-                    } else {
-                        println!("{} can't be parsed to a breakpoint target", bp_target);
-                        println!("Usage: b|break|breakpoint *address|line|func");
-                        continue;
+            DebuggerCommand::Step(count) | DebuggerCommand::Next(count) => {
+                let into = matches!(command, DebuggerCommand::Step(_));
+                if self.core.is_some() {
+                    outln!(
+                        "The program is not being run: a core file is a snapshot of a dead \
+                         process."
+                    );
+                } else if self.inferior.is_some() {
+                    self.step_lines(count, into);
+                } else {
+                    outln!("The program is not being run.");
+                }
+            }
+
+            DebuggerCommand::Finish => {
+                if self.core.is_some() {
+                    outln!(
+                        "The program is not being run: a core file is a snapshot of a dead \
+                         process."
+                    );
+                } else if self.inferior.is_some() {
+                    self.finish();
+                } else {
+                    outln!("The program is not being run.");
+                }
+            }
+
+            DebuggerCommand::Kill => {
+                if self.inferior.is_none() {
+                    outln!("The program is not being run.");
+                } else if self.confirm("Kill the program being debugged?") {
+                    let mut inferior = self.inferior.take().unwrap();
+                    match inferior.kill() {
+                        Ok(()) => outln!(
+                            "[Inferior {} (process {}) killed]",
+                            self.inferior_num,
+                            inferior.pid()
+                        ),
+                        Err(err) => outln!("Error killing inferior {}: {}", inferior.pid(), err),
                     }
+                    self.release_vfork_parent();
+                    self.stop_signal = None;
+                }
+            }
 
-                    println!("Set breakpoint {} at {:#x}", self.breakpoints.len(), addr);
-                    // If there exits inferior, we should get orig_byte of new breakpoints
-                    // and insert HashMap
-                    if let Some(inferior) = &mut self.inferior {
-                        match inferior.write_byte(addr, 0xcc) {
-                            Ok(orig_byte) => {
-                                self.breakpoints
-                                    .insert(addr, Some(Breakpoint { addr, orig_byte }));
-                            }
-                            Err(err) => {
-                                println!("Debugger::new breakpoint write_byte: {}", err)
-                            }
+            DebuggerCommand::Backtrace(full_depth) => self.print_backtrace(full_depth),
+
+            DebuggerCommand::Help(topic) => debugger_command::print_help(topic.as_deref()),
+
+            DebuggerCommand::Apropos(regex) => debugger_command::apropos(&regex),
+
+            DebuggerCommand::Alias(None) => self.print_aliases(),
+
+            DebuggerCommand::Alias(Some((name, words))) => self.define_alias(name, words),
+
+            DebuggerCommand::Define(name) => self.define_user_command(name),
+
+            DebuggerCommand::Source(path) => self.source(&path),
+
+            DebuggerCommand::Script(path) => match std::fs::read_to_string(&path) {
+                Ok(code) => self.run_script(&code, &path),
+                Err(err) => outln!("{}: {}.", path, err),
+            },
+
+            DebuggerCommand::Eval(code) => self.run_script(&code, "eval"),
+
+            DebuggerCommand::Unalias(name) => {
+                if self.aliases.remove(&name).is_none() {
+                    outln!("No alias \"{}\".", name);
+                }
+            }
+
+            DebuggerCommand::Quit => {
+                if !self.confirm_quit() {
+                    return None;
+                }
+                self.save_history();
+                let current = self.inferior.take();
+                let stopped_by = self.stop_signal.filter(|_| current.is_some());
+                let others = std::mem::take(&mut self.other_inferiors).into_values();
+                for mut inferior in current.into_iter().chain(others) {
+                    if self.settings.bool("kill-on-quit") {
+                        if let Err(err) = inferior.kill() {
+                            outln!("Error killing inferior {}: {}", inferior.pid(), err);
                         }
                     } else {
-                        self.breakpoints.insert(addr, None);
+                        outln!("Detaching from inferior (pid {})", inferior.pid());
+                        for (addr, err) in inferior.remove_breakpoints() {
+                            let number = self.breakpoints.iter().position(|bp| bp.addr == addr);
+                            print_remove_error(number, addr, err);
+                        }
+                        if let Err(err) = inferior.detach() {
+                            outln!(
+                                "{}",
+                                paint(
+                                    Style::Error,
+                                    format!("Error detaching from inferior: {}", err)
+                                )
+                            );
+                        }
                     }
                 }
+                self.release_vfork_parent();
+                return Some(match (self.last_exit_status, stopped_by) {
+                    (Some(status), _)
+                        if self.settings.bool("exit-with-inferior-status") || self.batch =>
+                    {
+                        status
+                    }
+                    (_, Some(signal)) if self.batch => 128 + signal as i32,
+                    _ => 0,
+                });
             }
-        }
-    }
 
-    fn continue_exec(&mut self, breakpoints: &mut HashMap<usize, Option<Breakpoint>>) {
-        if let Some(inferior) = &mut self.inferior {
-            match inferior.continue_exec(breakpoints) {
-                Ok(status) => match status {
-                    Status::Exited(exit_status_code) => {
-                        self.inferior = None;
-                        println!("Child exited (status {})", exit_status_code);
-                    }
-                    Status::Signaled(signal) => {
-                        self.inferior = None;
-                        println!("Child exited (signal {})", signal);
-                    }
-                    Status::Stopped(signal, rip) => {
-                        println!("Child stopped (signal {})", signal);
-                        if let Some(line) = self.debug_data.get_line_from_addr(rip) {
-                            println!("Stopped at {}", line);
-                        }
+            DebuggerCommand::Watch(_) | DebuggerCommand::WatchRange(..) if self.core.is_some() => {
+                outln!("Can't watch memory in a core file: there is no process to stop.");
+            }
+
+            DebuggerCommand::Watch(name) => self.add_watchpoint(name),
+
+            DebuggerCommand::WatchRange(addr, len) => self.add_watch_range(&addr, &len),
+
+            DebuggerCommand::DeleteBreakpoints => {
+                let any = !self.breakpoints.is_empty()
+                    || !self.watch_ranges.is_empty()
+                    || !self.watchpoints.is_empty();
+                if any && self.confirm("Delete all breakpoints?") {
+                    self.delete_breakpoints();
+                }
+            }
+
+            DebuggerCommand::File(path) => self.change_file(path),
+
+            DebuggerCommand::Breakpoint(_) if self.target.is_none() => {
+                outln!("{}", NO_EXECUTABLE);
+            }
+
+            DebuggerCommand::Breakpoint(_) if self.core.is_some() => {
+                outln!("Can't set breakpoints in a core file: there is no process to stop.");
+            }
+
+            DebuggerCommand::Breakpoint(location) => {
+                for (addr, spec) in self.resolve_location(&location)? {
+                    self.set_breakpoint(addr, spec);
+                }
+            }
+
+            DebuggerCommand::Trace(..) if self.target.is_none() => {
+                outln!("{}", NO_EXECUTABLE);
+            }
+
+            DebuggerCommand::Trace(..) if self.core.is_some() => {
+                outln!("Can't set tracepoints in a core file: there is no process to trace.");
+            }
+
+            DebuggerCommand::Trace(location, exprs) => {
+                for (addr, spec) in self.resolve_location(&location)? {
+                    let number = self.add_breakpoint(addr, spec);
+                    outln!(
+                        "Tracepoint {} at {:#x}: {}",
+                        number,
+                        addr,
+                        self.describe_location(addr)
+                    );
+                    self.breakpoints[number].trace = Some(Tracepoint {
+                        exprs: exprs.clone(),
+                        hits: 0,
+                    });
+                }
+            }
+
+            DebuggerCommand::TraceDump => self.print_trace_records(),
+
+            DebuggerCommand::TimingReset => {
+                for bp in &mut self.breakpoints {
+                    bp.timing = BreakpointTiming::default();
+                }
+                self.run_since_hit = Duration::ZERO;
+            }
+
+            DebuggerCommand::CountReset => {
+                for bp in &mut self.breakpoints {
+                    bp.instructions = None;
+                }
+                self.instructions = 0;
+                self.instructions_since_hit = 0;
+                self.line_steps = 0;
+            }
+
+            DebuggerCommand::TraceSave(path) => match self.save_trace_records(&path) {
+                Ok(()) => outln!(
+                    "Saved {} trace records to {}.",
+                    self.trace_records.len(),
+                    path
+                ),
+                Err(err) => outln!("Can't write {}: {}", path, err),
+            },
+
+            DebuggerCommand::Print(expr) => match self.print_value(&expr) {
+                Ok(value) => {
+                    self.value_history += 1;
+                    outln!("${} = {}", self.value_history, value);
+                }
+                Err(err) => outln!("{}", err),
+            },
+
+            DebuggerCommand::Ptype(name, show_offsets) => {
+                let scope = self
+                    .current_pc()
+                    .and_then(|rip| self.debug_data.get_dwarf_function_from_addr(rip));
+                match self.debug_data.get_variable(&name, scope) {
+                    Some(var) => outln!(
+                        "type = {}",
+                        self.debug_data
+                            .types()
+                            .describe(var.entity_type, show_offsets)
+                    ),
+                    None => outln!("No symbol \"{}\" in current context.", name),
+                }
+            }
+
+            DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+
+            DebuggerCommand::InfoInferiors => self.print_inferiors(),
+
+            DebuggerCommand::InfoSharedLibrary => self.print_shared_libraries(),
+
+            DebuggerCommand::InfoSkip => self.print_skips(),
+            DebuggerCommand::InfoStackRaw(words) => self.print_stack_raw(words),
+            DebuggerCommand::InfoSignals => self.print_signals(),
+            DebuggerCommand::InfoRegisters(names, all) => self.print_registers(&names, all),
+
+            DebuggerCommand::InfoSource => self.print_source_info(),
+
+            DebuggerCommand::InfoSources(regex) => self.print_source_files(regex.as_deref()),
+
+            DebuggerCommand::Inferior(num) => self.switch_inferior(num),
+
+            DebuggerCommand::MaintInfoBrokenCus => {
+                let broken_units = self.debug_data.broken_units();
+                if broken_units.is_empty() {
+                    outln!("No compilation units were skipped.");
+                }
+                for unit in broken_units {
+                    outln!("{}", unit);
+                }
+            }
+
+            DebuggerCommand::Set(args) => {
+                let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+                match args.as_slice() {
+                    ["environment", rest @ ..] => self.set_environment(rest),
+                    ["rlimit", resource, limits @ ..] => self.set_rlimit(resource, limits),
+                    ["logging", "on", file @ ..] if !file.is_empty() => {
+                        let file = file.join(" ");
+                        self.settings
+                            .set_value("logging file", Value::Path(Some(file.into())));
+                        self.settings.set_value("logging", Value::Bool(true));
+                        self.apply_setting("logging");
                     }
-                },
-                Err(err) => println!("Inferior can't be woken up and execute: {}", err),
+                    _ => match self.settings.set(&args) {
+                        Ok(name) => self.apply_setting(name),
+                        Err(message) => outln!("{}", message),
+                    },
+                }
             }
-        } else {
-            println!("inferior_continue_exec failed: there is no inferior");
-        }
-    }
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
-        loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
+
+            DebuggerCommand::Unset(args) => match args.as_slice() {
+                [what, name] if what == "environment" => {
+                    self.launch_options
+                        .environment
+                        .insert(name.to_string(), None);
                 }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+                _ => outln!("Usage: unset environment VAR"),
+            },
+
+            DebuggerCommand::Cd(path) => {
+                // relative to the directory the next run would use, like a shell cd
+                let base = self.inferior_cwd();
+                match base.join(&path).canonicalize() {
+                    Ok(dir) if dir.is_dir() => {
+                        outln!("Working directory {}.", dir.display());
+                        self.launch_options.cwd = Some(dir);
+                    }
+                    Ok(dir) => outln!("{}: Not a directory.", dir.display()),
+                    Err(err) => outln!("{}: {}.", path, err),
                 }
-                Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+            }
+
+            DebuggerCommand::CoreFile(Some(path)) => self.load_core(&path),
+
+            DebuggerCommand::CoreFile(None) => {
+                self.core = None;
+                outln!("No core file now.");
+            }
+
+            DebuggerCommand::TargetRemote(address) => self.connect_remote(&address),
+
+            DebuggerCommand::Gcore(path) => match &self.inferior {
+                Some(inferior) if inferior.is_remote() => {
+                    outln!("Can't create a corefile of a remote target.")
                 }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
+                Some(inferior) => {
+                    let path = path.unwrap_or_else(|| format!("core.{}", inferior.pid()));
+                    match core_file::write_core_file(
+                        inferior.pid(),
+                        &inferior.breakpoint_bytes(),
+                        &path,
+                    ) {
+                        Ok(()) => outln!("Saved corefile {}", path),
+                        Err(err) => outln!("Can't create a corefile: {}", err),
                     }
-                    self.readline.add_history_entry(line.as_str());
+                }
+                None => outln!("The program is not being run."),
+            },
+
+            DebuggerCommand::Shell(command) => self.run_shell(&command),
+
+            DebuggerCommand::Echo(text) => output::write_text(printf::unescape(&text)),
+
+            DebuggerCommand::Printf(line) => {
+                let text = Printf::parse(&line)
+                    .and_then(|printf| self.with_frame(|frame| printf.format(frame)));
+                match text {
+                    Ok(text) => output::write_text(text),
+                    Err(err) => outln!("{}", err),
+                }
+            }
 
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
+            DebuggerCommand::Skip(command) => self.skip(command),
+
+            DebuggerCommand::Pwd => {
+                outln!("Working directory {}.", self.inferior_cwd().display())
+            }
+
+            DebuggerCommand::Show(args) => match args.as_slice() {
+                [what, rest @ ..] if what == "environment" && rest.len() <= 1 => {
+                    self.show_environment(rest.first().map(String::as_str))
+                }
+                [what] if what == "cwd" => match &self.launch_options.cwd {
+                    Some(cwd) => outln!(
+                        "Current working directory that will be used when starting the inferior \
+                         is \"{}\".",
+                        cwd.display()
+                    ),
+                    None => outln!(
+                        "You have not set the inferior's current working directory.\nThe \
+                         inferior will inherit deet's cwd ({})",
+                        self.inferior_cwd().display()
+                    ),
+                },
+                [what] if what == "rlimits" => {
+                    for (name, resource) in inferior::RLIMITS {
+                        let (soft, hard) = inferior::current_rlimit(*resource);
+                        let (soft, hard, origin) = match self.launch_options.rlimits.get(*name) {
+                            Some((soft, new_hard)) => (*soft, new_hard.unwrap_or(hard), ""),
+                            None => (soft, hard, " (inherited)"),
+                        };
+                        outln!(
+                            "{:<8} soft {:<12} hard {}{}",
+                            name,
+                            Self::format_rlimit(soft),
+                            Self::format_rlimit(hard),
+                            origin
                         );
                     }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
+                }
+                [what] if what == "user" => {
+                    for name in self.user_commands.keys() {
+                        self.print_user_command(name);
+                    }
+                }
+                [what, name] if what == "user" => {
+                    if self.user_commands.contains_key(name) {
+                        self.print_user_command(name);
                     } else {
-                        println!("Unrecognized command.");
+                        outln!("Not a user command: \"{}\".", name);
                     }
                 }
+                _ => {
+                    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+                    if let Err(message) = self.settings.show(&args) {
+                        outln!("{}", message);
+                    }
+                }
+            },
+        }
+        None
+    }
+
+    /// `set rlimit <resource> <soft> [hard]`, where limits are numbers or "unlimited". `set rlimit
+    /// <resource>` with no limits goes back to inheriting deet's.
+    fn set_rlimit(&mut self, name: &str, limits: &[&str]) {
+        let resource = match inferior::RLIMITS.iter().find(|(known, _)| *known == name) {
+            Some((_, resource)) => *resource,
+            None => {
+                let names: Vec<&str> = inferior::RLIMITS.iter().map(|(name, _)| *name).collect();
+                outln!(
+                    "Unknown resource \"{}\"; expected one of {}",
+                    name,
+                    names.join(", ")
+                );
+                return;
+            }
+        };
+        let parse = |value: &str| match value {
+            "unlimited" | "inf" => Some(libc::RLIM_INFINITY),
+            _ => value.parse::<libc::rlim_t>().ok(),
+        };
+        let (soft, hard) = match limits {
+            [] => {
+                self.launch_options.rlimits.remove(name);
+                return;
+            }
+            [soft] => (parse(soft), None),
+            [soft, hard] => match parse(hard) {
+                Some(hard) => (parse(soft), Some(hard)),
+                None => (None, None),
+            },
+            _ => (None, None),
+        };
+        let soft = match soft {
+            Some(soft) => soft,
+            None => {
+                outln!("Usage: set rlimit <resource> <soft>|unlimited [<hard>|unlimited]");
+                return;
+            }
+        };
+        // a soft limit above the hard limit would make the child fail to start
+        let effective_hard = hard.unwrap_or_else(|| inferior::current_rlimit(resource).1);
+        if soft > effective_hard {
+            outln!(
+                "Soft limit {} is above the hard limit {}.",
+                Self::format_rlimit(soft),
+                Self::format_rlimit(effective_hard)
+            );
+            return;
+        }
+        self.launch_options
+            .rlimits
+            .insert(name.to_string(), (soft, hard));
+    }
+
+    fn format_rlimit(limit: libc::rlim_t) -> String {
+        if limit == libc::RLIM_INFINITY {
+            "unlimited".to_string()
+        } else {
+            limit.to_string()
+        }
+    }
+
+    /// Puts setting `name`, which has just changed, into effect, for the settings that aren't
+    /// just read when needed.
+    fn apply_setting(&mut self, name: &str) {
+        match name {
+            // on, off, or a list of names to trace just those
+            "trace-syscalls" => {
+                self.syscall_tracer = match self.settings.string("trace-syscalls") {
+                    "off" => None,
+                    "on" => Some(SyscallTracer::new(HashSet::new())),
+                    names => Some(SyscallTracer::new(
+                        names
+                            .split(',')
+                            .filter(|name| !name.is_empty())
+                            .map(String::from)
+                            .collect(),
+                    )),
+                }
+            }
+            "history size" => {
+                if let Some(readline) = &mut self.readline {
+                    let _ = readline
+                        .history_mut()
+                        .set_max_len(self.settings.int("history size"));
+                }
+            }
+            // e.g. from .deetinit, so the history of the file named is what's searched
+            "history filename" => {
+                if let (Some(readline), Some(path)) =
+                    (&mut self.readline, self.settings.path("history filename"))
+                {
+                    let _ = readline.clear_history();
+                    let _ = readline.load_history(path);
+                }
+            }
+            "history hints" => {
+                if let Some(helper) = self.readline.as_mut().and_then(|r| r.helper_mut()) {
+                    helper.set_hints(self.settings.bool("history hints"));
+                }
+            }
+            "editing-mode" => {
+                if let Some(readline) = &mut self.readline {
+                    readline.set_edit_mode(edit_mode(&self.settings));
+                }
+            }
+            "bell-style" => {
+                if let Some(readline) = &mut self.readline {
+                    readline.set_bell_style(bell_style(&self.settings));
+                }
+            }
+            "completion-type" => {
+                if let Some(readline) = &mut self.readline {
+                    readline.set_completion_type(completion_type(&self.settings));
+                }
+            }
+            // an empty prompt is the default one
+            "prompt" if self.settings.string("prompt").is_empty() => self
+                .settings
+                .set_value("prompt", Value::String("(deet)".to_string())),
+            "style enabled" => style::set_enabled(self.settings.bool("style enabled")),
+            "output-format" if !self.mi && !self.dap => {
+                output::set_format(match self.settings.choice("output-format") {
+                    "json" => Format::Json,
+                    _ => Format::Text,
+                })
+            }
+            "logging" | "logging file" | "logging redirect" | "logging commands" => {
+                self.apply_logging(name == "logging")
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens or closes the session log to match the logging settings. With `report`, says so.
+    fn apply_logging(&mut self, report: bool) {
+        let path = self
+            .settings
+            .path("logging file")
+            .unwrap_or(Path::new("deet.txt"))
+            .to_path_buf();
+        if !self.settings.bool("logging") {
+            if report {
+                outln!("Done logging to {}.", path.display());
+            }
+            output::stop_log();
+            return;
+        }
+        let redirect = self.settings.bool("logging redirect");
+        if report {
+            if redirect {
+                outln!("Redirecting output to {}.", path.display());
+            } else {
+                outln!("Copying output to {}.", path.display());
             }
         }
+        if let Err(message) =
+            output::start_log(&path, redirect, self.settings.bool("logging commands"))
+        {
+            outln!("{}", message);
+            self.settings.set_value("logging", Value::Bool(false));
+        }
+    }
+
+    /// The launch options with the ones kept in `settings` filled in.
+    fn launch_options(&self) -> LaunchOptions {
+        LaunchOptions {
+            tty: self.settings.path("inferior-tty").map(Path::to_path_buf),
+            disable_randomization: self.settings.bool("disable-randomization"),
+            output_log: self.settings.path("logging-child").map(Path::to_path_buf),
+            detach_stdio: self.dap,
+            stdin_pipe: self.settings.choice("new-console-input") == "pipe",
+            ..self.launch_options.clone()
+        }
+    }
+
+    fn follow_fork_mode(&self) -> FollowForkMode {
+        match self.settings.choice("follow-fork-mode") {
+            "child" => FollowForkMode::Child,
+            _ => FollowForkMode::Parent,
+        }
+    }
+
+    /// `set environment VAR=value` or `set environment VAR value`. The value may contain spaces.
+    fn set_environment(&mut self, args: &[&str]) {
+        let line = args.join(" ");
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => match line.split_once(' ') {
+                Some((name, value)) => (name, value.trim()),
+                None => (line.as_str(), ""),
+            },
+        };
+        if name.is_empty() {
+            outln!("Usage: set environment VAR=value");
+            return;
+        }
+        self.launch_options
+            .environment
+            .insert(name.to_string(), Some(value.to_string()));
+    }
+
+    /// The directory the next inferior will start in.
+    fn inferior_cwd(&self) -> PathBuf {
+        match &self.launch_options.cwd {
+            Some(cwd) => cwd.clone(),
+            None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        }
+    }
+
+    /// Runs `command` with /bin/sh, on deet's terminal. The shell ignores neither ctrl+c nor
+    /// background terminal writes like deet does, and gets none of deet's files beyond stdin,
+    /// stdout and stderr. When speaking MI or DAP, stdout is for the protocol, so the output is
+    /// passed on as console output instead.
+    fn run_shell(&mut self, command: &str) {
+        let mut shell = std::process::Command::new("/bin/sh");
+        if !command.is_empty() {
+            shell.arg("-c").arg(command);
+        }
+        shell.current_dir(self.inferior_cwd());
+        unsafe {
+            shell.pre_exec(|| {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+                // close-on-exec rather than closed, so std can still report a failed exec
+                libc::close_range(3, libc::c_uint::MAX, libc::CLOSE_RANGE_CLOEXEC as i32);
+                Ok(())
+            });
+        }
+        let status = if self.mi || self.dap {
+            shell.stdin(std::process::Stdio::null());
+            shell.output().map(|output| {
+                for bytes in [&output.stdout, &output.stderr] {
+                    for line in String::from_utf8_lossy(bytes).lines() {
+                        outln!("{}", line);
+                    }
+                }
+                output.status
+            })
+        } else {
+            shell.status()
+        };
+        self.terminal.restore_ours();
+        match status {
+            Ok(status) => {
+                if let Some(code) = status.code().filter(|&code| code != 0) {
+                    outln!("Shell command exited with status {}.", code);
+                } else if let Some(signal) = status.signal() {
+                    let name = Signal::try_from(signal).map_or("an unknown signal", signals::name);
+                    outln!("Shell command was killed by {}.", name);
+                }
+            }
+            Err(err) => outln!(
+                "{}",
+                paint(Style::Error, format!("Can't run /bin/sh: {}.", err))
+            ),
+        }
+    }
+
+    /// The environment the next inferior will get: deet's own, with the user's changes applied.
+    fn inferior_environment(&self) -> Environment {
+        let mut environment: BTreeMap<Vec<u8>, Vec<u8>> = std::env::vars_os()
+            .map(|(name, value)| (name.into_vec(), value.into_vec()))
+            .collect();
+        for (name, value) in &self.launch_options.environment {
+            match value {
+                Some(value) => environment.insert(name.clone().into(), value.clone().into()),
+                None => environment.remove(name.as_bytes()),
+            };
+        }
+        environment.into_iter().collect()
+    }
+
+    /// The environment the live, local inferior was started with, per /proc/pid/environ, in
+    /// its order. What it changed since with setenv isn't there.
+    fn running_environment(&self) -> Option<std::io::Result<Environment>> {
+        let inferior = self
+            .inferior
+            .as_ref()
+            .filter(|inferior| !inferior.is_remote())?;
+        let environ = match std::fs::read(format!("/proc/{}/environ", inferior.pid())) {
+            Ok(environ) => environ,
+            Err(err) => return Some(Err(err)),
+        };
+        let environment = environ
+            .split(|&byte| byte == 0)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match entry.iter().position(|&byte| byte == b'=') {
+                Some(i) => (entry[..i].to_vec(), entry[i + 1..].to_vec()),
+                None => (entry.to_vec(), Vec::new()),
+            })
+            .collect();
+        Some(Ok(environment))
+    }
+
+    /// `show environment [VAR]`: the running program's environment, or if there's none, the
+    /// one the next run will get.
+    fn show_environment(&self, name: Option<&str>) {
+        let (environment, staged) = match self.running_environment() {
+            Some(Ok(environment)) => (environment, false),
+            Some(Err(err)) => {
+                outln!("Could not read the program's environment: {}", err);
+                return;
+            }
+            None => (self.inferior_environment(), true),
+        };
+        match name {
+            Some(name) => match environment.iter().find(|(n, _)| n == name.as_bytes()) {
+                Some((_, value)) if staged => outln!(
+                    "{} = {} (to be used at the next run)",
+                    name,
+                    escape_environment(value)
+                ),
+                Some((_, value)) => outln!("{} = {}", name, escape_environment(value)),
+                None if staged => outln!(
+                    "Environment variable \"{}\" not defined for the next run.",
+                    name
+                ),
+                None => outln!(
+                    "Environment variable \"{}\" not defined in the running program.",
+                    name
+                ),
+            },
+            None => {
+                if staged {
+                    outln!("The program is not being run. Its environment at the next run:");
+                }
+                for (name, value) in &environment {
+                    outln!("{}={}", escape_environment(name), escape_environment(value));
+                }
+            }
+        }
+    }
+
+    fn print_modified_environment(&self) {
+        if self.launch_options.environment.is_empty() {
+            return;
+        }
+        let changes: Vec<String> = self
+            .launch_options
+            .environment
+            .iter()
+            .map(|(name, value)| match value {
+                Some(value) => format!("{}={}", name, value),
+                None => format!("-{}", name),
+            })
+            .collect();
+        outln!(
+            "Starting program with modified environment: {}",
+            changes.join(" ")
+        );
+    }
+
+    /// Tells the user what `quit` will do to the live inferiors, if any, and asks whether to go
+    /// ahead.
+    fn confirm_quit(&mut self) -> bool {
+        let mut live: Vec<(usize, Pid)> = self
+            .inferior
+            .iter()
+            .map(|inferior| (self.inferior_num, inferior.pid()))
+            .chain(
+                self.other_inferiors
+                    .iter()
+                    .map(|(num, inferior)| (*num, inferior.pid())),
+            )
+            .collect();
+        if live.is_empty() || self.batch || self.mi || self.dap || !self.settings.bool("confirm") {
+            return true;
+        }
+        live.sort();
+        let action = if self.settings.bool("kill-on-quit") {
+            "killed"
+        } else {
+            "detached"
+        };
+        outln!("A debugging session is active.\n");
+        for (num, pid) in live {
+            outln!("\tInferior {} [process {}] will be {}.", num, pid, action);
+        }
+        outln!();
+        self.confirm("Quit anyway?")
+    }
+
+    /// Asks a yes-or-no question. Answers yes by itself with `set confirm off`, and when stdin
+    /// isn't a terminal, so piped commands aren't eaten as answers.
+    fn confirm(&mut self, question: &str) -> bool {
+        if self.batch || self.mi || self.dap || !self.settings.bool("confirm") {
+            return true;
+        }
+        if !self.terminal.is_managed() {
+            outln!(
+                "{} (y or n) [answered Y; input not from terminal]",
+                question
+            );
+            return true;
+        }
+        loop {
+            match self.read_line(&format!("{} (y or n) ", question)) {
+                Ok(line) => match line.trim() {
+                    "y" | "Y" | "yes" => return true,
+                    "n" | "N" | "no" => return false,
+                    _ => outln!("Please answer y or n."),
+                },
+                Err(ReadlineError::Interrupted) => return false,
+                // like gdb, EOF means yes
+                Err(_) => return true,
+            }
+        }
+    }
+
+    /// Where a location that means more than one address (e.g. an overloaded C++ function)
+    /// should go: the user is asked which of them they meant. None if they cancel.
+    fn choose_locations(&mut self, candidates: Vec<Resolved>) -> Option<Vec<Resolved>> {
+        if candidates.len() == 1 {
+            return Some(candidates);
+        }
+        outln!("[0] cancel");
+        outln!("[1] all");
+        let print_mangled = self.settings.bool("print mangled");
+        for (i, candidate) in candidates.iter().enumerate() {
+            let name = candidate.function.as_deref().map_or_else(
+                || format!("{:#x}", candidate.address),
+                |name| format_symbol_name(name, print_mangled),
+            );
+            match &candidate.line {
+                Some((file, line)) => outln!("[{}] {} at {}:{}", i + 2, name, file, line),
+                None => outln!("[{}] {}", i + 2, name),
+            }
+        }
+        let choice = self.read_line("> ").ok()?;
+        let mut chosen = vec![false; candidates.len()];
+        for token in choice.split_whitespace() {
+            match token.parse::<usize>() {
+                Ok(0) => return None,
+                Ok(1) => return Some(candidates),
+                Ok(n) if n - 2 < candidates.len() => chosen[n - 2] = true,
+                _ => {
+                    outln!("Invalid choice: {}", token);
+                    return None;
+                }
+            }
+        }
+        let chosen: Vec<Resolved> = candidates
+            .into_iter()
+            .zip(chosen)
+            .filter_map(|(candidate, chosen)| chosen.then_some(candidate))
+            .collect();
+        (!chosen.is_empty()).then_some(chosen)
+    }
+
+    /// The addresses `location` stands for, with what to resolve them again from after an
+    /// exec. None if it stands for none, or the user chose none of them.
+    fn resolve_location(
+        &mut self,
+        location: &BreakpointLocation,
+    ) -> Option<Vec<(usize, BreakpointSpec)>> {
+        let resolved = match location.resolve(&self.debug_data, self.address_radix()) {
+            Ok(resolved) => resolved,
+            Err(err) => {
+                outln!("{}", err);
+                return None;
+            }
+        };
+        let resolved = self.choose_locations(resolved)?;
+        let addrs = resolved
+            .into_iter()
+            .map(|resolved| {
+                let spec = match location {
+                    BreakpointLocation::Address { .. } => BreakpointSpec::Address,
+                    BreakpointLocation::Line(file, line) => {
+                        BreakpointSpec::Line(file.clone(), *line)
+                    }
+                    BreakpointLocation::Function(_) => {
+                        BreakpointSpec::Function(resolved.function.unwrap_or_default())
+                    }
+                };
+                (resolved.address, spec)
+            })
+            .collect();
+        Some(addrs)
+    }
+
+    fn set_breakpoint(&mut self, addr: usize, spec: BreakpointSpec) {
+        // setting one where there is one already gets that one
+        let number = self
+            .breakpoints
+            .iter()
+            .position(|bp| bp.addr == addr)
+            .unwrap_or(self.breakpoints.len());
+        event::emit(Event::BreakpointSet {
+            number,
+            address: addr,
+            location: self.location(addr),
+        });
+        self.add_breakpoint(addr, spec);
+    }
+
+    /// Adds a breakpoint at `addr` to the list, unless there's one already, and installs it.
+    /// Returns its number. The list is what new inferiors install when they start; those running
+    /// already get it installed here.
+    fn add_breakpoint(&mut self, addr: usize, spec: BreakpointSpec) -> usize {
+        let number = match self.breakpoints.iter().position(|bp| bp.addr == addr) {
+            Some(number) => {
+                // one disabled for not carrying over to an execed program is back in use
+                let bp = &mut self.breakpoints[number];
+                if !bp.enabled {
+                    bp.enabled = true;
+                    bp.spec = spec;
+                }
+                number
+            }
+            None => {
+                self.breakpoints.push(UserBreakpoint {
+                    addr,
+                    spec,
+                    enabled: true,
+                    install_error: None,
+                    trace: None,
+                    timing: BreakpointTiming::default(),
+                    instructions: None,
+                });
+                self.breakpoints.len() - 1
+            }
+        };
+        for inferior in self
+            .inferior
+            .iter_mut()
+            .chain(self.other_inferiors.values_mut())
+        {
+            if let Err(err) = inferior.install_breakpoint(addr) {
+                print_insert_error(Some(number), addr, err);
+                self.breakpoints[number].install_error = Some(err);
+            }
+        }
+        number
+    }
+
+    /// Empties the breakpoint list and takes the breakpoints out of every inferior. Numbering
+    /// starts over, since a breakpoint's number is its index.
+    fn delete_breakpoints(&mut self) {
+        for inferior in self
+            .inferior
+            .iter_mut()
+            .chain(self.other_inferiors.values_mut())
+        {
+            for (addr, err) in inferior.remove_breakpoints() {
+                let number = self.breakpoints.iter().position(|bp| bp.addr == addr);
+                print_remove_error(number, addr, err);
+            }
+            if let Err(err) = inferior.unprotect() {
+                outln!(
+                    "{}",
+                    paint(
+                        Style::Error,
+                        format!(
+                            "Can't make the watched memory writable again: {}",
+                            breakpoint_error(err)
+                        )
+                    )
+                );
+            }
+            if let Err(err) = inferior.clear_watchpoints() {
+                outln!(
+                    "{}",
+                    paint(
+                        Style::Error,
+                        format!("Can't turn the watchpoints off: {}", breakpoint_error(err))
+                    )
+                );
+            }
+        }
+        self.breakpoints.clear();
+        self.watch_ranges.clear();
+        self.watchpoints.clear();
+    }
+
+    /// `watch NAME`: watches a variable with a debug register, in the current inferior and, for
+    /// a global, in the ones of later runs. A local is looked up in the function stopped in.
+    fn add_watchpoint(&mut self, name: String) {
+        if arch::WATCHPOINT_SLOTS == 0 {
+            outln!("Hardware watchpoints aren't supported on this machine; try \"watch-range\".");
+            return;
+        }
+        if self.inferior.as_ref().is_some_and(Inferior::is_remote) {
+            outln!("Can't set hardware watchpoints in a remote program.");
+            return;
+        }
+        if self.watchpoints.len() == arch::WATCHPOINT_SLOTS {
+            outln!(
+                "Can't watch {}: {}.",
+                name,
+                watchpoint_error(nix::Error::ENOSPC)
+            );
+            return;
+        }
+        let scope = self
+            .current_pc()
+            .and_then(|rip| self.debug_data.get_dwarf_function_from_addr(rip));
+        let Some(var) = self.debug_data.get_variable(&name, scope) else {
+            outln!("No symbol \"{}\" in current context.", name);
+            return;
+        };
+        let types = self.debug_data.types();
+        let len = types.size_of(var.entity_type);
+        let word_size = types.pointer_size();
+        let (addr, static_addr, frame) = match (&var.location, &self.inferior) {
+            (dwarf_data::Location::Address(addr), inferior) => {
+                let bias = inferior
+                    .as_ref()
+                    .map_or(0, |inferior| load_bias(&self.debug_data, inferior));
+                (addr + bias, Some(*addr), None)
+            }
+            (_, Some(inferior)) => {
+                let fp = match inferior.registers() {
+                    Ok(regs) => arch::frame_pointer(&regs),
+                    Err(err) => {
+                        outln!("Can't read the registers: {}", breakpoint_error(err));
+                        return;
+                    }
+                };
+                let frame = match inferior.read_word(fp + word_size) {
+                    Ok(return_addr) => WatchFrame {
+                        cfa: fp + 2 * word_size,
+                        return_addr,
+                    },
+                    Err(err) => {
+                        outln!(
+                            "Can't find where the frame of {} returns to: {}",
+                            name,
+                            breakpoint_error(err)
+                        );
+                        return;
+                    }
+                };
+                (variable_address(var, fp, word_size), None, Some(frame))
+            }
+            (_, None) => {
+                outln!("The program is not being run.");
+                return;
+            }
+        };
+        if len == 0 {
+            outln!("Can't watch {}: its size isn't known.", name);
+            return;
+        }
+        if hardware_span(addr, len).is_none() {
+            let why = match len {
+                1..=8 => {
+                    "it straddles an 8-byte boundary, which a debug register can't watch across"
+                        .to_string()
+                }
+                _ => format!("it is {} bytes, and a debug register watches up to 8", len),
+            };
+            outln!(
+                "Can't watch {} with a debug register: {}. \"watch-range &{} {}\" watches all of it.",
+                name,
+                why,
+                name,
+                len
+            );
+            return;
+        }
+        let mut watchpoint = Watchpoint {
+            number: self.next_watchpoint_num,
+            name,
+            entity_type: var.entity_type,
+            addr,
+            len,
+            static_addr,
+            slot: None,
+            value: None,
+            frame,
+        };
+        if let Some(inferior) = self.inferior.as_mut() {
+            if let Err(err) = watchpoint.arm(inferior) {
+                outln!(
+                    "{}",
+                    paint(
+                        Style::Error,
+                        format!("Can't watch {}: {}", watchpoint.name, watchpoint_error(err))
+                    )
+                );
+                return;
+            }
+        }
+        self.next_watchpoint_num += 1;
+        outln!(
+            "Hardware watchpoint {}: {}",
+            watchpoint.number,
+            watchpoint.name
+        );
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Sets the watchpoints in the current inferior, which has just been started: the globals at
+    /// their addresses in it. The locals were in the frames of the process before, so they go.
+    fn install_watchpoints(&mut self) {
+        let Some(inferior) = self.inferior.as_mut() else {
+            return;
+        };
+        let bias = load_bias(&self.debug_data, inferior);
+        self.watchpoints.retain_mut(|watchpoint| {
+            let Some(static_addr) = watchpoint.static_addr else {
+                outln!(
+                    "Watchpoint {} deleted: the frame of {} was in the last run.",
+                    watchpoint.number,
+                    watchpoint.name
+                );
+                return false;
+            };
+            watchpoint.addr = static_addr + bias;
+            if let Err(err) = watchpoint.arm(inferior) {
+                watchpoint.slot = None;
+                outln!(
+                    "{}",
+                    paint(
+                        Style::Error,
+                        format!(
+                            "Can't set watchpoint {}: {}",
+                            watchpoint.number,
+                            watchpoint_error(err)
+                        )
+                    )
+                );
+            }
+            true
+        });
+    }
+
+    /// Deletes the watchpoints on locals of the frames that have returned to `addr`, where the
+    /// program has hit the breakpoint they put there, and gives the number of one of them. A
+    /// recursive call returning to the same place leaves the frames further out alone. The
+    /// breakpoint goes too once nothing needs it.
+    fn end_watchpoint_frames(&mut self, addr: usize) -> Option<usize> {
+        let inferior = self.inferior.as_mut()?;
+        let sp = inferior
+            .registers()
+            .ok()
+            .and_then(|regs| unwind::Frame::new(&regs).sp())?;
+        let mut ended = None;
+        self.watchpoints.retain(|watchpoint| {
+            let returned = watchpoint
+                .frame
+                .is_some_and(|frame| frame.return_addr == addr && sp >= frame.cfa);
+            if returned {
+                if let Some(slot) = watchpoint.slot {
+                    // the process is stopped, so this only fails if it's gone
+                    let _ = inferior.clear_watchpoint(slot);
+                }
+                outln!(
+                    "\nWatchpoint {} deleted because the program has left the block in\nwhich its expression is valid.",
+                    watchpoint.number
+                );
+                ended = Some(watchpoint.number);
+            }
+            !returned
+        });
+        if !self.breakpoint_needed(addr) {
+            if let Some(inferior) = self.inferior.as_mut() {
+                if let Err(err) = inferior.remove_breakpoint(addr) {
+                    print_remove_error(None, addr, err);
+                }
+            }
+        }
+        ended
+    }
+
+    /// The watchpoint whose slot the current inferior's hardware trap was for, if it was one.
+    fn watchpoint_hit(&self) -> Option<usize> {
+        let slot = self.inferior.as_ref()?.watchpoint_hit().ok()??;
+        self.watchpoints
+            .iter()
+            .position(|watchpoint| watchpoint.slot == Some(slot))
+    }
+
+    /// `watch-range ADDRESS LENGTH`: watches the memory in every inferior from now on,
+    /// starting with the current one.
+    fn add_watch_range(&mut self, addr: &Expr, len: &Expr) {
+        let evaluate = |expr: &Expr| {
+            self.with_frame(|frame| expr.evaluate(frame)?.integer(frame))
+                .map(|value| value as usize)
+        };
+        let (addr, len) = match (evaluate(addr), evaluate(len)) {
+            (Ok(addr), Ok(len)) => (addr, len),
+            (Err(err), _) | (_, Err(err)) => {
+                outln!("{}", err);
+                return;
+            }
+        };
+        if len == 0 {
+            outln!("Can't watch a range of no bytes.");
+            return;
+        }
+        if addr.checked_add(len).is_none() {
+            outln!("The range runs past the end of the address space.");
+            return;
+        }
+        if self.debug_data.pointer_size() == 4 {
+            outln!("Can't watch memory of a 32-bit program.");
+            return;
+        }
+        self.watch_ranges.push(WatchRange {
+            addr,
+            len,
+            protect_error: None,
+        });
+        outln!(
+            "Range watchpoint {}: {:#x}-{:#x} ({} bytes)",
+            self.watch_ranges.len() - 1,
+            addr,
+            addr + len,
+            len
+        );
+        self.protect_watch_ranges();
+    }
+
+    /// Makes the pages of the watched ranges read-only in the current inferior, if they aren't
+    /// already, and reports the ones that can't be, e.g. because they aren't mapped yet.
+    fn protect_watch_ranges(&mut self) {
+        let Some(inferior) = self.inferior.as_mut() else {
+            return;
+        };
+        for (number, range) in self.watch_ranges.iter_mut().enumerate() {
+            let result = inferior.protect(range.addr, range.len);
+            if let Err(err) = result {
+                if range.protect_error != Some(err) {
+                    outln!(
+                        "{}",
+                        paint(
+                            Style::Error,
+                            format!(
+                                "Can't watch range {} at {:#x} yet: {}",
+                                number,
+                                range.addr,
+                                watch_error(err)
+                            )
+                        )
+                    );
+                }
+            }
+            range.protect_error = result.err();
+        }
+    }
+
+    /// Addresses of the enabled breakpoints, to install in a new inferior.
+    fn breakpoint_addrs(&self) -> Vec<usize> {
+        self.breakpoints
+            .iter()
+            .filter(|bp| bp.enabled)
+            .map(|bp| bp.addr)
+            .collect()
+    }
+
+    /// Installs the enabled breakpoints in the current inferior, which has just started or
+    /// execed a new program, and reports together the ones that couldn't be.
+    fn install_breakpoints(&mut self) {
+        let addrs = self.breakpoint_addrs();
+        let Some(inferior) = self.inferior.as_mut() else {
+            return;
+        };
+        for bp in &mut self.breakpoints {
+            bp.install_error = None;
+        }
+        for (addr, err) in inferior.install_breakpoints(&addrs) {
+            let number = self.breakpoints.iter().position(|bp| bp.addr == addr);
+            print_insert_error(number, addr, err);
+            if let Some(number) = number {
+                self.breakpoints[number].install_error = Some(err);
+            }
+        }
+    }
+
+    fn continue_exec(&mut self) {
+        self.resume(false);
+    }
+
+    /// Resumes the current inferior until it stops in a way worth reporting, and reports it; or
+    /// with `step`, until it has executed an instruction of the current thread. True if it
+    /// stopped without anything to report: after the step, or back from the frame of
+    /// `step_return`.
+    fn resume(&mut self, step: bool) -> bool {
+        // one deadline for all the resumes it takes to get to a stop worth reporting
+        let timeout = self
+            .run_timeout
+            .take()
+            .or_else(|| match self.settings.int("run-timeout") {
+                0 | usize::MAX => None,
+                seconds => Some(Duration::from_secs(seconds as u64)),
+            });
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut stepped = false;
+        loop {
+            // what the step ran into is handled, and it's done
+            if step && stepped {
+                return true;
+            }
+            stepped = true;
+            // pages of a new process, or that weren't mapped last time
+            if !self.watch_ranges.is_empty() {
+                self.protect_watch_ranges();
+            }
+            let Some(inferior) = &mut self.inferior else {
+                break;
+            };
+            let pid = inferior.pid();
+            // an inferior on its own tty, or on another machine, doesn't need ours
+            let share_terminal =
+                self.settings.path("inferior-tty").is_none() && !inferior.is_remote();
+            if share_terminal {
+                self.terminal.give_to_inferior(pid);
+            }
+            if self.dap {
+                dap::set_running(Some(pid));
+            }
+            let count = self.settings.bool("count-instructions");
+            if count != inferior.counts_instructions() {
+                match count {
+                    true => match inferior.count_instructions() {
+                        Ok(Counting::Hardware) => {}
+                        Ok(Counting::Steps(err)) => outln!(
+                            "No hardware instruction counter: {}. Counting instructions by \
+                             single-stepping the program, which is much slower.",
+                            perf_error(err)
+                        ),
+                        Err(_) => outln!("The instructions of a remote program can't be counted."),
+                    },
+                    false => inferior.stop_counting_instructions(),
+                }
+            }
+            let resumed = self.settings.bool("breakpoint-timing").then(Instant::now);
+            inferior.set_deadline(deadline);
+            let mut result = match step {
+                // a breakpoint or watchpoint stepped onto is reported as though it trapped
+                true => inferior.step_instruction().map(|status| match status {
+                    Status::Stepped(pc) if matches!(inferior.watchpoint_hit(), Ok(Some(_))) => {
+                        Status::HardwareTrap(pc)
+                    }
+                    Status::Stepped(pc) if inferior.has_breakpoint(pc) => Status::Breakpoint(pc),
+                    status => status,
+                }),
+                false => inferior.continue_exec(self.syscall_tracer.is_some()),
+            };
+            inferior.set_deadline(None);
+            if let Some(resumed) = resumed {
+                self.run_since_hit += resumed.elapsed();
+            }
+            // a write to the page of a watched range, which may or may not be in the range
+            let mut watch_hit = None;
+            let fault = match result {
+                Ok(Status::Stopped(Signal::SIGSEGV, _)) => inferior
+                    .siginfo()
+                    .ok()
+                    .and_then(|siginfo| inferior::fault_address(&siginfo))
+                    .filter(|&addr| inferior.is_protected(addr)),
+                _ => None,
+            };
+            if let Some(addr) = fault {
+                let hit = self
+                    .watch_ranges
+                    .iter()
+                    .position(|range| (range.addr..range.addr + range.len).contains(&addr));
+                let shown = |inferior: &Inferior| {
+                    let range = &self.watch_ranges[hit?];
+                    let len = WATCH_BYTES_SHOWN.min(range.addr + range.len - addr);
+                    inferior.read_memory(addr, len).ok()
+                };
+                let old = shown(inferior);
+                result = inferior.step_over_protection(addr);
+                if let Ok(Status::Stepped(pc)) = result {
+                    match hit {
+                        Some(number) => watch_hit = Some((number, addr, old, shown(inferior))),
+                        // a stop there would have been reported instead
+                        None if inferior.has_breakpoint(pc) => result = Ok(Status::Breakpoint(pc)),
+                        None => continue,
+                    }
+                }
+            }
+            if self.dap {
+                dap::set_running(None);
+            }
+            let counted = inferior.take_instructions().map(|executed| {
+                self.instructions += executed;
+                self.instructions_since_hit += executed;
+            });
+            inferior.flush_output();
+            if share_terminal {
+                let alive = matches!(
+                    result,
+                    Ok(Status::Stopped(..)
+                        | Status::Breakpoint(_)
+                        | Status::Stepped(_)
+                        | Status::HardwareTrap(_)
+                        | Status::TimedOut(_)
+                        | Status::Forked(..)
+                        | Status::Execed)
+                );
+                self.terminal.take_back(alive);
+            }
+            self.stop_signal = match result {
+                Ok(Status::Stopped(signal, _)) => Some(signal),
+                _ => None,
+            };
+            if let (Some((number, addr, old, new)), Ok(Status::Stepped(pc))) = (watch_hit, &result)
+            {
+                self.print_watch_hit(number, addr, old, new);
+                self.print_stop_location(*pc, StopReason::RangeWatchpoint(number, addr));
+                return false;
+            }
+            match result {
+                Ok(status) => match status {
+                    Status::Exited(exit_status_code, usage) => {
+                        self.inferior = None;
+                        self.release_vfork_parent();
+                        self.last_exit_status = Some(exit_status_code);
+                        self.exited_inferior = Some(pid);
+                        event::emit(Event::Exited {
+                            status: exit_status_code,
+                        });
+                        if counted.is_some() {
+                            self.print_instructions();
+                        }
+                        self.print_statistics(&usage);
+                        self.report_inferior_exit(pid);
+                    }
+                    Status::Signaled(signal, core_dumped, usage) => {
+                        self.inferior = None;
+                        self.release_vfork_parent();
+                        self.last_exit_status = Some(128 + signal as i32);
+                        self.exited_inferior = Some(pid);
+                        event::emit(Event::Signaled {
+                            signal,
+                            core_dumped,
+                        });
+                        if counted.is_some() {
+                            self.print_instructions();
+                        }
+                        self.print_statistics(&usage);
+                        self.report_inferior_exit(pid);
+                    }
+                    Status::Stopped(
+                        signal @ (Signal::SIGSEGV
+                        | Signal::SIGBUS
+                        | Signal::SIGFPE
+                        | Signal::SIGILL),
+                        rip,
+                    ) => {
+                        self.print_fault(signal, rip);
+                    }
+                    Status::Stopped(signal, rip) => {
+                        self.print_stop_location(rip, StopReason::Signal(signal));
+                    }
+                    Status::Breakpoint(addr) => {
+                        let number = self.breakpoints.iter().position(|bp| bp.addr == addr);
+                        // the return of a frame with a watched local or being stepped out of,
+                        // or of a deeper call returning to the same place
+                        let frame_return = self.watchpoints.iter().any(|watchpoint| {
+                            watchpoint
+                                .frame
+                                .is_some_and(|frame| frame.return_addr == addr)
+                        });
+                        let step_return =
+                            self.step_return.filter(|frame| frame.return_addr == addr);
+                        if frame_return || step_return.is_some() {
+                            let ended = match frame_return {
+                                true => self.end_watchpoint_frames(addr),
+                                false => None,
+                            };
+                            let returned =
+                                step_return.is_some_and(|frame| self.has_returned(frame));
+                            match (number, ended, returned) {
+                                (None, None, false) => continue,
+                                (None, None, true) => return true,
+                                (None, Some(ended), _) => {
+                                    self.print_stop_location(
+                                        addr,
+                                        StopReason::WatchpointScope(ended),
+                                    );
+                                    return false;
+                                }
+                                // reported as the breakpoint's
+                                (Some(_), ..) => {}
+                            }
+                        }
+                        if let (Some(number), Some(_)) = (number, resumed) {
+                            let timing = &mut self.breakpoints[number].timing;
+                            timing.hits += 1;
+                            timing.total += std::mem::take(&mut self.run_since_hit);
+                        }
+                        if let (Some(number), Some(())) = (number, counted) {
+                            self.breakpoints[number].instructions =
+                                Some(std::mem::take(&mut self.instructions_since_hit));
+                        }
+                        if let Some(number) =
+                            number.filter(|&number| self.breakpoints[number].trace.is_some())
+                        {
+                            self.collect_trace(number);
+                            continue;
+                        }
+                        let reason = match number {
+                            Some(number) => StopReason::Breakpoint(number),
+                            None => StopReason::Signal(Signal::SIGTRAP),
+                        };
+                        self.print_stop_location(addr, reason);
+                    }
+                    // a step of deet's own, over a breakpoint or a write to a watched page;
+                    // the program didn't ask to stop
+                    Status::Stepped(_) => continue,
+                    Status::HardwareTrap(rip) => match self.watchpoint_hit() {
+                        Some(index) => {
+                            let watchpoint = &self.watchpoints[index];
+                            let new = self.inferior.as_ref().and_then(|inferior| {
+                                inferior.read_memory(watchpoint.addr, watchpoint.len).ok()
+                            });
+                            // a write of the value it had, or to the bytes around it
+                            if new == watchpoint.value {
+                                continue;
+                            }
+                            let old = std::mem::replace(&mut self.watchpoints[index].value, new);
+                            self.print_watchpoint_hit(index, old);
+                            let watchpoint = &self.watchpoints[index];
+                            let reason =
+                                StopReason::Watchpoint(watchpoint.number, watchpoint.name.clone());
+                            self.print_stop_location(rip, reason);
+                        }
+                        // not one of deet's debug registers, so the program set it, e.g. with
+                        // perf_event_open, and gets the SIGTRAP
+                        None => {
+                            self.stop_signal = Some(Signal::SIGTRAP);
+                            self.print_stop_location(rip, StopReason::Signal(Signal::SIGTRAP));
+                        }
+                    },
+                    // left stopped where it was, to see what kept it busy
+                    Status::TimedOut(rip) => {
+                        self.print_stop_location(rip, StopReason::Timeout(timeout.unwrap()));
+                    }
+                    Status::Forked(child_pid, vfork) => {
+                        // keep running whichever process we end up following
+                        if let Err(err) = self.follow_fork(child_pid, vfork) {
+                            outln!(
+                                "{}",
+                                paint(
+                                    Style::Error,
+                                    format!("Error handling fork of child {}: {}", child_pid, err)
+                                )
+                            );
+                            return false;
+                        }
+                        continue;
+                    }
+                    Status::Execed => {
+                        self.release_vfork_parent();
+                        self.follow_exec();
+                        continue;
+                    }
+                    Status::Syscall(stop) => {
+                        let inferior = self.inferior.as_ref().unwrap();
+                        if let Some(tracer) = &mut self.syscall_tracer {
+                            match stop {
+                                SyscallStop::Entry => tracer.on_entry(inferior),
+                                SyscallStop::Exit => tracer.on_exit(inferior),
+                            }
+                        }
+                        continue;
+                    }
+                },
+                Err(err) => outln!("Inferior can't be woken up and execute: {}", err),
+            }
+            return false;
+        }
+        outln!("inferior_continue_exec failed: there is no inferior");
+        false
+    }
+
+    /// `step` and `next`: steps `count` source lines, into the functions called with `into`,
+    /// and says where it got to. A stop for anything else on the way is reported instead.
+    fn step_lines(&mut self, count: usize, into: bool) {
+        let mut new_function = false;
+        for _ in 0..count {
+            match self.step_line(into) {
+                Some(entered) => new_function |= entered,
+                None => return,
+            }
+            if self
+                .inferior
+                .as_ref()
+                .is_some_and(Inferior::counts_instructions)
+            {
+                self.line_steps += 1;
+            }
+        }
+        if let Some(pc) = self.current_pc() {
+            self.print_stop_location(pc, StopReason::Step(new_function));
+        }
+    }
+
+    /// Runs the current inferior until it gets to the start of another source line, stepping
+    /// into a function called on the way with `into` unless it has no line information or is
+    /// skipped, and over it otherwise. Some(whether that's in another function than it started
+    /// in) once it has; None if it stopped for something else, which has been reported.
+    fn step_line(&mut self, into: bool) -> Option<bool> {
+        // kept even if an exec replaces it meanwhile, as the functions and lines come from it
+        let debug_data = self.debug_data.clone();
+        let word_size = debug_data.pointer_size();
+        let mut pc = self.current_pc()?;
+        let mut function = debug_data.get_dwarf_function_from_addr(pc);
+        let mut line = debug_data
+            .get_line_from_addr(pc)
+            .filter(|_| function.is_some());
+        let mut new_function = false;
+        if line.is_none() {
+            outln!(
+                "Single stepping until exit from function {},\nwhich has no line number information.",
+                self.location(pc).function.as_deref().unwrap_or("??")
+            );
+        }
+        loop {
+            // out of code without lines, to where the caller of it has them
+            while line.is_none() {
+                let Some((return_addr, cfa)) = self.inferior.as_ref()?.frame_return(&debug_data)
+                else {
+                    self.resume(false);
+                    return None;
+                };
+                if !self.run_to_return(WatchFrame { cfa, return_addr }) {
+                    return None;
+                }
+                pc = self.current_pc()?;
+                function = debug_data.get_dwarf_function_from_addr(pc);
+                line = debug_data
+                    .get_line_from_addr(pc)
+                    .filter(|_| function.is_some());
+                new_function = true;
+            }
+            let before = self.inferior.as_ref()?.registers().ok()?;
+            if !self.resume(true) {
+                return None;
+            }
+            let inferior = self.inferior.as_ref()?;
+            let after = inferior.registers().ok()?;
+            pc = arch::pc(&after);
+            if let Some((return_addr, cfa)) =
+                arch::called(&before, &after, word_size, |addr| inferior.read_word(addr))
+            {
+                let callee = debug_data.get_dwarf_function_from_addr(pc);
+                let callee_line = debug_data.get_line_from_addr(pc);
+                let step_in = match (callee, &callee_line) {
+                    (Some(callee), Some(callee_line)) => {
+                        into && !self
+                            .skips
+                            .is_skipped(Some(&callee.demangled_name()), Some(&callee_line.file))
+                    }
+                    _ => false,
+                };
+                if step_in {
+                    // through the prologue, to where the body starts
+                    let callee = callee.unwrap();
+                    let body = debug_data.after_prologue(callee);
+                    while pc != body && callee.contains(pc) {
+                        if !self.resume(true) {
+                            return None;
+                        }
+                        pc = self.current_pc()?;
+                    }
+                    return Some(true);
+                }
+                if !self.run_to_return(WatchFrame { cfa, return_addr }) {
+                    return None;
+                }
+                pc = self.current_pc()?;
+            }
+            let started = debug_data.get_line_starting_at(pc);
+            if !function.is_some_and(|function| function.contains(pc)) {
+                // returned, or jumped to another function: on to the next line there, unless
+                // this is the start of one
+                function = debug_data.get_dwarf_function_from_addr(pc);
+                line = debug_data
+                    .get_line_from_addr(pc)
+                    .filter(|_| function.is_some());
+                new_function = true;
+                if line.is_some() && started.is_some() {
+                    return Some(true);
+                }
+                continue;
+            }
+            let current = line.as_ref().unwrap();
+            if started.is_some_and(|started| {
+                started.number != current.number || started.file != current.file
+            }) {
+                return Some(new_function);
+            }
+        }
+    }
+
+    /// `finish`: runs the current inferior until the function it's stopped in returns, and says
+    /// where that is.
+    fn finish(&mut self) {
+        let Some(pc) = self.current_pc() else {
+            return;
+        };
+        let Some((return_addr, cfa)) = self
+            .inferior
+            .as_ref()
+            .unwrap()
+            .frame_return(&self.debug_data)
+        else {
+            outln!("\"finish\" not meaningful in the outermost frame.");
+            return;
+        };
+        let location = Location {
+            args: self.frame_args(pc),
+            ..self.location(pc)
+        };
+        outln!("Run till exit from {}", location.frame_text());
+        if self.run_to_return(WatchFrame { cfa, return_addr }) {
+            self.print_stop_location(return_addr, StopReason::Finished);
+        }
+    }
+
+    /// Runs the current inferior until `frame` returns, with an internal breakpoint at its return
+    /// address. True if it got there; false if something else stopped it first, or the
+    /// breakpoint can't be installed, which has been reported.
+    fn run_to_return(&mut self, frame: WatchFrame) -> bool {
+        let Some(inferior) = self.inferior.as_mut() else {
+            return false;
+        };
+        if let Err(err) = inferior.install_breakpoint(frame.return_addr) {
+            outln!(
+                "Can't run until the return to {:#x}: {}",
+                frame.return_addr,
+                breakpoint_error(err)
+            );
+            return false;
+        }
+        self.step_return = Some(frame);
+        let returned = self.resume(false);
+        self.step_return = None;
+        if !self.breakpoint_needed(frame.return_addr) {
+            if let Some(inferior) = self.inferior.as_mut() {
+                if let Err(err) = inferior.remove_breakpoint(frame.return_addr) {
+                    print_remove_error(None, frame.return_addr, err);
+                }
+            }
+        }
+        returned
+    }
+
+    /// Whether `frame` of the current inferior has returned, rather than a deeper call of the
+    /// same function having got to the same return address.
+    fn has_returned(&self, frame: WatchFrame) -> bool {
+        self.inferior
+            .as_ref()
+            .and_then(|inferior| inferior.registers().ok())
+            .and_then(|regs| unwind::Frame::new(&regs).sp())
+            .is_some_and(|sp| sp >= frame.cfa)
+    }
+
+    /// Whether the current inferior needs a breakpoint at `addr`: an enabled breakpoint's, or
+    /// the internal one at the return address of a watched local's frame, or of a step's.
+    fn breakpoint_needed(&self, addr: usize) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|bp| bp.enabled && bp.addr == addr)
+            || self.watchpoints.iter().any(|watchpoint| {
+                watchpoint
+                    .frame
+                    .is_some_and(|frame| frame.return_addr == addr)
+            })
+            || self
+                .step_return
+                .is_some_and(|frame| frame.return_addr == addr)
+    }
+
+    /// Evaluates the expressions of tracepoint `number`, which the program has just got to, and
+    /// adds a record of their values.
+    fn collect_trace(&mut self, number: usize) {
+        let trace = self.breakpoints[number].trace.as_ref().unwrap();
+        let values = trace
+            .exprs
+            .iter()
+            .map(|(_, expr)| self.print_value(expr))
+            .collect();
+        let time = self
+            .inferior
+            .as_ref()
+            .map_or(Duration::ZERO, Inferior::run_time);
+        let trace = self.breakpoints[number].trace.as_mut().unwrap();
+        trace.hits += 1;
+        self.trace_records.push(TraceRecord {
+            tracepoint: number,
+            hit: trace.hits,
+            time,
+            values,
+        });
+    }
+
+    /// `tdump`: every trace record, with the values named by their expressions.
+    fn print_trace_records(&self) {
+        if self.trace_records.is_empty() {
+            outln!("No trace records.");
+            return;
+        }
+        for (index, record) in self.trace_records.iter().enumerate() {
+            outln!(
+                "Record {}: tracepoint {}, hit {}, at {:.6}s",
+                index,
+                record.tracepoint,
+                record.hit,
+                record.time.as_secs_f64()
+            );
+            for (name, value) in self.trace_names(record).iter().zip(&record.values) {
+                match value {
+                    Ok(value) => outln!("  {} = {}", name, value),
+                    Err(err) => {
+                        outln!("  {} = {}", name, paint(Style::Error, format!("<{}>", err)))
+                    }
+                }
+            }
+        }
+    }
+
+    /// The expressions of the tracepoint that collected `record`.
+    fn trace_names(&self, record: &TraceRecord) -> Vec<&str> {
+        match self
+            .breakpoints
+            .get(record.tracepoint)
+            .and_then(|bp| bp.trace.as_ref())
+        {
+            Some(trace) => trace.exprs.iter().map(|(text, _)| text.as_str()).collect(),
+            // deleted since
+            None => vec!["?"; record.values.len()],
+        }
+    }
+
+    /// `tsave PATH`: the trace records as CSV, with a column for every expression any of them
+    /// collected.
+    fn save_trace_records(&self, path: &str) -> std::io::Result<()> {
+        let mut columns: Vec<&str> = Vec::new();
+        for record in &self.trace_records {
+            for name in self.trace_names(record) {
+                if !columns.contains(&name) {
+                    columns.push(name);
+                }
+            }
+        }
+        let mut csv = String::from("record,tracepoint,hit,time");
+        for column in &columns {
+            csv.push(',');
+            csv.push_str(&csv_field(column));
+        }
+        csv.push('\n');
+        for (index, record) in self.trace_records.iter().enumerate() {
+            let mut cells = vec![String::new(); columns.len()];
+            for (name, value) in self.trace_names(record).iter().zip(&record.values) {
+                let column = columns.iter().position(|column| column == name).unwrap();
+                cells[column] = match value {
+                    Ok(value) => value.clone(),
+                    Err(err) => format!("<{}>", err),
+                };
+            }
+            csv.push_str(&format!(
+                "{},{},{},{:.6}",
+                index,
+                record.tracepoint,
+                record.hit,
+                record.time.as_secs_f64()
+            ));
+            for cell in cells {
+                csv.push(',');
+                csv.push_str(&csv_field(&cell));
+            }
+            csv.push('\n');
+        }
+        std::fs::write(path, csv)
+    }
+
+    /// Says which range watchpoint the program wrote to, and the bytes from where it did before
+    /// and after.
+    /// Says which watched variable changed, and from what to what.
+    fn print_watchpoint_hit(&self, index: usize, old: Option<Vec<u8>>) {
+        let watchpoint = &self.watchpoints[index];
+        let types = self.debug_data.types();
+        outln!(
+            "{}",
+            paint(
+                Style::Stop,
+                format!(
+                    "Hardware watchpoint {}: {} ({})",
+                    watchpoint.number,
+                    watchpoint.name,
+                    types.name_of(watchpoint.entity_type)
+                )
+            )
+        );
+        outln!();
+        outln!("Old value = {}", self.format_watched(watchpoint, &old));
+        outln!(
+            "New value = {}",
+            self.format_watched(watchpoint, &watchpoint.value)
+        );
+        outln!();
+    }
+
+    /// `bytes` of a watched variable, formatted as `print` would the variable if they were in
+    /// it. What its pointers point to is read from the program as it is now.
+    fn format_watched(&self, watchpoint: &Watchpoint, bytes: &Option<Vec<u8>>) -> String {
+        let (Some(bytes), Some(inferior)) = (bytes, &self.inferior) else {
+            return "<unreadable>".to_string();
+        };
+        let read_word = |addr: usize| {
+            let mut word = inferior.read_word(addr)?.to_le_bytes();
+            for (at, byte) in (addr..).zip(word.iter_mut()) {
+                if let Some(value) = at
+                    .checked_sub(watchpoint.addr)
+                    .and_then(|offset| bytes.get(offset))
+                {
+                    *byte = *value;
+                }
+            }
+            Ok(usize::from_le_bytes(word))
+        };
+        let frame = Frame {
+            debug_data: &self.debug_data,
+            scope: None,
+            registers: None,
+            frame_pointer: None,
+            read_word: &read_word,
+            print_symbol: self.settings.bool("print symbol"),
+            print_mangled: self.settings.bool("print mangled"),
+        };
+        let value = expr::Value {
+            ty: expr::Ty::Dwarf(watchpoint.entity_type),
+            place: expr::Place::Memory(watchpoint.addr),
+        };
+        value
+            .format(&frame)
+            .unwrap_or_else(|err| format!("<{}>", err))
+    }
+
+    fn print_watch_hit(
+        &self,
+        number: usize,
+        addr: usize,
+        old: Option<Vec<u8>>,
+        new: Option<Vec<u8>>,
+    ) {
+        let range = &self.watch_ranges[number];
+        outln!(
+            "{}",
+            paint(
+                Style::Stop,
+                format!(
+                    "Range watchpoint {}: write to {:#x} (offset {:#x})",
+                    number,
+                    addr,
+                    addr - range.addr
+                )
+            )
+        );
+        let bytes = |bytes: Option<Vec<u8>>| match bytes {
+            Some(bytes) => bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => "<unreadable>".to_string(),
+        };
+        outln!("Old bytes = {}", bytes(old));
+        outln!("New bytes = {}", bytes(new));
+        outln!();
+    }
+
+    /// Once the current inferior is gone, tells the user which other inferiors are still around.
+    fn report_inferior_exit(&self, pid: Pid) {
+        if self.other_inferiors.is_empty() {
+            return;
+        }
+        outln!("[Inferior {} (process {}) exited]", self.inferior_num, pid);
+        let remaining: Vec<String> = self.other_inferiors.keys().map(|n| n.to_string()).collect();
+        outln!(
+            "Remaining inferiors: {} (use \"inferior <n>\" to switch)",
+            remaining.join(", ")
+        );
+    }
+
+    fn print_statistics(&self, usage: &ResourceUsage) {
+        if !self.settings.bool("print-statistics") {
+            return;
+        }
+        outln!(
+            "Run time: {:.3}s wall, {:.3}s user, {:.3}s system",
+            usage.wall_time.as_secs_f64(),
+            usage.user_time.as_secs_f64(),
+            usage.system_time.as_secs_f64()
+        );
+        outln!(
+            "Max resident set size: {} kB, major page faults: {}",
+            usage.max_rss_kb,
+            usage.major_faults
+        );
+    }
+
+    /// The file mapped at `addr` in the live, local inferior, if it's one.
+    fn mapping_name(&self, addr: usize) -> Option<String> {
+        let inferior = self
+            .inferior
+            .as_ref()
+            .filter(|inferior| !inferior.is_remote())?;
+        inferior::read_memory_maps(inferior.pid())
+            .ok()?
+            .into_iter()
+            .find(|mapping| (mapping.start..mapping.end).contains(&addr))
+            .map(|mapping| mapping.name)
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Lists the shared libraries mapped into the program, as /proc/pid/maps shows them.
+    fn print_shared_libraries(&self) {
+        let Some(target) = &self.target else {
+            outln!("{}", NO_EXECUTABLE);
+            return;
+        };
+        let Some(interpreter) = self.debug_data.interpreter() else {
+            outln!("No shared libraries: {} is statically linked.", target);
+            return;
+        };
+        let mappings = match &self.inferior {
+            Some(inferior) if !inferior.is_remote() => {
+                inferior::read_memory_maps(inferior.pid()).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+        // each library is mapped a segment at a time, so its range covers all of them
+        let mut libraries: Vec<(usize, usize, &str)> = Vec::new();
+        for mapping in &mappings {
+            let name = mapping.name.as_str();
+            let file_name = name.rsplit('/').next().unwrap_or(name);
+            if !name.starts_with('/') || !(file_name.ends_with(".so") || file_name.contains(".so."))
+            {
+                continue;
+            }
+            match libraries
+                .iter_mut()
+                .find(|(_, _, library)| *library == name)
+            {
+                Some((start, end, _)) => {
+                    *start = (*start).min(mapping.start);
+                    *end = (*end).max(mapping.end);
+                }
+                None => libraries.push((mapping.start, mapping.end, name)),
+            }
+        }
+        if libraries.is_empty() {
+            outln!(
+                "No shared libraries loaded at this time; {} is run by {}.",
+                target,
+                interpreter
+            );
+            return;
+        }
+        outln!("{:<20}{:<20}Shared Object Library", "From", "To");
+        for (start, end, name) in libraries {
+            outln!(
+                "{:<20}{:<20}{}",
+                format!("{:#x}", start),
+                format!("{:#x}", end),
+                name
+            );
+        }
+    }
+
+    /// `info breakpoints`. A breakpoint the current inferior should have but doesn't is shown as
+    /// not installed, with why if installing it failed.
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty()
+            && self.watch_ranges.is_empty()
+            && self.watchpoints.is_empty()
+        {
+            outln!("No breakpoints.");
+            return;
+        }
+        // the timings, while they're being taken or there are some to show
+        let timing = self.settings.bool("breakpoint-timing")
+            || self.breakpoints.iter().any(|bp| bp.timing.hits > 0);
+        // and the instruction counts, likewise
+        let counting = self.settings.bool("count-instructions")
+            || self.breakpoints.iter().any(|bp| bp.instructions.is_some());
+        if !self.breakpoints.is_empty() {
+            let mut header = format!("{:<5}{:<5}{:<20}", "Num", "Enb", "Address");
+            if timing {
+                header += &format!("{:<20}", "Time (total/mean)");
+            }
+            if counting {
+                header += &format!("{:<16}", "Instructions");
+            }
+            outln!("{}What", header);
+        }
+        for (number, bp) in self.breakpoints.iter().enumerate() {
+            let time = match bp.timing.hits {
+                _ if !timing => String::new(),
+                0 => format!("{:<20}", "-"),
+                hits => format!(
+                    "{:<20}",
+                    format!(
+                        "{}/{}",
+                        format_duration(bp.timing.total),
+                        format_duration(bp.timing.total / hits)
+                    )
+                ),
+            };
+            let instructions = match bp.instructions {
+                _ if !counting => String::new(),
+                None => format!("{:<16}", "-"),
+                Some(instructions) => format!("{:<16}", format_count(instructions)),
+            };
+            outln!(
+                "{:<5}{:<5}{:<20}{}{}{}",
+                number,
+                if bp.enabled { "y" } else { "n" },
+                format!("{:#x}", bp.addr),
+                time,
+                instructions,
+                self.describe_location(bp.addr)
+            );
+            let missing = match &self.inferior {
+                Some(inferior) => bp.enabled && !inferior.has_breakpoint(bp.addr),
+                None => false,
+            };
+            if missing {
+                let why = match bp.install_error {
+                    Some(err) => format!(": {}", breakpoint_error(err)),
+                    None => String::new(),
+                };
+                outln!("{}", paint(Style::Error, format!("\tnot installed{}", why)));
+            }
+            if let Some(trace) = &bp.trace {
+                let names: Vec<&str> = trace.exprs.iter().map(|(text, _)| text.as_str()).collect();
+                outln!(
+                    "\ttracepoint, collects {}; hit {} time{}",
+                    names.join(", "),
+                    trace.hits,
+                    if trace.hits == 1 { "" } else { "s" }
+                );
+            }
+        }
+        if !self.watch_ranges.is_empty() {
+            outln!("{:<5}Range watchpoint", "Num");
+        }
+        for (number, range) in self.watch_ranges.iter().enumerate() {
+            outln!(
+                "{:<5}{:#x}-{:#x} ({} bytes)",
+                number,
+                range.addr,
+                range.addr + range.len,
+                range.len
+            );
+            if let (Some(_), Some(err)) = (&self.inferior, range.protect_error) {
+                outln!(
+                    "{}",
+                    paint(Style::Error, format!("\tnot watched: {}", watch_error(err)))
+                );
+            }
+        }
+        if !self.watchpoints.is_empty() {
+            outln!("{:<5}Hardware watchpoint", "Num");
+        }
+        for watchpoint in &self.watchpoints {
+            outln!(
+                "{:<5}{} ({}) at {:#x}",
+                watchpoint.number,
+                watchpoint.name,
+                self.debug_data.types().name_of(watchpoint.entity_type),
+                watchpoint.addr
+            );
+            if let Some(frame) = watchpoint.frame {
+                outln!(
+                    "\tin the frame returning to {}",
+                    self.describe_location(frame.return_addr)
+                );
+            }
+            if self.inferior.is_some() && watchpoint.slot.is_none() {
+                outln!("{}", paint(Style::Error, "\tnot set"));
+            }
+        }
+    }
+
+    /// `skip`: adds to the skip list, or enables, disables or deletes entries of it.
+    fn skip(&mut self, command: SkipCommand) {
+        let current = self.current_pc();
+        let added = match &command {
+            SkipCommand::Function(name) => {
+                let name = name.clone().or_else(|| {
+                    let (name, _) = self
+                        .debug_data
+                        .get_function_and_offset_from_addr(current?)?;
+                    Some(format_symbol_name(&name, false))
+                });
+                match name {
+                    Some(name) => self.skips.add_function(&name),
+                    None => {
+                        outln!("No default function now.");
+                        return;
+                    }
+                }
+            }
+            SkipCommand::File(glob) => {
+                let glob = glob
+                    .clone()
+                    .or_else(|| Some(self.debug_data.get_nearest_line(current?)?.file));
+                match glob {
+                    Some(glob) => self.skips.add_file(&glob),
+                    None => {
+                        outln!("No default file now.");
+                        return;
+                    }
+                }
+            }
+            SkipCommand::Enable(numbers) | SkipCommand::Disable(numbers) => {
+                let enable = matches!(command, SkipCommand::Enable(_));
+                match self.skips.select(numbers) {
+                    Ok(skips) => skips.into_iter().for_each(|skip| skip.enabled = enable),
+                    Err(number) => outln!("No skiplist entries found with number {}.", number),
+                }
+                return;
+            }
+            SkipCommand::Delete(numbers) => {
+                if let Err(number) = self.skips.delete(numbers) {
+                    outln!("No skiplist entries found with number {}.", number);
+                }
+                return;
+            }
+        };
+        match added {
+            Ok(skip) => outln!(
+                "Skip {}: {} {} will be skipped when stepping.",
+                skip.number,
+                skip.kind(),
+                skip.text
+            ),
+            Err(err) => outln!("{}", err),
+        }
+    }
+
+    /// `info skip`
+    fn print_skips(&self) {
+        if self.skips.entries().is_empty() {
+            outln!("Not skipping any files or functions.");
+            return;
+        }
+        outln!("{:<5}{:<5}{:<10}Pattern", "Num", "Enb", "Kind");
+        for skip in self.skips.entries() {
+            outln!(
+                "{:<5}{:<5}{:<10}{}",
+                skip.number,
+                if skip.enabled { "y" } else { "n" },
+                skip.kind(),
+                skip.text
+            );
+        }
+    }
+
+    /// `info signals`: deet's handling of every signal, and for a live, local program, what it
+    /// does with them itself. The real-time signals are summed up on one line unless verbose,
+    /// with "Some" where only some of them are blocked, say.
+    fn print_signals(&self) {
+        let masks = match &self.inferior {
+            Some(inferior) if !inferior.is_remote() => {
+                match inferior::read_signal_masks(inferior.pid()) {
+                    Ok(masks) => Some(masks),
+                    Err(err) => {
+                        outln!("Could not read the program's signal masks: {}", err);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let yes_no = |yes: bool| if yes { "Yes" } else { "No" };
+        let row = |name: &str, numbers: std::ops::RangeInclusive<i32>, description: &str| {
+            let (stop, print, pass) = signals::handling(*numbers.start());
+            let program = match &masks {
+                Some(masks) => {
+                    [masks.blocked, masks.ignored, masks.caught, masks.pending].map(|mask| {
+                        let set = numbers
+                            .clone()
+                            .filter(|number| mask & 1 << (number - 1) != 0)
+                            .count();
+                        match set {
+                            0 => "No",
+                            set if set == numbers.clone().count() => "Yes",
+                            _ => "Some",
+                        }
+                    })
+                }
+                None => ["-"; 4],
+            };
+            outln!(
+                "{:<14}{:<6}{:<7}{:<6}{:<9}{:<9}{:<8}{:<9}{}",
+                name,
+                yes_no(stop),
+                yes_no(print),
+                yes_no(pass),
+                program[0],
+                program[1],
+                program[2],
+                program[3],
+                description
+            );
+        };
+        outln!(
+            "{:<14}{:<6}{:<7}{:<6}{:<9}{:<9}{:<8}{:<9}Description",
+            "Signal",
+            "Stop",
+            "Print",
+            "Pass",
+            "Blocked",
+            "Ignored",
+            "Caught",
+            "Pending"
+        );
+        for number in 1..signals::FIRST_REALTIME {
+            row(
+                &signals::name_of(number),
+                number..=number,
+                &signals::meaning_of(number),
+            );
+        }
+        if self.settings.bool("verbose") {
+            for number in signals::FIRST_REALTIME..=signals::LAST_SIGNAL {
+                row(
+                    &signals::name_of(number),
+                    number..=number,
+                    &signals::meaning_of(number),
+                );
+            }
+        } else {
+            let name = format!(
+                "{}-{}",
+                signals::name_of(signals::FIRST_REALTIME),
+                signals::name_of(signals::LAST_SIGNAL)
+            );
+            row(
+                &name,
+                signals::FIRST_REALTIME..=signals::LAST_SIGNAL,
+                "Real-time events (set verbose on to list each)",
+            );
+        }
+        match &self.inferior {
+            None => {
+                outln!("\nThe program is not being run, so what it does with signals isn't known.")
+            }
+            Some(inferior) if inferior.is_remote() => {
+                outln!("\nThe program is remote, so what it does with signals isn't known.")
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Prints the registers of the thread that stopped, or of the core file: the general ones,
+    /// then with `all` the floating point and vector ones, or just those in `names`.
+    fn print_registers(&self, names: &[String], all: bool) {
+        let regs = match (&self.inferior, &self.core) {
+            (Some(inferior), _) => match inferior.registers() {
+                Ok(regs) => regs,
+                Err(err) => {
+                    outln!("Could not read the registers: {}", err);
+                    return;
+                }
+            },
+            (None, Some(core)) => *core.registers(),
+            (None, None) => {
+                outln!("The program has no registers now.");
+                return;
+            }
+        };
+        let print_mangled = self.settings.bool("print mangled");
+        let general = |name: &str| {
+            registers::general(&regs, name, |addr| {
+                self.debug_data.symbolize(addr, print_mangled)
+            })
+        };
+        let print = |register: &registers::Register| {
+            outln!(
+                "{:<15}{:<18} {}",
+                register.name,
+                register.hex,
+                register.natural
+            )
+        };
+        // the other register sets are read only when asked for
+        let names: Vec<&str> = names
+            .iter()
+            .map(|name| name.strip_prefix('$').unwrap_or(name))
+            .collect();
+        let sets = match all || names.iter().any(|name| general(name).is_none()) {
+            true => self.float_registers(),
+            false => Ok(Vec::new()),
+        };
+        let unavailable = |set: &RegisterSet| {
+            if let Err(err) = &set.registers {
+                outln!("(The {} registers are unavailable: {}.)", set.name, err);
+            }
+        };
+        if names.is_empty() {
+            arch::REMOTE_REGISTERS
+                .iter()
+                .filter_map(|(name, _)| general(name))
+                .for_each(|register| print(&register));
+            if !all {
+                return;
+            }
+            match &sets {
+                Ok(sets) => {
+                    for set in sets {
+                        set.registers.iter().flatten().for_each(print);
+                        unavailable(set);
+                    }
+                }
+                Err(err) => outln!("({})", err),
+            }
+            return;
+        }
+        for name in names {
+            let register = general(name);
+            let found = register.as_ref().or_else(|| {
+                let sets = sets.as_deref().unwrap_or_default();
+                sets.iter()
+                    .flat_map(|set| set.registers.iter().flatten())
+                    .find(|register| register.name == name)
+            });
+            match found {
+                Some(register) => print(register),
+                None => {
+                    outln!("Invalid register `{}'", name);
+                    // it may be in a set that couldn't be read
+                    match &sets {
+                        Ok(sets) => sets.iter().for_each(unavailable),
+                        Err(err) => outln!("({})", err),
+                    }
+                }
+            }
+        }
+    }
+
+    /// The floating point and vector register sets of the thread that stopped or the core file,
+    /// or why there are none.
+    fn float_registers(&self) -> Result<Vec<RegisterSet>, String> {
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) if inferior.is_remote() => {
+                Err("Only the general registers of a remote program are read.".to_string())
+            }
+            (Some(inferior), _) => Ok(registers::decode(|note, size| {
+                arch::read_regset(inferior.tid(), note, size).map_err(|err| err.desc().to_string())
+            })),
+            (None, Some(core)) => Ok(registers::decode(|note, _| {
+                core.regset(note)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| "the core file doesn't have them".to_string())
+            })),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    /// `info stack-raw`: `words` words of the stack from the stack pointer up, each with what it
+    /// seems to be. A word pointing into an executable mapping is likely a return address, and
+    /// one pointing into the stack a saved frame pointer.
+    fn print_stack_raw(&self, words: usize) {
+        let Some(inferior) = &self.inferior else {
+            outln!("The program is not being run.");
+            return;
+        };
+        let frame = match inferior.registers() {
+            Ok(regs) => unwind::Frame::new(&regs),
+            Err(err) => {
+                outln!("Could not read the registers: {}", err);
+                return;
+            }
+        };
+        let (Some(sp), fp) = (frame.sp(), frame.fp()) else {
+            outln!("The stack pointer isn't known.");
+            return;
+        };
+        let sp_name = arch::DWARF_REGISTERS[arch::DWARF_SP as usize];
+        let fp_name = arch::DWARF_REGISTERS[arch::DWARF_FP as usize];
+        let word_size = size_of::<usize>();
+        let bytes = match inferior.read_memory(sp, words * word_size) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                outln!("Cannot access memory at address {:#x}: {}", sp, err);
+                return;
+            }
+        };
+        let mappings = match inferior.is_remote() {
+            true => Vec::new(),
+            false => inferior::read_memory_maps(inferior.pid()).unwrap_or_default(),
+        };
+        let mapping_of = |addr: usize| {
+            mappings
+                .iter()
+                .find(|mapping| (mapping.start..mapping.end).contains(&addr))
+        };
+        let stack = mapping_of(sp);
+        for (i, word) in bytes.chunks_exact(word_size).enumerate() {
+            let addr = sp + i * word_size;
+            let value = usize::from_le_bytes(word.try_into().unwrap());
+            let mapping = mapping_of(value);
+            let note = if mapping.is_some_and(|mapping| mapping.perms.contains('x')) {
+                let symbol = self
+                    .debug_data
+                    .symbolize(value, self.settings.bool("print mangled"));
+                match (symbol, mapping) {
+                    (Some(symbol), _) => format!("<{}>", symbol),
+                    (None, Some(mapping)) if !mapping.name.is_empty() => {
+                        format!("in {}", mapping.name)
+                    }
+                    _ => "in code".to_string(),
+                }
+            } else if stack.is_some() && mapping.map(|m| m.start) == stack.map(|m| m.start) {
+                format!(
+                    "stack ({}{:+#x}), likely a saved {}",
+                    sp_name,
+                    value as isize - sp as isize,
+                    fp_name
+                )
+            } else {
+                let ascii: String = word
+                    .iter()
+                    .map(|&byte| match byte {
+                        0x20..=0x7e => byte as char,
+                        _ => '.',
+                    })
+                    .collect();
+                format!("\"{}\"", ascii)
+            };
+            let here = match fp == Some(addr) {
+                true => format!("  <- {}", fp_name),
+                false => String::new(),
+            };
+            outln!(
+                "{:#x} {}+{:#05x}: {:#018x}  {}{}",
+                addr,
+                sp_name,
+                i * word_size,
+                value,
+                note,
+                here
+            );
+        }
+        if bytes.len() < words * word_size {
+            outln!(
+                "Cannot access memory at address {:#x}",
+                sp + bytes.len() / word_size * word_size
+            );
+        }
+    }
+
+    /// `info source`: the current source file, GDB style, with what the debug info says of its
+    /// compilation unit.
+    fn print_source_info(&self) {
+        let Some(pc) = self.current_pc() else {
+            outln!("No current source file.");
+            return;
+        };
+        let (Some(line), Some(unit)) = (
+            self.debug_data.get_nearest_line(pc),
+            self.debug_data.unit_from_addr(pc),
+        ) else {
+            outln!("No current source file.");
+            return;
+        };
+        outln!("Current source file is {}", line.file);
+        if let Some(comp_dir) = &unit.comp_dir {
+            outln!("Compilation directory is {}", comp_dir);
+        }
+        if unit.name != line.file {
+            outln!("Compilation unit is {}", unit.name);
+        }
+        match self.source_files.find(&line.file) {
+            Some(path) => outln!("Located in {}", path.display()),
+            None => outln!("Not found on disk."),
+        }
+        if let Some(producer) = &unit.producer {
+            outln!("Producer is {}.", producer);
+        }
+        outln!(
+            "Compiled with DWARF {} debugging format.",
+            unit.dwarf_version
+        );
+        if unit.has_macros {
+            outln!("Includes preprocessor macro info.");
+        } else {
+            outln!("Does not include preprocessor macro info.");
+        }
+    }
+
+    /// `info sources [REGEX]`: the source files of the debug info, or those matching REGEX.
+    fn print_source_files(&self, regex: Option<&str>) {
+        let pattern = match regex.map(|regex| (regex, Regex::new(regex))) {
+            Some((regex, Err(message))) => {
+                outln!("Invalid regular expression \"{}\": {}", regex, message);
+                return;
+            }
+            Some((_, Ok(pattern))) => Some(pattern),
+            None => None,
+        };
+        let paths: Vec<&str> = self
+            .debug_data
+            .all_source_files()
+            .into_iter()
+            .filter(|path| {
+                pattern
+                    .as_ref()
+                    .is_none_or(|pattern| pattern.is_match(path))
+            })
+            .collect();
+        if paths.is_empty() {
+            outln!("No source files.");
+        }
+        for path in paths {
+            outln!("{}", path);
+        }
+    }
+
+    /// Lists the current and background inferiors, or the core file, with what each is doing.
+    /// A current inferior that has exited stays listed until there's another.
+    fn print_inferiors(&self) {
+        let exited = self
+            .exited_inferior
+            .filter(|_| self.inferior.is_none() && self.core.is_none());
+        if self.inferior.is_none()
+            && self.other_inferiors.is_empty()
+            && self.core.is_none()
+            && exited.is_none()
+        {
+            outln!("No inferiors.");
+            return;
+        }
+        outln!("  {:<5}{:<10}{:<10}Location", "Num", "PID", "State");
+        if let Some(core) = &self.core {
+            outln!(
+                "* {:<5}{:<10}{:<10}{}",
+                self.inferior_num,
+                core.pid(),
+                "core",
+                self.describe_location(arch::pc(core.registers()))
+            );
+        }
+        if let Some(pid) = exited {
+            outln!("* {:<5}{:<10}exited", self.inferior_num, pid);
+        }
+        let current = self
+            .inferior
+            .iter()
+            .map(|inferior| (self.inferior_num, inferior));
+        let mut all: Vec<(usize, &Inferior)> = current
+            .chain(
+                self.other_inferiors
+                    .iter()
+                    .map(|(num, inferior)| (*num, inferior)),
+            )
+            .collect();
+        all.sort_by_key(|(num, _)| *num);
+        for (num, inferior) in all {
+            let marker = if self.inferior.is_some() && num == self.inferior_num {
+                "*"
+            } else {
+                " "
+            };
+            let state = process_state(inferior);
+            let location = match inferior.instruction_pointer() {
+                Ok(rip) => self.describe_location(rip),
+                Err(_) if state != "stopped" => String::new(),
+                Err(_) => "<unknown>".to_string(),
+            };
+            outln!(
+                "{} {:<5}{:<10}{:<10}{}",
+                marker,
+                num,
+                inferior.pid(),
+                state,
+                location
+            );
+        }
+    }
+
+    /// Debugs the program stopped under the GDB stub at `address` in place of the current
+    /// inferior, after asking to kill that.
+    fn connect_remote(&mut self, address: &str) {
+        if self.core.is_some() {
+            outln!("Can't debug a remote target while examining a core file; use \"core-file\" without arguments to close it first.");
+            return;
+        }
+        if self.inferior.is_some() && !self.confirm("The program is running. Kill it?") {
+            return;
+        }
+        if let Some(mut inferior) = self.inferior.take() {
+            if let Err(err) = inferior.kill() {
+                outln!("Error killing inferior {}: {}", inferior.pid(), err);
+            }
+            self.release_vfork_parent();
+        }
+        let inferior = match Inferior::connect(address) {
+            Ok(inferior) => inferior,
+            Err(err) => {
+                outln!("{}", paint(Style::Error, err));
+                return;
+            }
+        };
+        outln!("Remote debugging using {}", address);
+        let pc = inferior.instruction_pointer();
+        self.inferior = Some(inferior);
+        self.install_breakpoints();
+        match pc {
+            Ok(pc) => self.print_stop_location(pc, StopReason::Connected),
+            Err(err) => outln!("Can't read the remote registers: {}", err),
+        }
+    }
+
+    /// Makes inferior `num` the one commands act on.
+    fn switch_inferior(&mut self, num: usize) {
+        if let Some(current) = self.inferior.as_ref().filter(|_| num == self.inferior_num) {
+            outln!("[Current inferior is {} [process {}]]", num, current.pid());
+            return;
+        }
+        let inferior = match self.other_inferiors.remove(&num) {
+            Some(inferior) => inferior,
+            None => {
+                outln!("Inferior ID {} not known.", num);
+                return;
+            }
+        };
+        if let Some(current) = self.inferior.take() {
+            self.other_inferiors.insert(self.inferior_num, current);
+        }
+        outln!(
+            "[Switching to inferior {} [process {}]]",
+            num,
+            inferior.pid()
+        );
+        let rip = inferior.instruction_pointer();
+        self.inferior = Some(inferior);
+        self.inferior_num = num;
+        if let Ok(rip) = rip {
+            self.print_stop_location(rip, StopReason::Switched(num));
+        }
+    }
+
+    /// Short description of an address for `info inferiors`: function and file:line if known.
+    fn describe_location(&self, rip: usize) -> String {
+        let function = self.debug_data.get_function_and_offset_from_addr(rip);
+        let line = self.debug_data.get_line_from_addr(rip);
+        match (function, line) {
+            (Some((name, _)), Some(line)) => {
+                format!(
+                    "{} ({})",
+                    format_symbol_name(&name, self.settings.bool("print mangled")),
+                    line
+                )
+            }
+            (Some(_), None) => self
+                .debug_data
+                .symbolize(rip, self.settings.bool("print mangled"))
+                .unwrap(),
+            (None, _) => format!("{:#x}", rip),
+        }
+    }
+
+    /// A code address as "0x401196 <merge_sort+0x26>", or bare if it's in no function or
+    /// `set print symbol` is off.
+    fn symbolic_address(&self, addr: usize) -> String {
+        let symbol = self
+            .settings
+            .bool("print symbol")
+            .then(|| {
+                self.debug_data
+                    .symbolize(addr, self.settings.bool("print mangled"))
+            })
+            .flatten();
+        match symbol {
+            Some(symbol) => format!("{:#x} <{}>", addr, symbol),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /// Explains a SIGSEGV or SIGBUS: the faulting address and why it faulted, where the faulting
+    /// instruction is, and what memory is at or around the address. For a SIGFPE or SIGILL, what
+    /// the instruction did wrong and where it is.
+    fn print_fault(&mut self, signal: Signal, rip: usize) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let (name, description) = (signals::name(signal), signals::meaning(signal));
+        let siginfo = match inferior.siginfo() {
+            Ok(siginfo) => siginfo,
+            Err(_) => {
+                outln!(
+                    "{}",
+                    paint(
+                        Style::Stop,
+                        format!("Program received signal {}, {}.", name, description)
+                    )
+                );
+                self.print_stop_location(rip, StopReason::Fault(signal, None));
+                return;
+            }
+        };
+        if matches!(signal, Signal::SIGFPE | Signal::SIGILL) {
+            // their si_addr is the instruction's, which the stop location shows anyway
+            let (code_name, reason) = match (signal, siginfo.si_code) {
+                (Signal::SIGFPE, 1) => ("FPE_INTDIV", "integer divide by zero"),
+                (Signal::SIGFPE, 2) => ("FPE_INTOVF", "integer overflow"),
+                (Signal::SIGFPE, 3) => ("FPE_FLTDIV", "floating point divide by zero"),
+                (Signal::SIGFPE, 4) => ("FPE_FLTOVF", "floating point overflow"),
+                (Signal::SIGFPE, 5) => ("FPE_FLTUND", "floating point underflow"),
+                (Signal::SIGFPE, 6) => ("FPE_FLTRES", "floating point inexact result"),
+                (Signal::SIGFPE, 7) => ("FPE_FLTINV", "invalid floating point operation"),
+                (Signal::SIGFPE, 8) => ("FPE_FLTSUB", "subscript out of range"),
+                (Signal::SIGILL, 1) => ("ILL_ILLOPC", "illegal opcode"),
+                (Signal::SIGILL, 2) => ("ILL_ILLOPN", "illegal operand"),
+                (Signal::SIGILL, 3) => ("ILL_ILLADR", "illegal addressing mode"),
+                (Signal::SIGILL, 4) => ("ILL_ILLTRP", "illegal trap"),
+                (Signal::SIGILL, 5) => ("ILL_PRVOPC", "privileged opcode"),
+                (Signal::SIGILL, 6) => ("ILL_PRVREG", "privileged register"),
+                (Signal::SIGILL, 7) => ("ILL_COPROC", "coprocessor error"),
+                (Signal::SIGILL, 8) => ("ILL_BADSTK", "internal stack error"),
+                // sent by a process, e.g. raise(SIGILL), rather than raised by an instruction
+                _ => ("", ""),
+            };
+            let message = match code_name {
+                "" => format!("Program received signal {}, {}.", name, description),
+                _ => format!(
+                    "Program received signal {}, {}: {} ({})",
+                    name, description, reason, code_name
+                ),
+            };
+            outln!("{}", paint(Style::Stop, message));
+            self.print_stop_location(rip, StopReason::Fault(signal, None));
+            return;
+        }
+        let addr = unsafe { siginfo.si_addr() } as usize;
+        let (code_name, reason) = match (signal, siginfo.si_code) {
+            (Signal::SIGSEGV, 1) => ("SEGV_MAPERR", "not mapped"),
+            (Signal::SIGSEGV, 2) => ("SEGV_ACCERR", "has invalid permissions for the access"),
+            (Signal::SIGSEGV, 3) => ("SEGV_BNDERR", "failed address bound checks"),
+            (Signal::SIGSEGV, 4) => ("SEGV_PKUERR", "denied by protection keys"),
+            (Signal::SIGBUS, 1) => ("BUS_ADRALN", "is misaligned"),
+            (Signal::SIGBUS, 2) => ("BUS_ADRERR", "does not exist"),
+            (Signal::SIGBUS, 3) => ("BUS_OBJERR", "hit an object-specific hardware error"),
+            // the kernel reports general protection faults, e.g. non-canonical addresses,
+            // without an address
+            (_, 0x80) => ("SI_KERNEL", "is invalid"),
+            _ => ("", "faulted"),
+        };
+        if siginfo.si_code == 0x80 {
+            outln!(
+                "{}",
+                paint(
+                    Style::Stop,
+                    format!(
+                        "Program received signal {}, {}: general protection fault ({})",
+                        name, description, code_name
+                    )
+                )
+            );
+        } else if code_name.is_empty() {
+            outln!(
+                "{}",
+                paint(
+                    Style::Stop,
+                    format!(
+                        "Program received signal {}, {}: address {} (si_code {})",
+                        name,
+                        description,
+                        self.symbolic_address(addr),
+                        siginfo.si_code
+                    )
+                )
+            );
+        } else {
+            outln!(
+                "{}",
+                paint(
+                    Style::Stop,
+                    format!(
+                        "Program received signal {}, {}: address {} {} ({})",
+                        name,
+                        description,
+                        self.symbolic_address(addr),
+                        reason,
+                        code_name
+                    )
+                )
+            );
+        }
+        let pid = inferior.pid();
+        let fault_addr = (siginfo.si_code != 0x80).then_some(addr);
+        self.print_stop_location(rip, StopReason::Fault(signal, fault_addr));
+        if siginfo.si_code != 0x80 {
+            Self::print_nearby_mapping(pid, addr);
+        }
+    }
+
+    /// Says which mapping `addr` falls in, or which one it is closest to.
+    fn print_nearby_mapping(pid: Pid, addr: usize) {
+        let mappings = match inferior::read_memory_maps(pid) {
+            Ok(mappings) => mappings,
+            Err(_) => return,
+        };
+        let describe = |mapping: &inferior::MemoryMapping| {
+            let name = if mapping.name.is_empty() {
+                "anonymous memory"
+            } else {
+                mapping.name.as_str()
+            };
+            format!(
+                "{:#x}-{:#x} {} {}",
+                mapping.start, mapping.end, mapping.perms, name
+            )
+        };
+        if let Some(mapping) = mappings
+            .iter()
+            .find(|mapping| (mapping.start..mapping.end).contains(&addr))
+        {
+            outln!("Address {:#x} is in mapping {}", addr, describe(mapping));
+            return;
+        }
+        let distance = |mapping: &&inferior::MemoryMapping| {
+            if addr < mapping.start {
+                mapping.start - addr
+            } else {
+                addr - mapping.end + 1
+            }
+        };
+        if let Some(nearest) = mappings.iter().min_by_key(distance) {
+            let direction = if addr < nearest.start {
+                "below"
+            } else {
+                "above"
+            };
+            outln!(
+                "Address {:#x} is {:#x} bytes {} the nearest mapping {}",
+                addr,
+                distance(&nearest),
+                direction,
+                describe(nearest)
+            );
+        }
+        if addr < 0x10000 {
+            outln!("(an address this small usually means a NULL pointer was dereferenced)");
+        }
+    }
+
+    /// Handles a fork event according to follow-fork-mode. With detach-on-fork on, the process we
+    /// stop following has its breakpoints removed (it inherited the 0xcc bytes) and is detached;
+    /// with it off, it is kept stopped as another inferior.
+    ///
+    /// The child of a vfork shares its parent's memory until it execs or exits, so removing the
+    /// breakpoints from one removes them from both. A detached child takes them out of the
+    /// parent for that long, and the parent puts them back after. A parent the child is
+    /// followed from keeps them in, still traced but not resumed (it's suspended anyway) until
+    /// the child execs or exits.
+    fn follow_fork(&mut self, child_pid: Pid, vfork: bool) -> Result<(), nix::Error> {
+        let parent = self.inferior.take().unwrap();
+        let parent_pid = parent.pid();
+        let child = Inferior::from_forked(child_pid, &parent)?;
+        if !self.settings.bool("detach-on-fork") {
+            let child_num = self.next_inferior_num;
+            self.next_inferior_num += 1;
+            outln!("[New inferior {} (process {})]", child_num, child_pid);
+            match self.follow_fork_mode() {
+                FollowForkMode::Parent => {
+                    self.other_inferiors.insert(child_num, child);
+                    self.inferior = Some(parent);
+                }
+                FollowForkMode::Child => {
+                    outln!(
+                        "[Switching to inferior {} [process {}]]",
+                        child_num,
+                        child_pid
+                    );
+                    self.other_inferiors.insert(self.inferior_num, parent);
+                    self.inferior = Some(child);
+                    self.inferior_num = child_num;
+                }
+            }
+            return Ok(());
+        }
+        let (mut followed, mut detached) = match self.follow_fork_mode() {
+            FollowForkMode::Parent => {
+                outln!(
+                    "[Detaching after fork from child process {} (parent {})]",
+                    child_pid,
+                    parent_pid
+                );
+                (parent, child)
+            }
+            FollowForkMode::Child => {
+                outln!(
+                    "[Attaching after process {} fork to child process {}]",
+                    parent_pid,
+                    child_pid
+                );
+                if vfork {
+                    self.vfork_parent = Some(parent);
+                    self.inferior = Some(child);
+                    return Ok(());
+                }
+                outln!("[Detaching after fork from parent process {}]", parent_pid);
+                (child, parent)
+            }
+        };
+        if vfork {
+            followed.restore_breakpoints_after_vfork();
+        }
+        for (addr, err) in detached.remove_breakpoints() {
+            let number = self.breakpoints.iter().position(|bp| bp.addr == addr);
+            print_remove_error(number, addr, err);
+        }
+        detached.detach()?;
+        self.inferior = Some(followed);
+        Ok(())
+    }
+
+    /// Detaches the parent of the vfork child being followed, if there's one, now that the child
+    /// has execed or exited and the parent's memory is its own again. The breakpoints are taken
+    /// out of it first.
+    fn release_vfork_parent(&mut self) {
+        let Some(mut parent) = self.vfork_parent.take() else {
+            return;
+        };
+        let pid = parent.pid();
+        outln!(
+            "[Detaching vfork parent process {} after child exit or exec]",
+            pid
+        );
+        for (addr, err) in parent.remove_breakpoints() {
+            let number = self.breakpoints.iter().position(|bp| bp.addr == addr);
+            print_remove_error(number, addr, err);
+        }
+        if let Err(err) = parent.detach() {
+            outln!(
+                "{}",
+                paint(
+                    Style::Error,
+                    format!("Error detaching from process {}: {}", pid, err)
+                )
+            );
+        }
+    }
+
+    /// Handles the current inferior calling execve: loads the debug info of the new program and
+    /// resolves the breakpoints against it. Breakpoints on raw addresses can't be carried over, so
+    /// they are disabled.
+    fn follow_exec(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let path = match inferior.executable_path() {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(err) => {
+                outln!(
+                    "Could not find the program process {} execed: {}",
+                    inferior.pid(),
+                    err
+                );
+                return;
+            }
+        };
+        outln!(
+            "process {} is executing new program: {}",
+            inferior.pid(),
+            path
+        );
+        match self.debug_data_cache.load(&path) {
+            Ok(debug_data) => self.set_debug_data(debug_data),
+            Err(_) => {
+                // keep the old data rather than not having any, but don't trust its addresses
+                outln!("warning: could not load debugging symbols from {}", path);
+            }
+        }
+        self.source_files = SourceFiles::new(&path);
+        self.target = Some(path);
+        // exec cleared the debug registers, and the variables were the old program's
+        for watchpoint in self.watchpoints.drain(..) {
+            outln!(
+                "Watchpoint {} deleted: {} was in the program before.",
+                watchpoint.number,
+                watchpoint.name
+            );
+        }
+        self.resolve_breakpoints();
+        self.install_breakpoints();
+    }
+
+    /// `file PATH`: debugs the program at PATH from now on, in place of the current one and its
+    /// processes, which are killed. `file` alone forgets the program.
+    fn change_file(&mut self, path: Option<String>) {
+        let debug_data = match path
+            .as_deref()
+            .map(|path| load_debug_data(&mut self.debug_data_cache, path))
+        {
+            Some(Ok(debug_data)) => debug_data,
+            Some(Err(message)) => {
+                outln!("{}", message);
+                return;
+            }
+            None => Rc::new(DwarfData::empty()),
+        };
+        if (self.inferior.is_some() || !self.other_inferiors.is_empty())
+            && !self.confirm(
+                "A program is being debugged already.\nAre you sure you want to change the file?",
+            )
+        {
+            return;
+        }
+        let current = self.inferior.take();
+        let others = std::mem::take(&mut self.other_inferiors).into_values();
+        for mut inferior in current.into_iter().chain(others) {
+            if let Err(err) = inferior.kill() {
+                outln!("Error killing inferior {}: {}", inferior.pid(), err);
+            }
+        }
+        self.release_vfork_parent();
+        self.stop_signal = None;
+        if self.core.take().is_some() {
+            outln!("No core file now.");
+        }
+        match &path {
+            Some(path) => outln!("Reading symbols from {}...", path),
+            None => outln!("No executable file now."),
+        }
+        self.set_debug_data(debug_data);
+        self.source_files = SourceFiles::new(path.as_deref().unwrap_or(""));
+        self.target = path;
+        self.resolve_breakpoints();
+    }
+
+    /// Loads the debug info of the target again now that it's been rebuilt, and looks the
+    /// breakpoints up in it. If it can't be loaded, the old one is kept.
+    fn reload_symbols(&mut self, target: &str) {
+        outln!("`{}` has changed; re-reading symbols.", target);
+        match load_debug_data(&mut self.debug_data_cache, target) {
+            Ok(debug_data) => {
+                self.set_debug_data(debug_data);
+                self.source_files = SourceFiles::new(target);
+                self.resolve_breakpoints();
+            }
+            Err(err) => outln!("{}", err),
+        }
+    }
+
+    /// Switches to the debug info of another program.
+    fn set_debug_data(&mut self, debug_data: Rc<DwarfData>) {
+        for unit in debug_data.broken_units() {
+            outln!("warning: skipped {}", unit);
+        }
+        self.debug_data = debug_data;
+        if let Some(helper) = self.readline.as_mut().and_then(|r| r.helper_mut()) {
+            helper.set_symbols(&self.debug_data);
+        }
+    }
+
+    /// Looks the breakpoints up again in new debug info. Breakpoints on raw addresses can't be
+    /// carried over, and neither can ones that no longer resolve, so they are disabled.
+    fn resolve_breakpoints(&mut self) {
+        for (num, bp) in self.breakpoints.iter_mut().enumerate() {
+            if !bp.enabled {
+                continue;
+            }
+            let addr = match &bp.spec {
+                BreakpointSpec::Address => {
+                    outln!(
+                        "Breakpoint {} at {:#x} disabled: addresses don't carry over to the new program",
+                        num, bp.addr
+                    );
+                    None
+                }
+                BreakpointSpec::Line(file, line_number) => self
+                    .debug_data
+                    .get_addr_for_line(file.as_deref(), *line_number),
+                BreakpointSpec::Function(name) => self.debug_data.get_addr_for_function(None, name),
+            };
+            match addr {
+                Some(addr) => bp.addr = addr,
+                None => {
+                    if !matches!(bp.spec, BreakpointSpec::Address) {
+                        outln!(
+                            "warning: breakpoint {} no longer resolves in the new program; disabled",
+                            num
+                        );
+                    }
+                    bp.enabled = false;
+                }
+            }
+        }
+        // globals are found again by name; locals are in frames of the old program's process,
+        // and go with it at the next run
+        let debug_data = &self.debug_data;
+        self.watchpoints.retain_mut(|watchpoint| {
+            if watchpoint.static_addr.is_none() {
+                return true;
+            }
+            let var = debug_data.get_variable(&watchpoint.name, None);
+            match var.map(|var| (&var.location, var.entity_type)) {
+                Some((dwarf_data::Location::Address(addr), entity_type)) => {
+                    watchpoint.static_addr = Some(*addr);
+                    watchpoint.addr = *addr;
+                    watchpoint.entity_type = entity_type;
+                    watchpoint.len = debug_data.types().size_of(entity_type);
+                    true
+                }
+                _ => {
+                    outln!(
+                        "warning: watchpoint {} no longer resolves in the new program; deleted",
+                        watchpoint.number
+                    );
+                    false
+                }
+            }
+        });
+    }
+
+    /// Prints why and where the inferior stopped, GDB style: "Breakpoint 1, main (argc=2,
+    /// argv=0x7ffd...) at file:line", followed by the text of that source line. Every stop
+    /// comes through here.
+    fn print_stop_location(&mut self, rip: usize, reason: StopReason) {
+        let mut location = Location {
+            args: self.frame_args(rip),
+            ..self.location(rip)
+        };
+        if location.function.is_none() {
+            location.mapping = self.mapping_name(rip);
+        }
+        let source_line = match (&location.file, location.line) {
+            (Some(file), Some(number)) => self
+                .source_files
+                .get_line(file, number)
+                .map(|text| (number, text)),
+            _ => None,
+        };
+        let stopped = !matches!(reason, StopReason::Core(_) | StopReason::Switched(_));
+        // a step in the same function says nothing else about where it got to
+        let unread_step = match (&location.file, location.line, &reason) {
+            (Some(file), Some(number), StopReason::Step(false)) if source_line.is_none() => {
+                Some(format!("{}\tin {}", number, file))
+            }
+            _ => None,
+        };
+        event::emit(Event::Stopped { reason, location });
+        if let Some((number, text)) = source_line {
+            outln!("{}\t{}", number, text);
+        } else if let Some(line) = unread_step {
+            outln!("{}", line);
+        }
+        if stopped {
+            let executed = self.inferior.as_mut().and_then(Inferior::take_instructions);
+            if let Some(executed) = executed {
+                self.instructions += executed;
+                self.instructions_since_hit += executed;
+                self.print_instructions();
+            }
+            self.queue_hook("hook-stop");
+        }
+    }
+
+    /// With count-instructions on, how many instructions the program has run since it started
+    /// or since `count reset`, and how many source lines it has been stepped, if any.
+    fn print_instructions(&self) {
+        match self.line_steps {
+            0 => outln!("{} instructions executed", format_count(self.instructions)),
+            1 => outln!(
+                "{} instructions executed, 1 source line stepped",
+                format_count(self.instructions)
+            ),
+            lines => outln!(
+                "{} instructions executed, {} source lines stepped",
+                format_count(self.instructions),
+                format_count(lines)
+            ),
+        }
+    }
+
+    /// Runs user-defined command `name`, if there is one, before the next command or prompt, as
+    /// if it had been typed. An error in it is reported like any other and just ends it. Front
+    /// ends talking MI or DAP show the state themselves, and get no hooks.
+    fn queue_hook(&mut self, name: &str) {
+        if self.user_commands.contains_key(name) && !self.mi && !self.dap {
+            self.pending_lines.push_front(PendingLine {
+                text: name.to_string(),
+                depth: self.command_depth,
+                origin: self.command_origin.clone(),
+            });
+        }
+    }
+
+    /// The arguments of the function at `rip` in the innermost frame, formatted.
+    fn frame_args(&self, rip: usize) -> Option<Vec<(String, String)>> {
+        let function = self.debug_data.get_dwarf_function_from_addr(rip)?;
+        let types = self.debug_data.types();
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) => Some(read_args(
+                function,
+                types,
+                arch::frame_pointer(&inferior.registers().ok()?),
+                |addr| inferior.read_word(addr),
+            )),
+            (None, Some(core)) => Some(read_args(
+                function,
+                types,
+                arch::frame_pointer(core.registers()),
+                |addr| core.read_word(addr),
+            )),
+            (None, None) => None,
+        }
+    }
+
+    /// Evaluates `expr` where the inferior or core file is stopped, and formats the value.
+    fn print_value(&self, expr: &Expr) -> Result<String, String> {
+        self.with_frame(|frame| expr.evaluate(frame)?.format(frame))
+    }
+
+    /// Calls `f` with what expressions see of the stopped program or core file.
+    fn with_frame<T>(&self, f: impl FnOnce(&Frame) -> T) -> T {
+        let registers = match (&self.inferior, &self.core) {
+            (Some(inferior), _) => inferior.registers().ok(),
+            (None, Some(core)) => Some(*core.registers()),
+            (None, None) => None,
+        };
+        let scope = registers.and_then(|regs| {
+            self.debug_data
+                .get_dwarf_function_from_addr(arch::pc(&regs))
+        });
+        let frame_pointer = registers.map(|regs| arch::frame_pointer(&regs));
+        self.with_frame_of(scope, registers, frame_pointer, f)
+    }
+
+    /// Calls `f` with what expressions see of frame `level` of the stopped program or core file,
+    /// the innermost being 0. The registers are only known in that one; in the others, variables
+    /// are found from the frame pointer the unwinding gave. Fails if there's no such frame.
+    fn with_frame_at<T>(&self, level: usize, f: impl FnOnce(&Frame) -> T) -> Result<T, String> {
+        if self.inferior.is_none() && self.core.is_none() {
+            return Err("No stack.".to_string());
+        }
+        if level == 0 {
+            return Ok(self.with_frame(f));
+        }
+        let mut found = None;
+        let mut count = 0;
+        let result = self.walk_stack(|location, frame_pointer| {
+            if count == level {
+                found = Some((location.address, frame_pointer));
+                return false;
+            }
+            count += 1;
+            true
+        });
+        let Some((pc, frame_pointer)) = found else {
+            result?;
+            return Err(format!("No frame at level {}.", level));
+        };
+        // a caller's pc is the return address, which is past the call
+        let scope = self.debug_data.get_dwarf_function_from_addr(pc - 1);
+        Ok(self.with_frame_of(scope, None, frame_pointer, f))
+    }
+
+    /// Calls `f` with a Frame in function `scope` of the stopped program or core file.
+    fn with_frame_of<T>(
+        &self,
+        scope: Option<&dwarf_data::Function>,
+        registers: Option<arch::Registers>,
+        frame_pointer: Option<usize>,
+        f: impl FnOnce(&Frame) -> T,
+    ) -> T {
+        let no_process = |_| Err(nix::Error::ESRCH);
+        let read_word: &dyn Fn(usize) -> Result<usize, nix::Error> =
+            match (&self.inferior, &self.core) {
+                (Some(inferior), _) => &|addr| inferior.read_word(addr),
+                (None, Some(core)) => &|addr| core.read_word(addr),
+                (None, None) => &no_process,
+            };
+        let frame = Frame {
+            debug_data: &self.debug_data,
+            scope,
+            registers,
+            frame_pointer,
+            read_word,
+            print_symbol: self.settings.bool("print symbol"),
+            print_mangled: self.settings.bool("print mangled"),
+        };
+        f(&frame)
+    }
+
+    /// The frames of the stopped program or core file, innermost first, and the error that cut
+    /// the walk up the stack short, if any.
+    fn frames(&self) -> (Vec<Location>, Result<(), String>) {
+        let mut frames = Vec::new();
+        let result = self.walk_stack(|frame, _| {
+            frames.push(frame);
+            true
+        });
+        (frames, result)
+    }
+
+    /// Passes the frames of the stopped program or core file to `frame`, innermost first, with
+    /// their frame pointers where they're known, until it returns false.
+    fn walk_stack(&self, frame: impl FnMut(Location, Option<usize>) -> bool) -> Result<(), String> {
+        let print_mangled = self.settings.bool("print mangled");
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) => inferior.backtrace(&self.debug_data, print_mangled, frame),
+            (None, Some(core)) => inferior::backtrace(
+                &self.debug_data,
+                &unwind::MappedFrameInfo::default(),
+                print_mangled,
+                core.registers(),
+                |addr| core.read_word(addr),
+                frame,
+            ),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// `backtrace`: up to BACKTRACE_LIMIT frames unless `full_depth`. As text, the frames are
+    /// shown as they're read, BACKTRACE_BATCH to an event, so a deep stack starts printing right
+    /// away and ctrl+c or quitting the pager stops the walk; other formats get them in one event.
+    fn print_backtrace(&self, full_depth: bool) {
+        let limit = if full_depth {
+            usize::MAX
+        } else {
+            BACKTRACE_LIMIT
+        };
+        let batch = match output::format() {
+            Format::Text => BACKTRACE_BATCH,
+            _ => usize::MAX,
+        };
+        let interrupts = Interrupts::catch();
+        let mut frames = Vec::new();
+        let mut count = 0;
+        let mut more = false;
+        let result = self.walk_stack(|frame, _| {
+            if count == limit {
+                more = true;
+                return false;
+            }
+            count += 1;
+            frames.push(frame);
+            if frames.len() == batch {
+                event::emit(Event::Backtrace {
+                    frames: std::mem::take(&mut frames),
+                });
+            }
+            !interrupts.occurred() && !output::page_quit()
+        });
+        if !frames.is_empty() {
+            event::emit(Event::Backtrace { frames });
+        }
+        if interrupts.occurred() {
+            outln!("Quit");
+        } else if more {
+            outln!("(More stack frames follow; use \"bt -full-depth\" to see all.)");
+        } else if let Err(message) = result {
+            outln!("{}", paint(Style::Error, message));
+        }
+    }
+
+    /// What the debug info knows about `rip`, for stop events.
+    fn location(&self, rip: usize) -> Location {
+        let function = self.debug_data.get_function_and_offset_from_addr(rip);
+        let line = self.debug_data.get_nearest_line(rip);
+        let print_mangled = self.settings.bool("print mangled");
+        match (function, line) {
+            (Some((name, _)), Some(line)) => Location {
+                address: rip,
+                function: Some(format_symbol_name(&name, print_mangled)),
+                offset: None,
+                file: Some(line.file),
+                line: Some(line.number),
+                column: line.column,
+                args: None,
+                mapping: None,
+            },
+            (Some((name, offset)), None) => Location {
+                address: rip,
+                function: Some(format_symbol_name(&name, print_mangled)),
+                offset: Some(offset),
+                file: None,
+                line: None,
+                column: 0,
+                args: None,
+                mapping: None,
+            },
+            (None, _) => Location {
+                address: rip,
+                function: None,
+                offset: None,
+                file: None,
+                line: None,
+                column: 0,
+                args: None,
+                mapping: None,
+            },
+        }
+    }
+
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    ///
+    /// You don't need to read, understand, or modify this function.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            // batch mode is over once the program has run to its end
+            if self.batch
+                && self.last_exit_status.is_some()
+                && self.inferior.is_none()
+                && self.other_inferiors.is_empty()
+            {
+                return DebuggerCommand::Quit;
+            }
+            if self.pending_lines.is_empty() {
+                if let Some(item) = self.startup.pop_front() {
+                    match item {
+                        Startup::InitFile(path) => self.queue_init_file(&path),
+                        Startup::Command(text) => self.pending_lines.push_back(PendingLine {
+                            text,
+                            depth: 0,
+                            origin: None,
+                        }),
+                    }
+                    continue;
+                }
+            }
+            let (line, depth, origin) = match self.next_pending_line() {
+                Some(pending) => (pending.text, pending.depth, pending.origin),
+                None if self.dap => match self.next_dap_command() {
+                    Some(cmd) => return cmd,
+                    None => continue,
+                },
+                None if self.mi => match self.read_mi_command() {
+                    Some(line) => (line, 0, None),
+                    None => return DebuggerCommand::Quit,
+                },
+                None => match self.read_command_line() {
+                    Some(line) => (line, 0, None),
+                    None => return DebuggerCommand::Quit,
+                },
+            };
+            if let Some(cmd) = self.parse_command(line, depth, origin) {
+                return cmd;
+            }
+        }
+    }
+
+    /// The next line of a script or user-defined command to run, skipping blank ones.
+    fn next_pending_line(&mut self) -> Option<PendingLine> {
+        loop {
+            let pending = self.pending_lines.pop_front()?;
+            if pending.text.trim().is_empty() {
+                continue;
+            }
+            if self.settings.bool("verbose") && pending.origin.is_some() {
+                outln!("+{}", pending.text);
+            }
+            return Some(pending);
+        }
+    }
+
+    /// Parses a command line, reporting errors. An empty line typed at the prompt repeats the
+    /// last command that can be repeated.
+    fn parse_command(
+        &mut self,
+        line: String,
+        depth: usize,
+        origin: Option<(Rc<str>, usize)>,
+    ) -> Option<DebuggerCommand> {
+        if line.trim().is_empty() {
+            let line = self.repeat_line.clone()?;
+            return self.parse_line(&line, 0, None).ok()?.map(|(cmd, _)| cmd);
+        }
+        match self.parse_line(&line, depth, origin.clone()) {
+            Ok(Some((cmd, repeatable))) => {
+                if depth == 0 {
+                    self.repeat_line = repeatable.then_some(line);
+                }
+                if self.mi {
+                    match cmd {
+                        DebuggerCommand::Continue
+                        | DebuggerCommand::Step(_)
+                        | DebuggerCommand::Next(_)
+                        | DebuggerCommand::Finish
+                            if self.inferior.is_none() =>
+                        {
+                            mi::error("The program is not being run.");
+                            return None;
+                        }
+                        DebuggerCommand::Run(..)
+                        | DebuggerCommand::Continue
+                        | DebuggerCommand::Step(_)
+                        | DebuggerCommand::Next(_)
+                        | DebuggerCommand::Finish => mi::running(),
+                        DebuggerCommand::Quit => mi::exit(),
+                        _ => {}
+                    }
+                }
+                self.command_depth = depth;
+                self.command_origin = origin;
+                Some(cmd)
+            }
+            Ok(None) => {
+                self.repeat_line = None;
+                None
+            }
+            Err(message) => {
+                self.report_script_error(&message, &origin);
+                None
+            }
+        }
+    }
+
+    /// Runs a command line as if it had been typed at the prompt, followed by whatever it queues
+    /// up, such as the lines of a user-defined command or a script, or a hook. The output is
+    /// printed as usual, and events go to the listeners of `event::subscribe`. Returns the
+    /// status deet would exit with if the line quit.
+    pub fn execute_line(&mut self, line: &str) -> Option<i32> {
+        let mut next = Some(PendingLine {
+            text: line.to_string(),
+            depth: 0,
+            origin: None,
+        });
+        while let Some(pending) = next {
+            if let Some(cmd) = self.parse_command(pending.text, pending.depth, pending.origin) {
+                if let Some(status) = self.execute(cmd) {
+                    return Some(status);
+                }
+            }
+            next = self.next_pending_line();
+        }
+        self.run_stop_hooks();
+        None
+    }
+
+    /// Where the program or core file is stopped, with the arguments of the function, as stop
+    /// events show it. None without either.
+    pub fn stop_location(&self) -> Option<Location> {
+        let pc = self.current_pc()?;
+        Some(Location {
+            args: self.frame_args(pc),
+            ..self.location(pc)
+        })
+    }
+
+    /// The frames of the stopped program or core file, innermost first, as `backtrace` shows
+    /// them. A stack that can't be read all the way up gives the frames up to there.
+    pub fn backtrace(&self) -> Result<Vec<Location>, String> {
+        if self.inferior.is_none() && self.core.is_none() {
+            return Err("No stack.".to_string());
+        }
+        Ok(self.frames().0)
+    }
+
+    /// Evaluates `expression` as `print` does, where the program or core file is stopped.
+    pub fn evaluate(&self, expression: &str) -> Result<expr::Value, String> {
+        let expr = Expr::parse(expression)?;
+        self.with_frame(|frame| expr.evaluate(frame))
+    }
+
+    /// Formats a value from `evaluate` the way `print` shows it, reading what it points to.
+    pub fn format_value(&self, value: &expr::Value) -> Result<String, String> {
+        self.with_frame(|frame| value.format(frame))
+    }
+
+    /// The name of the type of a value from `evaluate`, e.g. `char *`.
+    pub fn type_name(&self, value: &expr::Value) -> String {
+        self.with_frame(|frame| value.type_name(frame))
+    }
+
+    /// The value of a register where the program or core file is stopped, as `$name` gives it.
+    pub fn register(&self, name: &str) -> Result<u64, String> {
+        self.with_frame(|frame| expr::Context::register(frame, name))
+    }
+
+    /// `len` bytes of the program's or core file's memory at `addr`.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        let mut buf = vec![0; len];
+        self.with_frame(|frame| expr::Context::read_memory(frame, addr, &mut buf))?;
+        Ok(buf)
+    }
+
+    /// The program being debugged, if it's running.
+    pub fn inferior(&self) -> Option<&Inferior> {
+        self.inferior.as_ref()
+    }
+
+    /// The debug info of the program, empty without one.
+    pub fn debug_data(&self) -> &DwarfData {
+        &self.debug_data
+    }
+
+    /// Runs `~/.deetinit` and then `./.deetinit` before the first prompt, if they exist.
+    pub fn load_init_files(&mut self) {
+        let mut paths = Vec::new();
+        if let Some(home) = std::env::var_os("HOME") {
+            paths.push(PathBuf::from(home).join(".deetinit"));
+        }
+        paths.push(PathBuf::from(".deetinit"));
+        for path in paths {
+            let Ok(canonical) = path.canonicalize() else {
+                continue;
+            };
+            // in the home directory, both are the same file
+            if !self
+                .startup
+                .iter()
+                .any(|item| matches!(item, Startup::InitFile(path) if *path == canonical))
+            {
+                self.startup.push_back(Startup::InitFile(canonical));
+            }
+        }
+    }
+
+    /// Runs `command` before the first prompt, after the init files and earlier commands.
+    pub fn run_at_startup(&mut self, command: String) {
+        self.startup.push_back(Startup::Command(command));
+    }
+
+    /// Queues the next init file. Like gdb's safe-path, one that other users can write to is
+    /// declined, unless it's in a directory of `set auto-load-safe-path`; the check waits until
+    /// the file's turn so that an earlier init file can set that.
+    fn queue_init_file(&mut self, path: &Path) {
+        let writable = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.permissions().mode() & 0o002 != 0,
+            Err(_) => return,
+        };
+        let safe = std::env::split_paths(self.settings.string("auto-load-safe-path"))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| path.parent() == dir.canonicalize().ok().as_deref());
+        if writable && !safe {
+            outln!(
+                "warning: File \"{}\" auto-loading has been declined because it is world-writable.",
+                path.display()
+            );
+            outln!(
+                "To enable it anyway, add its directory to \"set auto-load-safe-path\" in ~/.deetinit."
+            );
+            return;
+        }
+        if let Err(message) = self.queue_script(&path.to_string_lossy(), 0) {
+            outln!("{}", message);
+        }
+    }
+
+    /// Prints an error, prefixed with the script line it came from if any, and aborts the
+    /// scripts and user-defined commands that were running.
+    fn report_script_error(&mut self, message: &str, origin: &Option<(Rc<str>, usize)>) {
+        match origin {
+            Some((path, line)) => outln!(
+                "{}",
+                paint(Style::Error, format!("{}:{}: {}", path, line, message))
+            ),
+            None if self.mi => mi::error(message),
+            None => outln!("{}", paint(Style::Error, message)),
+        }
+        self.pending_lines.clear();
+    }
+
+    /// Runs scripting-language `code` from `origin`, a file or "eval", printing its value if it
+    /// has one. The lines queued to run after it wait until it's done, so the commands it runs
+    /// don't run them early.
+    fn run_script(&mut self, code: &str, origin: &str) {
+        let Some(mut interpreter) = self.script.take() else {
+            outln!("A script is already running.");
+            return;
+        };
+        let queued = std::mem::take(&mut self.pending_lines);
+        match interpreter.run(self, code, origin) {
+            Ok(None) => {}
+            Ok(Some(value)) => outln!("{}", value),
+            Err(message) => outln!("{}", paint(Style::Error, message)),
+        }
+        self.pending_lines = queued;
+        self.script = Some(interpreter);
+    }
+
+    /// Calls the on_stop hooks of scripts if the program has stopped since they last ran.
+    fn run_stop_hooks(&mut self) {
+        if let Some(mut interpreter) = self.script.take() {
+            interpreter.after_command(self);
+            self.script = Some(interpreter);
+        }
+    }
+
+    /// `source FILE`: runs the commands in the file next, before any lines already queued. An
+    /// error reading it is reported as the error of the command that sourced it.
+    fn source(&mut self, path: &str) {
+        if let Err(message) = self.queue_script(path, self.command_depth) {
+            let origin = self.command_origin.clone();
+            self.report_script_error(&message, &origin);
+        }
+    }
+
+    /// Queues the commands in the file at `path` to run next, at `depth` as the lines of the
+    /// script or user-defined command sourcing it, skipping blank lines and # comments. Lines
+    /// ending in a backslash are joined with the next.
+    fn queue_script(&mut self, path: &str, depth: usize) -> Result<(), String> {
+        if depth >= MAX_USER_CALL_DEPTH {
+            return Err("Scripts are sourced too deeply -- command aborted.".to_string());
+        }
+        let contents =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {}.", path, err))?;
+        let origin: Rc<str> = Rc::from(path);
+        let mut lines: Vec<PendingLine> = Vec::new();
+        let mut continuing = false;
+        for (number, line) in contents.lines().enumerate() {
+            // a line continued from the last one is part of it, comment or not
+            if std::mem::take(&mut continuing) {
+                let last = lines.last_mut().unwrap();
+                last.text.push_str(line);
+                if let Some(text) = continued(&last.text) {
+                    last.text.truncate(text.len());
+                    continuing = true;
+                }
+                continue;
+            }
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let text = match continued(line) {
+                Some(text) => {
+                    continuing = true;
+                    text
+                }
+                None => line,
+            };
+            lines.push(PendingLine {
+                text: text.to_string(),
+                depth: depth + 1,
+                origin: Some((origin.clone(), number + 1)),
+            });
+        }
+        for line in lines.into_iter().rev() {
+            self.pending_lines.push_front(line);
+        }
+        Ok(())
+    }
+
+    /// Reads a line at the prompt, saving it to the history. None means the user wants out.
+    fn read_command_line(&mut self) -> Option<String> {
+        loop {
+            // Print prompt and get next line of user input
+            let (prompt, styled) = self.prompt();
+            if let Some(helper) = self
+                .readline
+                .as_mut()
+                .and_then(|readline| readline.helper_mut())
+            {
+                helper.set_prompt(prompt.clone(), styled);
+            }
+            match self.read_continued_line(&prompt) {
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    outln!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => {
+                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                    return None;
+                }
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    output::log_command(&prompt, &line);
+                    output::begin_page(self.settings.bool("pagination"));
+                    if let Some(readline) =
+                        self.readline.as_mut().filter(|_| !line.trim().is_empty())
+                    {
+                        let _ = readline.add_history_entry(line.as_str());
+                        if self.history_saved.elapsed() >= HISTORY_SAVE_INTERVAL {
+                            self.save_history();
+                        }
+                    }
+                    return Some(line);
+                }
+            }
+        }
+    }
+
+    /// Answers the previous MI command, then reads MI commands until one translates to a deet
+    /// command line, answering the rest right away. None at the end of input.
+    fn read_mi_command(&mut self) -> Option<String> {
+        loop {
+            mi::finish();
+            output::write_record("(gdb) ");
+            let line = self.read_line("").ok()?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            output::log_command("", &line);
+            let (token, request) = mi::translate(&line);
+            match request {
+                Request::Command(line, error) => {
+                    mi::begin(token, error);
+                    if !line.trim().is_empty() {
+                        return Some(line);
+                    }
+                }
+                Request::Done(results) => {
+                    mi::begin(token, None);
+                    mi::result(&results, "");
+                }
+                Request::Evaluate(expression, variable) => {
+                    mi::begin(token, None);
+                    let evaluated = self
+                        .evaluate(&expression)
+                        .and_then(|value| Ok((self.format_value(&value)?, self.type_name(&value))));
+                    match evaluated {
+                        Ok((value, ty)) => mi::evaluated(variable, &expression, &value, &ty),
+                        Err(message) => mi::error(&message),
+                    }
+                }
+                Request::Error(message) => {
+                    mi::begin(token, None);
+                    mi::error(&message);
+                }
+            }
+        }
+    }
+
+    /// Answers the previous DAP request, then handles requests until one maps onto a deet
+    /// command. None if the request queued commands to run instead.
+    fn next_dap_command(&mut self) -> Option<DebuggerCommand> {
+        loop {
+            let Some(line) = dap::next_command(self) else {
+                return Some(DebuggerCommand::Quit);
+            };
+            match self.parse_line(&line, 0, None) {
+                Ok(Some((cmd, _))) => return Some(cmd),
+                Ok(None) => return None,
+                Err(message) => dap::fail(&message),
+            }
+        }
+    }
+
+    /// `set prompt` as it is now, plain and styled, followed by a space.
+    fn prompt(&self) -> (String, String) {
+        let pid = self.inferior.as_ref().map(|inferior| inferior.pid());
+        let expanded = style::expand(self.settings.string("prompt"), Style::Prompt, |c| match c {
+            'p' => Some(pid.map_or(String::new(), |pid| pid.to_string())),
+            'f' => Some(
+                self.current_pc()
+                    .and_then(|pc| self.debug_data.get_function_from_addr(pc))
+                    .map_or(String::new(), |name| {
+                        format_symbol_name(&name, self.settings.bool("print mangled"))
+                    }),
+            ),
+            's' => Some(
+                match (&self.inferior, &self.core) {
+                    (Some(_), _) => "stopped",
+                    (None, Some(_)) => "core",
+                    (None, None) => "absent",
+                }
+                .to_string(),
+            ),
+            _ => None,
+        });
+        // `set prompt` checked the template
+        let (plain, styled) = expanded.unwrap();
+        (format!("{} ", plain), format!("{} ", styled))
+    }
+
+    /// Appends the history entries not saved yet to the history file, with `history save` on,
+    /// creating the directory for it if needed. The file is only rewritten when it would grow past
+    /// the history size, or was changed by another deet meanwhile.
+    fn save_history(&mut self) {
+        self.history_saved = Instant::now();
+        let (Some(readline), Some(path), true) = (
+            &mut self.readline,
+            self.settings.path("history filename"),
+            self.settings.bool("history save"),
+        ) else {
+            return;
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(err) = readline.append_history(path) {
+            if !std::mem::replace(&mut self.history_warned, true) {
+                outln!(
+                    "Warning: failed to save history file at {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Reads a command, which goes on over the following lines, at a `> ` prompt, as long as they
+    /// end in a backslash. Returns the lines joined without the backslashes, so that's what the
+    /// history records.
+    fn read_continued_line(&mut self, prompt: &str) -> rustyline::Result<String> {
+        let mut line = self.read_line(prompt)?;
+        while let Some(len) = continued(&line).map(str::len) {
+            line.truncate(len);
+            line.push_str(&self.read_line("> ")?);
+        }
+        Ok(line)
+    }
+
+    /// Reads a line with the line editor, or straight from stdin without one: without a prompt in
+    /// batch mode, and after a plain one if the editor couldn't be set up.
+    fn read_line(&mut self, prompt: &str) -> rustyline::Result<String> {
+        match &mut self.readline {
+            Some(readline) => readline.readline(prompt),
+            None => {
+                if !self.batch && !self.mi && !self.dap {
+                    output::write_raw(prompt);
+                }
+                let mut line = String::new();
+                match std::io::stdin().read_line(&mut line)? {
+                    0 => Err(ReadlineError::Eof),
+                    _ => Ok(line.trim_end_matches('\n').to_string()),
+                }
+            }
+        }
+    }
+
+    /// Parses a command line after expanding aliases in its first word. Also says whether an
+    /// empty line should repeat it. A user-defined command isn't parsed but queued to run, as
+    /// one nesting level below `depth`, and gives None.
+    fn parse_line(
+        &mut self,
+        line: &str,
+        depth: usize,
+        origin: Option<(Rc<str>, usize)>,
+    ) -> Result<Option<(DebuggerCommand, bool)>, String> {
+        // the shell gets the line as typed, quotes and all
+        if let Some(command) = line.trim_start().strip_prefix('!') {
+            return Ok(Some((
+                DebuggerCommand::Shell(command.trim().to_string()),
+                false,
+            )));
+        }
+        let mut tokens: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        let mut alias_depth = 0;
+        while let Some(expansion) = self.aliases.get(&tokens[0]) {
+            alias_depth += 1;
+            if alias_depth > MAX_ALIAS_DEPTH {
+                return Err(format!("Alias \"{}\" expands too deeply.", tokens[0]));
+            }
+            tokens.splice(..1, expansion.iter().cloned());
+        }
+        if let Some(body) = self.user_commands.get(&tokens[0]) {
+            if depth >= MAX_USER_CALL_DEPTH {
+                return Err("Max user call depth exceeded -- command aborted.".to_string());
+            }
+            let lines = body
+                .iter()
+                .map(|line| substitute_args(line, &tokens[1..]))
+                .collect::<Result<Vec<String>, String>>()?;
+            for text in lines.into_iter().rev() {
+                self.pending_lines.push_front(PendingLine {
+                    text,
+                    depth: depth + 1,
+                    origin: origin.clone(),
+                });
+            }
+            return Ok(None);
+        }
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        if !debugger_command::is_known_prefix(tokens[0]) {
+            let user_names = self.aliases.keys().chain(self.user_commands.keys());
+            return Err(debugger_command::undefined_command(
+                tokens[0],
+                user_names.map(String::as_str),
+            ));
+        }
+        let mut cmd = DebuggerCommand::from_tokens(&tokens)?;
+        // the shell, the program, eval, echo, input and printf get the rest of the line as typed, quotes,
+        // spaces and all
+        if let (
+            DebuggerCommand::Shell(rest)
+            | DebuggerCommand::Run(rest)
+            | DebuggerCommand::Eval(rest)
+            | DebuggerCommand::Echo(rest)
+            | DebuggerCommand::Input(rest)
+            | DebuggerCommand::Printf(rest),
+            0,
+        ) = (&mut cmd, alias_depth)
+        {
+            let line = line.trim_start();
+            *rest = line[line.find(char::is_whitespace).unwrap_or(line.len())..]
+                .trim()
+                .to_string();
+        }
+        Ok(Some((cmd, debugger_command::is_repeatable(&tokens))))
+    }
+
+    /// Reads the body of user-defined command `name`, up to its `end`. Inside another
+    /// user-defined command, the body is the lines that follow; otherwise the user types it.
+    fn define_user_command(&mut self, name: String) {
+        if debugger_command::is_command_name(&name) || self.aliases.contains_key(&name) {
+            outln!("\"{}\" is already a command or alias.", name);
+            return;
+        }
+        if self.user_commands.contains_key(&name)
+            && !self.confirm(&format!("Redefine command \"{}\"?", name))
+        {
+            return;
+        }
+        if self.pending_lines.is_empty() {
+            outln!(
+                "Type commands for definition of \"{}\".\nEnd with a line saying just \"end\".",
+                name
+            );
+        }
+        match self.read_block() {
+            Ok(body) => {
+                self.user_commands.insert(name, body);
+            }
+            Err(BlockError::Abandoned) => outln!("Definition of \"{}\" abandoned.", name),
+            Err(BlockError::MissingEnd) => {
+                outln!("Definition of \"{}\" is missing its \"end\".", name)
+            }
+        }
+    }
+
+    /// Reads the lines of a block, as `define` takes, up to a line saying just "end": from the
+    /// rest of the script or user-defined command the block is in, or else at a `>` prompt. Lines
+    /// continued with a backslash are joined, and blocks nested in it keep their own ends.
+    fn read_block(&mut self) -> Result<Vec<String>, BlockError> {
+        let typed = self.pending_lines.is_empty();
+        let mut body = Vec::new();
+        let mut nesting = 0;
+        loop {
+            let line = if typed {
+                self.read_continued_line(">")
+                    .map_err(|_| BlockError::Abandoned)?
+            } else {
+                self.pending_lines
+                    .pop_front()
+                    .ok_or(BlockError::MissingEnd)?
+                    .text
+            };
+            let line = line.trim();
+            if line == "end" {
+                if nesting == 0 {
+                    return Ok(body);
+                }
+                nesting -= 1;
+            } else if line
+                .split_whitespace()
+                .next()
+                .is_some_and(|word| BLOCK_COMMANDS.contains(&word))
+            {
+                nesting += 1;
+            }
+            if !line.is_empty() {
+                body.push(line.to_string());
+            }
+        }
+    }
+
+    fn print_user_command(&self, name: &str) {
+        outln!("User command \"{}\":", name);
+        for line in &self.user_commands[name] {
+            outln!("  {}", line);
+        }
+    }
+
+    /// Defines alias `name` for `words`, refusing to shadow a built-in command.
+    fn define_alias(&mut self, name: String, words: Vec<String>) {
+        if debugger_command::is_command_name(&name) {
+            outln!("Alias \"{}\" would shadow a built-in command.", name);
+        } else if self.user_commands.contains_key(&name) {
+            outln!("Alias \"{}\" would shadow a user-defined command.", name);
+        } else {
+            self.aliases.insert(name, words);
+        }
+    }
+
+    fn print_aliases(&self) {
+        if self.aliases.is_empty() {
+            outln!("No aliases.");
+        }
+        for (name, words) in &self.aliases {
+            outln!("{} = {}", name, words.join(" "));
+        }
+    }
+}
+
+impl dap::Session for Debugger {
+    fn launch(&mut self, program: Option<&str>, args: &[String]) -> Result<(), String> {
+        // the program may have been loaded at startup, from deet's command line
+        let canonical = |path: &str| std::fs::canonicalize(path).ok();
+        if let (None, Some(program)) = (&self.target, program) {
+            self.change_file(Some(program.to_string()));
+        }
+        match (program, &self.target) {
+            (_, None) => Err(NO_EXECUTABLE.to_string()),
+            (Some(program), Some(target))
+                if canonical(program).is_none() || canonical(program) != canonical(target) =>
+            {
+                Err(format!(
+                    "deet was started to debug {}, not {}.",
+                    target, program
+                ))
+            }
+            _ => {
+                let line: Vec<String> = args
+                    .iter()
+                    .map(|arg| debugger_command::quote_arg(arg))
+                    .collect();
+                self.settings
+                    .set_value("args", Value::String(line.join(" ")));
+                Ok(())
+            }
+        }
+    }
+
+    /// Lines without code move down to the next line that has some, as with `break FILE:LINE`.
+    /// Breakpoints left out of a later request stay set: deet can only delete all of them.
+    fn set_breakpoints(&mut self, path: &str, lines: &[usize]) -> Vec<JsonValue> {
+        // the program may have been built somewhere else, with other paths in its debug info
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let mut breakpoints = Vec::new();
+        for &line in lines {
+            let addr = self
+                .debug_data
+                .get_addr_for_line(Some(path), line)
+                .or_else(|| self.debug_data.get_addr_for_line(Some(name), line));
+            breakpoints.push(match addr {
+                Some(addr) => {
+                    let spec = BreakpointSpec::Line(Some(path.to_string()), line);
+                    let number = self.add_breakpoint(addr, spec);
+                    let location = Location {
+                        // report it where the editor put it
+                        file: Some(path.to_string()),
+                        ..self.location(addr)
+                    };
+                    location.dap_breakpoint(number)
+                }
+                None => JsonValue::object([
+                    ("verified", false.into()),
+                    ("line", line.into()),
+                    ("message", "No code at this line.".into()),
+                ]),
+            });
+        }
+        breakpoints
+    }
+
+    fn running(&self) -> Option<String> {
+        self.inferior
+            .as_ref()
+            .map(|_| self.target.clone().unwrap_or_default())
+    }
+
+    /// Formatted as `print` shows them.
+    fn locals(&self, level: usize) -> Result<Vec<(String, String, String)>, String> {
+        self.with_frame_at(level, |frame| {
+            let Some(function) = frame.scope else {
+                return Vec::new();
+            };
+            let types = self.debug_data.types();
+            function
+                .variables
+                .iter()
+                .map(|var| {
+                    let value = match frame.address_of(var) {
+                        Some(addr) => expr::Value {
+                            ty: expr::Ty::Dwarf(var.entity_type),
+                            place: expr::Place::Memory(addr),
+                        }
+                        .format(frame)
+                        .unwrap_or_else(|err| format!("<{}>", err)),
+                        None => "<unreadable>".to_string(),
+                    };
+                    (var.name.clone(), types.name_of(var.entity_type), value)
+                })
+                .collect()
+        })
+    }
+
+    fn evaluate(&self, expression: &str, level: usize) -> Result<String, String> {
+        let expr = Expr::parse(expression)?;
+        self.with_frame_at(level, |frame| expr.evaluate(frame)?.format(frame))?
+    }
+}
+
+impl Drop for Debugger {
+    /// Saves what's left of the history if deet goes down without quitting, e.g. on a panic.
+    fn drop(&mut self) {
+        self.save_history();
+    }
+}
+
+/// Reports that the breakpoint at `addr`, breakpoint `number` unless it's one of deet's own,
+/// couldn't be installed.
+fn print_insert_error(number: Option<usize>, addr: usize, err: nix::Error) {
+    let breakpoint = match number {
+        Some(number) => format!("breakpoint {}", number),
+        None => "a breakpoint".to_string(),
+    };
+    outln!(
+        "{}",
+        paint(
+            Style::Error,
+            format!(
+                "Cannot insert {} at {:#x}: {}",
+                breakpoint,
+                addr,
+                breakpoint_error(err)
+            )
+        )
+    );
+}
+
+/// Reports that the breakpoint at `addr`, breakpoint `number` if it's still in the list,
+/// couldn't be taken out of an inferior, which will trap on it if it gets there.
+fn print_remove_error(number: Option<usize>, addr: usize, err: nix::Error) {
+    let breakpoint = match number {
+        Some(number) => format!("breakpoint {}", number),
+        None => "the breakpoint".to_string(),
+    };
+    outln!(
+        "{}",
+        paint(
+            Style::Error,
+            format!(
+                "Cannot remove {} at {:#x}: {}",
+                breakpoint,
+                addr,
+                breakpoint_error(err)
+            )
+        )
+    );
+}
+
+/// Why a breakpoint couldn't be written or restored, in words.
+fn breakpoint_error(err: nix::Error) -> String {
+    match err {
+        nix::Error::ESRCH => "the process is gone or isn't stopped (ESRCH)".to_string(),
+        nix::Error::EIO | nix::Error::EFAULT => {
+            format!("cannot access memory at that address ({:?})", err)
+        }
+        _ => err.to_string(),
+    }
+}
+
+/// `field` for a CSV file, quoted if it has commas, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// What keeps a watched range's pages from being made read-only, for people.
+fn watch_error(err: nix::Error) -> String {
+    match err {
+        nix::Error::ENOMEM => "not all of it is mapped (ENOMEM)".to_string(),
+        nix::Error::ENOSYS => "remote targets can't protect memory".to_string(),
+        _ => breakpoint_error(err),
+    }
+}
+
+fn watchpoint_error(err: nix::Error) -> String {
+    match err {
+        nix::Error::ENOSPC => format!(
+            "all {} debug registers are in use (ENOSPC)",
+            arch::WATCHPOINT_SLOTS
+        ),
+        nix::Error::EINVAL => "the kernel won't watch that address (EINVAL)".to_string(),
+        _ => breakpoint_error(err),
+    }
+}
+
+/// The aligned 1, 2, 4 or 8 bytes a debug register can watch that cover the `len` bytes at
+/// `addr`, if there are any. Writes to the bytes around the variable trap too, but don't
+/// change its value.
+fn hardware_span(addr: usize, len: usize) -> Option<(usize, usize)> {
+    [1, 2, 4, 8].into_iter().find_map(|size| {
+        let start = addr - addr % size;
+        (addr + len <= start + size).then_some((start, size))
+    })
+}
+
+/// How far from the addresses of the debug info `inferior` has the program: where the kernel
+/// loaded it if it's position-independent, else nothing.
+fn load_bias(debug_data: &DwarfData, inferior: &Inferior) -> usize {
+    if !debug_data.is_position_independent() {
+        return 0;
+    }
+    let Ok(path) = inferior.executable_path() else {
+        return 0;
+    };
+    let path = path.to_string_lossy();
+    inferior::read_memory_maps(inferior.pid())
+        .ok()
+        .and_then(|maps| {
+            maps.iter()
+                .filter(|mapping| mapping.name == path)
+                .map(|mapping| mapping.start)
+                .min()
+        })
+        .unwrap_or(0)
+}
+
+/// Reads and formats the parameters of `function` in a frame whose frame pointer is `fp`.
+/// Their DWARF locations are relative to the frame's CFA, which is two words above the frame
+/// pointer once the prologue has set it up, as it has at function breakpoints; before that, the
+/// values are whatever is on the stack.
+fn read_args(
+    function: &dwarf_data::Function,
+    types: &TypeTable,
+    fp: usize,
+    read_word: impl Fn(usize) -> Result<usize, nix::Error>,
+) -> Vec<(String, String)> {
+    function
+        .variables
+        .iter()
+        .filter(|var| var.is_parameter)
+        .map(|var| {
+            let value = match read_word(variable_address(var, fp, types.pointer_size())) {
+                Ok(word) => types.format_value(var.entity_type, &word.to_le_bytes()),
+                Err(_) => "<unreadable>".to_string(),
+            };
+            (var.name.clone(), value)
+        })
+        .collect()
+}
+
+/// Where a variable is, given the frame's frame pointer and the program's word size. Frame
+/// offsets are from the canonical frame address, which is past the saved frame pointer and the
+/// return address once the prologue has pushed the frame pointer.
+fn variable_address(var: &dwarf_data::Variable, fp: usize, word_size: usize) -> usize {
+    match var.location {
+        dwarf_data::Location::Address(addr) => addr,
+        dwarf_data::Location::FramePointerOffset(offset) => {
+            (fp + 2 * word_size).wrapping_add_signed(offset)
+        }
+    }
+}
+
+/// What `print` sees of a stopped process or a core file, or of just the program without either.
+struct Frame<'a> {
+    debug_data: &'a DwarfData,
+    /// The function stopped in, whose locals and parameters hide globals of the same name
+    scope: Option<&'a dwarf_data::Function>,
+    /// Only known in the innermost frame
+    registers: Option<arch::Registers>,
+    /// What the locals and parameters of `scope` are relative to
+    frame_pointer: Option<usize>,
+    read_word: &'a dyn Fn(usize) -> Result<usize, nix::Error>,
+    /// `set print symbol` and `set print mangled`
+    print_symbol: bool,
+    print_mangled: bool,
+}
+
+impl Frame<'_> {
+    /// Where `var` is, None for a local if the frame pointer isn't known.
+    fn address_of(&self, var: &dwarf_data::Variable) -> Option<usize> {
+        let fp = match (&var.location, self.frame_pointer) {
+            (dwarf_data::Location::Address(_), _) => 0,
+            (_, Some(fp)) => fp,
+            (_, None) => return None,
+        };
+        Some(variable_address(var, fp, self.debug_data.pointer_size()))
+    }
+}
+
+impl expr::Context for Frame<'_> {
+    fn types(&self) -> &TypeTable {
+        self.debug_data.types()
+    }
+
+    fn variable(&self, name: &str) -> Option<(dwarf_data::TypeId, usize)> {
+        let var = self.debug_data.get_variable(name, self.scope)?;
+        Some((var.entity_type, self.address_of(var)?))
+    }
+
+    fn register(&self, name: &str) -> Result<u64, String> {
+        let regs = self.registers.ok_or("No registers.")?;
+        arch::register(&regs, name).ok_or_else(|| format!("Invalid register \"${}\".", name))
+    }
+
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<(), String> {
+        for (i, chunk) in buf.chunks_mut(8).enumerate() {
+            let word = (self.read_word)(addr + 8 * i)
+                .map_err(|_| format!("Cannot access memory at address {:#x}", addr + 8 * i))?;
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    fn symbol(&self, addr: usize) -> Option<String> {
+        self.print_symbol
+            .then(|| self.debug_data.symbolize(addr, self.print_mangled))
+            .flatten()
+    }
+}
+
+/// Loads the debug info of the program at `path`, or says why it can't.
+fn load_debug_data(cache: &mut DebugDataCache, path: &str) -> Result<Rc<DwarfData>, DeetError> {
+    let debug_data = cache.load(path).map_err(|err| match err {
+        DwarfError::ErrorOpeningFile(err) => DeetError::opening(path, err),
+        DwarfError::NotObjectFile(err) => DeetError::NotElf(path.to_string(), err),
+        DwarfError::DwarfFormatError(err, unit) => DeetError::Dwarf {
+            path: path.to_string(),
+            error: format!("{:?}", err),
+            unit: unit.map(|unit| unit.to_string()),
+        },
+    })?;
+    if debug_data.pointer_size() == 4 && !arch::RUNS_32_BIT {
+        return Err(DeetError::Unsupported32Bit(path.to_string()));
+    }
+    Ok(debug_data)
+}
+
+/// Replaces `$argc` in a line of a user-defined command with the number of arguments, and
+/// `$arg0`, `$arg1`, ... with the arguments.
+fn substitute_args(line: &str, args: &[String]) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("$arg") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + "$arg".len()..];
+        if let Some(after) = rest.strip_prefix('c') {
+            result.push_str(&args.len().to_string());
+            rest = after;
+            continue;
+        }
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            result.push_str("$arg");
+            continue;
+        }
+        let index: usize = rest[..digits].parse().map_err(|_| "Bad argument number.")?;
+        match args.get(index) {
+            Some(arg) => result.push_str(arg),
+            None => return Err(format!("Missing argument {} in user function.", index)),
+        }
+        rest = &rest[digits..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// The line editor's key bindings, per `set editing-mode`.
+fn edit_mode(settings: &Settings) -> EditMode {
+    match settings.choice("editing-mode") {
+        "vi" => EditMode::Vi,
+        _ => EditMode::Emacs,
+    }
+}
+
+fn bell_style(settings: &Settings) -> BellStyle {
+    match settings.choice("bell-style") {
+        "visible" => BellStyle::Visible,
+        "none" => BellStyle::None,
+        _ => BellStyle::Audible,
+    }
+}
+
+fn completion_type(settings: &Settings) -> CompletionType {
+    match settings.choice("completion-type") {
+        "circular" => CompletionType::Circular,
+        _ => CompletionType::List,
+    }
+}
+
+/// What `info inferiors` says a process is doing, by the kernel's account of it: "stopped"
+/// for us (as a remote program always is between commands), "running", or "exited" once it's
+/// gone or a zombie.
+fn process_state(inferior: &Inferior) -> &'static str {
+    if inferior.is_remote() {
+        return "stopped";
+    }
+    match inferior::read_stat(inferior.pid()) {
+        Ok(('t' | 'T', ..)) => "stopped",
+        Ok(('Z' | 'X', ..)) | Err(_) => "exited",
+        Ok(_) => "running",
+    }
+}
+
+/// A count with its digits in groups of three: "12,345,678".
+fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut text = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            text.push(',');
+        }
+        text.push(digit);
+    }
+    text
+}
+
+/// Why perf_event_open couldn't give us a hardware counter, from its error.
+fn perf_error(err: nix::Error) -> String {
+    match err {
+        nix::Error::ENOENT | nix::Error::ENODEV | nix::Error::EOPNOTSUPP => {
+            "the machine has none that perf can use".to_string()
+        }
+        nix::Error::EACCES | nix::Error::EPERM => {
+            "perf doesn't allow it (see /proc/sys/kernel/perf_event_paranoid)".to_string()
+        }
+        nix::Error::ENOSYS => "the kernel has no perf events".to_string(),
+        err => err.desc().to_string(),
+    }
+}
+
+/// A duration in the unit that suits it: "12us", "4.1ms", "1.250s".
+fn format_duration(duration: Duration) -> String {
+    match duration.as_secs_f64() {
+        secs if secs < 1e-3 => format!("{}us", duration.as_micros()),
+        secs if secs < 1.0 => format!("{:.1}ms", secs * 1e3),
+        secs => format!("{:.3}s", secs),
+    }
+}
+
+/// An environment variable's name or value for printing on a line: valid UTF-8 as it is, but
+/// with backslashes, control characters and bytes that aren't UTF-8 escaped as in C.
+fn escape_environment(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => text.push_str("\\\\"),
+                '\n' => text.push_str("\\n"),
+                '\t' => text.push_str("\\t"),
+                '\r' => text.push_str("\\r"),
+                c if c.is_control() => text.push_str(&format!("\\x{:02x}", c as u32)),
+                c => text.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    text
+}
+
+/// `line` without the backslash at its end, if it has one that isn't escaped by another and so
+/// continues it onto the next line.
+fn continued(line: &str) -> Option<&str> {
+    let backslashes = line.len() - line.trim_end_matches('\\').len();
+    (backslashes % 2 == 1).then(|| &line[..line.len() - 1])
+}
+
+/// `$XDG_STATE_HOME/deet/history`, or `~/.deet_history` without XDG_STATE_HOME, or
+/// `.deet_history` in the current directory without HOME either. None if there's no current
+/// directory to fall back on.
+fn default_history_path() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|dir| !dir.is_empty());
+    if let Some(dir) = var("XDG_STATE_HOME") {
+        return Some(PathBuf::from(dir).join("deet").join("history"));
+    }
+    // HOME is often unset under `env -i`, in systemd units and in minimal containers
+    let dir = var("HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok())?;
+    Some(dir.join(".deet_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Mutex};
+
+    /// The output and the event listeners are global, so the tests take turns
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// Runs `f` with what's printed going to a log rather than into the test's output.
+    fn quietly<T>(f: impl FnOnce() -> T) -> T {
+        let _lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let log = std::env::temp_dir().join(format!("deet-test-{}.log", std::process::id()));
+        output::start_log(&log, true, false).unwrap();
+        let result = f();
+        output::stop_log();
+        let _ = std::fs::remove_file(&log);
+        result
+    }
+
+    fn options() -> Options {
+        Options {
+            batch: true,
+            history_file: None,
+            args: Vec::new(),
+        }
+    }
+
+    /// Runs `lines` in a batch mode debugger of `program`, and returns the events they caused.
+    fn events(program: &str, lines: &[&str]) -> Vec<Event> {
+        quietly(|| {
+            let (sender, receiver) = mpsc::channel();
+            event::subscribe(move |event| {
+                let _ = sender.send(event.clone());
+            });
+            let mut debugger = Debugger::new(Some(program), options()).unwrap();
+            for line in lines {
+                debugger.execute_line(line);
+            }
+            if debugger.inferior().is_some() {
+                debugger.execute_line("kill");
+            }
+            receiver.try_iter().collect()
+        })
+    }
+
+    /// Loads `target` the way deet's command line does.
+    fn open(target: Option<&str>) -> Result<(), DeetError> {
+        quietly(|| Debugger::new(target, options()).map(|_| ()))
+    }
+
+    /// A copy of `program` in the temporary directory, named after `name`, with `change`
+    /// applied to its bytes.
+    fn copy(program: &str, name: &str, change: impl FnOnce(&mut Vec<u8>)) -> String {
+        let mut bytes = std::fs::read(program).unwrap();
+        change(&mut bytes);
+        let path = std::env::temp_dir().join(format!("deet-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// The stops and exits among `events`, e.g. "breakpoint 1 in func2" or "exited 0".
+    fn stops(events: &[Event]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Stopped {
+                    reason: StopReason::Breakpoint(number),
+                    location,
+                } => Some(format!(
+                    "breakpoint {} in {}",
+                    number,
+                    location.function.as_deref().unwrap_or("?")
+                )),
+                Event::Stopped { reason, location } => Some(format!(
+                    "{:?} in {}",
+                    reason,
+                    location.function.as_deref().unwrap_or("?")
+                )),
+                Event::Exited { status } => Some(format!("exited {}", status)),
+                Event::Signaled { signal, .. } => Some(format!("killed by {}", signal)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The functions of the frames of the backtraces among `events`, innermost first.
+    fn backtrace_functions(events: &[Event]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Backtrace { frames } => Some(frames),
+                _ => None,
+            })
+            .flatten()
+            .map(|frame| frame.function.clone().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn breakpoints_set_before_during_and_after_a_run_all_fire_on_the_next() {
+        let events = events(
+            "samples/function_calls",
+            &[
+                "break func1",
+                "run > /dev/null",
+                "break func3",
+                "continue",
+                "continue",
+                "continue",
+                "break func2",
+                "run > /dev/null",
+                "continue",
+                "continue",
+                "continue",
+                "continue",
+            ],
+        );
+        assert_eq!(
+            stops(&events),
+            [
+                "breakpoint 0 in func1",
+                "breakpoint 1 in func3",
+                "breakpoint 1 in func3",
+                "exited 0",
+                "breakpoint 0 in func1",
+                "breakpoint 2 in func2",
+                "breakpoint 1 in func3",
+                "breakpoint 1 in func3",
+                "exited 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn breakpoints_replaced_in_the_middle_of_a_run_fire_after_a_restart() {
+        let events = events(
+            "samples/function_calls",
+            &[
+                "break func2",
+                "run > /dev/null",
+                "delete",
+                "break func1",
+                "break func3",
+                "run > /dev/null",
+                "continue",
+                "continue",
+                "continue",
+            ],
+        );
+        assert_eq!(
+            stops(&events),
+            [
+                "breakpoint 0 in func2",
+                "breakpoint 0 in func1",
+                "breakpoint 1 in func3",
+                "breakpoint 1 in func3",
+                "exited 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn deep_backtraces_are_capped_and_quick() {
+        // samples/recursion crashes 100000 calls deep
+        let started = Instant::now();
+        let capped = events("samples/recursion", &["run > /dev/null", "backtrace"]);
+        let functions = backtrace_functions(&capped);
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            started.elapsed()
+        );
+        assert_eq!(functions.len(), BACKTRACE_LIMIT);
+        assert!(functions.iter().all(|function| function == "dive"));
+
+        let started = Instant::now();
+        let full = events(
+            "samples/recursion",
+            &["run > /dev/null", "backtrace -full-depth"],
+        );
+        let functions = backtrace_functions(&full);
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "{:?}",
+            started.elapsed()
+        );
+        assert_eq!(functions.len(), 100_002);
+        assert_eq!(functions.last().map(String::as_str), Some("main"));
+        assert!(functions[..100_001]
+            .iter()
+            .all(|function| function == "dive"));
+    }
+
+    #[test]
+    fn loading_reports_what_kept_the_program_from_loading() {
+        assert!(open(None).is_ok());
+        assert!(open(Some("samples/hello")).is_ok());
+        assert!(matches!(
+            open(Some("samples/nosuch")),
+            Err(DeetError::NotFound(path)) if path == "samples/nosuch"
+        ));
+        assert!(matches!(
+            open(Some("samples")),
+            Err(DeetError::Io(path, _)) if path == "samples"
+        ));
+        assert!(matches!(
+            open(Some("samples/hello.c")),
+            Err(DeetError::NotElf(path, _)) if path == "samples/hello.c"
+        ));
+
+        // hello with its compilation unit's header overwritten
+        let broken = copy("samples/hello", "broken-dwarf", |bytes| {
+            use object::{Object, ObjectSection};
+            let object = object::File::parse(&bytes[..]).unwrap();
+            let (start, size) = object
+                .section_by_name(".debug_info")
+                .and_then(|section| section.file_range())
+                .unwrap();
+            let start = start as usize;
+            bytes[start..start + size as usize].fill(0xff);
+        });
+        let result = open(Some(&broken));
+        let _ = std::fs::remove_file(&broken);
+        assert!(
+            matches!(&result, Err(DeetError::Dwarf { path, .. }) if *path == broken),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn the_programs_own_trap_is_a_signal_not_a_breakpoint() {
+        let unbroken = events("samples/int3", &["run > /dev/null"]);
+        assert_eq!(stops(&unbroken), ["Signal(SIGTRAP) in trap"]);
+
+        // on either side of the trap
+        let broken = events(
+            "samples/int3",
+            &[
+                "break trap",
+                "break after",
+                "run > /dev/null",
+                "continue",
+                "continue",
+                "continue",
+            ],
+        );
+        assert_eq!(
+            stops(&broken),
+            [
+                "breakpoint 0 in trap",
+                "Signal(SIGTRAP) in trap",
+                "breakpoint 1 in after",
+                "exited 0"
+            ]
+        );
     }
 }
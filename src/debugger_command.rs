@@ -1,30 +1,1380 @@
+use crate::expr::Expr;
+use crate::inferior::{OutputRedirect, Redirections};
+use crate::location::Location;
+use crate::printf::Printf;
+use crate::settings;
+use regex::RegexBuilder;
+use std::cell::RefCell;
+use std::time::Duration;
+
 pub enum DebuggerCommand {
     Quit,
-    Run(Vec<String>),
+    /// The arguments as typed, quotes and all; empty to use the last ones
+    Run(String),
     Continue,
-    Backtrace,
-    Breakpoint(String),
+    /// Steps this many source lines, into the functions called unless they're skipped
+    Step(usize),
+    /// Steps this many source lines, over the functions called
+    Next(usize),
+    /// Runs until the current function returns
+    Finish,
+    Kill,
+    /// Writes a line to the program's stdin pipe: the text as typed, with backslash escapes
+    Input(String),
+    /// Closes the program's stdin pipe
+    InputEof,
+    /// Prints the frames of the stack, all of them if true, else up to BACKTRACE_LIMIT
+    Backtrace(bool),
+    Breakpoint(Location),
+    /// Stops the program when it changes the value of a variable, by name
+    Watch(String),
+    /// Stops the program when it writes to the memory from an address, of a length
+    WatchRange(Expr, Expr),
+    /// A tracepoint at a location, collecting expressions: their text and what it parses to
+    Trace(Location, Vec<(String, Expr)>),
+    TraceDump,
+    /// Writes the trace records to a CSV file
+    TraceSave(String),
+    /// Clears what breakpoint-timing has measured
+    TimingReset,
+    /// Clears what count-instructions has counted
+    CountReset,
+    DeleteBreakpoints,
+    Set(Vec<String>),
+    Unset(Vec<String>),
+    Cd(String),
+    Pwd,
+    /// Runs a command line under /bin/sh, or an interactive shell given an empty one
+    Shell(String),
+    Gcore(Option<String>),
+    CoreFile(Option<String>),
+    /// Debugs a program under a GDB stub at HOST:PORT
+    TargetRemote(String),
+    /// Debugs another program, or none
+    File(Option<String>),
+    Show(Vec<String>),
+    Ptype(String, bool),
+    Print(Expr),
+    MaintInfoBrokenCus,
+    InfoBreakpoints,
+    InfoInferiors,
+    InfoSharedLibrary,
+    InfoSource,
+    /// Lists the source files, those matching a regular expression if given
+    InfoSources(Option<String>),
+    Inferior(usize),
+    Help(Option<String>),
+    /// Searches the commands and settings for a regular expression
+    Apropos(String),
+    /// Defines an alias for some command words, or lists the aliases given None
+    Alias(Option<(String, Vec<String>)>),
+    Unalias(String),
+    Define(String),
+    Source(String),
+    /// Runs a file in the scripting language
+    Script(String),
+    /// Runs a line of the scripting language
+    Eval(String),
+    /// Prints text with backslash escapes, as typed
+    Echo(String),
+    /// Prints the values of expressions by a C format; the arguments as typed
+    Printf(String),
+    Skip(SkipCommand),
+    InfoSkip,
+    /// Dumps this many words of the stack from the stack pointer up, each annotated
+    InfoStackRaw(usize),
+    InfoSignals,
+    /// Shows the registers of these names, or all the general ones given none; the floating
+    /// point and vector ones too if true
+    InfoRegisters(Vec<String>, bool),
 }
 
-impl DebuggerCommand {
-    pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
-        match tokens[0] {
-            "q" | "quit" => Some(DebuggerCommand::Quit),
-            "r" | "run" => {
-                let args = tokens[1..].to_vec();
-                Some(DebuggerCommand::Run(
-                    args.iter().map(|s| s.to_string()).collect(),
-                ))
+/// What `skip` does to the skip list.
+pub enum SkipCommand {
+    /// Skips a function by name or regular expression, or the current one given None
+    Function(Option<String>),
+    /// Skips the source files matching a glob, or the current one given None
+    File(Option<String>),
+    /// Enables, disables or deletes the entries of these numbers, or all of them given none
+    Enable(Vec<usize>),
+    Disable(Vec<usize>),
+    Delete(Vec<usize>),
+}
+
+/// A command deet understands: how to parse it and how to document it. `from_tokens` and `help`
+/// both work off COMMANDS, so every command that can be run is also documented.
+struct CommandSpec {
+    /// The command's name followed by its aliases
+    names: &'static [&'static str],
+    /// One line for the `help` listing
+    summary: &'static str,
+    /// Argument forms and examples, for `help <command>`
+    usage: &'static str,
+    /// What the arguments are, for tab completion
+    arguments: Arguments,
+    /// Parses the arguments, given the name the command was invoked by. None means they don't
+    /// fit the usage, or that something in them is wrong, as said by `invalid`.
+    parse: fn(&str, &[&str]) -> Option<DebuggerCommand>,
+    /// Whether an empty line repeats the command, as for `continue`. Commands with lasting side
+    /// effects, like `run` or `quit`, shouldn't repeat by accident.
+    repeat: bool,
+}
+
+/// What a command's arguments are, for tab completion.
+enum Arguments {
+    /// Nothing that can be completed
+    None,
+    /// One of these phrases, whose words are completed one at a time
+    Words(&'static [&'static str]),
+    /// Settings of the registry in `settings`, after these other subcommands. With `values`,
+    /// the values of settings with a fixed set of them are completed too.
+    Settings {
+        extra: &'static [&'static str],
+        values: bool,
+    },
+    /// File names
+    Files,
+    /// Command names, for `help`
+    Commands,
+    /// Function names, or source file names for FILE:LINE
+    Locations,
+    /// Names of global variables
+    Variables,
+}
+
+/// How to complete an argument word, as worked out by `complete_argument`.
+pub enum ArgumentCompletion {
+    Words(Vec<&'static str>),
+    Files,
+    Locations,
+    Variables,
+}
+
+/// Splits `text` at the commas between expressions, leaving those in brackets and character
+/// literals alone.
+pub fn split_expressions(text: &str) -> Vec<&str> {
+    let mut exprs = Vec::new();
+    let (mut depth, mut start, mut in_char) = (0, 0, false);
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_char => {
+                chars.next();
+            }
+            '\'' => in_char = !in_char,
+            _ if in_char => {}
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                exprs.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    exprs.push(text[start..].trim());
+    exprs
+}
+
+/// The COUNT of `step` and `next`: 1 if not given.
+fn step_count(args: &[&str]) -> Option<usize> {
+    match args {
+        [] => Some(1),
+        [count] => count.parse().ok().filter(|&count| count > 0),
+        _ => None,
+    }
+}
+
+/// The words of a command line, owned, for the commands that keep them as typed.
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        names: &["run", "r"],
+        summary: "Start the program being debugged, killing the current one",
+        usage: "run [--timeout SECONDS] [ARGS...] [< IN] [> OUT | >> OUT] [2> ERR | 2>> ERR | 2>&1]
+
+Starts the program with the given arguments and shell-style redirections of its standard
+streams, and runs it until it stops or exits. Arguments are quoted as in a shell, with '', \"\"
+and backslashes. Without arguments, the ones of the last run are used again; see \"show args\".
+With --timeout, a program still running after SECONDS is stopped there, as \"set run-timeout\"
+does for every run and continue, but only this once.
+
+Examples:
+  run
+  run input.txt --verbose
+  run --name \"hello world\" 'it'\\''s'
+  run < input.txt > output.txt 2>&1
+  run --timeout 10",
+        arguments: Arguments::Files,
+        parse: |_, args| {
+            let line = args.join(" ");
+            match split_run_timeout(&line).and_then(|(_, rest)| parse_run_args(rest)) {
+                Ok(_) => Some(DebuggerCommand::Run(line)),
+                Err(err) => invalid(err),
+            }
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["continue", "c", "cont"],
+        summary: "Continue the stopped program",
+        usage: "continue
+
+Resumes the program until it hits a breakpoint, gets a signal or exits.",
+        arguments: Arguments::None,
+        parse: |_, args| args.is_empty().then_some(DebuggerCommand::Continue),
+        repeat: true,
+    },
+    CommandSpec {
+        names: &["step", "s"],
+        summary: "Step to the next source line, into calls",
+        usage: "step [COUNT]
+
+Runs the program until it gets to the start of another source line, COUNT times (once by
+default). A function called on the way is stepped into, stopping at the start of its body,
+unless it has no line information or is on the skip list (see \"skip\"); then the whole call
+is run, as \"next\" does. Returning from the function steps on to the next line of its caller.
+A breakpoint, watchpoint or signal on the way stops it there.",
+        arguments: Arguments::None,
+        parse: |_, args| step_count(args).map(DebuggerCommand::Step),
+        repeat: true,
+    },
+    CommandSpec {
+        names: &["next", "n"],
+        summary: "Step to the next source line, over calls",
+        usage: "next [COUNT]
+
+Runs the program until it gets to the start of another source line in the same function, or
+its caller once it returns, COUNT times (once by default). Functions called on the way are run
+until they return. A breakpoint, watchpoint or signal on the way stops it there.",
+        arguments: Arguments::None,
+        parse: |_, args| step_count(args).map(DebuggerCommand::Next),
+        repeat: true,
+    },
+    CommandSpec {
+        names: &["finish", "fin"],
+        summary: "Run until the current function returns",
+        usage: "finish
+
+Runs the program until the function it's stopped in returns to its caller, or something else
+stops it first. Recursive calls of the function returning to the same place don't count.",
+        arguments: Arguments::None,
+        parse: |_, args| args.is_empty().then_some(DebuggerCommand::Finish),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["kill", "k"],
+        summary: "Kill the program being debugged",
+        usage: "kill
+
+Kills the current program, after asking; see \"set confirm\". Its breakpoints stay set for the
+next run.",
+        arguments: Arguments::None,
+        parse: |_, args| args.is_empty().then_some(DebuggerCommand::Kill),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["input"],
+        summary: "Write to the program's standard input",
+        usage: "input [TEXT | --eof]
+
+Writes TEXT and a newline to the program's stdin, for it to read when it next runs. TEXT is
+taken as typed, with the backslash escapes of C strings such as \\t replaced, as by \"echo\".
+\"input --eof\" closes the pipe instead: the program reads end-of-file once it has read what
+was written before, and nothing can be written after that.
+
+This needs the program's stdin to be a pipe from deet, which \"set new-console-input pipe\"
+gives the next run unless the run redirects stdin with <.",
+        arguments: Arguments::None,
+        parse: |_, args| match args {
+            ["--eof"] => Some(DebuggerCommand::InputEof),
+            _ => Some(DebuggerCommand::Input(args.join(" "))),
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["backtrace", "bt", "back"],
+        summary: "Print the call stack of the stopped program or core file",
+        usage: "backtrace [-full-depth]
+
+Prints a line per frame, innermost first, with the function and source location. Only the
+innermost 1000 frames are shown unless -full-depth is given; ctrl+c stops a long backtrace.",
+        arguments: Arguments::None,
+        parse: |_, args| match args {
+            [] => Some(DebuggerCommand::Backtrace(false)),
+            ["-full-depth"] => Some(DebuggerCommand::Backtrace(true)),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["break", "b", "breakpoint"],
+        summary: "Set a breakpoint at an address, line or function",
+        usage: "break *ADDRESS | [FILE:]LINE | FUNCTION | FUNCTION+OFFSET
+
+Sets a breakpoint, in the running program as well as in the ones started later. A line without
+a file is in the first file of the program.
+
+Examples:
+  break *0x401136    at an address (0d4198710 in decimal; bare digits are read in
+                     the radix of \"set address-radix\", hex by default)
+  break 12           at a source line
+  break structs.c:12 at a line of a given file
+  break main         at the start of a function (after its prologue)
+  break *main+0x1a   at an offset from a symbol, as objdump shows it; the same
+                     as break main+0x1a",
+        arguments: Arguments::Locations,
+        parse: |_, args| match args {
+            [] => None,
+            _ => match Location::parse(&args.join(" ")) {
+                Ok(location) => Some(DebuggerCommand::Breakpoint(location)),
+                Err(err) => invalid(err),
+            },
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["trace", "tp"],
+        summary: "Collect values at a location without stopping there",
+        usage: "trace LOCATION collect EXPRESSION[, EXPRESSION...]
+
+Sets a tracepoint: a breakpoint that, whenever the program gets to it, evaluates the
+expressions there and adds what they come to to the trace records, with the time since the
+program started and how many times it has got there, and then lets the program go on without
+a word. LOCATION is as for \"break\"; a breakpoint already there becomes the tracepoint. The
+records of the last run are shown by \"tdump\" and saved by \"tsave\".
+
+Examples:
+  trace 42 collect i, sum
+  trace hash.c:88 collect $rdi, bucket->count",
+        arguments: Arguments::Locations,
+        parse: |_, args| {
+            let at = args.iter().position(|&arg| arg == "collect")?;
+            let location = match Location::parse(&args[..at].join(" ")) {
+                Ok(location) => location,
+                Err(err) => {
+                    return invalid(err);
+                }
+            };
+            let mut exprs = Vec::new();
+            for text in split_expressions(&args[at + 1..].join(" ")) {
+                match Expr::parse(text) {
+                    Ok(expr) => exprs.push((text.to_string(), expr)),
+                    Err(err) => {
+                        return invalid(err);
+                    }
+                }
+            }
+            Some(DebuggerCommand::Trace(location, exprs))
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["tdump"],
+        summary: "Show what the tracepoints collected",
+        usage: "tdump
+
+Lists the trace records of the last run in the order they were collected: which tracepoint,
+which hit of it, when, and the values.",
+        arguments: Arguments::None,
+        parse: |_, args| args.is_empty().then_some(DebuggerCommand::TraceDump),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["tsave"],
+        summary: "Save what the tracepoints collected as CSV",
+        usage: "tsave FILE
+
+Writes the trace records of the last run to FILE, one row each: record, tracepoint, hit, time
+in seconds, and a column for every expression collected, empty where the record's tracepoint
+doesn't collect it.
+
+Example:
+  tsave loop.csv",
+        arguments: Arguments::Files,
+        parse: |_, args| match args {
+            [path] => Some(DebuggerCommand::TraceSave(path.to_string())),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["timing"],
+        summary: "Clear the breakpoint timings",
+        usage: "timing reset
+
+With \"set breakpoint-timing on\", each time the program stops at a breakpoint, the time it
+ran since its last breakpoint stop is added to that breakpoint's, not counting the time it sat
+stopped. \"info breakpoints\" shows each breakpoint's total and its mean per hit, so with two
+breakpoints the one after the other shows the time between them. \"timing reset\" sets them
+all back to zero.",
+        arguments: Arguments::Words(&["reset"]),
+        parse: |_, args| match args {
+            ["reset"] => Some(DebuggerCommand::TimingReset),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["count"],
+        summary: "Clear the instruction and line step counts",
+        usage: "count reset
+
+With \"set count-instructions on\", deet counts the instructions the program runs, and each
+time it stops, says how many it has run since it started. It also counts the source lines
+\"step\" and \"next\" step, each of their COUNT, and says how many once there are any.
+\"info breakpoints\" shows for each breakpoint how many instructions it ran from its previous
+breakpoint stop to getting there, the last time it did. \"count reset\" sets them all back
+to zero.
+
+The count comes from a hardware counter if perf provides one. Otherwise deet single-steps the
+program to count its instructions, which makes it run many times slower, and Ctrl-C or
+run-timeout are the way to stop it early. trace-syscalls sees no syscalls while it does.",
+        arguments: Arguments::Words(&["reset"]),
+        parse: |_, args| match args {
+            ["reset"] => Some(DebuggerCommand::CountReset),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["watch"],
+        summary: "Stop when the program changes a variable",
+        usage: "watch VARIABLE
+
+Stops the program right after an instruction of it changes the value of VARIABLE, and shows the
+value before and after. A write of the same value doesn't stop it. The variable is looked up as
+by \"print\": a local or parameter of the function stopped in, or else a global. A debug
+register of the processor watches it, so the program runs at full speed, but there are only 4
+of them, and a variable of more than 8 bytes needs \"watch-range\" instead.
+
+A global is watched in every run from now on. A local is watched in the frame it's in: when
+the frame returns, the program stops and the watchpoint is deleted.",
+        arguments: Arguments::Variables,
+        parse: |_, args| match args {
+            [name] => Some(DebuggerCommand::Watch(name.to_string())),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["watch-range"],
+        summary: "Stop when the program writes to a range of memory",
+        usage: "watch-range ADDRESS LENGTH
+
+Stops the program right after an instruction of it writes to any of the LENGTH bytes from
+ADDRESS, and shows the bytes it wrote to before and after. Both are expressions; the last
+word is the length. The range can be any size: the pages it's on are made read-only, and writes
+to them outside the range are let through one instruction at a time, which slows down a
+program writing to them a lot. Writes by the kernel, e.g. read(2) into a watched buffer, don't
+fault and aren't seen; the syscall fails with EFAULT instead.
+
+Examples:
+  watch-range &buffer 4096
+  watch-range &grid[2] 8*64
+  watch-range 0x4c0000 0x100000",
+        arguments: Arguments::Variables,
+        parse: |_, args| match args {
+            [address @ .., length] if !address.is_empty() => {
+                match (Expr::parse(&address.join(" ")), Expr::parse(length)) {
+                    (Ok(address), Ok(length)) => Some(DebuggerCommand::WatchRange(address, length)),
+                    (Err(err), _) | (_, Err(err)) => invalid(err),
+                }
             }
-            "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
-            "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
-            
-            "b" | "break" | "breakpoint" => {
-                Some(DebuggerCommand::Breakpoint(tokens[1].to_string()))
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["skip"],
+        summary: "Have stepping go over functions or files",
+        usage: "skip [function [NAME | REGEX]]
+skip file [GLOB]
+skip enable|disable|delete [NUM...]
+
+Adds an entry to the skip list: stepping into a call to a function it matches, or to one in a
+file it matches, steps over the call instead, as \"next\" does. A function is matched by its
+demangled name, so C++ names are matched as they're printed: NAME is the name with or without
+its parameter list, and anything with the special characters of a regular expression (see
+\"apropos\") is one, matching anywhere in the name. GLOB is matched against the file's base
+name, or against its path, or the end of the path after a /, if it has a / itself. Without a
+name or glob, the function or file the program is stopped in is skipped. Entries are numbered
+from 1; \"info skip\" lists them, and enable, disable and delete act on those numbered, or
+on them all.
+
+Examples:
+  skip function log_debug
+  skip function ^std::
+  skip file *.h
+  skip file include/c++/*/bits/*
+  skip disable 2",
+        arguments: Arguments::Words(&["function", "file", "enable", "disable", "delete"]),
+        parse: |_, args| {
+            let numbers = |args: &[&str]| -> Option<Vec<usize>> {
+                args.iter().map(|arg| arg.parse().ok()).collect()
+            };
+            match args {
+                [] | ["function"] => Some(SkipCommand::Function(None)),
+                ["function", name @ ..] => Some(SkipCommand::Function(Some(name.join(" ")))),
+                ["file"] => Some(SkipCommand::File(None)),
+                ["file", glob] => Some(SkipCommand::File(Some(glob.to_string()))),
+                ["enable", rest @ ..] => numbers(rest).map(SkipCommand::Enable),
+                ["disable", rest @ ..] => numbers(rest).map(SkipCommand::Disable),
+                ["delete", rest @ ..] => numbers(rest).map(SkipCommand::Delete),
+                _ => None,
+            }
+            .map(DebuggerCommand::Skip)
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["delete", "d"],
+        summary: "Delete all breakpoints",
+        usage: "delete
+
+Removes every breakpoint and range watchpoint from the running programs and the list, after
+asking; see \"set confirm\".",
+        arguments: Arguments::None,
+        parse: |_, args| {
+            args.is_empty()
+                .then_some(DebuggerCommand::DeleteBreakpoints)
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["print", "p", "inspect"],
+        summary: "Print the value of an expression",
+        usage: "print EXPRESSION
+
+Evaluates a C expression in the frame the program is stopped in, or the core file, and prints
+its value. Variables, registers like $rip, integer and character literals, arithmetic,
+comparisons, logical operators, casts, *, &, [], . and -> are understood; assignment and calls
+aren't. Without a process or core file there's no memory to read, only literals.
+
+Examples:
+  print count * 2 + 1
+  print *node->next
+  print argv[1]
+  print &buffer[8]
+  print $rsp",
+        arguments: Arguments::Variables,
+        parse: |_, args| match Expr::parse(&args.join(" ")) {
+            Ok(expr) => Some(DebuggerCommand::Print(expr)),
+            Err(err) => invalid(err),
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["ptype", "ptype/o"],
+        summary: "Print the definition of a type",
+        usage: "ptype TYPE
+ptype/o TYPE
+
+Prints the declaration of a struct, union, enum, typedef or base type. ptype/o also shows the
+offset and size of every member, and the holes between them.
+
+Examples:
+  ptype struct point
+  ptype/o struct node",
+        arguments: Arguments::Variables,
+        parse: |name, args| {
+            (!args.is_empty()).then(|| DebuggerCommand::Ptype(args.join(" "), name.ends_with("/o")))
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["info"],
+        summary: "Show information about the debugging session",
+        usage: "info all-registers
+info breakpoints
+info inferiors
+info registers [NAME...]
+info sharedlibrary
+info signals
+info skip
+info source
+info sources [REGEX]
+info stack-raw [NWORDS]
+
+\"info all-registers\" shows the floating point and vector registers after the general ones: on
+x86_64 the x87 stack st0-st7 with its control and status words decoded (fctrl, fstat), the SSE
+registers xmm0-xmm15 with mxcsr, and the AVX registers ymm0-ymm15 where the CPU has them; on
+aarch64 v0-v31 with fpsr and fpcr. Each vector register is shown in hex and as doubles and
+floats. A register set the kernel or CPU doesn't have is noted in place of its registers.
+\"info breakpoints\" lists the breakpoints, with where each is and whether it's enabled. One the
+current program couldn't have written into its code is marked as not installed, with why.
+\"info inferiors\" lists the processes being debugged, marking the current one with *.
+\"info registers\" shows the general registers of the thread that stopped, in hex and as a
+number, the program counter as a symbol and the flags by name. With NAMEs (with or without a $),
+just those registers, which can be any \"info all-registers\" shows, e.g. \"info registers xmm0\".
+\"info sharedlibrary\" lists the shared libraries the program has loaded, with the address range
+of each, or says it's statically linked and has none.
+\"info signals\" lists the signals with what deet does when the program gets one: whether it
+stops, prints a message, and passes the signal on when the program continues. For a running
+program it also shows which signals the program blocks, ignores, catches with a handler, and
+has pending. The real-time signals share a line unless \"set verbose\" is on.
+\"info skip\" lists the functions and files \"skip\" has stepping go over, with whether each
+entry is enabled.
+\"info source\" describes the source file the program is stopped in: where it is, and what the
+debug info says of its compilation unit, i.e. the compiler and flags, the DWARF version and
+whether it has macro info.
+\"info sources\" lists every source file the debug info names, headers included, or those
+whose path matches REGEX.
+\"info stack-raw\" dumps the top NWORDS words of the stack (32 by default), from the stack
+pointer up, for when the backtrace is broken. Each word that points into code is shown as a
+symbol, one that points into the stack as a likely saved frame pointer, and any other with its
+bytes as ASCII.",
+        arguments: Arguments::Words(&[
+            "all-registers",
+            "breakpoints",
+            "inferiors",
+            "registers",
+            "sharedlibrary",
+            "signals",
+            "skip",
+            "source",
+            "sources",
+            "stack-raw",
+        ]),
+        parse: |_, args| match args {
+            ["all-registers"] => Some(DebuggerCommand::InfoRegisters(Vec::new(), true)),
+            ["breakpoints" | "b"] => Some(DebuggerCommand::InfoBreakpoints),
+            ["inferiors"] => Some(DebuggerCommand::InfoInferiors),
+            ["registers" | "r", names @ ..] => {
+                Some(DebuggerCommand::InfoRegisters(strings(names), false))
             }
+            ["sharedlibrary"] => Some(DebuggerCommand::InfoSharedLibrary),
+            ["signals"] => Some(DebuggerCommand::InfoSignals),
+            ["skip"] => Some(DebuggerCommand::InfoSkip),
+            ["source"] => Some(DebuggerCommand::InfoSource),
+            ["sources"] => Some(DebuggerCommand::InfoSources(None)),
+            ["sources", regex @ ..] => Some(DebuggerCommand::InfoSources(Some(regex.join(" ")))),
+            ["stack-raw"] => Some(DebuggerCommand::InfoStackRaw(32)),
+            ["stack-raw", words] => words.parse().ok().map(DebuggerCommand::InfoStackRaw),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["inferior"],
+        summary: "Switch to another inferior",
+        usage: "inferior NUM
+
+Makes inferior NUM (see \"info inferiors\") the current one.",
+        arguments: Arguments::None,
+        parse: |_, args| match args {
+            [num] => num.parse().ok().map(DebuggerCommand::Inferior),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["set"],
+        summary: "Change a setting",
+        usage: "set SETTING VALUE
+
+Besides the settings below, the program's environment and resource limits are changed with:
+  set environment VAR=value             set a variable in the program's environment
+  set rlimit RESOURCE SOFT [HARD]       limit a resource of the program (or \"unlimited\")
+Leaving out the limits of an rlimit goes back to inheriting deet's.",
+        arguments: Arguments::Settings {
+            extra: &["environment", "rlimit"],
+            values: true,
+        },
+        parse: |_, args| (!args.is_empty()).then(|| DebuggerCommand::Set(strings(args))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["unset"],
+        summary: "Remove a variable from the program's environment",
+        usage: "unset environment VAR",
+        arguments: Arguments::Words(&["environment"]),
+        parse: |_, args| (!args.is_empty()).then(|| DebuggerCommand::Unset(strings(args))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["show"],
+        summary: "Show a setting",
+        usage: "show [SETTING]
+
+Shows a setting of \"set\", or all of them without one. Also:
+  show environment [VAR]  the program's environment
+  show cwd                the directory the program runs in
+  show rlimits            the program's resource limits
+  show user [NAME]        the definitions of user-defined commands
+
+\"show environment\" reads the environment of the running program from /proc, as the program
+was started with it. Without a running program, it shows the environment the next run will get.
+Newlines, other control characters and bytes that aren't UTF-8 are shown as escapes.",
+        arguments: Arguments::Settings {
+            extra: &["environment", "cwd", "rlimits", "user"],
+            values: false,
+        },
+        parse: |_, args| Some(DebuggerCommand::Show(strings(args))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["cd"],
+        summary: "Set the working directory of the program",
+        usage: "cd DIR
+
+Programs started from now on run in DIR. Relative paths in redirections are taken from it.",
+        arguments: Arguments::Files,
+        parse: |_, args| (!args.is_empty()).then(|| DebuggerCommand::Cd(args.join(" "))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["pwd"],
+        summary: "Print the working directory of the program",
+        usage: "pwd",
+        arguments: Arguments::None,
+        parse: |_, args| args.is_empty().then_some(DebuggerCommand::Pwd),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["shell"],
+        summary: "Run a shell command",
+        usage: "shell [COMMAND]
+!COMMAND
+
+Runs the rest of the line with /bin/sh -c, in the program's working directory, and says so if
+it fails. Without COMMAND, starts an interactive shell.
+
+Example:
+  shell grep -n count src/*.c",
+        arguments: Arguments::Files,
+        parse: |_, args| Some(DebuggerCommand::Shell(args.join(" "))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["core-file", "core"],
+        summary: "Examine a core file, or close it",
+        usage: "core-file [FILE]
+
+Loads FILE for a post-mortem look at a crashed process. Without FILE, closes the core file.",
+        arguments: Arguments::Files,
+        parse: |_, args| match args {
+            [] => Some(DebuggerCommand::CoreFile(None)),
+            [path] => Some(DebuggerCommand::CoreFile(Some(path.to_string()))),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["target"],
+        summary: "Debug a program running under a GDB stub",
+        usage: "target remote HOST:PORT
+
+Connects to a gdbserver, qemu's gdbstub or another stub speaking the GDB remote protocol over
+TCP, and debugs the program it has stopped. :PORT is a port on this machine. \"continue\",
+breakpoints, \"backtrace\" and \"print\" work as for a local program. \"quit\" kills it or, with
+\"set kill-on-quit off\", lets it go on.
+  target remote localhost:1234",
+        arguments: Arguments::Words(&["remote"]),
+        parse: |_, args| match args {
+            ["remote", address] => Some(DebuggerCommand::TargetRemote(address.to_string())),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["file"],
+        summary: "Use a program as the one to debug",
+        usage: "file [FILE]
+
+Loads the debug info of FILE, which \"run\" starts from then on. Processes of the old program are
+killed, after asking; breakpoints on functions and lines are looked up again in FILE, and ones
+on addresses are disabled. Without FILE, forgets the program.",
+        arguments: Arguments::Files,
+        parse: |_, args| match args {
+            [] => Some(DebuggerCommand::File(None)),
+            [path] => Some(DebuggerCommand::File(Some(path.to_string()))),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["gcore", "generate-core-file"],
+        summary: "Write a core file of the stopped program",
+        usage: "gcore [FILE]
+
+Writes the memory and registers of the stopped program to FILE, core.PID by default.",
+        arguments: Arguments::Files,
+        parse: |_, args| match args {
+            [] => Some(DebuggerCommand::Gcore(None)),
+            [path] => Some(DebuggerCommand::Gcore(Some(path.to_string()))),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["maintenance", "maint"],
+        summary: "Commands for debugging deet itself",
+        usage: "maintenance info broken-cus
+
+Lists the compilation units whose debug info couldn't be loaded, and why.",
+        arguments: Arguments::Words(&["info broken-cus"]),
+        parse: |_, args| match args {
+            ["info", "broken-cus"] => Some(DebuggerCommand::MaintInfoBrokenCus),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["alias"],
+        summary: "Define a short name for a command",
+        usage: "alias [NAME = COMMAND...]
+
+Makes NAME run COMMAND, followed by any arguments given to NAME. Aliases last for the session
+and can't shadow built-in commands. Without arguments, lists the aliases.
+
+Examples:
+  alias inf = info inferiors
+  alias rr = run --test-threads 1",
+        arguments: Arguments::None,
+        parse: |_, args| match args {
+            [] => Some(DebuggerCommand::Alias(None)),
+            [name, "=", words @ ..] if !words.is_empty() => Some(DebuggerCommand::Alias(Some((
+                name.to_string(),
+                strings(words),
+            )))),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["unalias"],
+        summary: "Remove an alias",
+        usage: "unalias NAME",
+        arguments: Arguments::None,
+        parse: |_, args| match args {
+            [name] => Some(DebuggerCommand::Unalias(name.to_string())),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["define"],
+        summary: "Define a new command from a sequence of commands",
+        usage: "define NAME
+
+Reads commands up to a line saying \"end\", which NAME then runs in order. In them, $arg0,
+$arg1, ... stand for the arguments NAME is given, and $argc for how many there are. The rest of
+the commands are skipped if one fails. \"show user NAME\" prints the definition.
+
+A command named hook-stop runs by itself each time the program stops, after the stop is shown.
 
-            // Default case:
+Example:
+  define both
+  break $arg0
+  break $arg1
+  end",
+        arguments: Arguments::None,
+        parse: |_, args| match args {
+            [name] => Some(DebuggerCommand::Define(name.to_string())),
             _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["source"],
+        summary: "Run the commands in a file",
+        usage: "source FILE
+
+Runs each line of FILE as a command, skipping blank lines and lines starting with #. An error
+stops the rest of the file from running. \"set verbose on\" prints each command first.",
+        arguments: Arguments::Files,
+        parse: |_, args| (!args.is_empty()).then(|| DebuggerCommand::Source(args.join(" "))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["script"],
+        summary: "Run a script",
+        usage: "script FILE
+
+Runs FILE in deet's scripting language, rhai, for what the commands can't express. It has
+integers, booleans, strings, arrays and maps, and a syntax after Rust's:
+  let hits = 0;
+  if x > 0 && name != \"\" { ... } else if ... { ... } else { ... }
+  while cont() { ... }    for frame in frames() { ... }
+  fn name(a, b) { a + b }    let count = || hits += 1;    // comment
+Functions see only their arguments; closures see the variables around them. Variables and
+functions a script defines stay around for later scripts and \"eval\". Errors stop the
+script, saying FILE and the line.
+
+The built-in functions, which work like the commands do:
+  command(LINE)              run a command
+  set_breakpoint(LOCATION)   break at a location
+  cont()                     continue the program; false once it has exited
+  alive()                    whether there is a program
+  reg(NAME)                  the value of a register, e.g. reg(\"rip\")
+  read_mem(ADDRESS, LENGTH)  an array of the bytes at an address
+  frames()                   the backtrace, a string per frame
+  print_var(EXPRESSION)      a value as \"print\" shows it
+  value(EXPRESSION)          the value of an integer expression
+  on_stop(FUNCTION)          call a function or closure whenever the program stops
+  print(VALUE)               print a value, e.g. print(`x is ${x}`)
+  write_file(PATH, TEXT)     write a file, replacing it
+
+Example:
+  set_breakpoint(\"func2\");
+  command(\"run\");
+  let calls = [];
+  while alive() { calls.push(value(\"a\")); cont(); }
+  print(`func2 was called with ${calls}`);",
+        arguments: Arguments::Files,
+        parse: |_, args| (!args.is_empty()).then(|| DebuggerCommand::Script(args.join(" "))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["eval"],
+        summary: "Run a line of script",
+        usage: "eval CODE
+
+Runs CODE in the scripting language of \"script\", and prints its value if it's an expression
+that has one.
+
+Example:
+  eval reg(\"rsp\") % 16
+  eval for f in frames() { print(f) }",
+        arguments: Arguments::None,
+        parse: |_, args| (!args.is_empty()).then(|| DebuggerCommand::Eval(args.join(" "))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["echo"],
+        summary: "Print text",
+        usage: "echo TEXT
+
+Prints TEXT as it is typed, with the backslash escapes of C strings such as \\n and \\t replaced.
+Nothing else ends the line, so a line of output ends with \\n. Meant for scripts, user-defined
+commands and hooks; see also \"printf\".
+
+Example:
+  echo --- at the checkpoint ---\\n",
+        arguments: Arguments::None,
+        parse: |_, args| Some(DebuggerCommand::Echo(args.join(" "))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["printf"],
+        summary: "Print values by a C format",
+        usage: "printf \"FORMAT\", EXPRESSION...
+
+Prints the values of the expressions, evaluated as by \"print\", as C's printf would with FORMAT.
+Conversions are %d, %i, %u, %x, %X, %o, %c, %s (a string read out of the program), %p and %%,
+with flags, widths and precisions; without an l length modifier, integers are taken as ints.
+There must be as many expressions as conversions. As with \"echo\", only \\n ends a line.
+
+Examples:
+  printf \"i = %d, name = %s\\n\", i, node->name
+  printf \"%-10s %#x\\n\", argv[0], $rsp",
+        arguments: Arguments::Variables,
+        parse: |_, args| match Printf::parse(&args.join(" ")) {
+            Ok(_) => Some(DebuggerCommand::Printf(args.join(" "))),
+            Err(err) => invalid(err),
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["help", "h"],
+        summary: "List the commands, or describe one",
+        usage: "help [COMMAND]",
+        arguments: Arguments::Commands,
+        parse: |_, args| match args {
+            [] => Some(DebuggerCommand::Help(None)),
+            [command] => Some(DebuggerCommand::Help(Some(command.to_string()))),
+            _ => None,
+        },
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["apropos"],
+        summary: "Search the commands and settings for a regular expression",
+        usage: "apropos REGEX
+
+Lists the commands whose names or documentation match REGEX, ignoring case, and the settings
+whose names or descriptions do. REGEX has the syntax of Rust's regex crate.
+
+Example:
+  apropos break",
+        arguments: Arguments::None,
+        parse: |_, args| (!args.is_empty()).then(|| DebuggerCommand::Apropos(args.join(" "))),
+        repeat: false,
+    },
+    CommandSpec {
+        names: &["quit", "q"],
+        summary: "Exit deet",
+        usage: "quit
+
+Asks first if there are live inferiors (see \"set confirm\"); see \"set kill-on-quit\" for
+what happens to them.",
+        arguments: Arguments::None,
+        parse: |_, args| args.is_empty().then_some(DebuggerCommand::Quit),
+        repeat: false,
+    },
+];
+
+/// Looks up a command by name, alias or unambiguous prefix of either. An exact match wins over
+/// prefixes, so `c` is continue even though cd and core-file start with it too.
+fn find_command(name: &str) -> Result<&'static CommandSpec, String> {
+    if let Some(spec) = COMMANDS.iter().find(|spec| spec.names.contains(&name)) {
+        return Ok(spec);
+    }
+    let candidates: Vec<&CommandSpec> = COMMANDS
+        .iter()
+        .filter(|spec| spec.names.iter().any(|known| known.starts_with(name)))
+        .collect();
+    match candidates.as_slice() {
+        [spec] => Ok(spec),
+        [] => Err(undefined_command(name, [])),
+        _ => {
+            let names: Vec<&str> = candidates.iter().map(|spec| spec.names[0]).collect();
+            Err(format!(
+                "Ambiguous command \"{}\": {}.",
+                name,
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+thread_local! {
+    /// What's wrong with the arguments of the command being parsed, when that's more to the point
+    /// than its usage, e.g. the syntax error in an expression
+    static PARSE_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Fails the parse of a command with `message`, shown in place of its usage.
+fn invalid(message: String) -> Option<DebuggerCommand> {
+    PARSE_ERROR.set(Some(message));
+    None
+}
+
+impl DebuggerCommand {
+    /// Parses a command line, where the command may be abbreviated. On failure, returns the
+    /// message to show: the usage of a command given the wrong arguments, the candidates for an
+    /// ambiguous abbreviation, or a hint to try `help` for an unknown command.
+    pub fn from_tokens(tokens: &[&str]) -> Result<DebuggerCommand, String> {
+        let spec = find_command(tokens[0])?;
+        PARSE_ERROR.set(None);
+        let command = (spec.parse)(tokens[0], &tokens[1..]);
+        if let Some(message) = PARSE_ERROR.take() {
+            return Err(message);
+        }
+        command.ok_or_else(|| match spec.arguments {
+            // a subcommand that doesn't exist, rather than the wrong arguments to one
+            Arguments::Words(phrases)
+                if tokens.len() > 1
+                    && !phrases
+                        .iter()
+                        .any(|phrase| phrase.split(' ').next() == Some(tokens[1])) =>
+            {
+                let subcommands = phrases.iter().filter_map(|phrase| phrase.split(' ').next());
+                let hint = settings::did_you_mean(&settings::suggestions(tokens[1], subcommands))
+                    .unwrap_or_else(|| format!("Try \"help {}\".", spec.names[0]));
+                format!(
+                    "Undefined {} command: \"{}\". {}",
+                    spec.names[0], tokens[1], hint
+                )
+            }
+            _ => format!("Usage: {}", spec.usage.lines().next().unwrap_or("")),
+        })
+    }
+}
+
+/// Whether `name` names a built-in command or abbreviates one or more of them.
+pub fn is_known_prefix(name: &str) -> bool {
+    COMMANDS
+        .iter()
+        .any(|spec| spec.names.iter().any(|known| known.starts_with(name)))
+}
+
+/// The error for a command that doesn't exist, suggesting the built-in commands and the
+/// `others` (user aliases and commands) it could be a misspelling of.
+pub fn undefined_command<'a>(name: &str, others: impl IntoIterator<Item = &'a str>) -> String {
+    let names = COMMANDS
+        .iter()
+        .flat_map(|spec| spec.names.iter().copied())
+        .chain(others);
+    match settings::did_you_mean(&settings::suggestions(name, names)) {
+        Some(hint) => format!("Undefined command: \"{}\". {}", name, hint),
+        None => format!("Undefined command: \"{}\". Try \"help\".", name),
+    }
+}
+
+/// Whether `name` is a built-in command or one of its aliases, which user aliases can't shadow.
+pub fn is_command_name(name: &str) -> bool {
+    COMMANDS.iter().any(|spec| spec.names.contains(&name))
+}
+
+/// Whether an empty line after `tokens` should run them again.
+pub fn is_repeatable(tokens: &[&str]) -> bool {
+    find_command(tokens[0]).is_ok_and(|spec| spec.repeat)
+}
+
+/// The command names starting with `partial`, for completing the first word of a line.
+pub fn complete_command(partial: &str) -> Vec<&'static str> {
+    COMMANDS
+        .iter()
+        .map(|spec| spec.names[0])
+        .filter(|name| name.starts_with(partial))
+        .collect()
+}
+
+/// How to complete the argument `partial` of `command`, following the arguments `args` before
+/// it. None if there's nothing to offer.
+pub fn complete_argument(
+    command: &str,
+    args: &[&str],
+    partial: &str,
+) -> Option<ArgumentCompletion> {
+    match find_command(command).ok()?.arguments {
+        Arguments::None => None,
+        Arguments::Files => Some(ArgumentCompletion::Files),
+        Arguments::Locations if args.is_empty() => Some(ArgumentCompletion::Locations),
+        Arguments::Variables => Some(ArgumentCompletion::Variables),
+        Arguments::Locations => None,
+        Arguments::Commands if args.is_empty() => {
+            Some(ArgumentCompletion::Words(complete_command(partial)))
+        }
+        Arguments::Commands => None,
+        Arguments::Words(phrases) => {
+            let phrases = phrases.iter().map(|phrase| phrase.split(' ').collect());
+            Some(complete_phrase(phrases, args, partial))
+        }
+        Arguments::Settings { extra, values } => {
+            let phrases = extra
+                .iter()
+                .map(|word| vec![*word])
+                .chain(settings::phrases(values));
+            Some(complete_phrase(phrases, args, partial))
+        }
+    }
+}
+
+/// The words that can come after `args` in `phrases`, starting with `partial`.
+fn complete_phrase(
+    phrases: impl Iterator<Item = Vec<&'static str>>,
+    args: &[&str],
+    partial: &str,
+) -> ArgumentCompletion {
+    let mut words: Vec<&'static str> = phrases
+        .filter_map(|phrase| {
+            // the words typed so far have to match the phrase exactly
+            if phrase.len() <= args.len() || phrase[..args.len()] != *args {
+                return None;
+            }
+            Some(phrase[args.len()]).filter(|word| word.starts_with(partial))
+        })
+        .collect();
+    words.dedup();
+    ArgumentCompletion::Words(words)
+}
+
+/// `help` lists the commands; `help COMMAND` describes one.
+pub fn print_help(topic: Option<&str>) {
+    match topic {
+        None => {
+            outln!("List of commands:\n");
+            for spec in COMMANDS {
+                outln!("{} -- {}", spec.names.join(", "), spec.summary);
+            }
+            outln!("\nType \"help\" followed by a command name for its full documentation.");
+            outln!("Commands may be abbreviated to any unambiguous prefix.");
+            outln!("A line ending in a backslash goes on to the next line.");
+        }
+        Some(name) => match find_command(name) {
+            Ok(spec) => {
+                outln!("{}", spec.summary);
+                outln!("Usage: {}", spec.usage);
+                if let Arguments::Settings { values: true, .. } = spec.arguments {
+                    settings::print_help();
+                }
+                if spec.names.len() > 1 {
+                    outln!("Aliases: {}", spec.names[1..].join(", "));
+                }
+                if spec.repeat {
+                    outln!("An empty line repeats this command.");
+                }
+            }
+            Err(message) => outln!("{}", message),
+        },
+    }
+}
+
+pub fn apropos(regex: &str) {
+    let pattern = match RegexBuilder::new(regex).case_insensitive(true).build() {
+        Ok(pattern) => pattern,
+        Err(message) => {
+            outln!("Invalid regular expression \"{}\": {}", regex, message);
+            return;
+        }
+    };
+    let mut found = false;
+    for spec in COMMANDS {
+        let names = spec.names.join(", ");
+        if [names.as_str(), spec.summary, spec.usage]
+            .iter()
+            .any(|text| pattern.is_match(text))
+        {
+            outln!("{} -- {}", names, spec.summary);
+            found = true;
+        }
+    }
+    for (name, description) in settings::matching(&pattern) {
+        outln!("set {} -- {}", name, description);
+        found = true;
+    }
+    if !found {
+        outln!("Nothing matches \"{}\".", regex);
+    }
+}
+
+/// A word of a shell-like command line, with its quotes removed.
+struct Word {
+    text: String,
+    /// How many bytes at its start were typed unquoted, to tell `>` from `">"`
+    plain: usize,
+}
+
+/// Splits `line` into words the way a shell would. Whitespace separates words. Inside 'single
+/// quotes' everything is literal. Inside "double quotes" a backslash escapes only `"`, `\`, `$`
+/// and `` ` ``. Elsewhere a backslash escapes any character.
+fn split_words(line: &str) -> Result<Vec<Word>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(words);
+        }
+        let mut word = Word {
+            text: String::new(),
+            plain: 0,
+        };
+        let mut quoted = false;
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            match c {
+                '\'' => {
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => word.text.push(c),
+                            None => return Err("Unterminated single quote.".to_string()),
+                        }
+                    }
+                }
+                '"' => {
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some(c @ ('"' | '\\' | '$' | '`')) => word.text.push(c),
+                                Some(c) => {
+                                    word.text.push('\\');
+                                    word.text.push(c);
+                                }
+                                None => return Err("Unterminated double quote.".to_string()),
+                            },
+                            Some(c) => word.text.push(c),
+                            None => return Err("Unterminated double quote.".to_string()),
+                        }
+                    }
+                }
+                '\\' => {
+                    quoted = true;
+                    match chars.next() {
+                        Some(c) => word.text.push(c),
+                        None => return Err("Trailing backslash.".to_string()),
+                    }
+                }
+                c => {
+                    word.text.push(c);
+                    if !quoted {
+                        word.plain = word.text.len();
+                    }
+                }
+            }
+        }
+        words.push(word);
+    }
+}
+
+/// `arg` quoted for a command line split by `split_words`, if it needs to be.
+pub fn quote_arg(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-+=.,/:@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Takes a leading `--timeout SECONDS` off the arguments of `run`, as typed.
+pub fn split_run_timeout(line: &str) -> Result<(Option<Duration>, &str), String> {
+    let Some(rest) = line.trim_start().strip_prefix("--timeout") else {
+        return Ok((None, line));
+    };
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return Ok((None, line));
+    }
+    let rest = rest.trim_start();
+    let (seconds, rest) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+    match seconds.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+            Ok((Some(Duration::from_secs_f64(seconds)), rest.trim_start()))
+        }
+        _ => Err(format!(
+            "Invalid timeout \"{}\": a number of seconds expected.",
+            seconds
+        )),
+    }
+}
+
+/// Splits the arguments of `run`, as typed, into the inferior's argv and shell-style
+/// redirections: `< in`, `> out`, `>> out`, `2> err`, `2>> err` and `2>&1`. Words are split and
+/// unquoted as by a shell, and quoted operators are plain arguments. The file name may follow the
+/// operator directly (`>out.log`) or as the next word.
+pub fn parse_run_args(line: &str) -> Result<(Vec<String>, Redirections), String> {
+    let mut args = Vec::new();
+    let mut redirections = Redirections::default();
+    let mut words = split_words(line)?.into_iter();
+    while let Some(word) = words.next() {
+        let Word { text, plain } = word;
+        if text == "2>&1" && plain == text.len() {
+            redirections.stderr = Some(OutputRedirect::Stdout);
+            continue;
+        }
+        // longest operators first, so ">>" isn't taken for ">"
+        let operator = ["2>>", "2>", ">>", ">", "<"]
+            .into_iter()
+            .find(|op| text.starts_with(op) && op.len() <= plain);
+        let operator = match operator {
+            Some(operator) => operator,
+            None => {
+                args.push(text);
+                continue;
+            }
+        };
+        let path = match &text[operator.len()..] {
+            "" => match words.next() {
+                Some(path) => path.text,
+                None => return Err(format!("Missing file name after \"{}\"", operator)),
+            },
+            path => path.to_string(),
+        };
+        let append = operator.ends_with(">>");
+        match operator {
+            "<" => redirections.stdin = Some(path),
+            ">" | ">>" => redirections.stdout = Some(OutputRedirect::File { path, append }),
+            _ => redirections.stderr = Some(OutputRedirect::File { path, append }),
         }
     }
+    Ok((args, redirections))
 }
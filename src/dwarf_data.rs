@@ -1,49 +1,278 @@
 use crate::gimli_wrapper;
+use crate::unwind::{self, CallFrameInfo};
 use addr2line::Context;
-use object::Object;
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::{Object, ObjectSection, ObjectSymbol};
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryInto;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 use std::{fmt, fs};
 
 #[derive(Debug)]
 pub enum Error {
-    ErrorOpeningFile,
-    DwarfFormatError(gimli_wrapper::Error),
+    ErrorOpeningFile(std::io::Error),
+    /// Not an object file the object crate can parse
+    NotObjectFile(String),
+    /// With the compilation unit found broken while loading, if there was one
+    DwarfFormatError(gimli_wrapper::Error, Option<gimli_wrapper::BrokenUnit>),
+}
+
+/// A function symbol from the ELF symbol table, used when DWARF doesn't describe an address.
+#[derive(Debug, Clone)]
+struct Symbol {
+    name: String,
+    address: usize,
+    size: usize,
 }
 
 pub struct DwarfData {
     files: Vec<File>,
-    addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    /// Parsed by `types` the first time one is asked for, as most sessions never need one
+    types: OnceCell<TypeTable>,
+    /// Until then, the placeholders of the variables' types, whose ids the parsed ones get
+    variable_types: Cell<Option<TypeTable>>,
+    /// 4 for a 32-bit program, 8 for a 64-bit one
+    pointer_size: usize,
+    broken_units: Vec<gimli_wrapper::BrokenUnit>,
+    symbols: Vec<Symbol>,             // sorted by address
+    text_ranges: Vec<(usize, usize)>, // [start, end) of each executable section
+    /// The dynamic loader the program asks for (its PT_INTERP), None if it's statically linked
+    interpreter: Option<String>,
+    /// Whether the program is position-independent (ET_DYN), so loaded at an address of the
+    /// kernel's choosing rather than at the ones the debug info gives
+    position_independent: bool,
+    addr2line: Context<gimli_wrapper::DwarfReader>,
+    cfi: CallFrameInfo,
+    /// Lookups by address already made. Stepping makes the same ones after every instruction, so
+    /// they're only worked out once. They go with the DwarfData when `file` loads another.
+    line_cache: RefCell<HashMap<usize, Option<Line>>>,
+    function_cache: RefCell<HashMap<usize, Option<(String, usize)>>>,
+    /// The DWARF functions, as the index of the file and of the function in it
+    dwarf_function_cache: RefCell<HashMap<usize, Option<(usize, usize)>>>,
+    /// What the file was like when this was loaded from it, to tell when it's been rebuilt
+    stamp: Option<Stamp>,
 }
 
-impl fmt::Debug for DwarfData {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DwarfData {{files: {:?}}}", self.files)
+/// What tells a rebuilt program from the one loaded: its GNU build ID, or without one its
+/// modification time and size. Cheaper than `file_identity`, so it can be checked before every
+/// run.
+#[derive(PartialEq)]
+enum Stamp {
+    BuildId(Vec<u8>),
+    Modified(std::time::SystemTime, u64),
+}
+
+impl Stamp {
+    fn of(file: &fs::File, object: &object::File) -> Option<Stamp> {
+        if let Ok(Some(build_id)) = object.build_id() {
+            return Some(Stamp::BuildId(build_id.to_vec()));
+        }
+        let metadata = file.metadata().ok()?;
+        Some(Stamp::Modified(metadata.modified().ok()?, metadata.len()))
     }
 }
 
-impl From<gimli_wrapper::Error> for Error {
-    fn from(err: gimli_wrapper::Error) -> Self {
-        Error::DwarfFormatError(err)
+/// How many programs' debug info DebugDataCache keeps
+const LOADED_PROGRAMS: usize = 4;
+
+/// The debug info of the programs loaded lately, by what identifies their contents, so that one
+/// loaded again (by `file`, or an exec of the same program) isn't parsed again. A rebuilt program
+/// is told apart by its build ID even if it has the same path and mtime.
+#[derive(Default)]
+pub struct DebugDataCache {
+    /// The most recently used last
+    entries: VecDeque<(String, Rc<DwarfData>)>,
+}
+
+impl DebugDataCache {
+    /// The debug info of the program at `path`, parsed unless it's one loaded lately.
+    pub fn load(&mut self, path: &str) -> Result<Rc<DwarfData>, Error> {
+        let identity = file_identity(path)?;
+        if let Some(index) = self.entries.iter().position(|(id, _)| *id == identity) {
+            let entry = self.entries.remove(index).unwrap();
+            let debug_data = entry.1.clone();
+            self.entries.push_back(entry);
+            return Ok(debug_data);
+        }
+        let debug_data = Rc::new(DwarfData::from_file(path)?);
+        if self.entries.len() == LOADED_PROGRAMS {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((identity, debug_data.clone()));
+        Ok(debug_data)
+    }
+}
+
+/// What identifies the contents of the program at `path`: its GNU build ID, or a hash of the
+/// whole file if it has none.
+fn file_identity(path: &str) -> Result<String, Error> {
+    let file = fs::File::open(path).map_err(Error::ErrorOpeningFile)?;
+    let mmap = unsafe { memmap2::Mmap::map(&file).map_err(Error::ErrorOpeningFile)? };
+    let object =
+        object::File::parse(&*mmap).map_err(|err| Error::NotObjectFile(err.to_string()))?;
+    if let Ok(Some(build_id)) = object.build_id() {
+        let hex: Vec<String> = build_id
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        return Ok(format!("build-id {}", hex.concat()));
+    }
+    let mut hasher = DefaultHasher::new();
+    mmap.hash(&mut hasher);
+    Ok(format!("hash {:016x} {}", hasher.finish(), mmap.len()))
+}
+
+/// How many addresses each lookup cache holds before it starts over, to bound its memory
+const CACHE_SIZE: usize = 1 << 16;
+
+/// Looks `addr` up in `cache`, working it out with `lookup` the first time.
+fn cached<T: Clone>(
+    cache: &RefCell<HashMap<usize, T>>,
+    addr: usize,
+    lookup: impl FnOnce() -> T,
+) -> T {
+    if let Some(value) = cache.borrow().get(&addr) {
+        return value.clone();
+    }
+    let value = lookup();
+    let mut cache = cache.borrow_mut();
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(addr, value.clone());
+    value
+}
+
+impl fmt::Debug for DwarfData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DwarfData {{files: {:?}}}", self.files)
     }
 }
 
 impl DwarfData {
     pub fn from_file(path: &str) -> Result<DwarfData, Error> {
-        let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
-        let mmap = unsafe { memmap2::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
-        let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+        let file = fs::File::open(path).map_err(Error::ErrorOpeningFile)?;
+        let mmap = unsafe { memmap2::Mmap::map(&file).map_err(Error::ErrorOpeningFile)? };
+        let object =
+            object::File::parse(&*mmap).map_err(|err| Error::NotObjectFile(err.to_string()))?;
         let endian = if object.is_little_endian() {
             gimli::RunTimeEndian::Little
         } else {
             gimli::RunTimeEndian::Big
         };
+        let pointer_size = if object.is_64() { 8 } else { 4 };
+        let dwarf = gimli_wrapper::load_sections(&object, endian)
+            .map_err(|err| Error::DwarfFormatError(err, None))?;
+        let gimli_wrapper::DebugInfo {
+            mut files,
+            types,
+            mut broken_units,
+        } = gimli_wrapper::load_file(&dwarf, pointer_size)
+            .map_err(|err| Error::DwarfFormatError(err, None))?;
+        Self::fill_linkage_names(&object, &mut files);
+        let mut symbols: Vec<Symbol> = object
+            .symbols()
+            .filter(|sym| sym.kind() == object::SymbolKind::Text && sym.address() != 0)
+            .filter_map(|sym| {
+                Some(Symbol {
+                    name: sym.name().ok()?.to_string(),
+                    address: sym.address() as usize,
+                    size: sym.size() as usize,
+                })
+            })
+            .collect();
+        symbols.sort_by_key(|sym| sym.address);
+        let text_ranges = object
+            .sections()
+            .filter(|section| section.kind() == object::SectionKind::Text)
+            .map(|section| {
+                let start = section.address() as usize;
+                (start, start + section.size() as usize)
+            })
+            .collect();
+        let interpreter = match object.is_64() {
+            true => elf_interpreter::<object::elf::FileHeader64<object::Endianness>>(&mmap),
+            false => elf_interpreter::<object::elf::FileHeader32<object::Endianness>>(&mmap),
+        };
+        // addr2line doesn't skip the units we could do without
+        let addr2line = Context::from_dwarf(dwarf).map_err(|err| {
+            let unit = (!broken_units.is_empty()).then(|| broken_units.remove(0));
+            Error::DwarfFormatError(gimli_wrapper::Error::from(err), unit)
+        })?;
+        let cfi = CallFrameInfo::load(&object, endian);
+        let stamp = Stamp::of(&file, &object);
+        let position_independent = object.kind() == object::ObjectKind::Dynamic;
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            files,
+            types: OnceCell::new(),
+            variable_types: Cell::new(Some(types)),
+            pointer_size,
+            broken_units,
+            symbols,
+            text_ranges,
+            interpreter,
+            position_independent,
+            addr2line,
+            cfi,
+            line_cache: RefCell::new(HashMap::new()),
+            function_cache: RefCell::new(HashMap::new()),
+            dwarf_function_cache: RefCell::new(HashMap::new()),
+            stamp,
         })
     }
 
+    /// Debug info about nothing, for when there's no program yet.
+    pub fn empty() -> DwarfData {
+        let endian = gimli::RunTimeEndian::Little;
+        let dwarf = gimli::Dwarf::load(|_| {
+            Ok::<_, gimli::Error>(gimli::EndianArcSlice::new(
+                std::sync::Arc::from(&[][..]),
+                endian,
+            ))
+        })
+        .expect("empty DWARF sections");
+        DwarfData {
+            files: Vec::new(),
+            types: OnceCell::new(),
+            variable_types: Cell::new(None),
+            pointer_size: std::mem::size_of::<usize>(),
+            broken_units: Vec::new(),
+            symbols: Vec::new(),
+            text_ranges: Vec::new(),
+            interpreter: None,
+            position_independent: false,
+            addr2line: Context::from_dwarf(dwarf).expect("empty DWARF context"),
+            cfi: CallFrameInfo::empty(),
+            line_cache: RefCell::new(HashMap::new()),
+            function_cache: RefCell::new(HashMap::new()),
+            dwarf_function_cache: RefCell::new(HashMap::new()),
+            stamp: None,
+        }
+    }
+
+    /// gcc doesn't emit DW_AT_linkage_name for internal-linkage C++ functions (e.g. `static`
+    /// overloads), so recover their mangled names from the symbol table by address.
+    fn fill_linkage_names(object: &object::File, files: &mut [File]) {
+        let symbols: HashMap<usize, String> = object
+            .symbols()
+            .filter(|sym| sym.kind() == object::SymbolKind::Text)
+            .filter_map(|sym| Some((sym.address() as usize, sym.name().ok()?.to_string())))
+            .collect();
+        for func in files.iter_mut().flat_map(|file| file.functions.iter_mut()) {
+            if func.linkage_name.is_some() {
+                continue;
+            }
+            if let Some(name) = symbols.get(&func.address) {
+                if *name != func.name && demangle(name).is_some() {
+                    func.linkage_name = Some(name.clone());
+                }
+            }
+        }
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
         self.files.iter().find(|f| {
@@ -55,7 +284,7 @@ impl DwarfData {
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None => self.files.first()?,
         };
         Some(
             target_file
@@ -66,20 +295,23 @@ impl DwarfData {
         )
     }
 
-    #[allow(dead_code)]
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
         match file {
             Some(filename) => Some(
                 self.get_target_file(filename)?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
-                    .address,
+                    .find(|func| func.matches_name(func_name))
+                    .map(|func| self.after_prologue(func))?,
             ),
             None => {
                 for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
+                    if let Some(func) = file
+                        .functions
+                        .iter()
+                        .find(|func| func.matches_name(func_name))
+                    {
+                        return Some(self.after_prologue(func));
                     }
                 }
                 None
@@ -87,8 +319,246 @@ impl DwarfData {
         }
     }
 
+    /// Where `func`'s body starts: the second row of the line table in it, as gdb does, since
+    /// the first covers the prologue that sets up the frame and stores the arguments. The
+    /// function's own address if it has no second row.
+    pub fn after_prologue(&self, func: &Function) -> usize {
+        self.files
+            .iter()
+            .flat_map(|file| &file.lines)
+            .map(|line| line.address)
+            .filter(|&addr| addr > func.address && func.contains(addr))
+            .min()
+            .unwrap_or(func.address)
+    }
+
+    /// The names `break` accepts for functions: the plain name, and for C++ also the qualified
+    /// one without parameters. Names with spaces (e.g. operators) are left out, as they can't be
+    /// typed as a single word.
+    pub fn function_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for func in self.files.iter().flat_map(|file| file.functions.iter()) {
+            names.push(func.name.clone());
+            if let Some(qualified) = func
+                .linkage_name
+                .as_deref()
+                .and_then(demangle_without_params)
+            {
+                names.push(qualified);
+            }
+        }
+        names.retain(|name| !name.contains(' '));
+        names
+    }
+
+    /// The base names of the source files, which is how `break FILE:LINE` usually names them.
+    pub fn source_file_names(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .map(|file| {
+                file.name
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(&file.name)
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// The compilation unit with the function at `addr`.
+    pub fn unit_from_addr(&self, addr: usize) -> Option<&File> {
+        self.files
+            .iter()
+            .find(|file| file.functions.iter().any(|func| func.contains(addr)))
+    }
+
+    /// The paths of all files the line tables name, sorted.
+    pub fn all_source_files(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = self
+            .files
+            .iter()
+            .flat_map(|file| &file.source_files)
+            .map(String::as_str)
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        paths
+    }
+
+    pub fn global_variable_names(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .map(|var| var.name.clone())
+            .collect()
+    }
+
+    /// Whether the file at `path`, which this was loaded from, has been rebuilt since. A file
+    /// that can't be read now isn't taken for a rebuilt one; running it will say what's wrong.
+    pub fn has_changed(&self, path: &str) -> bool {
+        let Some(stamp) = &self.stamp else {
+            return false;
+        };
+        let current = fs::File::open(path).ok().and_then(|file| {
+            let mmap = unsafe { memmap2::Mmap::map(&file).ok()? };
+            let object = object::File::parse(&*mmap).ok()?;
+            Stamp::of(&file, &object)
+        });
+        current.is_some_and(|current| current != *stamp)
+    }
+
+    /// Whether the program is loaded at an offset from the addresses its debug info gives, the
+    /// offset being where the kernel put it.
+    pub fn is_position_independent(&self) -> bool {
+        self.position_independent
+    }
+
+    /// The dynamic loader the program is run by, e.g. "/lib64/ld-linux-x86-64.so.2". None if it's
+    /// statically linked, so there are no shared libraries or loader to deal with.
+    pub fn interpreter(&self) -> Option<&str> {
+        self.interpreter.as_deref()
+    }
+
+    /// How to find the caller of a frame at `pc`, from the call frame information, if it
+    /// describes the function there.
+    pub fn unwind_row(&self, pc: usize) -> Option<unwind::Row> {
+        self.cfi.row(pc)
+    }
+
+    /// Returns the compilation units that were skipped because their debug info was malformed.
+    pub fn broken_units(&self) -> &[gimli_wrapper::BrokenUnit] {
+        &self.broken_units
+    }
+
+    /// The types the debug info describes, parsed the first time this is called.
+    pub fn types(&self) -> &TypeTable {
+        self.types.get_or_init(|| {
+            let mut types = self
+                .variable_types
+                .take()
+                .unwrap_or_else(|| TypeTable::new(self.pointer_size));
+            gimli_wrapper::load_types(self.addr2line.dwarf(), &mut types);
+            types
+        })
+    }
+
+    /// The size of the program's pointers, and of the words on its stack.
+    pub fn pointer_size(&self) -> usize {
+        self.pointer_size
+    }
+
+    /// Looks up a variable by name, preferring the locals and parameters of `scope` (normally the
+    /// function the inferior is stopped in) over globals.
+    pub fn get_variable<'a>(
+        &'a self,
+        name: &str,
+        scope: Option<&'a Function>,
+    ) -> Option<&'a Variable> {
+        if let Some(var) = scope.and_then(|func| func.variables.iter().find(|v| v.name == name)) {
+            return Some(var);
+        }
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// The addresses `name` could refer to: the entry of each function by that name, a global
+    /// variable, or failing those, a function in the symbol table.
+    pub fn symbol_addresses(&self, name: &str) -> Vec<usize> {
+        let mut addrs: Vec<usize> = self
+            .get_functions_by_name(name)
+            .iter()
+            .map(|(_, func)| func.address)
+            .collect();
+        for var in self.files.iter().flat_map(|file| &file.global_variables) {
+            if let (true, Location::Address(addr)) = (var.name == name, &var.location) {
+                addrs.push(*addr);
+            }
+        }
+        if addrs.is_empty() {
+            addrs = self.function_symbol_addresses(name);
+        }
+        addrs.sort_unstable();
+        addrs.dedup();
+        addrs
+    }
+
+    /// The addresses of the functions called `name` in the symbol table, for functions without
+    /// debug info, like the C library's in a statically linked program.
+    pub fn function_symbol_addresses(&self, name: &str) -> Vec<usize> {
+        self.symbols
+            .iter()
+            .filter(|sym| sym.name == name)
+            .map(|sym| sym.address)
+            .collect()
+    }
+
+    /// Returns every function (along with the name of the file defining it) that `func_name`
+    /// could refer to. More than one match means the name is ambiguous, e.g. an overload set.
+    pub fn get_functions_by_name(&self, func_name: &str) -> Vec<(&str, &Function)> {
+        let mut matches = Vec::new();
+        for file in &self.files {
+            for func in &file.functions {
+                if func.matches_name(func_name) {
+                    matches.push((file.name.as_str(), func));
+                }
+            }
+        }
+        matches
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
+        cached(&self.line_cache, curr_addr, || self.find_line(curr_addr))
+    }
+
+    /// The line at `curr_addr`, or if the line table has no row for it, the nearest one before
+    /// it in the same function, e.g. for an instruction the compiler left without a line. For
+    /// saying where the program stopped rather than stepping, which needs the exact line.
+    pub fn get_nearest_line(&self, curr_addr: usize) -> Option<Line> {
+        if let Some(line) = self.get_line_from_addr(curr_addr) {
+            return Some(line);
+        }
+        let func = self.get_dwarf_function_from_addr(curr_addr)?;
+        let start = func
+            .ranges
+            .iter()
+            .find(|&&(low, high)| low <= curr_addr && curr_addr < high)
+            .map_or(func.address, |&(low, _)| low);
+        let (address, _, location) = self
+            .addr2line
+            .find_location_range(start as u64, curr_addr as u64 + 1)
+            .ok()?
+            .filter(|(address, _, location)| {
+                *address as usize <= curr_addr && location.line.is_some()
+            })
+            .max_by_key(|(address, ..)| *address)?;
+        Some(Line {
+            file: location.file?.to_string(),
+            number: location.line?.try_into().unwrap(),
+            column: location.column.unwrap_or(0).try_into().unwrap(),
+            address: address as usize,
+        })
+    }
+
+    /// The line whose row of the line table starts at `addr`, if one does: where stepping to
+    /// another line stops.
+    pub fn get_line_starting_at(&self, addr: usize) -> Option<Line> {
+        let (_, _, location) = self
+            .addr2line
+            .find_location_range(addr as u64, addr as u64 + 1)
+            .ok()?
+            .find(|(address, _, location)| *address as usize == addr && location.line.is_some())?;
+        Some(Line {
+            file: location.file?.to_string(),
+            number: location.line?.try_into().unwrap(),
+            column: location.column.unwrap_or(0).try_into().unwrap(),
+            address: addr,
+        })
+    }
+
+    fn find_line(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
             .find_location(curr_addr.try_into().unwrap())
@@ -96,69 +566,488 @@ impl DwarfData {
         Some(Line {
             file: location.file?.to_string(),
             number: location.line?.try_into().unwrap(),
+            column: location.column.unwrap_or(0).try_into().unwrap(),
             address: curr_addr,
         })
     }
 
-    #[allow(dead_code)]
+    /// Returns the raw (possibly mangled) name of the function containing `curr_addr`. The DWARF
+    /// subprogram ranges are consulted first since they cover `static` functions too; the symbol
+    /// table is only a fallback for code without debug info. Returns None rather than guessing
+    /// when the address lies past the end of the nearest preceding function.
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
-        let frame = self
-            .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
-            .ok()?
-            .next()
-            .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+        Some(self.get_function_and_offset_from_addr(curr_addr)?.0)
+    }
+
+    /// Like `get_function_from_addr`, but also returns how far `curr_addr` is into the function.
+    pub fn get_function_and_offset_from_addr(&self, curr_addr: usize) -> Option<(String, usize)> {
+        cached(&self.function_cache, curr_addr, || {
+            self.find_function_and_offset(curr_addr)
+        })
+    }
+
+    /// `addr` as the function it's in plus an offset, e.g. "merge_sort+0x26", or just the
+    /// function at its start. Functions are looked up as by `get_function_and_offset_from_addr`.
+    pub fn symbolize(&self, addr: usize, print_mangled: bool) -> Option<String> {
+        let (name, offset) = self.get_function_and_offset_from_addr(addr)?;
+        let name = format_symbol_name(&name, print_mangled);
+        Some(match offset {
+            0 => name,
+            _ => format!("{}+{:#x}", name, offset),
+        })
+    }
+
+    fn find_function_and_offset(&self, curr_addr: usize) -> Option<(String, usize)> {
+        if let Some(func) = self.get_dwarf_function_from_addr(curr_addr) {
+            return Some((func.raw_name().to_string(), curr_addr - func.address));
+        }
+        let index = self
+            .symbols
+            .partition_point(|sym| sym.address <= curr_addr)
+            .checked_sub(1)?;
+        let symbol = &self.symbols[index];
+        let end = if symbol.size != 0 {
+            symbol.address + symbol.size
+        } else {
+            // Symbols without a size (e.g. hand-written assembly) extend at most up to the next
+            // symbol or the end of their section
+            let next = self
+                .symbols
+                .get(index + 1)
+                .map_or(usize::MAX, |sym| sym.address);
+            let section_end = self
+                .text_ranges
+                .iter()
+                .find(|&&(start, end)| start <= symbol.address && symbol.address < end)?
+                .1;
+            next.min(section_end)
+        };
+        if curr_addr >= end {
+            return None;
+        }
+        Some((symbol.name.clone(), curr_addr - symbol.address))
+    }
+
+    /// Returns the DWARF function whose pc ranges contain `curr_addr`.
+    pub fn get_dwarf_function_from_addr(&self, curr_addr: usize) -> Option<&Function> {
+        let (file, function) = cached(&self.dwarf_function_cache, curr_addr, || {
+            self.files.iter().enumerate().find_map(|(i, file)| {
+                let function = file.functions.iter().position(|f| f.contains(curr_addr))?;
+                Some((i, function))
+            })
+        })?;
+        Some(&self.files[file].functions[function])
     }
 
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
-            println!("------");
-            println!("{}", file.name);
-            println!("------");
+            outln!("------");
+            outln!("{}", file.name);
+            outln!("------");
 
-            println!("Global variables:");
+            outln!("Global variables:");
             for var in &file.global_variables {
-                println!(
+                outln!(
                     "  * {} ({}, located at {}, declared at line {})",
-                    var.name, var.entity_type.name, var.location, var.line_number
+                    var.name,
+                    self.types().name_of(var.entity_type),
+                    var.location,
+                    var.line_number
                 );
             }
 
-            println!("Functions:");
+            outln!("Functions:");
             for func in &file.functions {
-                println!(
+                outln!(
                     "  * {} (declared on line {}, located at {:#x}, {} bytes long)",
-                    func.name, func.line_number, func.address, func.text_length
+                    func.demangled_name(),
+                    func.line_number,
+                    func.address,
+                    func.text_length
                 );
                 for var in &func.variables {
-                    println!(
+                    outln!(
                         "    * Variable: {} ({}, located at {}, declared at line {})",
-                        var.name, var.entity_type.name, var.location, var.line_number
+                        var.name,
+                        self.types().name_of(var.entity_type),
+                        var.location,
+                        var.line_number
                     );
                 }
             }
 
-            println!("Line numbers:");
+            outln!("Line numbers:");
             for line in &file.lines {
-                println!("  * {} (at {:#x})", line.number, line.address);
+                outln!("  * {} (at {:#x})", line.number, line.address);
             }
         }
     }
 }
 
+/// Index of a type in a `TypeTable`. Types refer to each other through these rather than by
+/// value, so recursive types (e.g. a linked list node pointing at itself) stay finite.
+pub type TypeId = usize;
+
 #[derive(Debug, Clone, Default)]
-pub struct Type {
+pub enum TypeKind {
+    #[default]
+    Unknown,
+    Base,
+    Pointer(Option<TypeId>), // None for void *
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    Enum(Vec<(String, i64)>),
+    Array(Option<TypeId>, Vec<Option<usize>>), // element type, then the count of each dimension
+    Typedef(Option<TypeId>),
+    Const(Option<TypeId>),
+    Volatile(Option<TypeId>),
+    Function(Option<TypeId>), // return type
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
     pub name: String,
-    pub _size: usize,
+    pub type_id: Option<TypeId>,
+    pub offset: usize, // Byte offset within the containing struct
 }
 
-impl Type {
-    pub fn new(name: String, size: usize) -> Self {
-        Type {
-            name: name,
-            _size: size,
+#[derive(Debug, Clone, Default)]
+pub struct Type {
+    pub name: Option<String>,
+    pub size: Option<usize>,
+    pub kind: TypeKind,
+}
+
+/// Arena of every type described in the debug info. Types are interned once, when the first is
+/// needed, and the rendered names are computed on first use and cached, so formatting a value of a
+/// deeply nested struct doesn't re-walk anything.
+pub struct TypeTable {
+    types: Vec<Type>,
+    offsets: HashMap<usize, TypeId>,
+    names: RefCell<HashMap<TypeId, String>>,
+    /// 4 for a 32-bit program, 8 for a 64-bit one
+    pointer_size: usize,
+}
+
+impl TypeTable {
+    pub fn new(pointer_size: usize) -> TypeTable {
+        TypeTable {
+            types: Vec::new(),
+            offsets: HashMap::new(),
+            names: RefCell::new(HashMap::new()),
+            pointer_size,
+        }
+    }
+
+    /// The size of the program's pointers, and of the words on its stack.
+    pub fn pointer_size(&self) -> usize {
+        self.pointer_size
+    }
+
+    /// Returns the id for the type DIE at `offset` in .debug_info, reserving a placeholder if that
+    /// DIE hasn't been parsed yet (types are often referenced before they are defined).
+    pub fn id_for_offset(&mut self, offset: usize) -> TypeId {
+        if let Some(&id) = self.offsets.get(&offset) {
+            return id;
+        }
+        let id = self.types.len();
+        self.types.push(Type::default());
+        self.offsets.insert(offset, id);
+        id
+    }
+
+    /// Adds the types of a table filled from other compilation units, and returns the id each of
+    /// them has here, by its id there. Of a type both tables have, only the one from the unit
+    /// holding its DIE is defined; the other is a placeholder for a reference to it.
+    pub fn merge(&mut self, other: TypeTable) -> Vec<TypeId> {
+        let mut offsets: Vec<(usize, TypeId)> = other.offsets.into_iter().collect();
+        // in the order the other table made them, which is the order they'd have been made here
+        offsets.sort_unstable_by_key(|&(_, id)| id);
+        let ids: Vec<TypeId> = offsets
+            .iter()
+            .map(|&(offset, _)| self.id_for_offset(offset))
+            .collect();
+        for (mut dtype, &id) in other.types.into_iter().zip(&ids) {
+            let placeholder = dtype.name.is_none()
+                && dtype.size.is_none()
+                && matches!(dtype.kind, TypeKind::Unknown);
+            if placeholder {
+                continue;
+            }
+            let remap = |target: &mut Option<TypeId>| *target = target.map(|target| ids[target]);
+            match &mut dtype.kind {
+                TypeKind::Pointer(target)
+                | TypeKind::Array(target, _)
+                | TypeKind::Typedef(target)
+                | TypeKind::Const(target)
+                | TypeKind::Volatile(target)
+                | TypeKind::Function(target) => remap(target),
+                TypeKind::Struct(members) | TypeKind::Union(members) => members
+                    .iter_mut()
+                    .for_each(|member| remap(&mut member.type_id)),
+                TypeKind::Unknown | TypeKind::Base | TypeKind::Enum(_) => {}
+            }
+            self.types[id] = dtype;
+        }
+        ids
+    }
+
+    pub fn define(&mut self, id: TypeId, dtype: Type) {
+        self.types[id] = dtype;
+    }
+
+    pub fn get(&self, id: TypeId) -> &Type {
+        &self.types[id]
+    }
+
+    pub fn get_mut(&mut self, id: TypeId) -> &mut Type {
+        &mut self.types[id]
+    }
+
+    /// Follows typedefs and const/volatile qualifiers down to the underlying type.
+    pub fn strip_typedefs(&self, mut id: TypeId) -> TypeId {
+        for _ in 0..self.types.len() {
+            match self.types[id].kind {
+                TypeKind::Typedef(Some(target))
+                | TypeKind::Const(Some(target))
+                | TypeKind::Volatile(Some(target)) => id = target,
+                _ => break,
+            }
+        }
+        id
+    }
+
+    /// Returns the size in bytes of a value of this type.
+    pub fn size_of(&self, id: TypeId) -> usize {
+        let resolved = self.strip_typedefs(id);
+        let dtype = &self.types[resolved];
+        if let Some(size) = dtype.size {
+            return size;
+        }
+        match &dtype.kind {
+            TypeKind::Pointer(_) => self.pointer_size,
+            TypeKind::Array(Some(element), dimensions) => {
+                dimensions
+                    .iter()
+                    .map(|count| count.unwrap_or(0))
+                    .product::<usize>()
+                    * self.size_of(*element)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Returns the C spelling of a type, e.g. `struct node *` or `const char [16]`.
+    /// The type `name_of` calls `name`, e.g. `int` or `struct node`, leaving out declarations of
+    /// structs defined elsewhere.
+    pub fn find(&self, name: &str) -> Option<TypeId> {
+        (0..self.types.len()).find(|&id| {
+            let dtype = &self.types[id];
+            let defined = match dtype.kind {
+                TypeKind::Struct(_) | TypeKind::Union(_) | TypeKind::Enum(_) => {
+                    dtype.size.is_some()
+                }
+                TypeKind::Base | TypeKind::Typedef(_) => true,
+                _ => false,
+            };
+            // rendered only for the types it could be, as that caches the name
+            defined
+                && dtype.name.as_deref().is_some_and(|own| name.ends_with(own))
+                && self.name_of(id) == name
+        })
+    }
+
+    pub fn name_of(&self, id: TypeId) -> String {
+        if let Some(name) = self.names.borrow().get(&id) {
+            return name.clone();
+        }
+        let name = self.render_name(id, 0);
+        self.names.borrow_mut().insert(id, name.clone());
+        name
+    }
+
+    fn render_name(&self, id: TypeId, depth: usize) -> String {
+        if depth > 16 {
+            return "...".to_string();
+        }
+        let dtype = &self.types[id];
+        let target_name = |target: &Option<TypeId>| match target {
+            Some(target) => self.render_name(*target, depth + 1),
+            None => "void".to_string(),
+        };
+        match &dtype.kind {
+            TypeKind::Unknown => "<unknown type>".to_string(),
+            TypeKind::Base | TypeKind::Typedef(_) => dtype
+                .name
+                .clone()
+                .unwrap_or_else(|| "<unnamed>".to_string()),
+            TypeKind::Pointer(target) => {
+                let target = target_name(target);
+                if target.ends_with('*') {
+                    format!("{}*", target)
+                } else {
+                    format!("{} *", target)
+                }
+            }
+            TypeKind::Struct(_) => Self::tagged_name("struct", &dtype.name),
+            TypeKind::Union(_) => Self::tagged_name("union", &dtype.name),
+            TypeKind::Enum(_) => Self::tagged_name("enum", &dtype.name),
+            TypeKind::Array(element, dimensions) => {
+                format!("{} {}", target_name(element), Self::dimensions(dimensions))
+            }
+            TypeKind::Const(target) => {
+                // C has no const arrays, only arrays of const elements, but gcc still puts a
+                // const in front of the array type
+                let target = target_name(target);
+                if target.starts_with("const ") {
+                    target
+                } else {
+                    format!("const {}", target)
+                }
+            }
+            TypeKind::Volatile(target) => format!("volatile {}", target_name(target)),
+            TypeKind::Function(ret) => format!("{} (...)", target_name(ret)),
+        }
+    }
+
+    fn tagged_name(tag: &str, name: &Option<String>) -> String {
+        match name {
+            Some(name) => format!("{} {}", tag, name),
+            None => format!("{} {{...}}", tag),
+        }
+    }
+
+    fn dimensions(dimensions: &[Option<usize>]) -> String {
+        dimensions
+            .iter()
+            .map(|count| match count {
+                Some(count) => format!("[{}]", count),
+                None => "[]".to_string(),
+            })
+            .collect()
+    }
+
+    /// Returns a C declaration of `var_name` with this type, e.g. `int data[4]`.
+    pub fn declaration(&self, id: TypeId, var_name: &str) -> String {
+        if let TypeKind::Array(element, dimensions) = &self.types[id].kind {
+            let element = match element {
+                Some(element) => self.name_of(*element),
+                None => "void".to_string(),
+            };
+            return format!("{} {}{}", element, var_name, Self::dimensions(dimensions));
+        }
+        let name = self.name_of(id);
+        if name.ends_with('*') {
+            format!("{}{}", name, var_name)
+        } else {
+            format!("{} {}", name, var_name)
+        }
+    }
+
+    /// Describes a type the way ptype does: structs, unions and enums (including ones behind
+    /// typedefs and pointers) are expanded one level, with members named by type. With
+    /// `show_offsets`, each member is prefixed with its byte offset and size like `ptype/o`.
+    pub fn describe(&self, id: TypeId, show_offsets: bool) -> String {
+        let mut pointers = String::new();
+        let mut resolved = self.strip_typedefs(id);
+        while let TypeKind::Pointer(Some(target)) = self.types[resolved].kind {
+            pointers.push('*');
+            resolved = self.strip_typedefs(target);
+        }
+        let dtype = &self.types[resolved];
+        let body = match &dtype.kind {
+            TypeKind::Struct(members) | TypeKind::Union(members) => {
+                let mut body =
+                    format!("{} {{\n", self.name_of(resolved).trim_end_matches(" {...}"));
+                for member in members {
+                    let decl = match member.type_id {
+                        Some(member_type) => self.declaration(member_type, &member.name),
+                        None => format!("<unknown type> {}", member.name),
+                    };
+                    if show_offsets {
+                        let size = member.type_id.map_or(0, |t| self.size_of(t));
+                        body.push_str(&format!("/* {:6} | {:6} */", member.offset, size));
+                    }
+                    body.push_str(&format!("    {};\n", decl));
+                }
+                body.push('}');
+                body
+            }
+            TypeKind::Enum(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                format!(
+                    "{} {{{}}}",
+                    self.name_of(resolved).trim_end_matches(" {...}"),
+                    values.join(", ")
+                )
+            }
+            _ => return self.name_of(id),
+        };
+        if pointers.is_empty() {
+            body
+        } else {
+            format!("{} {}", body, pointers)
+        }
+    }
+
+    /// Formats a scalar value from its little-endian bytes, as in a stop banner's arguments.
+    /// Structs, unions and arrays are just `...`, like gdb's `print frame-arguments scalars`.
+    pub fn format_value(&self, id: TypeId, bytes: &[u8]) -> String {
+        let resolved = self.strip_typedefs(id);
+        let dtype = &self.types[resolved];
+        let size = self.size_of(resolved).min(8);
+        if bytes.len() < size {
+            return "<unreadable>".to_string();
+        }
+        let mut word = [0; 8];
+        word[..size].copy_from_slice(&bytes[..size]);
+        let unsigned = u64::from_le_bytes(word);
+        // sign-extend from the value's size
+        let signed = match size {
+            0 => 0,
+            _ => (unsigned << (64 - 8 * size)) as i64 >> (64 - 8 * size),
+        };
+        match &dtype.kind {
+            TypeKind::Base => {
+                let name = dtype.name.as_deref().unwrap_or("");
+                if name.contains("double") && size == 8 {
+                    format!("{:?}", f64::from_bits(unsigned))
+                } else if name.contains("float") && size == 4 {
+                    format!("{:?}", f32::from_bits(unsigned as u32))
+                } else if name == "_Bool" || name == "bool" {
+                    (unsigned != 0).to_string()
+                } else if name.contains("char") && size == 1 {
+                    let byte = unsigned as u8;
+                    let value = if name.contains("unsigned") {
+                        byte.to_string()
+                    } else {
+                        signed.to_string()
+                    };
+                    let escaped = match byte {
+                        b'\'' | b'\\' => format!("\\{}", byte as char),
+                        b' '..=b'~' => (byte as char).to_string(),
+                        b'\n' => "\\n".to_string(),
+                        b'\t' => "\\t".to_string(),
+                        0 => "\\0".to_string(),
+                        _ => format!("\\{:o}", byte),
+                    };
+                    format!("{} '{}'", value, escaped)
+                } else if name.contains("unsigned") {
+                    unsigned.to_string()
+                } else {
+                    signed.to_string()
+                }
+            }
+            TypeKind::Pointer(_) | TypeKind::Function(_) => format!("{:#x}", unsigned),
+            TypeKind::Enum(values) => values
+                .iter()
+                .find(|(_, value)| *value == signed)
+                .map_or_else(|| signed.to_string(), |(name, _)| name.clone()),
+            _ => "...".to_string(),
         }
     }
 }
@@ -188,23 +1077,119 @@ impl fmt::Debug for Location {
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: String,
-    pub entity_type: Type,
+    pub entity_type: TypeId,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct Function {
     pub name: String,
+    pub linkage_name: Option<String>, // Mangled name, for C++ functions
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
     pub variables: Vec<Variable>,
+    pub ranges: Vec<(usize, usize)>, // [low_pc, high_pc) ranges covered by the function's code
+}
+
+impl Function {
+    /// Returns whether `addr` falls inside one of this function's pc ranges.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(low, high)| low <= addr && addr < high)
+    }
+
+    /// Returns the raw symbol name: the linkage name if there is one, otherwise the DWARF name.
+    pub fn raw_name(&self) -> &str {
+        self.linkage_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Returns the fully qualified, demangled name (e.g. `shapes::Square::area() const`), or the
+    /// plain DWARF name for functions that don't have a mangled linkage name.
+    pub fn demangled_name(&self) -> String {
+        self.linkage_name
+            .as_deref()
+            .and_then(demangle)
+            .unwrap_or_else(|| self.name.clone())
+    }
+
+    /// Checks whether a user-supplied name refers to this function. The name may be the mangled
+    /// linkage name, the full demangled signature, the qualified name without parameters, or just
+    /// the unqualified function name.
+    fn matches_name(&self, name: &str) -> bool {
+        if self.name == name || self.linkage_name.as_deref() == Some(name) {
+            return true;
+        }
+        match self.linkage_name.as_deref() {
+            Some(linkage_name) => {
+                demangle(linkage_name).as_deref() == Some(name)
+                    || demangle_without_params(linkage_name).as_deref() == Some(name)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Demangles an Itanium C++ ABI symbol name. Returns None for names that aren't mangled (e.g. C
+/// functions).
+pub fn demangle(name: &str) -> Option<String> {
+    // without the prefix, short C names can parse as mangled types, like "f" for float
+    if !name.starts_with("_Z") {
+        return None;
+    }
+    let symbol = cpp_demangle::Symbol::new(name).ok()?;
+    symbol.demangle(&cpp_demangle::DemangleOptions::new()).ok()
+}
+
+fn demangle_without_params(name: &str) -> Option<String> {
+    let symbol = cpp_demangle::Symbol::new(name).ok()?;
+    symbol
+        .demangle(
+            &cpp_demangle::DemangleOptions::new()
+                .no_params()
+                .no_return_type(),
+        )
+        .ok()
+}
+
+/// The PT_INTERP of an ELF file, which only dynamically linked programs have.
+fn elf_interpreter<Elf: FileHeader<Endian = object::Endianness>>(data: &[u8]) -> Option<String> {
+    let header = Elf::parse(data).ok()?;
+    let endian = header.endian().ok()?;
+    let segment = header
+        .program_headers(endian, data)
+        .ok()?
+        .iter()
+        .find(|segment| segment.p_type(endian) == object::elf::PT_INTERP)?;
+    let path = segment.data(endian, data).ok()?;
+    let path = path.split(|&byte| byte == 0).next()?;
+    Some(String::from_utf8_lossy(path).into_owned())
+}
+
+/// Formats a raw symbol name for display, demangling it unless `print_mangled` is set.
+pub fn format_symbol_name(name: &str, print_mangled: bool) -> String {
+    if print_mangled {
+        name.to_string()
+    } else {
+        demangle(name).unwrap_or_else(|| name.to_string())
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct File {
     pub name: String,
+    /// Where the compiler ran, which relative paths in the unit are relative to
+    pub comp_dir: Option<String>,
+    /// The compiler, and its flags if it records them (GCC does, unless -gno-record-gcc-switches)
+    pub producer: Option<String>,
+    pub dwarf_version: u16,
+    /// Whether there's preprocessor macro info for the unit, as -g3 gives
+    pub has_macros: bool,
+    /// Every file the unit's line table names, headers included
+    pub source_files: Vec<String>,
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
@@ -214,6 +1199,7 @@ pub struct File {
 pub struct Line {
     pub file: String,
     pub number: usize,
+    pub column: usize, // 0 if the line table doesn't record columns
     pub address: usize,
 }
 
@@ -221,4 +1207,4 @@ impl fmt::Display for Line {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}:{}", self.file, self.number)
     }
-}
\ No newline at end of file
+}
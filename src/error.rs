@@ -0,0 +1,86 @@
+//! What can go wrong setting up a debugging session: loading the program's debug info, as
+//! `Debugger::new` and `file` do, and starting it, as `Inferior::new` does. Commands report their
+//! own failures as strings; these are the ones an embedder may want to tell apart.
+
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum DeetError {
+    /// There's no file at the path
+    NotFound(String),
+    /// The file isn't readable, or not executable
+    PermissionDenied(String),
+    /// The file couldn't be read for another reason
+    Io(String, io::Error),
+    /// The file isn't an ELF file; the object crate's complaint comes second
+    NotElf(String, String),
+    /// The file's DWARF couldn't be parsed. `unit` describes the compilation unit found broken
+    /// while loading it, if any, which is usually the culprit.
+    Dwarf {
+        path: String,
+        error: String,
+        unit: Option<String>,
+    },
+    /// A 32-bit program, on a machine where deet can't debug those
+    Unsupported32Bit(String),
+    /// The program couldn't be executed; the error has exec's errno
+    Spawn(String, io::Error),
+    /// What the program is started with couldn't be set up: its redirections, terminal or
+    /// output log
+    Launch(String),
+    /// The started program couldn't be traced
+    Ptrace(String, nix::Error),
+}
+
+impl DeetError {
+    /// The error of opening the file at `path`.
+    pub fn opening(path: &str, err: io::Error) -> DeetError {
+        match err.kind() {
+            io::ErrorKind::NotFound => DeetError::NotFound(path.to_string()),
+            io::ErrorKind::PermissionDenied => DeetError::PermissionDenied(path.to_string()),
+            _ => DeetError::Io(path.to_string(), err),
+        }
+    }
+}
+
+impl fmt::Display for DeetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeetError::NotFound(path) => write!(f, "Could not open file {}: no such file", path),
+            DeetError::PermissionDenied(path) => {
+                write!(f, "Could not open file {}: permission denied", path)
+            }
+            DeetError::Io(path, err) => write!(f, "Could not read file {}: {}", path, err),
+            DeetError::NotElf(path, err) => write!(f, "{} is not an ELF file: {}", path, err),
+            DeetError::Dwarf { path, error, unit } => {
+                write!(
+                    f,
+                    "Could not load debugging symbols from {}: {}",
+                    path, error
+                )?;
+                match unit {
+                    Some(unit) => write!(f, " (broken {})", unit),
+                    None => Ok(()),
+                }
+            }
+            DeetError::Unsupported32Bit(path) => {
+                write!(f, "{}: 32-bit targets unsupported on this host", path)
+            }
+            DeetError::Spawn(program, err) => {
+                let why = match err.raw_os_error() {
+                    Some(libc::ENOENT) => "no such file, or no dynamic loader it asks for".into(),
+                    Some(libc::EACCES) => "permission denied".into(),
+                    Some(libc::ENOEXEC) => {
+                        "exec format error (did you pass a script or wrong-arch binary?)".into()
+                    }
+                    _ => err.to_string(),
+                };
+                write!(f, "Could not start {}: {}", program, why)
+            }
+            DeetError::Launch(message) => write!(f, "{}", message),
+            DeetError::Ptrace(program, err) => write!(f, "Could not trace {}: {}", program, err),
+        }
+    }
+}
+
+impl std::error::Error for DeetError {}
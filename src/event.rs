@@ -0,0 +1,691 @@
+use crate::dap;
+use crate::json::{quote, Value};
+use crate::mi::{self, Tuple};
+use crate::output::{self, Format};
+use crate::signals::{self, meaning};
+use crate::style::{paint, Style};
+use nix::sys::signal::Signal;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What a command did or what happened to the inferior, as data rather than text, so it can be
+/// printed for people or, in `set output-format json`, as one JSON object per line for scripts.
+/// Programs embedding deet get them as they are; see `subscribe`.
+#[derive(Clone, Debug)]
+pub enum Event {
+    BreakpointSet {
+        number: usize,
+        address: usize,
+        location: Location,
+    },
+    Stopped {
+        reason: StopReason,
+        location: Location,
+    },
+    Exited {
+        status: i32,
+    },
+    Signaled {
+        signal: Signal,
+        core_dumped: bool,
+    },
+    Backtrace {
+        frames: Vec<Location>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub enum StopReason {
+    /// Breakpoint number
+    Breakpoint(usize),
+    /// Range watchpoint number, and the address written to
+    RangeWatchpoint(usize, usize),
+    /// Hardware watchpoint number, and the variable it watches, whose value changed
+    Watchpoint(usize, String),
+    /// Hardware watchpoint number, deleted as the frame of its local variable returned
+    WatchpointScope(usize),
+    Signal(Signal),
+    /// SIGSEGV, SIGBUS, SIGFPE or SIGILL, with the address a SIGSEGV or SIGBUS faulted on if the
+    /// kernel gave one
+    Fault(Signal, Option<usize>),
+    /// A core file was loaded; the signal that killed the process, if any
+    Core(Option<Signal>),
+    /// `inferior N` switched to another process
+    Switched(usize),
+    /// `target remote` connected to a stub with the program stopped
+    Connected,
+    /// The program ran past the run timeout and was stopped
+    Timeout(Duration),
+    /// `step` or `next` got to another line; whether it's in another function than before
+    Step(bool),
+    /// `finish` got back to the caller
+    Finished,
+}
+
+/// A code address and what the debug info says about it.
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub address: usize,
+    /// Already demangled or not, per `set print mangled`
+    pub function: Option<String>,
+    /// From the start of `function`; only given when there's no line to show instead
+    pub offset: Option<usize>,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    /// 0 if unknown
+    pub column: usize,
+    /// The function's arguments, names and formatted values, for stop banners
+    pub args: Option<Vec<(String, String)>>,
+    /// The file mapped at the address, e.g. a shared library, for stops where no function is
+    /// known there
+    pub mapping: Option<String>,
+}
+
+impl Location {
+    /// "name (file:line)" or "name+0x1a (0x401136)" or "0x401136", as in stop lines.
+    fn text(&self) -> String {
+        match (&self.function, &self.file, self.offset) {
+            (Some(name), Some(file), _) => {
+                let mut location = format!("{}:{}", file, self.line.unwrap_or(0));
+                if self.column != 0 {
+                    let _ = write!(location, ":{}", self.column);
+                }
+                format!(
+                    "{} ({})",
+                    paint(Style::Function, name),
+                    paint(Style::Location, location)
+                )
+            }
+            (Some(name), None, offset) => {
+                let name = match offset {
+                    Some(offset) => format!("{}+{:#x}", name, offset),
+                    None => name.clone(),
+                };
+                format!(
+                    "{} ({})",
+                    paint(Style::Function, name),
+                    paint(Style::Location, format!("{:#x}", self.address))
+                )
+            }
+            (None, _, _) => paint(Style::Location, format!("{:#x}", self.address)).to_string(),
+        }
+    }
+
+    /// "func2 (a=1, b=2) at file:line" or "0x401136 in name ()", as in stop banners.
+    pub fn frame_text(&self) -> String {
+        let args = self.args.as_deref().unwrap_or_default();
+        let args: Vec<String> = args
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let call = |name: &str| format!("{} ({})", paint(Style::Function, name), args.join(", "));
+        match (&self.function, &self.file) {
+            (Some(name), Some(file)) => format!(
+                "{} at {}",
+                call(name),
+                paint(
+                    Style::Location,
+                    format!("{}:{}", file, self.line.unwrap_or(0))
+                )
+            ),
+            (Some(name), None) => {
+                let name = match self.offset {
+                    Some(offset) => format!("{}+{:#x}", name, offset),
+                    None => name.clone(),
+                };
+                format!(
+                    "{} in {}",
+                    paint(Style::Location, format!("{:#x}", self.address)),
+                    call(&name)
+                )
+            }
+            (None, _) => {
+                let mut text = format!(
+                    "{} in {}",
+                    paint(Style::Location, format!("{:#x}", self.address)),
+                    call("??")
+                );
+                if let Some(mapping) = &self.mapping {
+                    let _ = write!(text, " from {}", paint(Style::Location, mapping));
+                }
+                text
+            }
+        }
+    }
+
+    fn json(&self) -> String {
+        let mut object = Object::new();
+        object.string("address", &format!("{:#x}", self.address));
+        if let Some(function) = &self.function {
+            object.string("function", function);
+        }
+        if let Some(offset) = self.offset {
+            object.number("offset", offset);
+        }
+        if let Some(file) = &self.file {
+            object.string("file", file);
+        }
+        if let Some(line) = self.line {
+            object.number("line", line);
+        }
+        if self.column != 0 {
+            object.number("column", self.column);
+        }
+        if let Some(args) = &self.args {
+            let args: Vec<String> = args
+                .iter()
+                .map(|(name, value)| {
+                    let mut arg = Object::new();
+                    arg.string("name", name);
+                    arg.string("value", value);
+                    arg.finish()
+                })
+                .collect();
+            object.raw("args", &format!("[{}]", args.join(",")));
+        }
+        if let Some(mapping) = &self.mapping {
+            object.string("from", mapping);
+        }
+        object.finish()
+    }
+}
+
+impl Location {
+    /// The `func`, `args`, `file`, `fullname` and `line` results of a frame or breakpoint.
+    fn mi_source(&self, tuple: &mut Tuple) {
+        if let Some(function) = &self.function {
+            tuple.string("func", function);
+        }
+        if let Some(args) = &self.args {
+            let args: Vec<String> = args
+                .iter()
+                .map(|(name, value)| {
+                    let mut arg = Tuple::default();
+                    arg.string("name", name).string("value", value);
+                    arg.braced()
+                })
+                .collect();
+            tuple.raw("args", &format!("[{}]", args.join(",")));
+        }
+        if let (Some(file), Some(line)) = (&self.file, self.line) {
+            let name = file.rsplit('/').next().unwrap_or(file);
+            tuple
+                .string("file", name)
+                .string("fullname", file)
+                .string("line", &line.to_string());
+        }
+    }
+
+    fn mi_frame(&self, level: Option<usize>) -> String {
+        let mut frame = Tuple::default();
+        if let Some(level) = level {
+            frame.string("level", &level.to_string());
+        }
+        frame.string("addr", &format!("{:#x}", self.address));
+        self.mi_source(&mut frame);
+        if let Some(mapping) = &self.mapping {
+            frame.string("from", mapping);
+        }
+        frame.braced()
+    }
+}
+
+impl Event {
+    fn text(&self) -> Vec<String> {
+        match self {
+            Event::BreakpointSet {
+                number, address, ..
+            } => {
+                vec![format!("Set breakpoint {} at {:#x}", number, address)]
+            }
+            Event::Stopped { reason, location } => match reason {
+                StopReason::Breakpoint(number) => vec![format!(
+                    "{} {}",
+                    paint(Style::Stop, format!("Breakpoint {},", number)),
+                    location.frame_text()
+                )],
+                StopReason::Signal(signal) => vec![
+                    paint(
+                        Style::Stop,
+                        format!(
+                            "Program received signal {}, {}.",
+                            signals::name(*signal),
+                            meaning(*signal)
+                        ),
+                    )
+                    .to_string(),
+                    location.frame_text(),
+                ],
+                StopReason::Timeout(timeout) => vec![
+                    paint(
+                        Style::Stop,
+                        format!("Timeout after {}s", timeout.as_secs_f64()),
+                    )
+                    .to_string(),
+                    location.frame_text(),
+                ],
+                // just the line, which the caller prints, unless it's in another function
+                StopReason::Step(false) => Vec::new(),
+                StopReason::Step(true) | StopReason::Finished => vec![location.frame_text()],
+                // the caller already said why
+                StopReason::RangeWatchpoint(..)
+                | StopReason::Watchpoint(..)
+                | StopReason::WatchpointScope(_)
+                | StopReason::Fault(..)
+                | StopReason::Core(_)
+                | StopReason::Switched(_)
+                | StopReason::Connected => {
+                    vec![location.frame_text()]
+                }
+            },
+            Event::Exited { status } => vec![format!("Child exited (status {})", status)],
+            Event::Signaled {
+                signal,
+                core_dumped,
+            } => vec![
+                format!(
+                    "Program terminated with signal {}, {}{}.",
+                    signals::name(*signal),
+                    meaning(*signal),
+                    if *core_dumped { " (core dumped)" } else { "" }
+                ),
+                "The program no longer exists.".to_string(),
+            ],
+            Event::Backtrace { frames } => frames
+                .iter()
+                .map(|frame| match &frame.function {
+                    Some(_) => frame.text(),
+                    None => format!(
+                        "{} ({})",
+                        paint(Style::Function, "??"),
+                        paint(Style::Location, format!("{:#x}", frame.address))
+                    ),
+                })
+                .collect(),
+        }
+    }
+
+    fn json(&self) -> String {
+        let mut object = Object::new();
+        match self {
+            Event::BreakpointSet {
+                number,
+                address,
+                location,
+            } => {
+                object.string("event", "breakpoint-set");
+                object.number("number", number);
+                object.string("address", &format!("{:#x}", address));
+                object.raw("location", &location.json());
+            }
+            Event::Stopped { reason, location } => {
+                object.string("event", "stopped");
+                match reason {
+                    StopReason::Breakpoint(number) => {
+                        object.string("reason", "breakpoint-hit");
+                        object.number("breakpoint", number);
+                    }
+                    StopReason::RangeWatchpoint(number, address) => {
+                        object.string("reason", "range-watchpoint-trigger");
+                        object.number("watchpoint", number);
+                        object.string("address", &format!("{:#x}", address));
+                    }
+                    StopReason::Watchpoint(number, name) => {
+                        object.string("reason", "watchpoint-trigger");
+                        object.number("watchpoint", number);
+                        object.string("variable", name);
+                    }
+                    StopReason::WatchpointScope(number) => {
+                        object.string("reason", "watchpoint-scope");
+                        object.number("watchpoint", number);
+                    }
+                    StopReason::Signal(signal) => {
+                        object.string("reason", "signal-received");
+                        object.string("signal", signals::name(*signal));
+                    }
+                    StopReason::Fault(signal, address) => {
+                        object.string("reason", "fault");
+                        object.string("signal", signals::name(*signal));
+                        if let Some(address) = address {
+                            object.string("fault_address", &format!("{:#x}", address));
+                        }
+                    }
+                    StopReason::Core(signal) => {
+                        object.string("reason", "core-file");
+                        if let Some(signal) = signal {
+                            object.string("signal", signals::name(*signal));
+                        }
+                    }
+                    StopReason::Switched(number) => {
+                        object.string("reason", "inferior-switched");
+                        object.number("inferior", number);
+                    }
+                    StopReason::Connected => object.string("reason", "remote-connected"),
+                    StopReason::Timeout(timeout) => {
+                        object.string("reason", "timeout");
+                        object.number("seconds", timeout.as_secs_f64());
+                    }
+                    StopReason::Step(_) => object.string("reason", "end-stepping-range"),
+                    StopReason::Finished => object.string("reason", "function-finished"),
+                }
+                object.raw("frame", &location.json());
+            }
+            Event::Exited { status } => {
+                object.string("event", "exited");
+                object.number("status", status);
+            }
+            Event::Signaled {
+                signal,
+                core_dumped,
+            } => {
+                object.string("event", "signaled");
+                object.string("signal", signals::name(*signal));
+                object.number("core_dumped", core_dumped);
+            }
+            Event::Backtrace { frames } => {
+                object.string("event", "backtrace");
+                let frames: Vec<String> = frames.iter().map(Location::json).collect();
+                object.raw("frames", &format!("[{}]", frames.join(",")));
+            }
+        }
+        object.finish()
+    }
+
+    /// Breakpoints and backtraces are results of the command that asked for them; the rest are
+    /// exec async records.
+    fn mi(&self) {
+        match self {
+            Event::BreakpointSet {
+                number,
+                address,
+                location,
+            } => {
+                let mut bkpt = Tuple::default();
+                bkpt.string("number", &number.to_string())
+                    .string("type", "breakpoint")
+                    .string("disp", "keep")
+                    .string("enabled", "y")
+                    .string("addr", &format!("{:#x}", address));
+                location.mi_source(&mut bkpt);
+                bkpt.string("times", "0");
+                mi::result(&format!("bkpt={}", bkpt.braced()), "breakpoint-created");
+            }
+            Event::Stopped { reason, location } => {
+                let mut record = Tuple::default();
+                match reason {
+                    StopReason::Breakpoint(number) => {
+                        record
+                            .string("reason", "breakpoint-hit")
+                            .string("disp", "keep")
+                            .string("bkptno", &number.to_string());
+                    }
+                    StopReason::RangeWatchpoint(number, _) => {
+                        let mut wpt = Tuple::default();
+                        wpt.string("number", &number.to_string());
+                        record
+                            .string("reason", "watchpoint-trigger")
+                            .raw("wpt", &wpt.braced());
+                    }
+                    StopReason::Watchpoint(number, name) => {
+                        let mut wpt = Tuple::default();
+                        wpt.string("number", &number.to_string())
+                            .string("exp", name);
+                        record
+                            .string("reason", "watchpoint-trigger")
+                            .raw("wpt", &wpt.braced());
+                    }
+                    StopReason::WatchpointScope(number) => {
+                        record
+                            .string("reason", "watchpoint-scope")
+                            .string("wpnum", &number.to_string());
+                    }
+                    StopReason::Signal(signal)
+                    | StopReason::Fault(signal, _)
+                    | StopReason::Core(Some(signal)) => {
+                        record
+                            .string("reason", "signal-received")
+                            .string("signal-name", signals::name(*signal))
+                            .string("signal-meaning", meaning(*signal));
+                    }
+                    // stopped with a SIGSTOP, which is what front ends know
+                    StopReason::Timeout(_) => {
+                        record
+                            .string("reason", "signal-received")
+                            .string("signal-name", "SIGSTOP")
+                            .string("signal-meaning", meaning(Signal::SIGSTOP));
+                    }
+                    StopReason::Step(_) => {
+                        record.string("reason", "end-stepping-range");
+                    }
+                    StopReason::Finished => {
+                        record.string("reason", "function-finished");
+                    }
+                    StopReason::Core(None) | StopReason::Switched(_) | StopReason::Connected => {}
+                }
+                record
+                    .raw("frame", &location.mi_frame(None))
+                    .string("thread-id", "1")
+                    .string("stopped-threads", "all");
+                mi::exec_async(&format!("stopped,{}", record.results()));
+            }
+            Event::Exited { status: 0 } => mi::exec_async("stopped,reason=\"exited-normally\""),
+            Event::Exited { status } => {
+                // gdb gives the exit code in octal
+                mi::exec_async(&format!(
+                    "stopped,reason=\"exited\",exit-code=\"{:02o}\"",
+                    status
+                ))
+            }
+            Event::Signaled { signal, .. } => {
+                let mut record = Tuple::default();
+                record
+                    .string("reason", "exited-signalled")
+                    .string("signal-name", signals::name(*signal))
+                    .string("signal-meaning", meaning(*signal));
+                mi::exec_async(&format!("stopped,{}", record.results()));
+            }
+            Event::Backtrace { frames } => {
+                let frames: Vec<String> = frames
+                    .iter()
+                    .enumerate()
+                    .map(|(level, frame)| format!("frame={}", frame.mi_frame(Some(level))))
+                    .collect();
+                mi::result(&format!("stack=[{}]", frames.join(",")), "stack");
+            }
+        }
+    }
+}
+
+impl Event {
+    /// Backtraces answer the stackTrace request that asked for them; the rest are events.
+    fn dap(&self) {
+        match self {
+            Event::BreakpointSet {
+                number, location, ..
+            } => dap::event(
+                "breakpoint",
+                Value::object([
+                    ("reason", "new".into()),
+                    ("breakpoint", location.dap_breakpoint(*number)),
+                ]),
+            ),
+            Event::Stopped { reason, .. } => {
+                let (reason, description, hit) = match reason {
+                    StopReason::Breakpoint(number) => ("breakpoint", None, vec![(*number).into()]),
+                    StopReason::RangeWatchpoint(..) | StopReason::Watchpoint(..) => {
+                        ("data breakpoint", None, vec![])
+                    }
+                    StopReason::WatchpointScope(number) => (
+                        "pause",
+                        Some(format!("Watchpoint {} went out of scope", number)),
+                        vec![],
+                    ),
+                    StopReason::Fault(signal, _) | StopReason::Core(Some(signal)) => {
+                        ("exception", Some(meaning(*signal).to_string()), vec![])
+                    }
+                    StopReason::Signal(signal) => {
+                        ("pause", Some(signals::name(*signal).to_string()), vec![])
+                    }
+                    StopReason::Core(None) | StopReason::Switched(_) => ("pause", None, vec![]),
+                    StopReason::Connected => ("entry", None, vec![]),
+                    StopReason::Step(_) | StopReason::Finished => ("step", None, vec![]),
+                    StopReason::Timeout(timeout) => (
+                        "pause",
+                        Some(format!("Timeout after {}s", timeout.as_secs_f64())),
+                        vec![],
+                    ),
+                };
+                let mut body = Value::object([
+                    ("reason", reason.into()),
+                    ("threadId", 1.into()),
+                    ("allThreadsStopped", true.into()),
+                    ("hitBreakpointIds", hit.into()),
+                ]);
+                if let (Some(description), Value::Object(members)) = (description, &mut body) {
+                    members.push(("description".to_string(), description.clone().into()));
+                    members.push(("text".to_string(), description.into()));
+                }
+                dap::event("stopped", body);
+            }
+            Event::Exited { status } => {
+                dap::event("exited", Value::object([("exitCode", (*status).into())]));
+                dap::event("terminated", Value::object([]));
+            }
+            Event::Signaled { signal, .. } => {
+                let status = 128 + *signal as i32;
+                dap::event("exited", Value::object([("exitCode", status.into())]));
+                dap::event("terminated", Value::object([]));
+            }
+            Event::Backtrace { frames } => {
+                let frames: Vec<Value> = frames
+                    .iter()
+                    .enumerate()
+                    .map(|(level, frame)| frame.dap_frame(level))
+                    .collect();
+                dap::respond(Value::object([
+                    ("totalFrames", frames.len().into()),
+                    ("stackFrames", frames.into()),
+                ]));
+            }
+        }
+    }
+}
+
+impl Location {
+    /// A DAP Source, if the file is known.
+    fn dap_source(&self) -> Option<Value> {
+        let file = self.file.as_ref()?;
+        let name = file.rsplit('/').next().unwrap_or(file);
+        Some(Value::object([
+            ("name", name.into()),
+            ("path", file.as_str().into()),
+        ]))
+    }
+
+    fn dap_frame(&self, level: usize) -> Value {
+        let mut frame = vec![
+            ("id".to_string(), level.into()),
+            (
+                "name".to_string(),
+                self.function.as_deref().unwrap_or("??").into(),
+            ),
+            ("line".to_string(), self.line.unwrap_or(0).into()),
+            ("column".to_string(), self.column.into()),
+            (
+                "instructionPointerReference".to_string(),
+                format!("{:#x}", self.address).into(),
+            ),
+        ];
+        if let Some(source) = self.dap_source() {
+            frame.push(("source".to_string(), source));
+        }
+        Value::Object(frame)
+    }
+
+    /// A verified DAP Breakpoint.
+    pub fn dap_breakpoint(&self, number: usize) -> Value {
+        let mut breakpoint = vec![
+            ("id".to_string(), number.into()),
+            ("verified".to_string(), true.into()),
+            (
+                "instructionReference".to_string(),
+                format!("{:#x}", self.address).into(),
+            ),
+        ];
+        if let Some(line) = self.line {
+            breakpoint.push(("line".to_string(), line.into()));
+        }
+        if let Some(source) = self.dap_source() {
+            breakpoint.push(("source".to_string(), source));
+        }
+        Value::Object(breakpoint)
+    }
+}
+
+/// What gets every event along with the output, for programs that embed deet
+type Listener = Box<dyn FnMut(&Event) + Send>;
+
+static LISTENERS: Mutex<Vec<Listener>> = Mutex::new(Vec::new());
+
+/// Calls `listener` with every event from now on, right after it's printed, e.g. to learn where
+/// the program stopped without parsing the output. A channel's sender gets them to another
+/// thread. The listener can't run commands or subscribe itself.
+pub fn subscribe(listener: impl FnMut(&Event) + Send + 'static) {
+    LISTENERS.lock().unwrap().push(Box::new(listener));
+}
+
+/// Prints `event` in the current output format, and passes it on to the listeners.
+pub fn emit(event: Event) {
+    match output::format() {
+        Format::Text => {
+            for line in event.text() {
+                outln!("{}", line);
+            }
+        }
+        Format::Json => output::write_record(&event.json()),
+        Format::Mi => event.mi(),
+        Format::Dap => event.dap(),
+    }
+    for listener in LISTENERS.lock().unwrap().iter_mut() {
+        listener(&event);
+    }
+}
+
+/// Just enough of a JSON writer for flat objects of strings and numbers.
+struct Object(String);
+
+impl Object {
+    fn new() -> Self {
+        Object(String::from("{"))
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        self.0.push_str(&quote(key));
+        self.0.push(':');
+    }
+
+    fn string(&mut self, key: &str, value: &str) {
+        self.key(key);
+        self.0.push_str(&quote(value));
+    }
+
+    fn number(&mut self, key: &str, value: impl std::fmt::Display) {
+        self.key(key);
+        let _ = write!(self.0, "{}", value);
+    }
+
+    /// `value` is already JSON
+    fn raw(&mut self, key: &str, value: &str) {
+        self.key(key);
+        self.0.push_str(value);
+    }
+
+    fn finish(mut self) -> String {
+        self.0.push('}');
+        self.0
+    }
+}
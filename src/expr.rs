@@ -0,0 +1,1419 @@
+//! C expressions over the state of the program being debugged, for `print` and whatever else
+//! needs a value (conditions, watchpoints, ...). An expression is parsed once into an `Expr`, then
+//! evaluated against a `Context`, which is all an expression can see of the program: its
+//! memory, registers and variables. Keeping that behind a trait means the same evaluator works
+//! on a live process, a core file, or nothing at all (for `print 1+2`).
+
+use crate::arch;
+use crate::dwarf_data::{TypeId, TypeKind, TypeTable};
+
+/// The grammar is C's without assignment, calls or the conditional operator: integer and
+/// character literals, variables, registers like `$rip`, the unary `- ! ~ * &`, casts, the binary
+/// operators from `*` down to `||` with C's precedence, and `a[i]`, `a.b` and `a->b`.
+///
+/// A cast is told from a parenthesized expression by its type name: one with a keyword such as
+/// `unsigned` or `struct`, or a `*`, is always a type, while a lone name like `size_t` is one only
+/// where a variable couldn't be, e.g. `(size_t) n` but not `(size_t) -n`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Integer(u64),
+    Float(f64),
+    Char(u8),
+    Variable(String),
+    /// Without the `$`
+    Register(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `a.b`; `a->b` is `(*a).b`, and `a[i]` is `*(a + i)`
+    Member(Box<Expr>, String),
+    /// `(type) a`: the name of the type without its `*`s, and how many of those there are
+    Cast(String, usize, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    BitNot,
+    Deref,
+    AddressOf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+/// What an expression can see of the program.
+pub trait Context {
+    fn types(&self) -> &TypeTable;
+    /// The type and address of variable `name`, as seen from where the program is stopped
+    fn variable(&self, name: &str) -> Option<(TypeId, usize)>;
+    /// The value of register `name`, without the `$`
+    fn register(&self, name: &str) -> Result<u64, String>;
+    fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<(), String>;
+    /// The function `addr` is in, with the offset into it, for printing pointers into code.
+    /// None if it isn't in one, or they aren't shown.
+    fn symbol(&self, _addr: usize) -> Option<String> {
+        None
+    }
+}
+
+/// The type of a value: one from the debug info, or one the evaluator makes up for literals,
+/// arithmetic and `&`, which the debug info may well not have.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ty {
+    Void,
+    /// Literals and integer arithmetic, like C's long
+    Long,
+    ULong,
+    Double,
+    Dwarf(TypeId),
+    PointerTo(Box<Ty>),
+    /// An array of the element type with these dimensions, e.g. a row of a 2D array
+    Array(Box<Ty>, Vec<Option<usize>>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Value {
+    pub ty: Ty,
+    pub place: Place,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Place {
+    /// Somewhere in the program's memory, so `&` can take its address
+    Memory(usize),
+    /// Computed: the bits of an integer, pointer or double
+    Scalar(u64),
+}
+
+/// The most array elements and string characters shown, like gdb's `print elements`
+const MAX_ELEMENTS: usize = 200;
+
+const MAX_DEPTH: usize = 8;
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Err("Argument required (expression to compute).".to_string());
+        }
+        let mut parser = Parser {
+            text,
+            tokens,
+            pos: 0,
+        };
+        let expr = parser.binary(0)?;
+        match parser.tokens.get(parser.pos) {
+            Some(_) => Err(parser.syntax_error()),
+            None => Ok(expr),
+        }
+    }
+
+    pub fn evaluate<C: Context>(&self, context: &C) -> Result<Value, String> {
+        Evaluator { context }.evaluate(self)
+    }
+}
+
+impl Value {
+    /// The value as `print` shows it: structs and arrays in braces, pointers with their type,
+    /// and strings for `char` pointers and arrays.
+    pub fn format<C: Context>(&self, context: &C) -> Result<String, String> {
+        Evaluator { context }.format(self, 0)
+    }
+
+    /// The name of its type, in C's syntax, e.g. `char *` or `int [3]`.
+    pub fn type_name<C: Context>(&self, context: &C) -> String {
+        Evaluator { context }.type_name(&self.ty)
+    }
+
+    /// The value as a number, for commands that take an address or a size: an integer or a
+    /// pointer, arrays and functions being pointers to themselves.
+    pub fn integer<C: Context>(&self, context: &C) -> Result<u64, String> {
+        match (Evaluator { context }).scalar(self)? {
+            Scalar::Int { bits, .. } | Scalar::Pointer(bits, _) => Ok(bits),
+            Scalar::Float(_) => Err("Can't use a floating point value here.".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Integer(u64),
+    Float(f64),
+    Char(u8),
+    Name(String),
+    Register(String),
+    Punct(&'static str),
+}
+
+/// Operators, the longest first so `<<` isn't taken for `<`
+const PUNCTUATION: &[&str] = &[
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "|", "^", "!", "~", "(", ")", "[", "]", ".",
+];
+
+/// The tokens of `text`, each with the byte offset it starts at.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let bytes = text.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        let word_end = |from: usize| {
+            from + bytes[from..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                .count()
+        };
+        let token = if c.is_ascii_digit() {
+            match float_end(text, pos) {
+                Some(end) => {
+                    pos = end;
+                    let literal = &text[start..pos];
+                    Token::Float(
+                        literal
+                            .parse()
+                            .map_err(|_| format!("Invalid number \"{}\".", literal))?,
+                    )
+                }
+                None => {
+                    pos = word_end(pos);
+                    Token::Integer(parse_integer(&text[start..pos])?)
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            pos = word_end(pos);
+            Token::Name(text[start..pos].to_string())
+        } else if c == b'$' {
+            pos = word_end(pos + 1);
+            if pos == start + 1 {
+                return Err("Missing register name after \"$\".".to_string());
+            }
+            Token::Register(text[start + 1..pos].to_string())
+        } else if c == b'\'' {
+            let (value, len) = parse_char(&text[pos..])?;
+            pos += len;
+            Token::Char(value)
+        } else {
+            let punct = PUNCTUATION
+                .iter()
+                .find(|punct| text[pos..].starts_with(**punct))
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid character '{}' in expression.",
+                        &text[pos..].chars().next().unwrap()
+                    )
+                })?;
+            pos += punct.len();
+            Token::Punct(punct)
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+/// Where a floating point literal like `2.5` or `1e-3` starting at `start` ends, or None if the
+/// number there is an integer.
+fn float_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let digits = |from: usize| {
+        from + bytes[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    let integer_end = digits(start);
+    let mut end = integer_end;
+    if bytes.get(end) == Some(&b'.') {
+        end = digits(end + 1);
+    }
+    if let Some(b'e' | b'E') = bytes.get(end) {
+        let sign = matches!(bytes.get(end + 1), Some(b'+' | b'-')) as usize;
+        let exponent_end = digits(end + 1 + sign);
+        if exponent_end == end + 1 + sign {
+            return None;
+        }
+        end = exponent_end;
+    }
+    (end > integer_end).then_some(end)
+}
+
+/// A C integer literal: decimal, hex after 0x, octal after a 0, with an optional u/l suffix.
+fn parse_integer(text: &str) -> Result<u64, String> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        _ if digits.len() > 1 && digits.starts_with('0') => (8, &digits[1..]),
+        _ => (10, digits),
+    };
+    u64::from_str_radix(digits, radix).map_err(|err| match err.kind() {
+        std::num::IntErrorKind::PosOverflow => format!("Numeric constant too large: {}.", text),
+        _ => format!("Invalid number \"{}\".", text),
+    })
+}
+
+/// A character literal at the start of `text`, like `'a'` or `'\n'`, and its length.
+fn parse_char(text: &str) -> Result<(u8, usize), String> {
+    let bytes = text.as_bytes();
+    let (value, len) = match bytes.get(1..) {
+        Some([b'\\', b'x', rest @ ..]) => {
+            let digits = rest.iter().take_while(|b| b.is_ascii_hexdigit()).count();
+            let value = u8::from_str_radix(&text[3..3 + digits], 16)
+                .map_err(|_| "Invalid hex escape in character constant.".to_string())?;
+            (value, 3 + digits)
+        }
+        Some([b'\\', b'0'..=b'7', ..]) => {
+            let digits = bytes[2..]
+                .iter()
+                .take(3)
+                .take_while(|b| (b'0'..=b'7').contains(*b))
+                .count();
+            let value = u8::from_str_radix(&text[2..2 + digits], 8)
+                .map_err(|_| "Invalid octal escape in character constant.".to_string())?;
+            (value, 2 + digits)
+        }
+        Some([b'\\', escaped, ..]) => {
+            let value = match escaped {
+                b'n' => b'\n',
+                b't' => b'\t',
+                b'r' => b'\r',
+                b'a' => 7,
+                b'b' => 8,
+                b'f' => 12,
+                b'v' => 11,
+                b'e' => 27,
+                other => *other,
+            };
+            (value, 3)
+        }
+        Some([b'\'', ..]) => return Err("Empty character constant.".to_string()),
+        Some([c, ..]) if c.is_ascii() => (*c, 2),
+        _ => return Err("Unmatched single quote.".to_string()),
+    };
+    match bytes.get(len) {
+        Some(b'\'') => Ok((value, len + 1)),
+        _ => Err("Unmatched single quote.".to_string()),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+/// The words that can only be part of a type name
+const TYPE_KEYWORDS: &[&str] = &[
+    "struct", "union", "enum", "unsigned", "signed", "char", "short", "int", "long", "float",
+    "double", "void", "_Bool", "const", "volatile",
+];
+
+/// C's shorthands for the base types, with the names gcc gives them in the debug info
+const TYPE_ALIASES: &[(&str, &str)] = &[
+    ("unsigned", "unsigned int"),
+    ("signed", "int"),
+    ("signed int", "int"),
+    ("short", "short int"),
+    ("unsigned short", "short unsigned int"),
+    ("long", "long int"),
+    ("unsigned long", "long unsigned int"),
+    ("long long", "long long int"),
+    ("unsigned long long", "long long unsigned int"),
+];
+
+/// The binary operators by token, with their precedence: higher binds tighter.
+fn binary_op(token: &Token) -> Option<(BinaryOp, usize)> {
+    let Token::Punct(punct) = token else {
+        return None;
+    };
+    let op = match *punct {
+        "||" => (BinaryOp::Or, 1),
+        "&&" => (BinaryOp::And, 2),
+        "|" => (BinaryOp::BitOr, 3),
+        "^" => (BinaryOp::BitXor, 4),
+        "&" => (BinaryOp::BitAnd, 5),
+        "==" => (BinaryOp::Eq, 6),
+        "!=" => (BinaryOp::Ne, 6),
+        "<" => (BinaryOp::Lt, 7),
+        "<=" => (BinaryOp::Le, 7),
+        ">" => (BinaryOp::Gt, 7),
+        ">=" => (BinaryOp::Ge, 7),
+        "<<" => (BinaryOp::Shl, 8),
+        ">>" => (BinaryOp::Shr, 8),
+        "+" => (BinaryOp::Add, 9),
+        "-" => (BinaryOp::Sub, 9),
+        "*" => (BinaryOp::Mul, 10),
+        "/" => (BinaryOp::Div, 10),
+        "%" => (BinaryOp::Rem, 10),
+        _ => return None,
+    };
+    Some(op)
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Punct(p)) if *p == punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn syntax_error(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some((_, start)) => format!(
+                "A syntax error in expression, near \"{}\".",
+                &self.text[*start..]
+            ),
+            None => "A syntax error in expression, near the end.".to_string(),
+        }
+    }
+
+    /// Binary operators of at least precedence `min`, left to right.
+    fn binary(&mut self, min: usize) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some((op, precedence)) = self.peek().and_then(binary_op) {
+            if precedence < min {
+                break;
+            }
+            self.pos += 1;
+            let right = self.binary(precedence + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if let Some((name, pointers, len)) = self.cast() {
+            self.pos += len;
+            return Ok(Expr::Cast(name, pointers, Box::new(self.unary()?)));
+        }
+        let op = match self.peek() {
+            Some(Token::Punct("-")) => UnaryOp::Negate,
+            Some(Token::Punct("!")) => UnaryOp::Not,
+            Some(Token::Punct("~")) => UnaryOp::BitNot,
+            Some(Token::Punct("*")) => UnaryOp::Deref,
+            Some(Token::Punct("&")) => UnaryOp::AddressOf,
+            Some(Token::Punct("+")) => {
+                self.pos += 1;
+                return self.unary();
+            }
+            _ => return self.postfix(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.binary(0)?;
+                if !self.eat("]") {
+                    return Err(self.syntax_error());
+                }
+                expr = Expr::Unary(
+                    UnaryOp::Deref,
+                    Box::new(Expr::Binary(BinaryOp::Add, Box::new(expr), Box::new(index))),
+                );
+            } else if self.eat(".") {
+                expr = Expr::Member(Box::new(expr), self.member_name()?);
+            } else if self.eat("->") {
+                let deref = Expr::Unary(UnaryOp::Deref, Box::new(expr));
+                expr = Expr::Member(Box::new(deref), self.member_name()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// The type of the cast that starts here, if one does: its name, how many `*`s follow, and
+    /// how many tokens it takes up with its parentheses.
+    fn cast(&self) -> Option<(String, usize, usize)> {
+        let tokens = self.tokens.get(self.pos..)?;
+        if !matches!(tokens.first(), Some((Token::Punct("("), _))) {
+            return None;
+        }
+        let names: Vec<&str> = tokens[1..]
+            .iter()
+            .map_while(|(token, _)| match token {
+                Token::Name(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        let rest = &tokens[1 + names.len()..];
+        let pointers = rest
+            .iter()
+            .take_while(|(token, _)| *token == Token::Punct("*"))
+            .count();
+        if names.is_empty() || !matches!(rest.get(pointers), Some((Token::Punct(")"), _))) {
+            return None;
+        }
+        let is_type = names.len() > 1
+            || pointers > 0
+            || TYPE_KEYWORDS.contains(&names[0])
+            // what follows can't go after a variable in parentheses
+            || matches!(
+                rest.get(pointers + 1),
+                Some((
+                    Token::Integer(_)
+                        | Token::Float(_)
+                        | Token::Char(_)
+                        | Token::Name(_)
+                        | Token::Register(_)
+                        | Token::Punct("(" | "!" | "~"),
+                    _
+                ))
+            );
+        is_type.then(|| (names.join(" "), pointers, 1 + names.len() + pointers + 1))
+    }
+
+    fn member_name(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.syntax_error()),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let expr = match self.peek() {
+            Some(Token::Integer(value)) => Expr::Integer(*value),
+            Some(Token::Float(value)) => Expr::Float(*value),
+            Some(Token::Char(value)) => Expr::Char(*value),
+            Some(Token::Name(name)) => Expr::Variable(name.clone()),
+            Some(Token::Register(name)) => Expr::Register(name.clone()),
+            Some(Token::Punct("(")) => {
+                self.pos += 1;
+                let expr = self.binary(0)?;
+                if !self.eat(")") {
+                    return Err(self.syntax_error());
+                }
+                return Ok(expr);
+            }
+            _ => return Err(self.syntax_error()),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+/// How a type behaves in arithmetic.
+enum Class {
+    Signed(usize),
+    Unsigned(usize),
+    Float(usize),
+    Pointer(Ty),
+    Array(Ty, Vec<Option<usize>>),
+    Struct(Vec<crate::dwarf_data::Member>),
+    Function,
+    Void,
+    Unknown,
+}
+
+/// A value loaded for arithmetic.
+enum Scalar {
+    Int { bits: u64, signed: bool },
+    Float(f64),
+    Pointer(u64, Ty),
+}
+
+struct Evaluator<'a, C> {
+    context: &'a C,
+}
+
+impl<C: Context> Evaluator<'_, C> {
+    fn types(&self) -> &TypeTable {
+        self.context.types()
+    }
+
+    fn classify(&self, ty: &Ty) -> Class {
+        let id = match ty {
+            Ty::Void => return Class::Void,
+            Ty::Long => return Class::Signed(8),
+            Ty::ULong => return Class::Unsigned(8),
+            Ty::Double => return Class::Float(8),
+            Ty::PointerTo(target) => return Class::Pointer((**target).clone()),
+            Ty::Array(element, dimensions) => {
+                return Class::Array((**element).clone(), dimensions.clone())
+            }
+            Ty::Dwarf(id) => self.types().strip_typedefs(*id),
+        };
+        let dtype = self.types().get(id);
+        let size = self.types().size_of(id);
+        match &dtype.kind {
+            TypeKind::Base => {
+                let name = dtype.name.as_deref().unwrap_or("");
+                if name.contains("float") || name.contains("double") {
+                    Class::Float(size)
+                } else if name.contains("unsigned") || name == "_Bool" || name == "bool" {
+                    Class::Unsigned(size)
+                } else {
+                    Class::Signed(size)
+                }
+            }
+            TypeKind::Enum(_) => Class::Signed(size),
+            TypeKind::Pointer(target) => Class::Pointer(target.map_or(Ty::Void, Ty::Dwarf)),
+            TypeKind::Array(Some(element), dimensions) => {
+                Class::Array(Ty::Dwarf(*element), dimensions.clone())
+            }
+            TypeKind::Struct(members) | TypeKind::Union(members) => Class::Struct(members.clone()),
+            TypeKind::Function(_) => Class::Function,
+            _ => Class::Unknown,
+        }
+    }
+
+    fn size_of(&self, ty: &Ty) -> usize {
+        match ty {
+            // as in gcc, void * arithmetic counts bytes
+            Ty::Void => 1,
+            Ty::Long | Ty::ULong | Ty::Double => 8,
+            Ty::PointerTo(_) => self.types().pointer_size(),
+            Ty::Dwarf(id) => self.types().size_of(*id),
+            Ty::Array(element, dimensions) => {
+                dimensions
+                    .iter()
+                    .map(|count| count.unwrap_or(0))
+                    .product::<usize>()
+                    * self.size_of(element)
+            }
+        }
+    }
+
+    fn type_name(&self, ty: &Ty) -> String {
+        match ty {
+            Ty::Void => "void".to_string(),
+            Ty::Long => "long".to_string(),
+            Ty::ULong => "unsigned long".to_string(),
+            Ty::Double => "double".to_string(),
+            Ty::Dwarf(id) => self.types().name_of(*id),
+            Ty::PointerTo(target) => {
+                let target = self.type_name(target);
+                if target.ends_with('*') {
+                    format!("{}*", target)
+                } else {
+                    format!("{} *", target)
+                }
+            }
+            Ty::Array(element, dimensions) => {
+                let dimensions: String = dimensions
+                    .iter()
+                    .map(|count| count.map_or("[]".to_string(), |count| format!("[{}]", count)))
+                    .collect();
+                format!("{} {}", self.type_name(element), dimensions)
+            }
+        }
+    }
+
+    fn read(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![0; len];
+        self.context.read_memory(addr, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// The bits of a value of `size` bytes, wherever it is.
+    fn bits(&self, value: &Value, size: usize) -> Result<u64, String> {
+        match value.place {
+            Place::Scalar(bits) => Ok(bits),
+            Place::Memory(addr) => {
+                let mut word = [0; 8];
+                let size = size.min(8);
+                word[..size].copy_from_slice(&self.read(addr, size)?);
+                Ok(u64::from_le_bytes(word))
+            }
+        }
+    }
+
+    /// Loads a value for arithmetic. Arrays decay to a pointer to their first element.
+    fn scalar(&self, value: &Value) -> Result<Scalar, String> {
+        match self.classify(&value.ty) {
+            Class::Signed(size) => {
+                let bits = self.bits(value, size)?;
+                let shift = 64 - 8 * size.clamp(1, 8) as u32;
+                Ok(Scalar::Int {
+                    bits: ((bits << shift) as i64 >> shift) as u64,
+                    signed: true,
+                })
+            }
+            Class::Unsigned(size) => {
+                let bits = self.bits(value, size)?;
+                let shift = 64 - 8 * size.clamp(1, 8) as u32;
+                Ok(Scalar::Int {
+                    bits: bits << shift >> shift,
+                    signed: size < 8,
+                })
+            }
+            Class::Float(4) => Ok(Scalar::Float(
+                f32::from_bits(self.bits(value, 4)? as u32) as f64
+            )),
+            Class::Float(size) => Ok(Scalar::Float(f64::from_bits(self.bits(value, size)?))),
+            Class::Pointer(target) => Ok(Scalar::Pointer(
+                self.bits(value, self.size_of(&value.ty))?,
+                target,
+            )),
+            Class::Array(element, dimensions) => match value.place {
+                Place::Memory(addr) => {
+                    Ok(Scalar::Pointer(addr as u64, row_type(element, &dimensions)))
+                }
+                Place::Scalar(_) => Err("Array not in memory.".to_string()),
+            },
+            Class::Function => match value.place {
+                Place::Memory(addr) => Ok(Scalar::Pointer(addr as u64, value.ty.clone())),
+                Place::Scalar(_) => Err("Function not in memory.".to_string()),
+            },
+            Class::Struct(_) => Err(format!(
+                "Can't do arithmetic on a value of type {}.",
+                self.type_name(&value.ty)
+            )),
+            Class::Void => Err("Can't use a void value.".to_string()),
+            Class::Unknown => Err("Can't use a value of an unknown type.".to_string()),
+        }
+    }
+
+    fn evaluate(&self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Integer(value) => Ok(Value {
+                ty: if *value > i64::MAX as u64 {
+                    Ty::ULong
+                } else {
+                    Ty::Long
+                },
+                place: Place::Scalar(*value),
+            }),
+            Expr::Float(value) => Ok(double(*value)),
+            Expr::Char(value) => Ok(integer(*value as u64, true)),
+            Expr::Variable(name) => match self.context.variable(name) {
+                Some((type_id, addr)) => Ok(Value {
+                    ty: Ty::Dwarf(type_id),
+                    place: Place::Memory(addr),
+                }),
+                None => Err(format!("No symbol \"{}\" in current context.", name)),
+            },
+            Expr::Register(name) => {
+                let bits = self.context.register(name)?;
+                let ty = match arch::is_address_register(name) {
+                    true => Ty::PointerTo(Box::new(Ty::Void)),
+                    false => Ty::Long,
+                };
+                Ok(Value {
+                    ty,
+                    place: Place::Scalar(bits),
+                })
+            }
+            Expr::Unary(op, operand) => self.unary(*op, self.evaluate(operand)?),
+            Expr::Binary(op @ (BinaryOp::And | BinaryOp::Or), left, right) => {
+                // the right side is only evaluated if it matters, as in C
+                let left = self.truth(&self.evaluate(left)?)?;
+                let result = match (op, left) {
+                    (BinaryOp::And, false) => false,
+                    (BinaryOp::Or, true) => true,
+                    _ => self.truth(&self.evaluate(right)?)?,
+                };
+                Ok(integer(result as u64, true))
+            }
+            Expr::Binary(op, left, right) => {
+                self.binary(*op, self.evaluate(left)?, self.evaluate(right)?)
+            }
+            Expr::Member(operand, name) => {
+                let value = self.evaluate(operand)?;
+                let Class::Struct(members) = self.classify(&value.ty) else {
+                    return Err(format!(
+                        "Attempt to extract a component of a value that is not a structure: {}.",
+                        self.type_name(&value.ty)
+                    ));
+                };
+                let member = members
+                    .iter()
+                    .find(|member| member.name == *name)
+                    .ok_or_else(|| format!("There is no member named {}.", name))?;
+                let (Place::Memory(addr), Some(type_id)) = (value.place, member.type_id) else {
+                    return Err(format!("Can't read member {}.", name));
+                };
+                Ok(Value {
+                    ty: Ty::Dwarf(type_id),
+                    place: Place::Memory(addr + member.offset),
+                })
+            }
+            Expr::Cast(name, pointers, operand) => {
+                let mut ty = self.named_type(name)?;
+                for _ in 0..*pointers {
+                    ty = Ty::PointerTo(Box::new(ty));
+                }
+                self.cast(self.evaluate(operand)?, ty)
+            }
+        }
+    }
+
+    /// The type a cast names, from the debug info if it's there.
+    fn named_type(&self, name: &str) -> Result<Ty, String> {
+        let name = TYPE_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, name)| name);
+        if let Some(id) = self.types().find(name) {
+            return Ok(Ty::Dwarf(id));
+        }
+        match name {
+            "void" => Ok(Ty::Void),
+            "long int" => Ok(Ty::Long),
+            "long unsigned int" => Ok(Ty::ULong),
+            "double" => Ok(Ty::Double),
+            _ => Err(format!("No symbol \"{}\" in current context.", name)),
+        }
+    }
+
+    /// Converts a value to `ty` as C does. Only numbers and pointers can be converted.
+    fn cast(&self, value: Value, ty: Ty) -> Result<Value, String> {
+        let class = self.classify(&ty);
+        match class {
+            Class::Void => {
+                return Ok(Value {
+                    ty,
+                    place: Place::Scalar(0),
+                })
+            }
+            Class::Signed(_) | Class::Unsigned(_) | Class::Float(_) | Class::Pointer(_) => {}
+            _ => return Err(format!("Invalid cast to {}.", self.type_name(&ty))),
+        }
+        let bits = match (class, self.scalar(&value)?) {
+            (
+                Class::Signed(_) | Class::Unsigned(_) | Class::Pointer(_),
+                Scalar::Int { bits, .. } | Scalar::Pointer(bits, _),
+            ) => bits,
+            (Class::Signed(_), Scalar::Float(value)) => value as i64 as u64,
+            (Class::Unsigned(_), Scalar::Float(value)) => value as u64,
+            (Class::Float(_), Scalar::Pointer(..)) => {
+                return Err("Invalid cast of a pointer to a floating point type.".to_string())
+            }
+            (Class::Float(4), scalar) => (to_float(scalar) as f32).to_bits() as u64,
+            (_, scalar) => to_float(scalar).to_bits(),
+        };
+        Ok(Value {
+            ty,
+            place: Place::Scalar(bits),
+        })
+    }
+
+    fn truth(&self, value: &Value) -> Result<bool, String> {
+        Ok(match self.scalar(value)? {
+            Scalar::Int { bits, .. } | Scalar::Pointer(bits, _) => bits != 0,
+            Scalar::Float(value) => value != 0.0,
+        })
+    }
+
+    fn unary(&self, op: UnaryOp, value: Value) -> Result<Value, String> {
+        match op {
+            UnaryOp::AddressOf => match value.place {
+                Place::Memory(addr) => Ok(Value {
+                    ty: Ty::PointerTo(Box::new(value.ty)),
+                    place: Place::Scalar(addr as u64),
+                }),
+                Place::Scalar(_) => {
+                    Err("Attempt to take address of value not located in memory.".to_string())
+                }
+            },
+            UnaryOp::Deref => match self.scalar(&value)? {
+                Scalar::Pointer(_, Ty::Void) => {
+                    Err("Attempt to take contents of a void pointer.".to_string())
+                }
+                Scalar::Pointer(addr, target) => Ok(Value {
+                    ty: target,
+                    place: Place::Memory(addr as usize),
+                }),
+                _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+            },
+            UnaryOp::Not => Ok(integer(!self.truth(&value)? as u64, true)),
+            UnaryOp::Negate => match self.scalar(&value)? {
+                Scalar::Int { bits, signed } => Ok(integer(bits.wrapping_neg(), signed)),
+                Scalar::Float(value) => Ok(double(-value)),
+                Scalar::Pointer(..) => {
+                    Err("Argument to arithmetic operation not a number.".to_string())
+                }
+            },
+            UnaryOp::BitNot => match self.scalar(&value)? {
+                Scalar::Int { bits, signed } => Ok(integer(!bits, signed)),
+                _ => Err("Argument to complement operation not an integer.".to_string()),
+            },
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+        use BinaryOp::*;
+        let (left, right) = (self.scalar(&left)?, self.scalar(&right)?);
+        match (left, right) {
+            (Scalar::Pointer(addr, target), Scalar::Int { bits, .. })
+            | (Scalar::Int { bits, .. }, Scalar::Pointer(addr, target))
+                if matches!(op, Add | Sub) =>
+            {
+                let scaled = bits.wrapping_mul(self.size_of(&target) as u64);
+                let addr = match op {
+                    Add => addr.wrapping_add(scaled),
+                    _ => addr.wrapping_sub(scaled),
+                };
+                Ok(Value {
+                    ty: Ty::PointerTo(Box::new(target)),
+                    place: Place::Scalar(addr),
+                })
+            }
+            (Scalar::Pointer(left, target), Scalar::Pointer(right, _)) if op == Sub => {
+                let size = self.size_of(&target).max(1) as i64;
+                Ok(integer(
+                    ((left as i64).wrapping_sub(right as i64) / size) as u64,
+                    true,
+                ))
+            }
+            (Scalar::Pointer(left, _), Scalar::Pointer(right, _))
+            | (Scalar::Pointer(left, _), Scalar::Int { bits: right, .. })
+            | (Scalar::Int { bits: left, .. }, Scalar::Pointer(right, _)) => {
+                let result = match op {
+                    Eq => left == right,
+                    Ne => left != right,
+                    Lt => left < right,
+                    Le => left <= right,
+                    Gt => left > right,
+                    Ge => left >= right,
+                    _ => return Err("Invalid operation on a pointer.".to_string()),
+                };
+                Ok(integer(result as u64, true))
+            }
+            (Scalar::Float(left), right) => self.float(op, left, to_float(right)),
+            (left, Scalar::Float(right)) => self.float(op, to_float(left), right),
+            (
+                Scalar::Int {
+                    bits: left,
+                    signed: left_signed,
+                },
+                Scalar::Int {
+                    bits: right,
+                    signed: right_signed,
+                },
+            ) => {
+                let signed = left_signed && right_signed;
+                let ordering = if signed {
+                    (left as i64).cmp(&(right as i64))
+                } else {
+                    left.cmp(&right)
+                };
+                let truth = |result: bool| Ok(integer(result as u64, true));
+                let bits = match op {
+                    Add => left.wrapping_add(right),
+                    Sub => left.wrapping_sub(right),
+                    Mul => left.wrapping_mul(right),
+                    Div | Rem if right == 0 => return Err("Division by zero".to_string()),
+                    Div if signed => (left as i64).wrapping_div(right as i64) as u64,
+                    Div => left / right,
+                    Rem if signed => (left as i64).wrapping_rem(right as i64) as u64,
+                    Rem => left % right,
+                    Shl => left.wrapping_shl(right as u32),
+                    Shr if left_signed => (left as i64).wrapping_shr(right as u32) as u64,
+                    Shr => left.wrapping_shr(right as u32),
+                    BitAnd => left & right,
+                    BitOr => left | right,
+                    BitXor => left ^ right,
+                    Lt => return truth(ordering.is_lt()),
+                    Le => return truth(ordering.is_le()),
+                    Gt => return truth(ordering.is_gt()),
+                    Ge => return truth(ordering.is_ge()),
+                    Eq => return truth(ordering.is_eq()),
+                    Ne => return truth(ordering.is_ne()),
+                    And | Or => unreachable!("evaluated with short circuits"),
+                };
+                Ok(integer(bits, signed))
+            }
+        }
+    }
+
+    fn float(&self, op: BinaryOp, left: f64, right: f64) -> Result<Value, String> {
+        use BinaryOp::*;
+        let truth = |result: bool| Ok(integer(result as u64, true));
+        match op {
+            Add => Ok(double(left + right)),
+            Sub => Ok(double(left - right)),
+            Mul => Ok(double(left * right)),
+            Div => Ok(double(left / right)),
+            Lt => truth(left < right),
+            Le => truth(left <= right),
+            Gt => truth(left > right),
+            Ge => truth(left >= right),
+            Eq => truth(left == right),
+            Ne => truth(left != right),
+            _ => Err("Integer operation on a floating point value.".to_string()),
+        }
+    }
+
+    fn format(&self, value: &Value, depth: usize) -> Result<String, String> {
+        if depth > MAX_DEPTH {
+            return Ok("...".to_string());
+        }
+        match (self.classify(&value.ty), &value.ty) {
+            (Class::Struct(members), _) => {
+                let Place::Memory(addr) = value.place else {
+                    return Err("Structure not in memory.".to_string());
+                };
+                let mut fields = Vec::new();
+                for member in &members {
+                    let field = match member.type_id {
+                        Some(type_id) => self.format(
+                            &Value {
+                                ty: Ty::Dwarf(type_id),
+                                place: Place::Memory(addr + member.offset),
+                            },
+                            depth + 1,
+                        )?,
+                        None => "<unknown type>".to_string(),
+                    };
+                    fields.push(format!("{} = {}", member.name, field));
+                }
+                Ok(format!("{{{}}}", fields.join(", ")))
+            }
+            (Class::Array(element, dimensions), _) => {
+                let Place::Memory(addr) = value.place else {
+                    return Err("Array not in memory.".to_string());
+                };
+                let count = dimensions.first().copied().flatten().unwrap_or(0);
+                let row = row_type(element, &dimensions);
+                if dimensions.len() == 1 && self.is_char(&row) {
+                    let bytes = self.read(addr, count.min(MAX_ELEMENTS))?;
+                    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+                    let more = if len == MAX_ELEMENTS && count > len {
+                        "..."
+                    } else {
+                        ""
+                    };
+                    return Ok(format!("\"{}\"{}", escape(&bytes[..len]), more));
+                }
+                let size = self.size_of(&row);
+                let mut items = Vec::new();
+                for i in 0..count.min(MAX_ELEMENTS) {
+                    let item = Value {
+                        ty: row.clone(),
+                        place: Place::Memory(addr + i * size),
+                    };
+                    items.push(self.format(&item, depth + 1)?);
+                }
+                if count > MAX_ELEMENTS {
+                    items.push("...".to_string());
+                }
+                Ok(format!("{{{}}}", items.join(", ")))
+            }
+            (Class::Pointer(target), ty) => {
+                let addr = self.bits(value, self.size_of(&value.ty))?;
+                if self.is_char(&target) && addr != 0 {
+                    return Ok(format!("{:#x} {}", addr, self.string(addr as usize)));
+                }
+                Ok(format!(
+                    "({}) {:#x}{}",
+                    self.type_name(ty),
+                    addr,
+                    self.symbol_suffix(addr as usize)
+                ))
+            }
+            (Class::Void, _) => Ok("void".to_string()),
+            (Class::Function, ty) => match value.place {
+                Place::Memory(addr) => Ok(format!(
+                    "{{{}}} {:#x}{}",
+                    self.type_name(ty),
+                    addr,
+                    self.symbol_suffix(addr)
+                )),
+                Place::Scalar(_) => Err("Function not in memory.".to_string()),
+            },
+            (_, Ty::Dwarf(type_id)) => {
+                let size = self.size_of(&value.ty);
+                let bytes = match value.place {
+                    Place::Memory(addr) => self.read(addr, size.min(8))?,
+                    Place::Scalar(bits) => bits.to_le_bytes().to_vec(),
+                };
+                Ok(self.types().format_value(*type_id, &bytes))
+            }
+            _ => Ok(match self.scalar(value)? {
+                Scalar::Int { bits, signed: true } => (bits as i64).to_string(),
+                Scalar::Int {
+                    bits,
+                    signed: false,
+                } => bits.to_string(),
+                Scalar::Float(value) => format!("{:?}", value),
+                Scalar::Pointer(addr, _) => format!("{:#x}", addr),
+            }),
+        }
+    }
+
+    /// " <main+0x26>" for an address in a function, as pointers are printed
+    fn symbol_suffix(&self, addr: usize) -> String {
+        self.context
+            .symbol(addr)
+            .map_or_else(String::new, |symbol| format!(" <{}>", symbol))
+    }
+
+    fn is_char(&self, ty: &Ty) -> bool {
+        let Ty::Dwarf(id) = ty else {
+            return false;
+        };
+        let id = self.types().strip_typedefs(*id);
+        let dtype = self.types().get(id);
+        matches!(dtype.kind, TypeKind::Base)
+            && dtype.size == Some(1)
+            && dtype
+                .name
+                .as_deref()
+                .is_some_and(|name| name.contains("char"))
+    }
+
+    /// The NUL-terminated string at `addr`, quoted, or why it can't be read.
+    fn string(&self, addr: usize) -> String {
+        let mut bytes = Vec::new();
+        let mut chunk = [0; 8];
+        while bytes.len() < MAX_ELEMENTS {
+            if self
+                .context
+                .read_memory(addr + bytes.len(), &mut chunk)
+                .is_err()
+            {
+                if bytes.is_empty() {
+                    return format!("<error: Cannot access memory at address {:#x}>", addr);
+                }
+                break;
+            }
+            match chunk.iter().position(|b| *b == 0) {
+                Some(end) => {
+                    bytes.extend_from_slice(&chunk[..end]);
+                    return format!("\"{}\"", escape(&bytes));
+                }
+                None => bytes.extend_from_slice(&chunk),
+            }
+        }
+        bytes.truncate(MAX_ELEMENTS);
+        format!("\"{}\"...", escape(&bytes))
+    }
+}
+
+/// The type of the rows of an array: the element for one dimension, or an array of the rest.
+fn row_type(element: Ty, dimensions: &[Option<usize>]) -> Ty {
+    match dimensions {
+        [] | [_] => element,
+        [_, rest @ ..] => Ty::Array(Box::new(element), rest.to_vec()),
+    }
+}
+
+fn integer(bits: u64, signed: bool) -> Value {
+    Value {
+        ty: if signed { Ty::Long } else { Ty::ULong },
+        place: Place::Scalar(bits),
+    }
+}
+
+fn double(value: f64) -> Value {
+    Value {
+        ty: Ty::Double,
+        place: Place::Scalar(value.to_bits()),
+    }
+}
+
+fn to_float(scalar: Scalar) -> f64 {
+    match scalar {
+        Scalar::Int { bits, signed: true } => bits as i64 as f64,
+        Scalar::Int {
+            bits,
+            signed: false,
+        }
+        | Scalar::Pointer(bits, _) => bits as f64,
+        Scalar::Float(value) => value,
+    }
+}
+
+/// Bytes as the inside of a C string literal.
+fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                text.push('\\');
+                text.push(byte as char);
+            }
+            b' '..=b'~' => text.push(byte as char),
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            _ => text.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dwarf_data::{Member, Type};
+
+    /// Where the mock program's memory starts
+    const BASE: usize = 0x1000;
+
+    /// A program with a few variables of the common kinds, laid out in `memory` from BASE.
+    struct MockContext {
+        types: TypeTable,
+        variables: Vec<(&'static str, TypeId, usize)>,
+        memory: Vec<u8>,
+    }
+
+    impl MockContext {
+        fn new() -> MockContext {
+            let mut types = TypeTable::new(8);
+            let mut offset = 0;
+            let mut define = |name: Option<&str>, size: Option<usize>, kind: TypeKind| {
+                // each type a DIE of its own
+                offset += 1;
+                let id = types.id_for_offset(offset);
+                types.define(
+                    id,
+                    Type {
+                        name: name.map(str::to_string),
+                        size,
+                        kind,
+                    },
+                );
+                id
+            };
+            let int = define(Some("int"), Some(4), TypeKind::Base);
+            let char = define(Some("char"), Some(1), TypeKind::Base);
+            define(Some("unsigned int"), Some(4), TypeKind::Base);
+            let double = define(Some("double"), Some(8), TypeKind::Base);
+            let point = define(
+                Some("point"),
+                Some(8),
+                TypeKind::Struct(vec![
+                    Member {
+                        name: "x".to_string(),
+                        type_id: Some(int),
+                        offset: 0,
+                    },
+                    Member {
+                        name: "y".to_string(),
+                        type_id: Some(int),
+                        offset: 4,
+                    },
+                ]),
+            );
+            let int_pointer = define(None, None, TypeKind::Pointer(Some(int)));
+            let point_pointer = define(None, None, TypeKind::Pointer(Some(point)));
+            let int_array = define(None, None, TypeKind::Array(Some(int), vec![Some(4)]));
+            let char_array = define(None, None, TypeKind::Array(Some(char), vec![Some(8)]));
+
+            let mut memory = vec![0; 0x40];
+            let mut put = |addr: usize, bytes: &[u8]| {
+                memory[addr - BASE..addr - BASE + bytes.len()].copy_from_slice(bytes)
+            };
+            put(0x1000, &(-7i32).to_le_bytes());
+            put(0x1008, &3i32.to_le_bytes());
+            put(0x100c, &4i32.to_le_bytes());
+            for (i, value) in [10i32, 20, 30, 40].iter().enumerate() {
+                put(0x1010 + 4 * i, &value.to_le_bytes());
+            }
+            put(0x1020, &0x1010u64.to_le_bytes());
+            put(0x1028, &0x1008u64.to_le_bytes());
+            put(0x1030, b"hello\0");
+            put(0x1038, &2.5f64.to_le_bytes());
+            MockContext {
+                types,
+                variables: vec![
+                    ("n", int, 0x1000),
+                    ("p", point, 0x1008),
+                    ("arr", int_array, 0x1010),
+                    ("ptr", int_pointer, 0x1020),
+                    ("pp", point_pointer, 0x1028),
+                    ("name", char_array, 0x1030),
+                    ("d", double, 0x1038),
+                ],
+                memory,
+            }
+        }
+    }
+
+    impl Context for MockContext {
+        fn types(&self) -> &TypeTable {
+            &self.types
+        }
+
+        fn variable(&self, name: &str) -> Option<(TypeId, usize)> {
+            self.variables
+                .iter()
+                .find(|(variable, _, _)| *variable == name)
+                .map(|(_, type_id, addr)| (*type_id, *addr))
+        }
+
+        fn register(&self, name: &str) -> Result<u64, String> {
+            match name {
+                "rsp" => Ok(0x1030),
+                _ => Err(format!("Invalid register \"{}\".", name)),
+            }
+        }
+
+        fn read_memory(&self, addr: usize, buf: &mut [u8]) -> Result<(), String> {
+            let start = addr.wrapping_sub(BASE);
+            match self.memory.get(start..start + buf.len()) {
+                Some(bytes) if addr >= BASE => {
+                    buf.copy_from_slice(bytes);
+                    Ok(())
+                }
+                _ => Err(format!("Cannot access memory at address {:#x}", addr)),
+            }
+        }
+    }
+
+    /// `text` evaluated in the mock program and formatted as `print` shows it.
+    fn print(text: &str) -> Result<String, String> {
+        let context = MockContext::new();
+        Expr::parse(text)?.evaluate(&context)?.format(&context)
+    }
+
+    fn check(cases: &[(&str, &str)]) {
+        for (text, expected) in cases {
+            assert_eq!(print(text).as_deref(), Ok(*expected), "{}", text);
+        }
+    }
+
+    fn check_errors(cases: &[(&str, &str)]) {
+        for (text, expected) in cases {
+            assert_eq!(print(text), Err(expected.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        check(&[
+            ("1 + 2 * 3", "7"),
+            ("(1 + 2) * 3", "9"),
+            ("10 - 4 - 3", "3"),
+            ("7 / 2", "3"),
+            ("-7 % 3", "-1"),
+            ("1 << 4 | 1", "17"),
+            ("0xff & ~0xf", "240"),
+            ("6 ^ 3", "5"),
+            ("n * 2", "-14"),
+            ("n < 0 && n > -10", "1"),
+            ("0 || !5", "0"),
+            ("1 == 1 != 0", "1"),
+            ("'A' + 1", "66"),
+            ("d * 2", "5.0"),
+            ("1 / 2.0", "0.5"),
+            ("0xffffffffffffffff", "18446744073709551615"),
+            ("$rsp", "(void *) 0x1030"),
+        ]);
+    }
+
+    #[test]
+    fn members_indexes_and_dereferences() {
+        check(&[
+            ("p.x", "3"),
+            ("p.y * 10", "40"),
+            ("pp->y", "4"),
+            ("(*pp).x", "3"),
+            ("arr[2]", "30"),
+            ("*ptr", "10"),
+            ("ptr[3]", "40"),
+            ("*(ptr + 1)", "20"),
+            ("&arr[3] - ptr", "3"),
+            ("*&n", "-7"),
+            ("name[1]", "101 'e'"),
+            ("p", "{x = 3, y = 4}"),
+            ("arr", "{10, 20, 30, 40}"),
+            ("name", "\"hello\""),
+            ("&p", "(struct point *) 0x1008"),
+            ("ptr + 1", "(int *) 0x1014"),
+        ]);
+    }
+
+    #[test]
+    fn casts() {
+        check(&[
+            ("(char) 321", "65 'A'"),
+            ("(unsigned int) -1", "4294967295"),
+            ("(unsigned) n", "4294967289"),
+            ("(int) 2.9", "2"),
+            ("(int) -2.9", "-2"),
+            ("(double) 7 / 2", "3.5"),
+            ("(long) ptr", "4112"),
+            ("(int *) 0x1014", "(int *) 0x1014"),
+            ("*(int *) 0x1014", "20"),
+            ("((struct point *) &p)->y", "4"),
+            ("(char *) ptr", "0x1010 \"\\n\""),
+            ("(void) n", "void"),
+            // a variable in parentheses, not a cast
+            ("(n) - 1", "-8"),
+            ("-(int) d", "-2"),
+        ]);
+    }
+
+    #[test]
+    fn errors() {
+        check_errors(&[
+            ("", "Argument required (expression to compute)."),
+            ("nosuch + 1", "No symbol \"nosuch\" in current context."),
+            ("*n", "Attempt to take contents of a non-pointer value."),
+            ("p.z", "There is no member named z."),
+            (
+                "n.x",
+                "Attempt to extract a component of a value that is not a structure: int.",
+            ),
+            (
+                "&1",
+                "Attempt to take address of value not located in memory.",
+            ),
+            ("1 / 0", "Division by zero"),
+            (
+                "p + 1",
+                "Can't do arithmetic on a value of type struct point.",
+            ),
+            ("d % 2", "Integer operation on a floating point value."),
+            ("ptr * 2", "Invalid operation on a pointer."),
+            ("*(int *) 0x10", "Cannot access memory at address 0x10"),
+            ("$nope", "Invalid register \"nope\"."),
+            ("(struct point) n", "Invalid cast to struct point."),
+            ("(nosuch *) 0", "No symbol \"nosuch\" in current context."),
+            (
+                "(double) ptr",
+                "Invalid cast of a pointer to a floating point type.",
+            ),
+            ("1 +", "A syntax error in expression, near the end."),
+            ("(", "A syntax error in expression, near the end."),
+            ("arr[1", "A syntax error in expression, near the end."),
+            ("1 2", "A syntax error in expression, near \"2\"."),
+            ("p.", "A syntax error in expression, near the end."),
+            ("'ab'", "Unmatched single quote."),
+            (
+                "99999999999999999999",
+                "Numeric constant too large: 99999999999999999999.",
+            ),
+            ("1 # 2", "Invalid character '#' in expression."),
+        ]);
+    }
+}
@@ -5,252 +5,579 @@
 //!
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
-use gimli;
 use gimli::{UnitOffset, UnitSectionOffset};
 use object::{Object, ObjectSection};
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    File, Function, Line, Location, Member, Type, TypeId, TypeKind, TypeTable, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::fmt;
 use std::fmt::Write;
+use std::sync::Arc;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
-    // Load a section and return as `Cow<[u8]>`.
-    let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
-        match object.section_by_name(id.name()) {
-            Some(ref section) => Ok(section
+/// The DWARF sections of `object`, copied out of it so they outlast its mapping, and can be read
+/// by several threads at once.
+pub fn load_sections(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<gimli::Dwarf<DwarfReader>, Error> {
+    let load_section = |id: gimli::SectionId| -> Result<DwarfReader, gimli::Error> {
+        let data = match object.section_by_name(id.name()) {
+            Some(ref section) => section
                 .uncompressed_data()
-                .unwrap_or(borrow::Cow::Borrowed(&[][..]))),
-            None => Ok(borrow::Cow::Borrowed(&[][..])),
-        }
+                .unwrap_or(borrow::Cow::Borrowed(&[][..])),
+            None => borrow::Cow::Borrowed(&[][..]),
+        };
+        Ok(gimli::EndianArcSlice::new(Arc::from(&*data), endian))
     };
-    // Load a supplementary section. We don't have a supplementary object file,
-    // so always return an empty slice.
-    // let load_section_sup = |_| Ok(borrow::Cow::Borrowed(&[][..]));
-
-    // Load all of the sections.
-    let dwarf_cow = gimli::Dwarf::load(&load_section)?;
-
-    // Borrow a `Cow<[u8]>` to create an `EndianSlice`.
-    let borrow_section: &dyn for<'a> Fn(
-        &'a borrow::Cow<[u8]>,
-    ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
+    Ok(gimli::Dwarf::load(load_section)?)
+}
 
-    // Create `EndianSlice`s for all of the sections.
-    let dwarf = dwarf_cow.borrow(&borrow_section);
+/// What the DWARF sections are read through
+pub type DwarfReader = gimli::EndianArcSlice<gimli::RunTimeEndian>;
 
-    // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+/// Indexes the files, functions, variables and line tables of every compilation unit. The types
+/// are left for `load_types`: the variables' are only placeholders in the table this returns.
+pub fn load_file(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    pointer_size: usize,
+) -> Result<DebugInfo, Error> {
+    let (headers, header_error) = unit_headers(dwarf);
+    let shares = in_shares(&headers, |share| load_units(dwarf, share, pointer_size));
 
+    // The types of the variables, keyed by the offset of their DIEs in .debug_info
+    let mut types = TypeTable::new(pointer_size);
     let mut compilation_units: Vec<File> = Vec::new();
+    // The first unit by each name, which gets the line table rows for that file
+    let mut files_by_name: HashMap<String, usize> = HashMap::new();
+    let mut broken_units = Vec::new();
+    for share in shares {
+        let ids = types.merge(share.types);
+        for unit in share.units {
+            let (files, lines) = match unit {
+                Ok(unit) => unit,
+                Err(broken) => {
+                    broken_units.push(broken);
+                    continue;
+                }
+            };
+            for mut file in files {
+                files_by_name
+                    .entry(file.name.clone())
+                    .or_insert(compilation_units.len());
+                for var in file
+                    .global_variables
+                    .iter_mut()
+                    .chain(file.functions.iter_mut().flat_map(|f| &mut f.variables))
+                {
+                    var.entity_type = ids[var.entity_type];
+                }
+                compilation_units.push(file);
+            }
+            for line in lines {
+                if let Some(&index) = files_by_name.get(&line.file) {
+                    compilation_units[index].lines.push(line);
+                }
+            }
+        }
+    }
+    broken_units.extend(header_error);
+    Ok(DebugInfo {
+        files: compilation_units,
+        types,
+        broken_units,
+    })
+}
+
+/// Parses every type DIE into `types`, which keeps the ids of the types it already has.
+/// Compilation units that can't be parsed were reported by `load_file`, and only give the types
+/// read before the error.
+pub fn load_types(dwarf: &gimli::Dwarf<DwarfReader>, types: &mut TypeTable) {
+    let (headers, _) = unit_headers(dwarf);
+    let pointer_size = types.pointer_size();
+    let shares = in_shares(&headers, |share| {
+        let mut types = TypeTable::new(pointer_size);
+        for header in share {
+            if let Ok(unit) = dwarf.unit(header.clone()) {
+                let _ = load_unit_types(dwarf, &unit, &mut types);
+            }
+        }
+        types
+    });
+    for share in shares {
+        types.merge(share);
+    }
+}
 
-    // Iterate over the compilation units.
+/// The headers of the compilation units, up to the first that can't be read, if there is one.
+fn unit_headers<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+) -> (Vec<gimli::UnitHeader<R>>, Option<BrokenUnit>) {
+    // A unit that fails to parse (e.g. one emitted by an unusual toolchain for a vendored
+    // library) is recorded and skipped so the rest of the program stays debuggable.
+    let mut headers = Vec::new();
     let mut iter = dwarf.units();
-    while let Some(header) = iter.next()? {
-        let unit = dwarf.unit(header)?;
-
-        // Iterate over the Debugging Information Entries (DIEs) in the unit.
-        let mut depth = 0;
-        let mut entries = unit.entries();
-        while let Some((delta_depth, entry)) = entries.next_dfs()? {
-            depth += delta_depth;
-            // Update the offset_to_type mapping for types
-            // Update the variable list for formal params/variables
-            match entry.tag() {
-                gimli::DW_TAG_compile_unit => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
+    loop {
+        match iter.next() {
+            Ok(Some(header)) => headers.push(header),
+            Ok(None) => return (headers, None),
+            Err(err) => {
+                // Without a valid header we can't tell where the next unit starts
+                let broken = BrokenUnit {
+                    offset: None,
+                    producer: None,
+                    error: Error::from(err),
+                };
+                return (headers, Some(broken));
+            }
+        }
+    }
+}
+
+/// Runs `index` on contiguous shares of the units, a thread each, and returns what it gave in the
+/// order of the units in .debug_info, so merging them gives the same as indexing them in turn.
+fn in_shares<R: Reader, T: Send>(
+    headers: &[gimli::UnitHeader<R>],
+    index: impl Fn(&[gimli::UnitHeader<R>]) -> T + Sync,
+) -> Vec<T> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_INDEXING_THREADS)
+        .min(headers.len() / MIN_UNITS_PER_THREAD)
+        .max(1);
+    if threads == 1 {
+        return vec![index(headers)];
+    }
+    let share_size = headers.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = headers
+            .chunks(share_size)
+            .map(|share| scope.spawn(|| index(share)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("DWARF indexing thread panicked"))
+            .collect()
+    })
+}
+
+/// The most threads that index compilation units at once
+const MAX_INDEXING_THREADS: usize = 16;
+
+/// How many compilation units make another indexing thread worth starting
+const MIN_UNITS_PER_THREAD: usize = 8;
+
+/// What indexing a run of compilation units gives: their variables' types, and for each unit its files and
+/// line table rows, or why it was skipped. The TypeIds are those of `types`.
+struct Indexed {
+    types: TypeTable,
+    units: Vec<Result<UnitIndex, BrokenUnit>>,
+}
+
+/// The files of a compilation unit, and the rows of its line table
+type UnitIndex = (Vec<File>, Vec<Line>);
+
+fn load_units<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    headers: &[gimli::UnitHeader<R>],
+    pointer_size: usize,
+) -> Indexed {
+    let mut types = TypeTable::new(pointer_size);
+    let units = headers
+        .iter()
+        .map(|header| {
+            let offset = match header.offset() {
+                UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+                UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+            };
+            let mut producer = None;
+            let mut files = Vec::new();
+            let mut lines = Vec::new();
+            dwarf
+                .unit(header.clone())
+                .map_err(Error::from)
+                .and_then(|unit| {
+                    producer = get_unit_producer(&unit, dwarf);
+                    load_unit(dwarf, &unit, &mut types, &mut files, &mut lines)
+                })
+                .map(|()| (files, lines))
+                .map_err(|error| BrokenUnit {
+                    offset: Some(offset),
+                    producer,
+                    error,
+                })
+        })
+        .collect();
+    Indexed { types, units }
+}
+
+/// Everything extracted from the DWARF sections of an executable.
+pub struct DebugInfo {
+    pub files: Vec<File>,
+    pub types: TypeTable,
+    pub broken_units: Vec<BrokenUnit>,
+}
+
+/// A compilation unit that was skipped because its debug info couldn't be parsed.
+#[derive(Debug, Clone)]
+pub struct BrokenUnit {
+    pub offset: Option<usize>, // Offset of the unit header in .debug_info, if it could be read
+    pub producer: Option<String>,
+    pub error: Error,
+}
+
+impl fmt::Display for BrokenUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "compilation unit at offset {:#x}", offset)?,
+            None => write!(f, "compilation unit header")?,
+        }
+        if let Some(producer) = &self.producer {
+            write!(f, " (produced by {})", producer)?;
+        }
+        write!(f, ": {:?}", self.error)
+    }
+}
+
+fn get_unit_producer<R: Reader>(unit: &gimli::Unit<R>, dwarf: &gimli::Dwarf<R>) -> Option<String> {
+    let mut entries = unit.entries();
+    let (_, root) = entries.next_dfs().ok()??;
+    get_str_attr(root, gimli::DW_AT_producer, unit, dwarf)
+}
+
+/// Collects the files, functions and variables of a single compilation unit, and the rows of its
+/// line table, which belong to whichever file they name.
+fn load_unit<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    types: &mut TypeTable,
+    compilation_units: &mut Vec<File>,
+    lines: &mut Vec<Line>,
+) -> Result<(), Error> {
+    // Iterate over the Debugging Information Entries (DIEs) in the unit.
+    let mut depth = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        // Update the variable list for formal params/variables
+        match entry.tag() {
+            gimli::DW_TAG_compile_unit => {
+                let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
+                    if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, unit, dwarf) {
+                        name
                     } else {
                         "<unknown>".to_string()
-                    };
-                    compilation_units.push(File {
-                        name,
-                        global_variables: Vec::new(),
-                        functions: Vec::new(),
-                        lines: Vec::new(),
-                    });
-                }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
+                    }
+                } else {
+                    "<unknown>".to_string()
+                };
+                let has_macros = [
+                    gimli::DW_AT_macros,
+                    gimli::DW_AT_GNU_macros,
+                    gimli::DW_AT_macro_info,
+                ]
+                .into_iter()
+                .any(|attr| matches!(entry.attr(attr), Ok(Some(_))));
+                compilation_units.push(File {
+                    name,
+                    comp_dir: get_str_attr(entry, gimli::DW_AT_comp_dir, unit, dwarf),
+                    producer: get_str_attr(entry, gimli::DW_AT_producer, unit, dwarf),
+                    dwarf_version: unit.header.version(),
+                    has_macros,
+                    source_files: line_table_files(unit, dwarf)?,
+                    global_variables: Vec::new(),
+                    functions: Vec::new(),
+                    lines: Vec::new(),
+                });
+            }
+            gimli::DW_TAG_subprogram => {
+                let mut func: Function = Default::default();
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, unit, dwarf);
+                    //outln!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(name)) = val {
+                                func.name = name;
+                            }
                         }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
+                        gimli::DW_AT_high_pc => {
+                            if let Ok(DebugValue::Uint(high_pc)) = val {
+                                func.text_length = high_pc.try_into().unwrap();
+                            }
                         }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
-                gimli::DW_TAG_subprogram => {
-                    let mut func: Function = Default::default();
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(name)) = val {
-                                    func.name = name;
-                                }
+                        gimli::DW_AT_low_pc => {
+                            //outln!("low pc {:?}", attr.value());
+                            if let Ok(DebugValue::Uint(low_pc)) = val {
+                                func.address = low_pc.try_into().unwrap();
                             }
-                            gimli::DW_AT_high_pc => {
-                                if let Ok(DebugValue::Uint(high_pc)) = val {
-                                    func.text_length = high_pc.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_decl_line => {
+                            if let Ok(DebugValue::Uint(line_number)) = val {
+                                func.line_number = line_number.try_into().unwrap();
                             }
-                            gimli::DW_AT_low_pc => {
-                                //println!("low pc {:?}", attr.value());
-                                if let Ok(DebugValue::Uint(low_pc)) = val {
-                                    func.address = low_pc.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_linkage_name | gimli::DW_AT_MIPS_linkage_name => {
+                            if let Ok(DebugValue::Str(linkage_name)) = val {
+                                func.linkage_name = Some(linkage_name);
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(line_number)) = val {
-                                    func.line_number = line_number.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_specification | gimli::DW_AT_abstract_origin => {
+                            // C++ member functions defined outside their class only carry
+                            // their names on the declaration this attribute points to
+                            let (name, linkage_name) = get_declaration_names(&attr, unit, dwarf, 0);
+                            if func.name.is_empty() {
+                                func.name = name.unwrap_or_default();
+                            }
+                            if func.linkage_name.is_none() {
+                                func.linkage_name = linkage_name;
                             }
-                            _ => {}
                         }
+                        _ => {}
+                    }
+                }
+                // DW_AT_high_pc is either a length or an absolute address depending on its
+                // form, and functions split into hot/cold parts use DW_AT_ranges instead, so
+                // let gimli work out the real pc ranges
+                let mut ranges = dwarf.die_ranges(unit, entry)?;
+                while let Some(range) = ranges.next()? {
+                    if range.begin < range.end {
+                        func.ranges.push((
+                            range.begin.try_into().unwrap(),
+                            range.end.try_into().unwrap(),
+                        ));
                     }
+                }
+                if let Some(&(low, high)) = func.ranges.first() {
+                    func.address = low;
+                    func.text_length = high - low;
+                }
+                // Skip declarations (e.g. member function prototypes inside a class), which
+                // have no code of their own
+                if func.address != 0 {
                     compilation_units.last_mut().unwrap().functions.push(func);
                 }
-                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
-                    let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
-                    let mut location: Option<Location> = None;
-                    let mut line_number = 0;
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(attr_name)) = val {
-                                    name = attr_name;
-                                }
+            }
+            gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                let mut name = String::new();
+                let mut entity_type: Option<TypeId> = None;
+                let mut location: Option<Location> = None;
+                let mut line_number = 0;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, unit, dwarf);
+                    //outln!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(attr_name)) = val {
+                                name = attr_name;
                             }
-                            gimli::DW_AT_type => {
-                                if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
-                                }
+                        }
+                        gimli::DW_AT_type => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                entity_type = Some(types.id_for_offset(offset));
                             }
-                            gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
+                        }
+                        gimli::DW_AT_location => {
+                            if let Some(loc) = get_location(&attr, unit) {
+                                location = Some(loc);
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(num)) = val {
-                                    line_number = num;
-                                }
+                        }
+                        gimli::DW_AT_decl_line => {
+                            if let Ok(DebugValue::Uint(num)) = val {
+                                line_number = num;
                             }
-                            _ => {}
                         }
+                        _ => {}
                     }
-                    if entity_type.is_some() && location.is_some() {
-                        let var = Variable {
-                            name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
-                            line_number: line_number.try_into().unwrap(),
-                        };
-                        if depth == 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .global_variables
-                                .push(var);
-                        } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                }
+                if let (Some(entity_type), Some(location)) = (entity_type, location) {
+                    let var = Variable {
+                        name,
+                        entity_type,
+                        location,
+                        line_number: line_number.try_into().unwrap(),
+                        is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+                    };
+                    if depth == 1 {
+                        compilation_units
+                            .last_mut()
+                            .unwrap()
+                            .global_variables
+                            .push(var);
+                    } else if depth > 1 {
+                        if let Some(func) =
+                            compilation_units.last_mut().unwrap().functions.last_mut()
+                        {
+                            func.variables.push(var);
                         }
                     }
                 }
-                // NOTE: :You may consider supporting other types by extending this
-                // match statement
-                _ => {}
-            }
-        }
-
-        // Get line numbers
-        if let Some(program) = unit.line_program.clone() {
-            // Iterate over the line program rows.
-            let mut rows = program.rows();
-            while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
-                    // Determine the path. Real applications should cache this for performance.
-                    let mut path = path::PathBuf::new();
-                    if let Some(file) = row.file(header) {
-                        if let Some(dir) = file.directory(header) {
-                            path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
-                        }
-                        path.push(
-                            dwarf
-                                .attr_string(&unit, file.path_name())?
-                                .to_string_lossy()
-                                .as_ref(),
-                        );
+            }
+            // NOTE: :You may consider supporting other types by extending this
+            // match statement
+            _ => {}
+        }
+    }
+
+    // Get line numbers
+    if let Some(program) = unit.line_program.clone() {
+        // Iterate over the line program rows.
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            if !row.end_sequence() {
+                // Determine the path. Real applications should cache this for performance.
+                let mut path = path::PathBuf::new();
+                if let Some(file) = row.file(header) {
+                    if let Some(dir) = file.directory(header) {
+                        path.push(dwarf.attr_string(unit, dir)?.to_string_lossy()?.as_ref());
                     }
+                    path.push(
+                        dwarf
+                            .attr_string(unit, file.path_name())?
+                            .to_string_lossy()?
+                            .as_ref(),
+                    );
+                }
 
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                // Determine line/column. DWARF line/column is never 0, so we use that
+                // but other applications may want to display this differently.
+                let line = if let Some(line) = row.line() {
+                    line.get().try_into().unwrap()
+                } else {
+                    0
+                };
+                let column = match row.column() {
+                    gimli::ColumnType::LeftEdge => 0,
+                    gimli::ColumnType::Column(column) => column.get().try_into().unwrap(),
+                };
 
-                    // Determine line/column. DWARF line/column is never 0, so we use that
-                    // but other applications may want to display this differently.
-                    let line = if let Some(line) = row.line() {
-                        line.get().try_into().unwrap()
-                    } else {
-                        0
-                    };
+                lines.push(Line {
+                    file: path.to_string_lossy().into_owned(),
+                    number: line,
+                    column,
+                    address: row.address().try_into().unwrap(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
 
-                    if let Some(file) = file {
-                        file.lines.push(Line {
-                            file: file.name.clone(),
-                            number: line.try_into().unwrap(),
-                            address: row.address().try_into().unwrap(),
-                        });
+/// Interns the types a single compilation unit describes into `types`.
+fn load_unit_types<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    types: &mut TypeTable,
+) -> Result<(), Error> {
+    let mut depth = 0;
+    // Aggregate types whose children (members, enumerators, ...) are being visited, along
+    // with their depth in the tree
+    let mut type_parents: Vec<(TypeId, isize)> = Vec::new();
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        match entry.tag() {
+            gimli::DW_TAG_base_type
+            | gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_class_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_enumeration_type
+            | gimli::DW_TAG_array_type
+            | gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_subroutine_type => {
+                let type_offset = get_global_offset(entry.offset(), unit);
+                let id = types.id_for_offset(type_offset);
+                let name = get_str_attr(entry, gimli::DW_AT_name, unit, dwarf);
+                let size = get_uint_attr(entry, gimli::DW_AT_byte_size, unit, dwarf)
+                    .map(|size| size as usize);
+                let target =
+                    get_type_attr(entry, unit, dwarf).map(|offset| types.id_for_offset(offset));
+                let kind = match entry.tag() {
+                    gimli::DW_TAG_base_type => TypeKind::Base,
+                    gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+                        TypeKind::Pointer(target)
+                    }
+                    gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type => {
+                        TypeKind::Struct(Vec::new())
                     }
+                    gimli::DW_TAG_union_type => TypeKind::Union(Vec::new()),
+                    gimli::DW_TAG_enumeration_type => TypeKind::Enum(Vec::new()),
+                    gimli::DW_TAG_array_type => TypeKind::Array(target, Vec::new()),
+                    gimli::DW_TAG_typedef => TypeKind::Typedef(target),
+                    gimli::DW_TAG_const_type => TypeKind::Const(target),
+                    gimli::DW_TAG_volatile_type => TypeKind::Volatile(target),
+                    _ => TypeKind::Function(target),
+                };
+                types.define(id, Type { name, size, kind });
+                if entry.has_children() {
+                    type_parents.push((id, depth));
                 }
             }
+            // Children of the aggregate types above: struct/union members, enumerators and
+            // array dimensions
+            gimli::DW_TAG_member | gimli::DW_TAG_enumerator | gimli::DW_TAG_subrange_type => {
+                while matches!(type_parents.last(), Some(&(_, parent_depth)) if parent_depth >= depth)
+                {
+                    type_parents.pop();
+                }
+                if let Some(&(parent, parent_depth)) = type_parents.last() {
+                    if parent_depth == depth - 1 {
+                        add_type_child(types, parent, entry, unit, dwarf);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The paths of the files a unit's line table names, in its order and without repeats. Relative
+/// directories are taken to be in the compilation directory.
+fn line_table_files<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<Vec<String>, Error> {
+    let Some(program) = &unit.line_program else {
+        return Ok(Vec::new());
+    };
+    let header = program.header();
+    let mut files = Vec::new();
+    for file in header.file_names() {
+        let mut path = path::PathBuf::new();
+        if let Some(comp_dir) = &unit.comp_dir {
+            path.push(comp_dir.to_string_lossy()?.as_ref());
+        }
+        if let Some(dir) = file.directory(header) {
+            // an absolute directory replaces the compilation directory
+            path.push(dwarf.attr_string(unit, dir)?.to_string_lossy()?.as_ref());
+        }
+        path.push(
+            dwarf
+                .attr_string(unit, file.path_name())?
+                .to_string_lossy()?
+                .as_ref(),
+        );
+        let path = path.to_string_lossy().into_owned();
+        if !files.contains(&path) {
+            files.push(path);
         }
     }
-    Ok(compilation_units)
+    Ok(files)
 }
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub enum DebugValue {
     Str(String),
     Uint(u64),
@@ -260,6 +587,7 @@ pub enum DebugValue {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     GimliError(gimli::Error),
     // Addr2lineError(addr2line::gimli::Error),
@@ -291,10 +619,7 @@ impl From<std::fmt::Error> for Error {
     }
 }
 
-impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
-    Endian: gimli::Endianity + Send + Sync
-{
-}
+impl<Endian> Reader for gimli::EndianArcSlice<Endian> where Endian: gimli::Endianity + Send + Sync {}
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
@@ -319,6 +644,135 @@ fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) ->
     None
 }
 
+fn get_global_offset<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+fn get_str_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(s)) => Some(s),
+        _ => None,
+    }
+}
+
+fn get_uint_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<u64> {
+    let attr = entry.attr(name).ok()??;
+    // Small constants are usually encoded as DW_FORM_data1/2/4, which get_attr_value doesn't
+    // decode
+    if let Some(n) = attr.udata_value() {
+        return Some(n);
+    }
+    match get_attr_value(&attr, unit, dwarf) {
+        Ok(DebugValue::Uint(n)) => Some(n),
+        Ok(DebugValue::_Int(n)) => n.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Returns the .debug_info offset of the type referenced by an entry's DW_AT_type.
+fn get_type_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_type).ok()??, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+fn add_type_child<R: Reader>(
+    types: &mut TypeTable,
+    parent: TypeId,
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) {
+    let member_type = get_type_attr(entry, unit, dwarf).map(|offset| types.id_for_offset(offset));
+    let name = get_str_attr(entry, gimli::DW_AT_name, unit, dwarf).unwrap_or_default();
+    match (&mut types.get_mut(parent).kind, entry.tag()) {
+        (TypeKind::Struct(members) | TypeKind::Union(members), gimli::DW_TAG_member) => {
+            let offset = get_uint_attr(entry, gimli::DW_AT_data_member_location, unit, dwarf)
+                .unwrap_or(0) as usize;
+            members.push(Member {
+                name,
+                type_id: member_type,
+                offset,
+            });
+        }
+        (TypeKind::Enum(values), gimli::DW_TAG_enumerator) => {
+            let value = match entry.attr_value(gimli::DW_AT_const_value) {
+                Ok(Some(value)) => value
+                    .sdata_value()
+                    .or(value.udata_value().map(|v| v as i64)),
+                _ => None,
+            };
+            values.push((name, value.unwrap_or(0)));
+        }
+        (TypeKind::Array(_, dimensions), gimli::DW_TAG_subrange_type) => {
+            let count = get_uint_attr(entry, gimli::DW_AT_count, unit, dwarf).or_else(|| {
+                get_uint_attr(entry, gimli::DW_AT_upper_bound, unit, dwarf).map(|upper| upper + 1)
+            });
+            dimensions.push(count.map(|count| count as usize));
+        }
+        _ => {}
+    }
+}
+
+/// Follows a DW_AT_specification/DW_AT_abstract_origin reference and returns the name and linkage
+/// name recorded on the referenced declaration.
+fn get_declaration_names<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    depth: usize,
+) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut linkage_name = None;
+    let offset = match attr.value() {
+        gimli::AttributeValue::UnitRef(offset) => offset,
+        _ => return (name, linkage_name),
+    };
+    let entry = match unit.entry(offset) {
+        Ok(entry) => entry,
+        Err(_) => return (name, linkage_name),
+    };
+    let mut attrs = entry.attrs();
+    while let Ok(Some(attr)) = attrs.next() {
+        let val = get_attr_value(&attr, unit, dwarf);
+        match (attr.name(), val) {
+            (gimli::DW_AT_name, Ok(DebugValue::Str(attr_name))) => name = Some(attr_name),
+            (
+                gimli::DW_AT_linkage_name | gimli::DW_AT_MIPS_linkage_name,
+                Ok(DebugValue::Str(attr_name)),
+            ) => linkage_name = Some(attr_name),
+            // An inlined/out-of-line instance may point at a definition that itself points at
+            // the in-class declaration
+            (gimli::DW_AT_specification | gimli::DW_AT_abstract_origin, _) if depth < 4 => {
+                let (inner_name, inner_linkage_name) =
+                    get_declaration_names(&attr, unit, dwarf, depth + 1);
+                name = name.or(inner_name);
+                linkage_name = linkage_name.or(inner_linkage_name);
+            }
+            _ => {}
+        }
+    }
+    (name, linkage_name)
+}
+
 // based on dwarf_dump.rs
 fn get_attr_value<R: Reader>(
     attr: &gimli::Attribute<R>,
@@ -356,14 +810,17 @@ fn get_attr_value<R: Reader>(
             write!(w, "0x{:08x}", value)?;
             dump_file_index(w, value, unit, dwarf)?;
             Ok(DebugValue::Str(w.to_string()))
-        },
+        }
         gimli::AttributeValue::DebugLineStrRef(offset) => {
             if let Ok(s) = dwarf.debug_line_str.get_str(offset) {
                 Ok(DebugValue::Str(format!("{}", s.to_string_lossy()?)))
             } else {
-                Ok(DebugValue::Str(format!("<.debug_line_str+0x{:08x}>", offset.0)))
+                Ok(DebugValue::Str(format!(
+                    "<.debug_line_str+0x{:08x}>",
+                    offset.0
+                )))
             }
-        },
+        }
         _ => Ok(DebugValue::NoVal),
     }
 }
@@ -614,10 +1071,10 @@ fn dump_op<R: Reader, W: Write>(
         | gimli::Operation::PushObjectAddress
         | gimli::Operation::TLS
         | gimli::Operation::CallFrameCFA
-        | gimli::Operation::StackValue => {},
+        | gimli::Operation::StackValue => {}
         gimli::Operation::WasmGlobal { index: _ }
         | gimli::Operation::WasmLocal { index: _ }
         | gimli::Operation::WasmStack { index: _ } => {}
     };
     Ok(())
-}
\ No newline at end of file
+}
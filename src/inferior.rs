@@ -1,13 +1,34 @@
-use crate::debugger::Breakpoint;
-use crate::dwarf_data::DwarfData;
-use nix::sys::ptrace;
+use crate::arch;
+use crate::child_log::OutputLog;
+use crate::dwarf_data::{format_symbol_name, DwarfData};
+use crate::error::DeetError;
+use crate::event::Location;
+use crate::remote::{Remote, Stop};
+use crate::signals;
+use crate::tracer::{self, ptrace, Event, WaitResult};
+use crate::unwind;
+use nix::fcntl::OFlag;
 use nix::sys::signal;
-use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::sys::uio;
+use nix::sys::wait::WaitStatus;
 use nix::unistd::Pid;
-use std::collections::HashMap;
+use std::cell::{Ref, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{IoSliceMut, Read, Write};
 use std::mem::size_of;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::process::CommandExt;
-use std::process::{Child, Command};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const NO_STDIN_PIPE: &str = "The program's stdin isn't a pipe from deet; see \"help input\".";
+const STDIN_CLOSED: &str = "The program's stdin has already been closed.";
 
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
@@ -17,73 +38,1210 @@ pub enum Status {
     /// current instruction pointer that it is stopped at.
     Stopped(signal::Signal, usize),
 
-    /// Indicates inferior exited normally. Contains the exit status code.
-    Exited(i32),
+    /// Indicates inferior trapped on one of our breakpoints. Contains the breakpoint's address,
+    /// which the pc has been rewound to.
+    Breakpoint(usize),
+
+    /// Indicates a single step of the current thread finished. Contains the pc it got to.
+    Stepped(usize),
+
+    /// Indicates inferior trapped on a hardware breakpoint or watchpoint, set in the debug
+    /// registers. Contains the current instruction pointer.
+    HardwareTrap(usize),
+
+    /// Indicates inferior exited normally. Contains the exit status code and what the process
+    /// used.
+    Exited(i32, ResourceUsage),
 
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
-    /// process.
-    Signaled(signal::Signal),
+    /// process, whether it dumped core, and what it used.
+    Signaled(signal::Signal, bool, ResourceUsage),
+
+    /// Indicates the inferior called fork (or vfork) and is stopped at the fork event. Contains
+    /// the pid of the new child, which is also traced and stopped, and whether it was a vfork,
+    /// whose child shares the parent's memory until it execs or exits.
+    Forked(Pid, bool),
+
+    /// Indicates the inferior called execve and is now running a new program. Its memory was
+    /// replaced, so none of the previously installed breakpoints exist anymore.
+    Execed,
+
+    /// Indicates the inferior is stopped entering or leaving a syscall, when running with
+    /// syscall tracing.
+    Syscall(SyscallStop),
+
+    /// Indicates the deadline of `set_deadline` passed before anything else stopped the
+    /// inferior, so we stopped it with a SIGSTOP. Contains the current instruction pointer.
+    TimedOut(usize),
+}
+
+/// What raised a SIGTRAP, going by its si_code and the breakpoints we installed. Only our
+/// breakpoints move the pc past the instruction that trapped; the others leave it where it
+/// should be reported.
+enum Trap {
+    /// One of our breakpoints, at this address
+    Breakpoint(usize),
+    /// The end of a single step we asked for
+    Step,
+    /// A hardware breakpoint or watchpoint
+    Hardware,
+    /// The program itself: a breakpoint instruction compiled into it, or a SIGTRAP sent to it
+    Program,
+}
+
+/// What an inferior used over its lifetime, as reported by wait4 when it exits.
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceUsage {
+    /// Wall-clock time since the process was started (or forked)
+    pub wall_time: Duration,
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// Peak resident set size, in kilobytes
+    pub max_rss_kb: i64,
+    pub major_faults: i64,
+}
+
+impl ResourceUsage {
+    fn new(started: Instant, usage: &libc::rusage) -> ResourceUsage {
+        let duration =
+            |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+        ResourceUsage {
+            wall_time: started.elapsed(),
+            user_time: duration(usage.ru_utime),
+            system_time: duration(usage.ru_stime),
+            max_rss_kb: usage.ru_maxrss,
+            major_faults: usage.ru_majflt,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SyscallStop {
+    Entry,
+    Exit,
+}
+
+/// Where an output stream of the inferior should go.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputRedirect {
+    /// `> path`, or `>> path` when `append` is set
+    File { path: String, append: bool },
+    /// `2>&1`: stderr goes wherever stdout goes
+    Stdout,
+}
+
+/// Redirections of the inferior's standard streams given on the `run` line. Streams without a
+/// redirection are inherited from deet.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Redirections {
+    pub stdin: Option<String>,
+    pub stdout: Option<OutputRedirect>,
+    pub stderr: Option<OutputRedirect>,
+}
+
+impl Redirections {
+    /// Opens the redirection targets and hands them to `cmd`, which dup2s them onto the standard
+    /// streams in the child between fork and exec. Files are opened here, in deet, so that a
+    /// missing input file fails the run instead of starting a child that dies immediately.
+    /// Relative paths are taken from the child's working directory `cwd`, as a shell would.
+    fn apply(&self, cmd: &mut Command, cwd: Option<&Path>) -> Result<(), String> {
+        let resolve = |path: &str| match cwd {
+            Some(cwd) => cwd.join(path),
+            None => PathBuf::from(path),
+        };
+        if let Some(path) = &self.stdin {
+            let file = File::open(resolve(path)).map_err(|err| format!("{}: {}", path, err))?;
+            cmd.stdin(Stdio::from(file));
+        }
+        if let Some(OutputRedirect::File { path, append }) = &self.stdout {
+            cmd.stdout(Stdio::from(Self::open_output(&resolve(path), *append)?));
+        }
+        match &self.stderr {
+            Some(OutputRedirect::File { path, append }) => {
+                cmd.stderr(Stdio::from(Self::open_output(&resolve(path), *append)?));
+            }
+            Some(OutputRedirect::Stdout) => unsafe {
+                // runs after Command has set up stdout, so this picks up its redirection too
+                cmd.pre_exec(|| {
+                    if libc::dup2(1, 2) < 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            },
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn open_output(path: &Path, append: bool) -> Result<File, String> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+}
+
+/// How to start the inferior. Unlike redirections, these persist across runs.
+#[derive(Clone, Debug)]
+pub struct LaunchOptions {
+    /// Changes to deet's own environment for the child: `Some(value)` sets a variable, `None`
+    /// removes it.
+    pub environment: BTreeMap<String, Option<String>>,
+    /// Directory the child chdir()s into before exec; deet's own if unset
+    pub cwd: Option<PathBuf>,
+    /// Terminal to use for the child's stdio (and as its controlling terminal) instead of deet's
+    pub tty: Option<PathBuf>,
+    /// Start the child in its own process group, so the debugger can hand it the foreground of
+    /// deet's terminal while it runs
+    pub own_process_group: bool,
+    /// Turn off address space layout randomization in the child, so addresses are the same on
+    /// every run
+    pub disable_randomization: bool,
+    /// Resource limits set in the child, by name (see RLIMITS): the soft limit and optionally
+    /// the hard limit. Without a hard limit, the inherited one is kept.
+    pub rlimits: BTreeMap<String, (libc::rlim_t, Option<libc::rlim_t>)>,
+    /// File to copy the child's output into, for the streams that would go to deet's terminal
+    pub output_log: Option<PathBuf>,
+    /// Keep the child off deet's stdin and stdout, which carry a protocol (`--dap`): its stdin
+    /// is /dev/null and its output is captured and passed on through `output`
+    pub detach_stdio: bool,
+    /// Give the child a pipe from deet as its stdin, for `input` to write to, unless stdin is
+    /// redirected
+    pub stdin_pipe: bool,
+}
+
+/// The resources `set rlimit` knows, by the names it accepts.
+pub const RLIMITS: &[(&str, libc::__rlimit_resource_t)] = &[
+    ("cpu", libc::RLIMIT_CPU),
+    ("as", libc::RLIMIT_AS),
+    ("nofile", libc::RLIMIT_NOFILE),
+    ("core", libc::RLIMIT_CORE),
+    ("data", libc::RLIMIT_DATA),
+    ("fsize", libc::RLIMIT_FSIZE),
+    ("stack", libc::RLIMIT_STACK),
+    ("nproc", libc::RLIMIT_NPROC),
+    ("memlock", libc::RLIMIT_MEMLOCK),
+];
+
+/// Returns the soft and hard limits deet itself has for `resource`, which the inferior inherits
+/// unless told otherwise.
+pub fn current_rlimit(resource: libc::__rlimit_resource_t) -> (libc::rlim_t, libc::rlim_t) {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe { libc::getrlimit(resource, &mut limit) };
+    (limit.rlim_cur, limit.rlim_max)
+}
+
+impl Default for LaunchOptions {
+    fn default() -> LaunchOptions {
+        LaunchOptions {
+            environment: BTreeMap::new(),
+            cwd: None,
+            tty: None,
+            own_process_group: false,
+            // like GDB
+            disable_randomization: true,
+            rlimits: BTreeMap::new(),
+            output_log: None,
+            detach_stdio: false,
+            stdin_pipe: false,
+        }
+    }
+}
+
+impl LaunchOptions {
+    fn apply(&self, cmd: &mut Command) -> Result<(), String> {
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        for (name, value) in &self.environment {
+            match value {
+                Some(value) => cmd.env(name, value),
+                None => cmd.env_remove(name),
+            };
+        }
+        if let Some(tty) = &self.tty {
+            let open = || {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(tty)
+                    .map_err(|err| format!("{}: {}", tty.display(), err))
+            };
+            cmd.stdin(Stdio::from(open()?));
+            cmd.stdout(Stdio::from(open()?));
+            cmd.stderr(Stdio::from(open()?));
+            unsafe {
+                // a new session without a controlling terminal, then take the tty as ours
+                cmd.pre_exec(|| {
+                    libc::setsid();
+                    libc::ioctl(0, libc::TIOCSCTTY, 0);
+                    Ok(())
+                });
+            }
+        } else if self.detach_stdio {
+            cmd.stdin(Stdio::null());
+        } else if self.own_process_group {
+            unsafe {
+                cmd.pre_exec(|| {
+                    libc::setpgid(0, 0);
+                    Ok(())
+                });
+            }
+        }
+        if self.stdin_pipe {
+            cmd.stdin(Stdio::piped());
+        }
+        if !self.rlimits.is_empty() {
+            let limits: Vec<(
+                libc::__rlimit_resource_t,
+                libc::rlim_t,
+                Option<libc::rlim_t>,
+            )> = RLIMITS
+                .iter()
+                .filter_map(|(name, resource)| {
+                    let (soft, hard) = self.rlimits.get(*name)?;
+                    Some((*resource, *soft, *hard))
+                })
+                .collect();
+            unsafe {
+                cmd.pre_exec(move || {
+                    for (resource, soft, hard) in &limits {
+                        let mut limit = libc::rlimit {
+                            rlim_cur: 0,
+                            rlim_max: 0,
+                        };
+                        if libc::getrlimit(*resource, &mut limit) < 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        limit.rlim_cur = *soft;
+                        if let Some(hard) = hard {
+                            limit.rlim_max = *hard;
+                        }
+                        if libc::setrlimit(*resource, &limit) < 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+        if self.disable_randomization {
+            unsafe {
+                cmd.pre_exec(|| {
+                    // 0xffffffff queries the current persona without changing it. Failing (e.g.
+                    // under a seccomp policy) just leaves randomization on.
+                    let persona = libc::personality(0xffffffff);
+                    if persona != -1 {
+                        libc::personality((persona | libc::ADDR_NO_RANDOMIZE) as libc::c_ulong);
+                    }
+                    Ok(())
+                });
+            }
+        }
+        unsafe {
+            // deet ignores these, and ignored signals survive exec
+            cmd.pre_exec(|| {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// One line of /proc/pid/maps.
+pub struct MemoryMapping {
+    pub start: usize,
+    pub end: usize,
+    /// Permissions as the kernel shows them, e.g. "r-xp"
+    pub perms: String,
+    /// The mapped file, a pseudo-name like "[stack]", or empty for anonymous memory
+    pub name: String,
+}
+
+/// The fields of /proc/pid/stat we need: state, ppid, pgrp, session.
+pub fn read_stat(pid: Pid) -> std::io::Result<(char, i32, i32, i32)> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    // the command name is in parentheses and may itself contain spaces or parentheses
+    let rest = stat.rsplit_once(')').map_or("", |(_, rest)| rest);
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |i: usize| fields.get(i).and_then(|f| f.parse().ok()).unwrap_or(0);
+    let state = fields.first().and_then(|f| f.chars().next()).unwrap_or('?');
+    Ok((state, field(1), field(2), field(3)))
+}
+
+/// Reads the memory map of process `pid`.
+pub fn read_memory_maps(pid: Pid) -> std::io::Result<Vec<MemoryMapping>> {
+    let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    let mut mappings = Vec::new();
+    for line in maps.lines() {
+        // start-end perms offset dev inode [name], where the name may contain spaces
+        let fields: Vec<&str> = line.splitn(6, ' ').collect();
+        if fields.len() < 5 {
+            continue;
+        }
+        let (start, end) = match fields[0].split_once('-') {
+            Some((start, end)) => (start, end),
+            None => continue,
+        };
+        let (start, end) = match (
+            usize::from_str_radix(start, 16),
+            usize::from_str_radix(end, 16),
+        ) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => continue,
+        };
+        mappings.push(MemoryMapping {
+            start,
+            end,
+            perms: fields[1].to_string(),
+            name: fields.get(5).map_or("", |name| name.trim()).to_string(),
+        });
+    }
+    Ok(mappings)
+}
+
+/// What a process does with each signal, per /proc/pid/status, as masks with bit n - 1 for
+/// signal n. The blocked ones are those of its main thread.
+pub struct SignalMasks {
+    pub blocked: u64,
+    pub ignored: u64,
+    /// Those it has a handler for
+    pub caught: u64,
+    /// Those sent to it or its main thread and not delivered yet
+    pub pending: u64,
+}
+
+/// Reads the signal masks of process `pid`.
+pub fn read_signal_masks(pid: Pid) -> std::io::Result<SignalMasks> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid))?;
+    let mask = |field: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+            .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("no {} in /proc/{}/status", field, pid),
+                )
+            })
+    };
+    Ok(SignalMasks {
+        blocked: mask("SigBlk")?,
+        ignored: mask("SigIgn")?,
+        caught: mask("SigCgt")?,
+        pending: mask("SigPnd")? | mask("ShdPnd")?,
+    })
+}
+
+/// The protection of a mapping with `perms` as /proc/pid/maps shows them, for mprotect.
+fn protection(perms: &str) -> libc::c_int {
+    let mut prot = libc::PROT_NONE;
+    for (flag, value) in perms
+        .chars()
+        .zip([libc::PROT_READ, libc::PROT_WRITE, libc::PROT_EXEC])
+    {
+        if flag != '-' {
+            prot |= value;
+        }
+    }
+    prot
+}
+
+pub fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// The address a SIGSEGV with `siginfo` faulted on, if it accessed memory in a way its
+/// protection doesn't allow, as opposed to unmapped memory or a general protection fault.
+pub fn fault_address(siginfo: &libc::siginfo_t) -> Option<usize> {
+    // SEGV_ACCERR, which the libc crate lacks
+    (siginfo.si_signo == libc::SIGSEGV && siginfo.si_code == 2)
+        .then(|| unsafe { siginfo.si_addr() } as usize)
+}
+
+/// Sends `signal` to one thread of process `pid`.
+fn signal_thread(pid: Pid, tid: Pid, signal: signal::Signal) -> Result<(), nix::Error> {
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_tgkill,
+            pid.as_raw(),
+            tid.as_raw(),
+            signal as libc::c_int,
+        )
+    };
+    nix::Error::result(result).map(drop)
+}
+
+/// What the child does last, from pre_exec: PTRACE_TRACEME, then exec the program itself with
+/// execv. Command would use execvp, which runs a file the kernel won't exec (ENOEXEC), such as a
+/// script without a #! line or a binary for another machine, with /bin/sh instead, and deet
+/// would be debugging the shell. The spawn error only carries an errno, so a TRACEME failure is
+/// also told by a byte written to `traceme_failed`.
+struct ChildExec {
+    program: CString,
+    /// Owns what `argv` points to
+    _args: Vec<CString>,
+    /// Null-terminated
+    argv: Vec<*const libc::c_char>,
+    /// Owns what `envp` points to
+    _environment: Vec<CString>,
+    /// Null-terminated
+    envp: Vec<*const libc::c_char>,
+    traceme_failed: RawFd,
+}
+
+// the pointers are into `_args` and `_environment`, which go wherever they go
+unsafe impl Send for ChildExec {}
+unsafe impl Sync for ChildExec {}
+
+impl ChildExec {
+    /// The exec of `program` with `args`, in deet's environment with the changes of
+    /// `environment` made to it. Since the exec is our own, Command's environment isn't used.
+    fn new(
+        program: &Path,
+        args: &[String],
+        environment: &BTreeMap<String, Option<String>>,
+        traceme_failed: RawFd,
+    ) -> Option<ChildExec> {
+        let program = CString::new(program.as_os_str().as_bytes()).ok()?;
+        let args = std::iter::once(Ok(program.clone()))
+            .chain(args.iter().map(|arg| CString::new(arg.as_bytes())))
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        let mut variables: BTreeMap<Vec<u8>, Vec<u8>> = std::env::vars_os()
+            .map(|(name, value)| (name.into_vec(), value.into_vec()))
+            .collect();
+        for (name, value) in environment {
+            match value {
+                Some(value) => variables.insert(name.clone().into(), value.clone().into()),
+                None => variables.remove(name.as_bytes()),
+            };
+        }
+        let environment = variables
+            .into_iter()
+            .map(|(mut variable, value)| {
+                variable.push(b'=');
+                variable.extend(value);
+                CString::new(variable)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        Some(ChildExec {
+            program,
+            argv: null_terminated(&args),
+            _args: args,
+            envp: null_terminated(&environment),
+            _environment: environment,
+            traceme_failed,
+        })
+    }
+
+    /// Runs in the child, so allocates nothing. Only returns if it fails.
+    fn exec(&self) -> std::io::Result<()> {
+        if let Err(err) = nix::sys::ptrace::traceme() {
+            let _ = nix::unistd::write(self.traceme_failed, &[1]);
+            return Err(std::io::Error::from_raw_os_error(err as i32));
+        }
+        unsafe {
+            libc::execve(
+                self.program.as_ptr(),
+                self.argv.as_ptr(),
+                self.envp.as_ptr(),
+            )
+        };
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Pointers to `strings`, then a null one, as exec takes them.
+fn null_terminated(strings: &[CString]) -> Vec<*const libc::c_char> {
+    strings
+        .iter()
+        .map(|string| string.as_ptr())
+        .chain(std::iter::once(std::ptr::null()))
+        .collect()
+}
+
+/// A breakpoint installed in a process: where it is, and the bytes the breakpoint instruction
+/// replaced there. The debugger's breakpoints are addresses until installed, when each process
+/// that has one keeps its own of these.
+#[derive(Clone)]
+pub struct Breakpoint {
+    pub addr: usize,
+    pub orig_bytes: [u8; arch::BREAKPOINT.len()],
+}
+
+/// How `count_instructions` went about counting, for the debugger to tell.
+pub enum Counting {
+    /// With a hardware counter per thread, which costs the program nothing
+    Hardware,
+    /// By single-stepping every instruction, because there's no hardware counter for why
+    Steps(nix::Error),
+}
+
+/// How the instructions a process runs are being counted, with the count so far: the counters'
+/// reads add those of threads that are gone to `retired`. `taken` had been counted at the last
+/// `take_instructions`.
+enum InstructionCounter {
+    Hardware {
+        counters: HashMap<Pid, File>,
+        retired: u64,
+        taken: u64,
+    },
+    Steps {
+        count: u64,
+        taken: u64,
+    },
+    /// Not at all, for a remote program
+    Unavailable,
 }
 
-/// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
-/// pre_exec with Command to call this in the child process.
-fn child_traceme() -> Result<(), std::io::Error> {
-    ptrace::traceme().or(Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "ptrace TRACEME failed",
-    )))
+/// perf_event_attr, up to the first of the fields that later kernels added (PERF_ATTR_SIZE_VER0)
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    /// The bit fields that follow read_format in C
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
 }
 
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_ATTR_FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+const PERF_ATTR_FLAG_EXCLUDE_HV: u64 = 1 << 6;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 8;
+
+/// Opens a hardware counter of the instructions thread `tid` runs in user space. It counts from
+/// now on for as long as the thread lives, and only while it runs, so not while it's stopped
+/// for us.
+fn open_instruction_counter(tid: Pid) -> Result<File, nix::Error> {
+    let attr = PerfEventAttr {
+        kind: PERF_TYPE_HARDWARE,
+        size: size_of::<PerfEventAttr>() as u32,
+        config: PERF_COUNT_HW_INSTRUCTIONS,
+        flags: PERF_ATTR_FLAG_EXCLUDE_KERNEL | PERF_ATTR_FLAG_EXCLUDE_HV,
+        ..Default::default()
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            &attr as *const PerfEventAttr,
+            tid.as_raw(),
+            -1 as libc::c_int,
+            -1 as libc::c_int,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(unsafe { File::from_raw_fd(fd as RawFd) })
+}
+
+/// What an instruction counter has counted, or 0 if it can't be read.
+fn read_instruction_counter(mut counter: &File) -> u64 {
+    let mut count = [0; 8];
+    match counter.read_exact(&mut count) {
+        Ok(()) => u64::from_ne_bytes(count),
+        Err(_) => 0,
+    }
+}
+
+/// Collects the events of an inferior's threads, which the tracer thread sends down a channel
+/// as they report them, until they're gone. The inferior takes them off the channel in its own
+/// time, and can give up waiting at a deadline.
+struct Waiter {
+    sender: Sender<WaitResult>,
+    events: Receiver<WaitResult>,
+    /// The threads being waited for, i.e. whose last event (their death, or the error that
+    /// ended the wait) hasn't been taken off the channel yet
+    watched: HashSet<Pid>,
+    /// Events taken off the channel while waiting for another thread's, for the next wait
+    unclaimed: VecDeque<WaitResult>,
+}
+
+impl Waiter {
+    fn new() -> Waiter {
+        let (sender, events) = mpsc::channel();
+        Waiter {
+            sender,
+            events,
+            watched: HashSet::new(),
+            unclaimed: VecDeque::new(),
+        }
+    }
+
+    /// Starts waiting for the events of thread `tid`.
+    fn watch(&mut self, tid: Pid) {
+        tracer::watch(tid, self.sender.clone());
+        self.watched.insert(tid);
+    }
+
+    /// Takes an event off the channel, noting the end of its thread's wait if it's the last.
+    /// None once `deadline` passes, or with no threads left to wait for.
+    fn receive(&mut self, deadline: Option<Instant>) -> Option<WaitResult> {
+        if self.watched.is_empty() {
+            return None;
+        }
+        let event = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match self.events.recv_timeout(timeout) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => return None,
+                    // a sender is kept, so it's never disconnected
+                    Err(RecvTimeoutError::Disconnected) => unreachable!(),
+                }
+            }
+            None => self.events.recv().unwrap(),
+        };
+        match &event {
+            Ok((tid, WaitStatus::Exited(..) | WaitStatus::Signaled(..), _)) | Err((tid, _)) => {
+                self.watched.remove(tid);
+            }
+            Ok(_) => {}
+        }
+        Some(event)
+    }
+
+    /// The next event of any thread, waiting until `deadline` at the latest. None if it passes
+    /// first, or if there's nothing left to wait for.
+    fn next(&mut self, deadline: Option<Instant>) -> Option<WaitResult> {
+        self.unclaimed
+            .pop_front()
+            .or_else(|| self.receive(deadline))
+    }
+
+    /// Makes `event`, taken off the channel, the next one again.
+    fn put_back(&mut self, event: Event) {
+        self.unclaimed.push_front(Ok(event));
+    }
+
+    /// Whether every thread's wait has ended, and its events have been taken.
+    fn is_done(&self) -> bool {
+        self.watched.is_empty() && self.unclaimed.is_empty()
+    }
+
+    /// The next event of thread `tid`, leaving those of the others for later. ECHILD if it's
+    /// gone and everything it reported has been taken.
+    fn next_of(&mut self, tid: Pid) -> Result<Event, nix::Error> {
+        let of_tid = |event: &WaitResult| match event {
+            Ok((other, ..)) | Err((other, _)) => *other == tid,
+        };
+        if let Some(i) = self.unclaimed.iter().position(of_tid) {
+            return self.unclaimed.remove(i).unwrap().map_err(|(_, err)| err);
+        }
+        while self.watched.contains(&tid) {
+            match self.receive(None) {
+                Some(event) if of_tid(&event) => return event.map_err(|(_, err)| err),
+                Some(event) => self.unclaimed.push_back(event),
+                None => break,
+            }
+        }
+        Err(nix::Error::ECHILD)
+    }
+}
+
+/// A traced process, with all of its threads. The threads are stopped and resumed together: when
+/// one of them stops, the others are stopped too before the debugger gets to look at it.
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    /// The thread that reported the last stop, whose registers we show and which steps over a
+    /// breakpoint. The main thread unless another one hit a breakpoint or got a signal.
+    tid: Pid,
+    /// The threads besides the main one, as reported by PTRACE_EVENT_CLONE
+    threads: BTreeSet<Pid>,
+    /// Threads that have been resumed and haven't reported a stop since
+    running: HashSet<Pid>,
+    /// Threads with a SIGSTOP on its way that should be swallowed: new threads start with one,
+    /// and so do threads we stopped that reported something else first
+    pending_sigstop: HashSet<Pid>,
+    /// Events that threads reported while being stopped because another thread stopped. They
+    /// stay stopped at them until the events have been handled in turn.
+    deferred: VecDeque<(Pid, WaitStatus, libc::rusage)>,
+    /// Set while stepping `tid` over a breakpoint, so it isn't resumed with cont meanwhile
+    stepping: bool,
+    /// Whether resumed threads stop at syscall entries and exits as well
+    trace_syscalls: bool,
+    /// Breakpoints currently patched into this process's memory, keyed by address. The
+    /// debugger's breakpoint list is shared between inferiors, but each process has its own
+    /// copy of the original bytes.
+    breakpoints: HashMap<usize, Breakpoint>,
+    /// Threads inside a syscall, i.e. whose next syscall stop is the exit. The kernel reports
+    /// entry and exit the same way.
+    in_syscall: HashSet<Pid>,
+    /// Tee of the output, shared with the inferiors this one forks since they write into the
+    /// same pipes
+    output_log: Option<Arc<OutputLog>>,
+    /// deet's end of the pipe that is the child's stdin, with `stdin_pipe`. Shared with the
+    /// inferiors this one forks, which read from the same pipe; None inside once closed.
+    stdin: Option<Arc<Mutex<Option<ChildStdin>>>>,
+    /// Pages made read-only for `watch-range`, with the protection they had
+    protected: BTreeMap<usize, libc::c_int>,
+    /// The address and length each hardware watchpoint slot watches, set in every thread. New
+    /// threads and forked processes start without any, so they get them when first stopped.
+    watchpoints: [Option<(usize, usize)>; arch::WATCHPOINT_SLOTS],
+    /// Collects the threads' events, for a local process
+    waiter: Waiter,
+    /// The call frame information of the shared objects, as mapped when last needed
+    frame_info: RefCell<unwind::MappedFrameInfo>,
+    /// Counts the instructions the process runs, with `count-instructions` on
+    instruction_counter: Option<InstructionCounter>,
+    /// Whether the breakpoints are out of the memory this process shares with a vfork child
+    /// that was detached, to be put back once the child has execed or exited
+    vfork_breakpoints: bool,
+    /// When the process was started, for the wall-clock time in its resource usage
+    started: Instant,
+    /// When to stop the process if nothing else has by then, and whether we've sent the
+    /// SIGSTOP for it without seeing it arrive yet
+    deadline: Option<Instant>,
+    timed_out: bool,
+    /// The stub to go through instead of ptrace, for a program debugged with `target remote`.
+    /// It's one process with one thread as far as we know, and none of the tracking above
+    /// applies.
+    remote: Option<Remote>,
 }
 
 impl Inferior {
-    /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
+    /// Attempts to start a new inferior process, which is stopped right after the exec with no
+    /// breakpoints in it yet.
     pub fn new(
         target: &str,
         args: &Vec<String>,
-        breakpoints: &mut HashMap<usize, Option<Breakpoint>>,
-    ) -> Option<Inferior> {
-        let mut cmd = Command::new(target);
+        redirections: &Redirections,
+        options: &LaunchOptions,
+    ) -> Result<Inferior, DeetError> {
+        // a relative program path would be looked up from the child's new cwd, so pin it down
+        // from ours first
+        let program = match &options.cwd {
+            Some(_) if Path::new(target).is_relative() => {
+                std::fs::canonicalize(target).unwrap_or_else(|_| PathBuf::from(target))
+            }
+            _ => PathBuf::from(target),
+        };
+        // the program that was loaded, not one found in PATH
+        let program = match program.components().count() {
+            1 if program.is_relative() => Path::new(".").join(program),
+            _ => program,
+        };
+        let mut cmd = Command::new(&program);
         cmd.args(args);
-        unsafe {
-            cmd.pre_exec(child_traceme);
-        }
-        match cmd.spawn() {
-            Ok(child) => {
-                let mut inferior = Inferior { child };
-                for (addr, breakpoint) in breakpoints {
-                    // replacing the byte at breakpoint with the value 0xcc
-                    // and record the original instrction's first byte
-                    match inferior.write_byte(*addr, 0xcc) {
-                        Ok(orig_byte) => {
-                            *breakpoint = Some(Breakpoint {
-                                addr: *addr,
-                                orig_byte,
-                            });
-                        }
-                        Err(_) => println!("Inferior::new can't write_byte {}", addr),
+        // redirections come second so they take precedence over the inferior tty
+        options
+            .apply(&mut cmd)
+            .and_then(|_| redirections.apply(&mut cmd, options.cwd.as_deref()))
+            .map_err(DeetError::Launch)?;
+        let output_log = if options.output_log.is_some() || options.detach_stdio {
+            let log = Self::log_output(
+                &mut cmd,
+                options.output_log.as_deref(),
+                redirections,
+                options,
+            )
+            .map_err(DeetError::Launch)?;
+            Some(Arc::new(log))
+        } else {
+            None
+        };
+        let (traceme_read, traceme_failed) = nix::unistd::pipe2(OFlag::O_CLOEXEC)
+            .map_err(|err| DeetError::Launch(format!("Could not start {}: {}", target, err)))?;
+        let exec =
+            ChildExec::new(&program, args, &options.environment, traceme_failed).ok_or_else(|| {
+                DeetError::Launch(format!(
+                    "{}: arguments and environment variables can't contain a nul byte",
+                    target
+                ))
+            });
+        let spawned = exec.map(|exec| {
+            unsafe {
+                cmd.pre_exec(move || exec.exec());
+            }
+            // from the tracer thread, the one PTRACE_TRACEME makes the tracer
+            tracer::run(|| cmd.spawn())
+        });
+        // the child's copy is closed by now, by its exec or its exit
+        let _ = nix::unistd::close(traceme_failed);
+        let traceme_failed = matches!(nix::unistd::read(traceme_read, &mut [0]), Ok(1));
+        let _ = nix::unistd::close(traceme_read);
+        let mut child = spawned?.map_err(|err| match traceme_failed {
+            true => DeetError::Ptrace(
+                target.to_string(),
+                nix::Error::from_i32(err.raw_os_error().unwrap_or(0)),
+            ),
+            false => DeetError::Spawn(target.to_string(), err),
+        })?;
+        let mut inferior =
+            Inferior::with_pid(Pid::from_raw(child.id() as i32), HashMap::new(), output_log);
+        inferior.waiter.watch(inferior.pid);
+        // None if a redirection took stdin's place. Nonblocking, so that writing more than the
+        // pipe holds to a program that isn't reading fails instead of hanging deet.
+        inferior.stdin = child.stdin.take().map(|stdin| {
+            let fd = stdin.as_raw_fd();
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+            Arc::new(Mutex::new(Some(stdin)))
+        });
+        // wait for the child to stop at the SIGTRAP raised by exec before touching its memory
+        match inferior.wait() {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, _)) => {}
+            Ok(Status::Exited(code, _)) => {
+                return Err(DeetError::Launch(format!(
+                    "{} exited with status {} before it could be traced",
+                    target, code
+                )))
+            }
+            Ok(Status::Signaled(signal, ..)) => {
+                return Err(DeetError::Launch(format!(
+                    "{} was killed by {} before it could be traced",
+                    target,
+                    signals::name(signal)
+                )))
+            }
+            Ok(_) => {
+                return Err(DeetError::Launch(format!(
+                    "{} didn't stop after exec",
+                    target
+                )))
+            }
+            Err(err) => return Err(DeetError::Ptrace(target.to_string(), err)),
+        }
+        // report forks so the debugger can decide which process to follow, and new threads so
+        // they're traced too and don't die of a breakpoint's SIGTRAP
+        ptrace::setoptions(
+            inferior.pid(),
+            ptrace::Options::PTRACE_O_TRACEFORK
+                | ptrace::Options::PTRACE_O_TRACEVFORK
+                // so breakpoints taken out for a vfork child can be put back
+                | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+                | ptrace::Options::PTRACE_O_TRACECLONE
+                | ptrace::Options::PTRACE_O_TRACEEXEC
+                // so syscall stops can be told apart from breakpoint traps
+                | ptrace::Options::PTRACE_O_TRACESYSGOOD
+                // don't leave the inferior running with breakpoints in it if deet dies
+                | ptrace::Options::PTRACE_O_EXITKILL,
+        )
+        .map_err(|err| DeetError::Ptrace(target.to_string(), err))?;
+        Ok(inferior)
+    }
+
+    /// Connects to the GDB stub at `address`, which has a program stopped for us.
+    pub fn connect(address: &str) -> Result<Inferior, String> {
+        let remote = Remote::connect(address)?;
+        let mut inferior = Inferior::with_pid(remote.pid(), HashMap::new(), None);
+        inferior.running.clear();
+        inferior.remote = Some(remote);
+        Ok(inferior)
+    }
+
+    /// Whether the program runs under a GDB stub rather than here.
+    pub fn is_remote(&self) -> bool {
+        self.remote.is_some()
+    }
+
+    /// Wraps a process that became traced automatically because a traced process forked it.
+    /// Waits for the initial SIGSTOP the kernel delivers to such a child. The child's memory is a
+    /// copy of the parent's, so it starts out with the parent's breakpoints installed.
+    pub fn from_forked(pid: Pid, parent: &Inferior) -> Result<Inferior, nix::Error> {
+        let mut inferior =
+            Inferior::with_pid(pid, parent.breakpoints.clone(), parent.output_log.clone());
+        // the child's copy of the memory is protected the same
+        inferior.protected = parent.protected.clone();
+        inferior.stdin = parent.stdin.clone();
+        inferior.waiter.watch(pid);
+        inferior.wait()?;
+        inferior.watchpoints = parent.watchpoints;
+        inferior.apply_watchpoints(pid)?;
+        Ok(inferior)
+    }
+
+    /// A just-started, single-threaded process that's about to report its first stop.
+    fn with_pid(
+        pid: Pid,
+        breakpoints: HashMap<usize, Breakpoint>,
+        output_log: Option<Arc<OutputLog>>,
+    ) -> Inferior {
+        Inferior {
+            pid,
+            tid: pid,
+            threads: BTreeSet::new(),
+            running: HashSet::from([pid]),
+            pending_sigstop: HashSet::new(),
+            deferred: VecDeque::new(),
+            stepping: false,
+            trace_syscalls: false,
+            breakpoints,
+            in_syscall: HashSet::new(),
+            output_log,
+            stdin: None,
+            protected: BTreeMap::new(),
+            watchpoints: [None; arch::WATCHPOINT_SLOTS],
+            waiter: Waiter::new(),
+            frame_info: RefCell::new(unwind::MappedFrameInfo::default()),
+            instruction_counter: None,
+            vfork_breakpoints: false,
+            started: Instant::now(),
+            deadline: None,
+            timed_out: false,
+            remote: None,
+        }
+    }
+
+    /// Starts a new log at `path`, or just captures the output without one, and sends the
+    /// streams that aren't redirected elsewhere through it.
+    fn log_output(
+        cmd: &mut Command,
+        path: Option<&Path>,
+        redirections: &Redirections,
+        options: &LaunchOptions,
+    ) -> Result<OutputLog, String> {
+        let log = match path {
+            Some(path) => OutputLog::create(path)?,
+            None => OutputLog::capture(),
+        };
+        if options.tty.is_none() {
+            if redirections.stdout.is_none() {
+                cmd.stdout(log.pipe(false)?);
+            }
+            // `2>&1` already follows stdout into its pipe
+            if redirections.stderr.is_none() {
+                cmd.stderr(log.pipe(true)?);
+            }
+        }
+        Ok(log)
+    }
+
+    /// Makes sure the output the inferior produced before its last stop has been shown and
+    /// logged.
+    pub fn flush_output(&self) {
+        if let Some(log) = &self.output_log {
+            log.flush();
+        }
+    }
+
+    /// Replaces the instruction at `addr` with the breakpoint instruction, remembering the
+    /// original bytes so they can be restored when stepping over the breakpoint. Returns them,
+    /// those it already had if the breakpoint was installed before.
+    pub fn install_breakpoint(
+        &mut self,
+        addr: usize,
+    ) -> Result<[u8; arch::BREAKPOINT.len()], nix::Error> {
+        if let Some(bp) = self.breakpoints.get(&addr) {
+            return Ok(bp.orig_bytes);
+        }
+        let orig_bytes = match &self.remote {
+            // the stub writes the breakpoint, and keeps it out of what memory reads show
+            Some(remote) => {
+                let orig_bytes = remote.read_memory(addr, arch::BREAKPOINT.len())?;
+                remote.insert_breakpoint(addr)?;
+                orig_bytes.try_into().unwrap()
+            }
+            None => self.write_bytes(addr, &arch::BREAKPOINT)?,
+        };
+        self.breakpoints
+            .insert(addr, Breakpoint { addr, orig_bytes });
+        Ok(orig_bytes)
+    }
+
+    /// Installs breakpoints at all of `addrs` at once, as install_breakpoint does one. Locally,
+    /// the words they fall in are read and written once each however many breakpoints share
+    /// them. Returns the breakpoints that couldn't be installed, with why.
+    pub fn install_breakpoints(&mut self, addrs: &[usize]) -> Vec<(usize, nix::Error)> {
+        let mut failed = Vec::new();
+        let addrs: BTreeSet<usize> = addrs
+            .iter()
+            .copied()
+            .filter(|addr| !self.breakpoints.contains_key(addr))
+            .collect();
+        if self.remote.is_some() {
+            // each one is a packet to the stub anyway
+            for addr in addrs {
+                if let Err(err) = self.install_breakpoint(addr) {
+                    failed.push((addr, err));
+                }
+            }
+            return failed;
+        }
+        // the bytes to write, by the word they're in
+        let mut words: BTreeMap<usize, Vec<(usize, u8)>> = BTreeMap::new();
+        for addr in &addrs {
+            for (at, byte) in (*addr..).zip(arch::BREAKPOINT) {
+                words
+                    .entry(align_addr_to_word(at))
+                    .or_default()
+                    .push((at, byte));
+            }
+        }
+        let mut orig_words = HashMap::new();
+        let mut failed_words = HashMap::new();
+        for (aligned_addr, bytes) in words {
+            let patched =
+                ptrace::read(self.tid, aligned_addr as ptrace::AddressType).and_then(|word| {
+                    let orig_word = word as u64;
+                    let mut updated_word = orig_word;
+                    for (at, byte) in bytes {
+                        let shift = 8 * (at - aligned_addr);
+                        updated_word = updated_word & !(0xff << shift) | (byte as u64) << shift;
                     }
+                    unsafe {
+                        ptrace::write(
+                            self.tid,
+                            aligned_addr as ptrace::AddressType,
+                            updated_word as *mut std::ffi::c_void,
+                        )?;
+                    }
+                    Ok(orig_word)
+                });
+            match patched {
+                Ok(orig_word) => {
+                    orig_words.insert(aligned_addr, orig_word);
+                }
+                Err(err) => {
+                    failed_words.insert(aligned_addr, err);
                 }
-                Some(inferior)
             }
-            Err(_) => None,
         }
+        // the original bytes are still kept per breakpoint, for deleting them one at a time
+        for addr in addrs {
+            let mut orig_bytes = [0; arch::BREAKPOINT.len()];
+            let mut error = None;
+            for (i, at) in (addr..).take(orig_bytes.len()).enumerate() {
+                let aligned_addr = align_addr_to_word(at);
+                match orig_words.get(&aligned_addr) {
+                    Some(word) => orig_bytes[i] = (word >> (8 * (at - aligned_addr))) as u8,
+                    None => error = failed_words.get(&aligned_addr).copied(),
+                }
+            }
+            match error {
+                // instructions are aligned, so a breakpoint is never half in a word that failed
+                Some(err) => failed.push((addr, err)),
+                None => {
+                    self.breakpoints
+                        .insert(addr, Breakpoint { addr, orig_bytes });
+                }
+            }
+        }
+        failed
+    }
+
+    /// Restores the original bytes of the breakpoint at `addr`, if one is installed there. Unlike
+    /// remove_breakpoints, this leaves the pc alone. A breakpoint that can't be removed stays
+    /// installed as far as the inferior knows.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(bp) = self.breakpoints.get(&addr) {
+            let orig_bytes = bp.orig_bytes;
+            match &self.remote {
+                Some(remote) => remote.remove_breakpoint(addr)?,
+                None => {
+                    self.write_bytes(addr, &orig_bytes)?;
+                }
+            }
+            self.breakpoints.remove(&addr);
+        }
+        Ok(())
+    }
+
+    /// Whether a breakpoint is installed at `addr`.
+    pub fn has_breakpoint(&self, addr: usize) -> bool {
+        self.breakpoints.contains_key(&addr)
+    }
+
+    /// The breakpoints installed in this process, by address.
+    pub fn installed_breakpoints(&self) -> Vec<&Breakpoint> {
+        let mut breakpoints: Vec<&Breakpoint> = self.breakpoints.values().collect();
+        breakpoints.sort_by_key(|bp| bp.addr);
+        breakpoints
+    }
+
+    /// The (address, original byte) pairs of every byte the installed breakpoints replaced.
+    pub fn breakpoint_bytes(&self) -> Vec<(usize, u8)> {
+        self.installed_breakpoints()
+            .into_iter()
+            .flat_map(|bp| (bp.addr..).zip(bp.orig_bytes))
+            .collect()
+    }
+
+    /// Restores the original bytes of all installed breakpoints, e.g. before detaching so the
+    /// process doesn't later hit a stray breakpoint instruction. A thread that has trapped on one
+    /// of them already has its pc back at it, so the replaced instruction still runs. Returns the
+    /// ones that couldn't be removed, with why.
+    pub fn remove_breakpoints(&mut self) -> Vec<(usize, nix::Error)> {
+        let addrs: Vec<usize> = self.breakpoints.keys().copied().collect();
+        addrs
+            .into_iter()
+            .filter_map(|addr| Some((addr, self.remove_breakpoint(addr).err()?)))
+            .collect()
+    }
+
+    /// Has the breakpoints put back once the vfork child this process is stopped at the fork of
+    /// has execed or exited, for when they were removed from the memory the two share.
+    pub fn restore_breakpoints_after_vfork(&mut self) {
+        self.vfork_breakpoints = true;
+    }
+
+    /// Writes the breakpoint instructions back over the original bytes, which are still
+    /// remembered, after something else took them out. Those that can't be are forgotten.
+    fn restore_breakpoints(&mut self) {
+        let addrs: Vec<usize> = self.breakpoints.keys().copied().collect();
+        for addr in addrs {
+            if self.write_bytes(addr, &arch::BREAKPOINT).is_err() {
+                self.breakpoints.remove(&addr);
+            }
+        }
+    }
+
+    /// Stops tracing the inferior and lets all of its threads run freely.
+    pub fn detach(mut self) -> Result<(), nix::Error> {
+        if let Some(remote) = &self.remote {
+            return remote.detach();
+        }
+        // a SIGSTOP still queued for a thread would stop the whole process once we're gone
+        for tid in std::mem::take(&mut self.pending_sigstop) {
+            if ptrace::cont(tid, None).is_ok() {
+                while let Ok((_, status, _)) = self.waiter.next_of(tid) {
+                    match status {
+                        WaitStatus::Stopped(_, signal::Signal::SIGSTOP)
+                        | WaitStatus::Exited(..)
+                        | WaitStatus::Signaled(..) => break,
+                        _ => ptrace::cont(tid, None)?,
+                    }
+                }
+            }
+        }
+        for tid in &self.threads {
+            let _ = ptrace::detach(*tid, None);
+        }
+        ptrace::detach(self.pid(), None)
     }
 
     pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
+        let word = ptrace::read(self.tid, aligned_addr as ptrace::AddressType)? as u64;
+        let orig_byte = (word >> (8 * byte_offset)) & 0xff;
+        let masked_word = word & !(0xff << (8 * byte_offset));
+        let updated_word = masked_word | ((val as u64) << (8 * byte_offset));
 
         unsafe {
             ptrace::write(
-                self.pid(),
+                self.tid,
                 aligned_addr as ptrace::AddressType,
                 updated_word as *mut std::ffi::c_void,
             )?;
@@ -91,80 +1249,1352 @@ impl Inferior {
         Ok(orig_byte as u8)
     }
 
-    pub fn continue_exec(
+    /// Writes `bytes` to the inferior's memory at `addr`. Where a breakpoint is installed, the
+    /// original byte it keeps is changed instead, so the breakpoint stays in.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        if self.remote.is_some() {
+            return Err(nix::Error::ENOSYS);
+        }
+        for (at, byte) in (addr..).zip(bytes) {
+            let breakpoint = self
+                .breakpoints
+                .values_mut()
+                .find(|bp| (bp.addr..bp.addr + bp.orig_bytes.len()).contains(&at));
+            match breakpoint {
+                Some(bp) => bp.orig_bytes[at - bp.addr] = *byte,
+                None => {
+                    self.write_byte(at, *byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `bytes` at `addr` a byte at a time, returning the bytes they replaced.
+    fn write_bytes<const N: usize>(
         &mut self,
-        breakpoints: &HashMap<usize, Option<Breakpoint>>,
-    ) -> Result<Status, nix::Error> {
-        let mut regs = ptrace::getregs(self.pid())?;
-        let rip: usize = regs.rip.try_into().unwrap(); // rip as usize
-                                                       // check if inferior stopped at a breakpoint
-        if let Some(breakpoint) = breakpoints.get(&(rip - 1)) {
-            if let Some(bp) = breakpoint {
-                let orig_byte = bp.orig_byte;
-                println!("[inferior.continue_exec] Stopped at a breakpoint");
-                // restore the first byte of the instruction we replaced
-                self.write_byte(rip - 1, orig_byte).unwrap();
-                // set %rip = %rip - 1 to rewind the instruction pointer
-                regs.rip = (rip - 1) as u64;
-                ptrace::setregs(self.pid(), regs).unwrap();
-                // go to the next instruction
-                ptrace::step(self.pid(), None).unwrap();
-                // wait for inferior to stop due to SIGTRAP, just return if the inferior terminates here
-                match self.wait(None).unwrap() {
-                    Status::Exited(exit_code) => return Ok(Status::Exited(exit_code)),
-                    Status::Signaled(signal) => return Ok(Status::Signaled(signal)),
-                    Status::Stopped(_, _) => {
-                        // restore 0xcc in the breakpoint location
-                        self.write_byte(rip - 1, 0xcc).unwrap();
+        addr: usize,
+        bytes: &[u8; N],
+    ) -> Result<[u8; N], nix::Error> {
+        let mut orig_bytes = [0; N];
+        for (i, byte) in bytes.iter().enumerate() {
+            orig_bytes[i] = self.write_byte(addr + i, *byte)?;
+        }
+        Ok(orig_bytes)
+    }
+
+    /// Resumes the inferior until it stops. With `trace_syscalls`, it also stops at every syscall
+    /// entry and exit.
+    pub fn continue_exec(&mut self, trace_syscalls: bool) -> Result<Status, nix::Error> {
+        if self.remote.is_some() {
+            return self.continue_remote();
+        }
+        self.trace_syscalls = trace_syscalls;
+        let addr = self.instruction_pointer()?;
+        // check if inferior is at a breakpoint, having trapped on it or not yet
+        if self.breakpoints.contains_key(&addr) {
+            match self.step_instruction()? {
+                // the instruction under the breakpoint wrote to a watched variable
+                Status::Stepped(pc)
+                    if self.watchpoints.iter().any(Option::is_some)
+                        && arch::watchpoint_hit(self.tid)?.is_some() =>
+                {
+                    return Ok(Status::HardwareTrap(pc))
+                }
+                Status::Stepped(_) | Status::Breakpoint(_) | Status::Syscall(_) => {}
+                status => return Ok(status),
+            }
+        }
+
+        self.resume_stopped_threads(None)?;
+        let status = self.wait()?;
+        if let Status::Execed = status {
+            self.breakpoints.clear();
+            self.protected.clear();
+        }
+        Ok(status)
+    }
+
+    /// Executes the current thread's next instruction, with the other threads still stopped. A
+    /// breakpoint there is taken out meanwhile, so none of them runs past it while it's missing.
+    /// A signal arriving in the meantime (e.g. SIGCHLD) stops the thread before the instruction
+    /// executes, so it's passed along and the step tried again; a fault of the instruction
+    /// itself is returned, as is a SIGINT, from Ctrl-C, and Stepped once it has executed.
+    pub fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        if self.remote.is_some() {
+            return self.step_remote();
+        }
+        let addr = self.instruction_pointer()?;
+        let orig_bytes = self.breakpoints.get(&addr).map(|bp| bp.orig_bytes);
+        if let Some(orig_bytes) = orig_bytes {
+            // restore the instruction we replaced
+            self.write_bytes(addr, &orig_bytes)?;
+        }
+        let mut pending_signal = None;
+        self.stepping = true;
+        let status = loop {
+            let tid = self.tid;
+            match self
+                .resume_thread(tid, pending_signal)
+                .and_then(|_| self.wait())
+            {
+                Ok(Status::Stopped(signal, _))
+                    if !matches!(
+                        signal,
+                        signal::Signal::SIGTRAP
+                            | signal::Signal::SIGINT
+                            | signal::Signal::SIGSEGV
+                            | signal::Signal::SIGBUS
+                            | signal::Signal::SIGFPE
+                            | signal::Signal::SIGILL
+                    ) =>
+                {
+                    pending_signal = Some(signal)
+                }
+                status => break status?,
+            }
+        };
+        self.stepping = false;
+        if let Status::Stepped(_) = status {
+            self.count_step();
+        }
+        match status {
+            Status::Exited(..) | Status::Signaled(..) => return Ok(status),
+            Status::Execed => {
+                self.breakpoints.clear();
+                self.protected.clear();
+                return Ok(status);
+            }
+            _ => {}
+        }
+        // put the breakpoint instruction back; if that fails, it's no longer installed
+        if orig_bytes.is_some() && self.write_bytes(addr, &arch::BREAKPOINT).is_err() {
+            self.breakpoints.remove(&addr);
+        }
+        Ok(status)
+    }
+
+    /// Makes syscall `number` on the program's behalf, in its current thread: the thread runs a
+    /// syscall instruction put at its pc, and the instruction and its registers are restored
+    /// afterwards. Returns what the syscall returned, or the errno it failed with. Signals
+    /// that arrive meanwhile are sent again, to be delivered once the program is resumed.
+    fn inject_syscall(&mut self, number: libc::c_long, args: [u64; 3]) -> Result<u64, nix::Error> {
+        if self.remote.is_some() {
+            return Err(nix::Error::ENOSYS);
+        }
+        let regs = self.registers()?;
+        let pc = arch::pc(&regs);
+        let code = self.write_bytes(pc, &arch::SYSCALL_INSTRUCTION)?;
+        let mut call = regs;
+        arch::set_syscall(&mut call, number as u64, args);
+        let mut signals = Vec::new();
+        let result = self.set_registers(call).and_then(|_| loop {
+            ptrace::step(self.tid, None)?;
+            match self.waiter.next_of(self.tid)? {
+                (_, WaitStatus::Stopped(_, signal::Signal::SIGTRAP), _) => {
+                    break arch::get_registers(self.tid);
+                }
+                (_, WaitStatus::Stopped(_, signal), _) => signals.push(signal),
+                // killed from outside, say; the next wait reports it
+                event => {
+                    self.waiter.put_back(event);
+                    break Err(nix::Error::ESRCH);
+                }
+            }
+        });
+        self.write_bytes(pc, &code)?;
+        self.set_registers(regs)?;
+        for signal in signals {
+            signal_thread(self.pid, self.tid, signal)?;
+        }
+        let value = arch::syscall_return(&result?);
+        match value as i64 {
+            -4095..=-1 => Err(nix::Error::from_i32(-(value as i64) as i32)),
+            _ => Ok(value),
+        }
+    }
+
+    /// Makes the pages of `addr..addr + len` read-only, so that writes to them fault, and keeps
+    /// what they were. Pages that already are are left alone.
+    pub fn protect(&mut self, addr: usize, len: usize) -> Result<(), nix::Error> {
+        let page_size = page_size();
+        let start = addr - addr % page_size;
+        let end = addr
+            .checked_add(len)
+            .and_then(|end| end.checked_next_multiple_of(page_size))
+            .ok_or(nix::Error::EINVAL)?;
+        let maps = read_memory_maps(self.pid).map_err(|_| nix::Error::ESRCH)?;
+        for page in (start..end).step_by(page_size) {
+            if self.protected.contains_key(&page) {
+                continue;
+            }
+            let mapping = maps
+                .iter()
+                .find(|mapping| (mapping.start..mapping.end).contains(&page))
+                .ok_or(nix::Error::ENOMEM)?;
+            let prot = protection(&mapping.perms);
+            if prot & libc::PROT_WRITE == 0 {
+                // nothing can write to it anyway
+                continue;
+            }
+            self.mprotect(page, prot & !libc::PROT_WRITE)?;
+            self.protected.insert(page, prot);
+        }
+        Ok(())
+    }
+
+    /// Gives the pages `protect` made read-only their protection back.
+    pub fn unprotect(&mut self) -> Result<(), nix::Error> {
+        while let Some((page, prot)) = self.protected.pop_first() {
+            self.mprotect(page, prot)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `addr` is on a page `protect` made read-only.
+    pub fn is_protected(&self, addr: usize) -> bool {
+        self.protected.contains_key(&(addr - addr % page_size()))
+    }
+
+    /// Has every thread trap writes to the `len` bytes at `addr`, in a free watchpoint slot,
+    /// which is returned. `len` is 1, 2, 4 or 8, and `addr` a multiple of it. ENOSPC if all the
+    /// slots are taken.
+    pub fn set_watchpoint(&mut self, addr: usize, len: usize) -> Result<usize, nix::Error> {
+        if self.remote.is_some() {
+            return Err(nix::Error::ENOTSUP);
+        }
+        let slot = self
+            .watchpoints
+            .iter()
+            .position(Option::is_none)
+            .ok_or(nix::Error::ENOSPC)?;
+        self.watchpoints[slot] = Some((addr, len));
+        let threads: Vec<Pid> = self.all_threads().collect();
+        for tid in threads {
+            if let Err(err) = arch::set_watchpoint(tid, slot, Some((addr, len))) {
+                self.clear_watchpoint(slot)?;
+                return Err(err);
+            }
+        }
+        Ok(slot)
+    }
+
+    /// Turns the watchpoint in `slot` off in every thread.
+    pub fn clear_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        self.watchpoints[slot] = None;
+        self.all_threads()
+            .try_for_each(|tid| arch::set_watchpoint(tid, slot, None))
+    }
+
+    /// Turns every watchpoint off.
+    pub fn clear_watchpoints(&mut self) -> Result<(), nix::Error> {
+        for slot in 0..arch::WATCHPOINT_SLOTS {
+            if self.watchpoints[slot].is_some() {
+                self.clear_watchpoint(slot)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The slot of the watchpoint the current thread's hardware trap was for, if it was one of
+    /// ours. Each trap is told once.
+    pub fn watchpoint_hit(&self) -> Result<Option<usize>, nix::Error> {
+        let slot = arch::watchpoint_hit(self.tid)?;
+        arch::clear_watchpoint_hit(self.tid)?;
+        Ok(slot.filter(|&slot| self.watchpoints[slot].is_some()))
+    }
+
+    /// Sets the watchpoints in thread `tid`, which is new to them.
+    fn apply_watchpoints(&self, tid: Pid) -> Result<(), nix::Error> {
+        for (slot, watch) in self.watchpoints.iter().enumerate() {
+            if watch.is_some() {
+                arch::set_watchpoint(tid, slot, *watch)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Steps the current thread over its instruction that faulted writing to the protected page
+    /// at `addr`, with the page writable while it does. A write that straddles two protected
+    /// pages faults again on the second, which is then made writable as well.
+    pub fn step_over_protection(&mut self, mut addr: usize) -> Result<Status, nix::Error> {
+        let pc = self.instruction_pointer()?;
+        let mut opened = Vec::new();
+        let status = loop {
+            let page = addr - addr % page_size();
+            let prot = self.protected[&page];
+            self.mprotect(page, prot)?;
+            opened.push(page);
+            let status = self.step_instruction()?;
+            match status {
+                Status::Stopped(signal::Signal::SIGSEGV, at) if at == pc => {
+                    let fault = fault_address(&self.siginfo()?);
+                    match fault.filter(|&fault| {
+                        self.is_protected(fault) && !opened.contains(&(fault - fault % page_size()))
+                    }) {
+                        Some(fault) => addr = fault,
+                        None => break status,
                     }
                 }
+                status => break status,
+            }
+        };
+        if let Status::Exited(..) | Status::Signaled(..) | Status::Execed = status {
+            return Ok(status);
+        }
+        for page in opened {
+            self.mprotect(page, self.protected[&page] & !libc::PROT_WRITE)?;
+        }
+        Ok(status)
+    }
+
+    fn mprotect(&mut self, page: usize, prot: libc::c_int) -> Result<(), nix::Error> {
+        self.inject_syscall(
+            libc::SYS_mprotect,
+            [page as u64, page_size() as u64, prot as u64],
+        )
+        .map(drop)
+    }
+
+    /// Resumes the inferior for the GDB client of `deet --serve`, delivering `signal` to the
+    /// current thread: just that thread for one instruction if `step`, and all of them
+    /// otherwise. Unlike with continue_exec, the client takes a breakpoint out itself before
+    /// resuming from it.
+    pub fn resume(
+        &mut self,
+        step: bool,
+        signal: Option<signal::Signal>,
+    ) -> Result<Status, nix::Error> {
+        self.trace_syscalls = false;
+        let status = if step {
+            self.stepping = true;
+            let tid = self.tid;
+            let status = self.resume_thread(tid, signal).and_then(|_| self.wait());
+            self.stepping = false;
+            status?
+        } else {
+            self.resume_stopped_threads(signal)?;
+            self.wait()?
+        };
+        match status {
+            Status::Execed => {
+                self.breakpoints.clear();
+                Ok(status)
+            }
+            status => Ok(status),
+        }
+    }
+
+    /// Restarts the stopped threads, except those with an event still to be reported, with
+    /// `signal` for the current one.
+    fn resume_stopped_threads(&mut self, signal: Option<signal::Signal>) -> Result<(), nix::Error> {
+        let stopped: Vec<Pid> = self
+            .all_threads()
+            .filter(|tid| {
+                !self.running.contains(tid) && !self.deferred.iter().any(|(other, ..)| other == tid)
+            })
+            .collect();
+        for tid in stopped {
+            let signal = signal.filter(|_| tid == self.tid);
+            self.resume_thread(tid, signal)?;
+        }
+        Ok(())
+    }
+
+    /// Steps the remote program one instruction, taking out a breakpoint at its pc meanwhile.
+    fn step_remote(&mut self) -> Result<Status, nix::Error> {
+        let remote = self.remote.as_ref().unwrap();
+        let pc = arch::pc(&remote.registers()?);
+        let at_breakpoint = self.breakpoints.contains_key(&pc);
+        if at_breakpoint {
+            remote.remove_breakpoint(pc)?;
+        }
+        let stop = remote.resume(true);
+        if at_breakpoint {
+            remote.insert_breakpoint(pc)?;
+        }
+        let usage = unsafe { std::mem::zeroed() };
+        Ok(match stop? {
+            Stop::Signal(signal::Signal::SIGTRAP) => {
+                Status::Stepped(arch::pc(&remote.registers()?))
+            }
+            Stop::Signal(signal) => Status::Stopped(signal, arch::pc(&remote.registers()?)),
+            Stop::Exited(code) => Status::Exited(code, ResourceUsage::new(self.started, &usage)),
+            Stop::Killed(signal) => {
+                Status::Signaled(signal, false, ResourceUsage::new(self.started, &usage))
+            }
+        })
+    }
+
+    /// Continues a remote program. A breakpoint it's stopped at is taken out while it steps
+    /// past, like for a local one. Stubs report stops at breakpoints with the pc already at the
+    /// breakpoint.
+    fn continue_remote(&mut self) -> Result<Status, nix::Error> {
+        let remote = self.remote.as_ref().unwrap();
+        let pc = arch::pc(&remote.registers()?);
+        let stop = if self.breakpoints.contains_key(&pc) {
+            remote.remove_breakpoint(pc)?;
+            let stop = remote.resume(true);
+            remote.insert_breakpoint(pc)?;
+            match stop? {
+                Stop::Signal(signal::Signal::SIGTRAP) => remote.resume(false)?,
+                stop => stop,
+            }
+        } else {
+            remote.resume(false)?
+        };
+        let usage = unsafe { std::mem::zeroed() };
+        Ok(match stop {
+            Stop::Signal(signal) => {
+                let pc = arch::pc(&remote.registers()?);
+                if signal == signal::Signal::SIGTRAP && self.breakpoints.contains_key(&pc) {
+                    Status::Breakpoint(pc)
+                } else {
+                    Status::Stopped(signal, pc)
+                }
+            }
+            Stop::Exited(code) => Status::Exited(code, ResourceUsage::new(self.started, &usage)),
+            // the protocol doesn't say whether the stub's process dumped core
+            Stop::Killed(signal) => {
+                Status::Signaled(signal, false, ResourceUsage::new(self.started, &usage))
+            }
+        })
+    }
+
+    /// Returns the path of the program the inferior is currently running, which changes when it
+    /// calls execve.
+    pub fn executable_path(&self) -> std::io::Result<std::path::PathBuf> {
+        if self.remote.is_some() {
+            return Err(std::io::ErrorKind::Unsupported.into());
+        }
+        std::fs::read_link(format!("/proc/{}/exe", self.pid()))
+    }
+
+    /// Kill the inferior(child process), and reap it, so it doesn't linger as a zombie and its
+    /// death isn't picked up by a later wait.
+    pub fn kill(&mut self) -> Result<(), nix::Error> {
+        outln!("Killing running inferior (pid {})", self.pid());
+        if let Some(remote) = &self.remote {
+            return remote.kill();
+        }
+        signal::kill(self.pid(), signal::Signal::SIGKILL)?;
+        // each traced thread has its death reported to us, the main thread's only once the others
+        // have been reaped
+        let threads: Vec<Pid> = self.threads.iter().copied().collect();
+        for tid in threads.into_iter().chain([self.pid]) {
+            self.reap(tid)?;
+        }
+        self.threads.clear();
+        self.running.clear();
+        self.pending_sigstop.clear();
+        self.deferred.clear();
+        Ok(())
+    }
+
+    /// Waits until thread `tid`, which has been killed, is dead and its exit collected. One
+    /// reaped already is fine.
+    fn reap(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        loop {
+            match self.waiter.next_of(tid) {
+                Ok((_, WaitStatus::Exited(..) | WaitStatus::Signaled(..), _)) => return Ok(()),
+                // stops it reports on the way, such as PTRACE_EVENT_EXIT
+                Ok(_) => {}
+                Err(nix::Error::ECHILD) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns the signal information of the inferior's current stop.
+    pub fn siginfo(&self) -> Result<libc::siginfo_t, nix::Error> {
+        if self.remote.is_some() {
+            return Err(nix::Error::ENOSYS);
+        }
+        ptrace::getsiginfo(self.tid)
+    }
+
+    /// Returns the registers of the stopped inferior's current thread.
+    pub fn registers(&self) -> Result<arch::Registers, nix::Error> {
+        match &self.remote {
+            Some(remote) => remote.registers(),
+            None => arch::get_registers(self.tid),
+        }
+    }
+
+    /// Sets the registers of the stopped inferior's current thread.
+    pub fn set_registers(&self, regs: arch::Registers) -> Result<(), nix::Error> {
+        match &self.remote {
+            Some(_) => Err(nix::Error::ENOSYS),
+            None => arch::set_registers(self.tid, regs),
+        }
+    }
+
+    /// Reads `len` bytes of the inferior's memory at `addr`, with the original bytes in place of
+    /// the breakpoints. A read that runs into unmapped memory stops short, and fails only if it
+    /// couldn't read anything.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        if let Some(remote) = &self.remote {
+            return remote.read_memory(addr, len);
+        }
+        let start = align_addr_to_word(addr);
+        let mut bytes = Vec::new();
+        for word_addr in (start..addr + len).step_by(size_of::<usize>()) {
+            match self.read_word(word_addr) {
+                Ok(word) => bytes.extend_from_slice(&word.to_le_bytes()),
+                Err(err) if word_addr <= addr => return Err(err),
+                Err(_) => break,
+            }
+        }
+        bytes.truncate(addr + len - start);
+        bytes.drain(..addr - start);
+        for (at, byte) in self.breakpoint_bytes() {
+            if let Some(slot) = at.checked_sub(addr).and_then(|i| bytes.get_mut(i)) {
+                *slot = byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Reads a word of the inferior's memory.
+    pub fn read_word(&self, addr: usize) -> Result<usize, nix::Error> {
+        if let Some(remote) = &self.remote {
+            let bytes = remote.read_memory(addr, size_of::<usize>())?;
+            return Ok(usize::from_le_bytes(bytes.try_into().unwrap()));
+        }
+        Ok(ptrace::read(self.tid, addr as ptrace::AddressType)? as usize)
+    }
+
+    /// Returns the current instruction pointer of the stopped inferior's current thread.
+    pub fn instruction_pointer(&self) -> Result<usize, nix::Error> {
+        Ok(arch::pc(&self.registers()?))
+    }
+
+    /// Has the next `wait` stop the process with a SIGSTOP at `deadline`, if nothing has
+    /// stopped it by then, and report `Status::TimedOut`. None for no deadline.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Starts counting the instructions the process runs, for `take_instructions`: with a
+    /// hardware counter per thread if perf has one for us, and otherwise by single-stepping the
+    /// threads, which is slow and has them report no syscall stops. ENOTSUP for a remote
+    /// program, whose instructions can't be counted.
+    pub fn count_instructions(&mut self) -> Result<Counting, nix::Error> {
+        if self.remote.is_some() {
+            self.instruction_counter = Some(InstructionCounter::Unavailable);
+            return Err(nix::Error::ENOTSUP);
+        }
+        let counters = std::iter::once(self.pid)
+            .chain(self.threads.iter().copied())
+            .map(|tid| Ok((tid, open_instruction_counter(tid)?)))
+            .collect::<Result<HashMap<Pid, File>, nix::Error>>();
+        let (counter, counting) = match counters {
+            Ok(counters) => (
+                InstructionCounter::Hardware {
+                    counters,
+                    retired: 0,
+                    taken: 0,
+                },
+                Counting::Hardware,
+            ),
+            Err(err) => (
+                InstructionCounter::Steps { count: 0, taken: 0 },
+                Counting::Steps(err),
+            ),
+        };
+        self.instruction_counter = Some(counter);
+        Ok(counting)
+    }
+
+    /// Stops counting instructions, and lets the threads run freely again if they were being
+    /// stepped.
+    pub fn stop_counting_instructions(&mut self) {
+        self.instruction_counter = None;
+    }
+
+    /// Whether `count_instructions` has been called, and not undone since, even if it failed.
+    pub fn counts_instructions(&self) -> bool {
+        self.instruction_counter.is_some()
+    }
+
+    /// The instructions the process has run since the last call, or since it started counting
+    /// them. None if they aren't being counted.
+    pub fn take_instructions(&mut self) -> Option<u64> {
+        let (count, taken) = match self.instruction_counter.as_mut()? {
+            InstructionCounter::Hardware {
+                counters,
+                retired,
+                taken,
+            } => (
+                *retired + counters.values().map(read_instruction_counter).sum::<u64>(),
+                taken,
+            ),
+            InstructionCounter::Steps { count, taken } => (*count, taken),
+            InstructionCounter::Unavailable => return None,
+        };
+        Some(count.saturating_sub(std::mem::replace(taken, count)))
+    }
+
+    /// Whether the threads are single-stepped, to count their instructions.
+    fn counts_steps(&self) -> bool {
+        matches!(
+            self.instruction_counter,
+            Some(InstructionCounter::Steps { .. })
+        )
+    }
+
+    /// Counts an instruction that a thread has been stepped over, if they're counted that way.
+    fn count_step(&mut self) {
+        if let Some(InstructionCounter::Steps { count, .. }) = &mut self.instruction_counter {
+            *count += 1;
+        }
+    }
+
+    /// Whether the process's stdin is a pipe from deet that's still open.
+    pub fn has_stdin_pipe(&self) -> bool {
+        self.stdin
+            .as_ref()
+            .is_some_and(|stdin| stdin.lock().unwrap().is_some())
+    }
+
+    /// Writes `bytes` into the process's stdin pipe, for it to read when it gets to it. Fails
+    /// without a pipe, once it's been closed, and when the pipe fills up before all the bytes
+    /// are in, saying how many made it.
+    pub fn write_stdin(&self, bytes: &[u8]) -> Result<(), String> {
+        let stdin = self.stdin.as_ref().ok_or(NO_STDIN_PIPE)?;
+        let mut stdin = stdin.lock().unwrap();
+        let pipe = stdin.as_mut().ok_or(STDIN_CLOSED)?;
+        let mut written = 0;
+        while written < bytes.len() {
+            match pipe.write(&bytes[written..]) {
+                Ok(count) => written += count,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    return Err(format!(
+                        "The pipe is full: only {} of {} bytes were written. The program has \
+                         to read some before it can take more.",
+                        written,
+                        bytes.len()
+                    ))
+                }
+                Err(err) => return Err(format!("Couldn't write to the program's stdin: {}", err)),
             }
         }
+        Ok(())
+    }
 
-        ptrace::cont(self.pid(), None)?; // Restart the stopped tracee process
-        self.wait(None)
+    /// Closes deet's end of the stdin pipe, so the process reads end-of-file once it's read
+    /// what was written before. There's only one end-of-file to give: it fails after that.
+    pub fn close_stdin(&self) -> Result<(), String> {
+        let stdin = self.stdin.as_ref().ok_or(NO_STDIN_PIPE)?;
+        let closed = stdin.lock().unwrap().take();
+        closed.map(drop).ok_or_else(|| STDIN_CLOSED.to_string())
     }
 
-    /// Kill the inferior(child process).
-    pub fn kill(&mut self) -> Result<(), std::io::Error> {
-        println!("Killing running inferior (pid {})", self.pid());
-        self.child.kill()
+    /// Wall-clock time since the process was started (or forked).
+    pub fn run_time(&self) -> Duration {
+        self.started.elapsed()
     }
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Returns the id of the thread that reported the last stop.
+    pub fn tid(&self) -> Pid {
+        self.tid
+    }
+
+    /// The main thread followed by the others.
+    fn all_threads(&self) -> impl Iterator<Item = Pid> + '_ {
+        std::iter::once(self.pid).chain(self.threads.iter().copied())
+    }
+
+    /// Resumes one thread: single-stepping it if it's stepping over a breakpoint, and otherwise
+    /// up to the next syscall if we're tracing them.
+    fn resume_thread(
+        &mut self,
+        tid: Pid,
+        signal: Option<signal::Signal>,
+    ) -> Result<(), nix::Error> {
+        if self.stepping && tid == self.tid {
+            ptrace::step(tid, signal)?;
+        } else if self.counts_steps() {
+            // every instruction traps instead, syscalls included, so a syscall it was in the
+            // middle of won't report its exit either
+            self.in_syscall.remove(&tid);
+            ptrace::step(tid, signal)?;
+        } else if self.trace_syscalls {
+            ptrace::syscall(tid, signal)?;
+        } else {
+            // a syscall it was in the middle of won't report its exit
+            self.in_syscall.remove(&tid);
+            ptrace::cont(tid, signal)?;
+        }
+        self.running.insert(tid);
+        Ok(())
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    /// Registers a thread created by `parent`, which starts out headed for a SIGSTOP.
+    fn add_thread(&mut self, parent: Pid) -> Result<Pid, nix::Error> {
+        let tid = Pid::from_raw(ptrace::getevent(parent)? as i32);
+        self.waiter.watch(tid);
+        outln!("[New thread {}]", tid);
+        self.threads.insert(tid);
+        self.running.insert(tid);
+        self.pending_sigstop.insert(tid);
+        if let Some(InstructionCounter::Hardware { counters, .. }) = &mut self.instruction_counter {
+            // one that can't be opened leaves the thread's instructions out of the count
+            if let Ok(counter) = open_instruction_counter(tid) {
+                counters.insert(tid, counter);
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
+        }
+        Ok(tid)
+    }
+
+    fn remove_thread(&mut self, tid: Pid) {
+        outln!("[Thread {} exited]", tid);
+        self.forget_thread(tid);
+    }
+
+    /// Stops tracking a thread that's gone without reporting its exit: the one that called
+    /// execve, say, which lives on as the main thread.
+    fn forget_thread(&mut self, tid: Pid) {
+        self.threads.remove(&tid);
+        self.running.remove(&tid);
+        self.pending_sigstop.remove(&tid);
+        self.in_syscall.remove(&tid);
+        self.deferred.retain(|(other, ..)| *other != tid);
+        if let Some(InstructionCounter::Hardware {
+            counters, retired, ..
+        }) = &mut self.instruction_counter
+        {
+            if let Some(counter) = counters.remove(&tid) {
+                *retired += read_instruction_counter(&counter);
+            }
+        }
+    }
+
+    /// What raised the SIGTRAP thread `tid` has just stopped with. A breakpoint instruction
+    /// traps with the same si_code whether it's ours or the program's, so which it is goes by
+    /// whether we put one there. A single step runs the instruction under one of ours, taken out
+    /// meanwhile, so one it traps on is the program's. Steps are ours while we're stepping a
+    /// thread or counting instructions by steps.
+    fn classify_trap(&self, tid: Pid) -> Result<Trap, nix::Error> {
+        let code = ptrace::getsiginfo(tid)?.si_code;
+        if code == arch::BREAKPOINT_TRAP_CODE && !self.stepping {
+            let addr = arch::pc(&arch::get_registers(tid)?) - arch::PC_AFTER_BREAKPOINT;
+            if self.breakpoints.contains_key(&addr) {
+                return Ok(Trap::Breakpoint(addr));
+            }
+        }
+        Ok(if code == arch::HARDWARE_TRAP_CODE {
+            Trap::Hardware
+        } else if (self.stepping || self.counts_steps()) && arch::STEP_TRAP_CODES.contains(&code) {
+            Trap::Step
+        } else {
+            Trap::Program
         })
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let regs = ptrace::getregs(self.pid())?;
-        let mut rip = regs.rip.try_into().unwrap();
-        let mut rbp = regs.rbp.try_into().unwrap();
+    /// Stops the threads that are still running after `tid` reported a stop, so that the
+    /// inferior is stopped as a whole. Breakpoint traps they report meanwhile are undone so the
+    /// breakpoint is hit again once resumed; other stops are deferred.
+    fn stop_other_threads(&mut self) -> Result<(), nix::Error> {
+        let mut to_stop: Vec<Pid> = self.running.iter().copied().collect();
+        while let Some(tid) = to_stop.pop() {
+            if !self.pending_sigstop.contains(&tid) {
+                // ESRCH means it has just exited, which we'll see below
+                let _ = signal_thread(self.pid, tid, signal::Signal::SIGSTOP);
+                self.pending_sigstop.insert(tid);
+            }
+            let (_, status, usage) = self.waiter.next_of(tid)?;
+            self.running.remove(&tid);
+            match status {
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => {
+                    self.pending_sigstop.remove(&tid);
+                    // a thread that has just exited takes none
+                    let _ = self.apply_watchpoints(tid);
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => self.remove_thread(tid),
+                WaitStatus::Stopped(_, signal::Signal::SIGTRAP)
+                    if matches!(self.classify_trap(tid)?, Trap::Breakpoint(_)) =>
+                {
+                    arch::rewind_breakpoint(tid)?;
+                }
+                // a step counting an instruction, which leaves it stopped just the same
+                WaitStatus::Stopped(_, signal::Signal::SIGTRAP)
+                    if self.counts_steps() && matches!(self.classify_trap(tid)?, Trap::Step) =>
+                {
+                    self.count_step();
+                }
+                WaitStatus::PtraceEvent(_, _, event)
+                    if event == ptrace::Event::PTRACE_EVENT_CLONE as i32 =>
+                {
+                    to_stop.push(self.add_thread(tid)?);
+                }
+                status => self.deferred.push_back((tid, status, usage)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the next event of any of the inferior's threads. With a deadline, the process
+    /// is stopped once it passes.
+    fn wait_event(&mut self) -> Result<Event, nix::Error> {
+        // while stepping, only the stepping thread is running, and its step is what we want
+        if !self.stepping {
+            if let Some(event) = self.deferred.pop_front() {
+                return Ok(event);
+            }
+        }
+        loop {
+            let event = match self.waiter.next(self.deadline) {
+                Some(event) => event,
+                None if self.waiter.is_done() => return Err(nix::Error::ECHILD),
+                None => {
+                    // the stop it makes is the next event, unless something beats it
+                    self.deadline = None;
+                    signal_thread(self.pid, self.pid, signal::Signal::SIGSTOP)?;
+                    self.timed_out = true;
+                    continue;
+                }
+            };
+            match event {
+                // threads that were gone already, such as those an exec ended
+                Ok((tid, ..)) | Err((tid, _))
+                    if tid != self.pid && !self.threads.contains(&tid) => {}
+                Ok(event) => return Ok(event),
+                // gone without a trace, e.g. by calling execve
+                Err((tid, _)) if tid != self.pid => self.forget_thread(tid),
+                Err((_, err)) => return Err(err),
+            }
+        }
+    }
 
+    /// Waits until the inferior stops or exits and returns a Status to indicate its state.
+    /// Events that only concern its threads (one starting or exiting) are handled here, and a
+    /// stop of any thread stops all of them.
+    pub fn wait(&mut self) -> Result<Status, nix::Error> {
         loop {
-            let function = debug_data.get_function_from_addr(rip).unwrap();
-            let line = debug_data.get_line_from_addr(rip).unwrap();
-            println!("{} ({})", function, line);
-            if function == "main" {
-                break;
+            let (tid, status, usage) = self.wait_event()?;
+            self.running.remove(&tid);
+            if self.timed_out {
+                self.timed_out = false;
+                if let WaitStatus::Stopped(_, signal::Signal::SIGSTOP) = status {
+                    if tid == self.pid {
+                        self.tid = tid;
+                        self.stop_other_threads()?;
+                        return Ok(Status::TimedOut(self.instruction_pointer()?));
+                    }
+                }
+                // it stopped for something else first; stopping the other threads sends the
+                // main thread a SIGSTOP of its own anyway, but if it's the one that stopped, ours
+                // is still on its way
+                if tid == self.pid {
+                    self.pending_sigstop.insert(tid);
+                }
+            }
+            match status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) if tid != self.pid => {
+                    self.remove_thread(tid);
+                }
+                WaitStatus::Stopped(_, signal::Signal::SIGSTOP)
+                    if self.pending_sigstop.remove(&tid) =>
+                {
+                    let _ = self.apply_watchpoints(tid);
+                    self.resume_thread(tid, None)?;
+                }
+                WaitStatus::PtraceEvent(_, _, event)
+                    if event == ptrace::Event::PTRACE_EVENT_CLONE as i32 =>
+                {
+                    self.add_thread(tid)?;
+                    self.resume_thread(tid, None)?;
+                }
+                WaitStatus::PtraceEvent(_, _, event)
+                    if event == ptrace::Event::PTRACE_EVENT_VFORK_DONE as i32 =>
+                {
+                    if std::mem::take(&mut self.vfork_breakpoints) {
+                        self.restore_breakpoints();
+                    }
+                    self.resume_thread(tid, None)?;
+                }
+                // a step counting an instruction, after which the thread goes on stepping
+                WaitStatus::Stopped(_, signal::Signal::SIGTRAP)
+                    if self.counts_steps()
+                        && !self.stepping
+                        && matches!(self.classify_trap(tid)?, Trap::Step) =>
+                {
+                    self.count_step();
+                    // with steps coming one after the other, the wait for events never times out
+                    if self
+                        .deadline
+                        .is_some_and(|deadline| Instant::now() >= deadline)
+                    {
+                        self.deadline = None;
+                        self.tid = tid;
+                        self.stop_other_threads()?;
+                        return Ok(Status::TimedOut(self.instruction_pointer()?));
+                    }
+                    self.resume_thread(tid, None)?;
+                }
+                WaitStatus::PtraceEvent(_, _, event)
+                    if event == ptrace::Event::PTRACE_EVENT_EXEC as i32 =>
+                {
+                    // the other threads are gone, and whichever one called execve now has the
+                    // main thread's id
+                    self.tid = self.pid;
+                    self.threads.clear();
+                    self.running.clear();
+                    self.pending_sigstop.clear();
+                    self.in_syscall.clear();
+                    // exec clears the debug registers
+                    self.watchpoints = [None; arch::WATCHPOINT_SLOTS];
+                    return Ok(Status::Execed);
+                }
+                // a thread at a syscall is resumed right away, so stopping the others (and
+                // interrupting the syscalls they're blocked in) would only slow them down
+                status @ WaitStatus::PtraceSyscall(_) => {
+                    self.tid = tid;
+                    return self.status_of(status, &usage);
+                }
+                status => {
+                    self.tid = tid;
+                    self.stop_other_threads()?;
+                    return self.status_of(status, &usage);
+                }
             }
-            rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType)? as usize;
-            rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType)? as usize;
         }
-        Ok(())
+    }
+
+    fn status_of(
+        &mut self,
+        status: WaitStatus,
+        usage: &libc::rusage,
+    ) -> Result<Status, nix::Error> {
+        Ok(match status {
+            WaitStatus::Exited(_pid, exit_code) => {
+                Status::Exited(exit_code, ResourceUsage::new(self.started, usage))
+            }
+            WaitStatus::Signaled(_pid, signal, core_dumped) => {
+                Status::Signaled(signal, core_dumped, ResourceUsage::new(self.started, usage))
+            }
+            WaitStatus::Stopped(_pid, signal) => match signal {
+                signal::Signal::SIGTRAP => match self.classify_trap(self.tid)? {
+                    // so the replaced instruction runs once resumed, and the stop is reported
+                    // at the breakpoint rather than in the middle of or after its instruction
+                    Trap::Breakpoint(addr) => {
+                        arch::rewind_breakpoint(self.tid)?;
+                        Status::Breakpoint(addr)
+                    }
+                    Trap::Step => Status::Stepped(self.instruction_pointer()?),
+                    Trap::Hardware => Status::HardwareTrap(self.instruction_pointer()?),
+                    // reported after the instruction, which has run, as the program would see
+                    Trap::Program => Status::Stopped(signal, self.instruction_pointer()?),
+                },
+                _ => Status::Stopped(signal, self.instruction_pointer()?),
+            },
+            WaitStatus::PtraceEvent(_pid, _signal, event)
+                if event == ptrace::Event::PTRACE_EVENT_FORK as i32
+                    || event == ptrace::Event::PTRACE_EVENT_VFORK as i32 =>
+            {
+                let child = ptrace::getevent(self.tid)?;
+                let vfork = event == ptrace::Event::PTRACE_EVENT_VFORK as i32;
+                Status::Forked(Pid::from_raw(child as i32), vfork)
+            }
+            WaitStatus::PtraceSyscall(_pid) => {
+                if self.in_syscall.remove(&self.tid) {
+                    Status::Syscall(SyscallStop::Exit)
+                } else {
+                    self.in_syscall.insert(self.tid);
+                    Status::Syscall(SyscallStop::Entry)
+                }
+            }
+            other => panic!("waitpid returned unexpected status: {:?}", other),
+        })
+    }
+
+    /// The call frame information of the objects mapped into the process now, loading that of
+    /// any that weren't before. There are none for a remote program, whose mappings we can't see.
+    pub fn mapped_frame_info(&self) -> Ref<'_, unwind::MappedFrameInfo> {
+        if self.remote.is_none() {
+            if let Ok(mappings) = read_memory_maps(self.pid) {
+                self.frame_info.borrow_mut().update(&mappings);
+            }
+        }
+        self.frame_info.borrow()
+    }
+
+    /// Walks the stack of the current thread, passing each frame and its frame pointer to `frame`
+    /// until it returns false. The stack is read a window at a time rather than a word at a time,
+    /// which matters for the deep ones of runaway recursion.
+    pub fn backtrace(
+        &self,
+        debug_data: &DwarfData,
+        print_mangled: bool,
+        frame: impl FnMut(Location, Option<usize>) -> bool,
+    ) -> Result<(), String> {
+        let regs = self
+            .registers()
+            .map_err(|err| format!("Error reading the stack: {}", err))?;
+        let window: RefCell<(usize, Vec<u8>)> = RefCell::new((0, Vec::new()));
+        let read_stack = |addr: usize| {
+            if self.remote.is_none() {
+                if let Some(word) = self.read_stack_window(&mut window.borrow_mut(), addr) {
+                    return Ok(word);
+                }
+            }
+            self.read_word(addr)
+        };
+        backtrace(
+            debug_data,
+            &self.mapped_frame_info(),
+            print_mangled,
+            &regs,
+            read_stack,
+            frame,
+        )
+    }
+
+    /// Where the current thread's frame returns to, and its CFA: the stack pointer once it has.
+    /// The frame is unwound as `backtrace` would, one frame up. None for the outermost frame, or
+    /// if the stack can't be read.
+    pub fn frame_return(&self, debug_data: &DwarfData) -> Option<(usize, usize)> {
+        let current = unwind::Frame::new(&self.registers().ok()?);
+        let word_size = debug_data.pointer_size();
+        let read_stack = |addr: usize| {
+            let word = self.read_word(addr)?;
+            Ok(match word_size {
+                4 => word & 0xffff_ffff,
+                _ => word,
+            })
+        };
+        let rule = debug_data
+            .unwind_row(current.pc)
+            .or_else(|| self.mapped_frame_info().row(current.pc))
+            .and_then(|row| Some((current.cfa(&row)?, row)));
+        let caller = match &rule {
+            Some((cfa, row)) => current.caller(row, *cfa, read_stack),
+            None => current.caller_by_frame_pointer(word_size, read_stack),
+        };
+        let caller = caller.ok()??;
+        Some((caller.pc, caller.sp()?))
+    }
+
+    /// The word at `addr` from `window`, the bytes read last from the stack and where they start,
+    /// reading the STACK_WINDOW from `addr` up into it if they don't include it. None if it can't
+    /// be read that way.
+    fn read_stack_window(&self, window: &mut (usize, Vec<u8>), addr: usize) -> Option<usize> {
+        let word = |(start, bytes): &(usize, Vec<u8>)| {
+            let offset = addr.checked_sub(*start)?;
+            let word = bytes.get(offset..offset + size_of::<usize>())?;
+            Some(usize::from_le_bytes(word.try_into().unwrap()))
+        };
+        if let Some(word) = word(window) {
+            return Some(word);
+        }
+        let mut bytes = vec![0; STACK_WINDOW];
+        // the window may run past the top of the stack, in which case less of it is read
+        let read = uio::process_vm_readv(
+            self.pid,
+            &mut [IoSliceMut::new(&mut bytes)],
+            &[uio::RemoteIoVec {
+                base: addr,
+                len: STACK_WINDOW,
+            }],
+        )
+        .ok()?;
+        bytes.truncate(read);
+        *window = (addr, bytes);
+        word(window)
+    }
+}
+
+/// How much of the stack a backtrace reads in one go
+const STACK_WINDOW: usize = 64 * 1024;
+
+/// Walks the stack from the frame with registers `regs`, reading it with `read_word`, and passes
+/// each frame to `frame` until it returns false, with its frame pointer if that's known. Says why
+/// if the walk is cut short of the outermost frame. Shared by live inferiors and core files.
+///
+/// Callers are found with the call frame information of the program and of the objects in
+/// `mapped`, and with the frame pointer chain where neither describes the code. The stack's words
+/// are the program's pointer size, which is smaller than `read_word`'s for a 32-bit program.
+pub fn backtrace(
+    debug_data: &DwarfData,
+    mapped: &unwind::MappedFrameInfo,
+    print_mangled: bool,
+    regs: &arch::Registers,
+    read_word: impl Fn(usize) -> Result<usize, nix::Error>,
+    mut frame: impl FnMut(Location, Option<usize>) -> bool,
+) -> Result<(), String> {
+    let word_size = debug_data.pointer_size();
+    let read_stack = |addr: usize| {
+        let word = read_word(addr)?;
+        Ok::<_, nix::Error>(match word_size {
+            4 => word & 0xffff_ffff,
+            _ => word,
+        })
+    };
+    let mut current = unwind::Frame::new(regs);
+    let mut innermost = true;
+    loop {
+        let pc = current.pc;
+        // a caller's pc is the return address, which may be past the end of the calling
+        // function if what it called doesn't return; the call is before it
+        let lookup = if innermost { pc } else { pc - 1 };
+        let function = debug_data.get_function_from_addr(lookup);
+        let line = debug_data
+            .get_line_from_addr(lookup)
+            .filter(|_| function.is_some());
+        let is_main = function.as_deref() == Some("main");
+        let wanted = frame(
+            Location {
+                address: pc,
+                function: function.map(|name| format_symbol_name(&name, print_mangled)),
+                offset: None,
+                file: line.as_ref().map(|line| line.file.clone()),
+                line: line.as_ref().map(|line| line.number),
+                column: 0,
+                args: None,
+                mapping: None,
+            },
+            current.fp(),
+        );
+        if !wanted || is_main {
+            return Ok(());
+        }
+        let rule = debug_data
+            .unwind_row(lookup)
+            .or_else(|| mapped.row(lookup))
+            .and_then(|row| Some((current.cfa(&row)?, row)));
+        let next = match &rule {
+            Some((cfa, row)) => current.caller(row, *cfa, read_stack),
+            None => current.caller_by_frame_pointer(word_size, read_stack),
+        };
+        let next = match next.map_err(|err| format!("Error reading the stack: {}", err))? {
+            Some(next) => next,
+            None => return Ok(()),
+        };
+        // callers' frames are further up the stack, so a walk that doesn't go up loops
+        let inner = match rule {
+            Some(_) => matches!((current.sp(), next.sp()), (Some(sp), Some(next)) if next <= sp),
+            None => {
+                matches!((current.fp(), next.fp()), (Some(fp), Some(next)) if next != 0 && next <= fp)
+            }
+        };
+        if inner {
+            return Err(
+                "Backtrace stopped: previous frame inner to this frame (corrupt stack?)"
+                    .to_string(),
+            );
+        }
+        current = next;
+        innermost = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Starts `program`, stopped at its exec, with its output thrown away.
+    fn start(program: &str) -> (Inferior, DwarfData) {
+        let redirections = Redirections {
+            stdout: Some(OutputRedirect::File {
+                path: "/dev/null".to_string(),
+                append: false,
+            }),
+            ..Redirections::default()
+        };
+        let inferior = Inferior::new(
+            program,
+            &Vec::new(),
+            &redirections,
+            &LaunchOptions::default(),
+        )
+        .unwrap();
+        let debug_data = DwarfData::from_file(program).unwrap();
+        (inferior, debug_data)
+    }
+
+    fn function(debug_data: &DwarfData, name: &str) -> usize {
+        debug_data.get_addr_for_function(None, name).unwrap()
+    }
+
+    /// The bytes at `addr` as they are in memory, breakpoint instructions and all.
+    fn memory(inferior: &Inferior, addr: usize) -> Vec<u8> {
+        let word = inferior.read_word(addr).unwrap().to_le_bytes();
+        word[..arch::BREAKPOINT.len()].to_vec()
+    }
+
+    #[test]
+    fn installs_removes_and_reinstalls_a_breakpoint() {
+        let (mut inferior, debug_data) = start("samples/function_calls");
+        let addr = function(&debug_data, "func2");
+        let original = memory(&inferior, addr);
+        assert_ne!(original, arch::BREAKPOINT);
+
+        assert_eq!(inferior.install_breakpoint(addr).unwrap()[..], original[..]);
+        assert_eq!(memory(&inferior, addr), arch::BREAKPOINT);
+        // what the debugger reads has the original bytes
+        assert_eq!(
+            inferior.read_memory(addr, original.len()).unwrap(),
+            original
+        );
+        assert!(inferior.has_breakpoint(addr));
+        let installed = inferior.installed_breakpoints();
+        assert_eq!(installed.len(), 1);
+        assert_eq!(installed[0].addr, addr);
+        assert_eq!(installed[0].orig_bytes[..], original[..]);
+        assert_eq!(
+            inferior.breakpoint_bytes(),
+            (addr..).zip(original.iter().copied()).collect::<Vec<_>>()
+        );
+        // installing it again keeps the original bytes, not the breakpoint's
+        assert_eq!(inferior.install_breakpoint(addr).unwrap()[..], original[..]);
+        assert_eq!(inferior.installed_breakpoints().len(), 1);
+
+        inferior.remove_breakpoint(addr).unwrap();
+        assert_eq!(memory(&inferior, addr), original);
+        assert!(!inferior.has_breakpoint(addr));
+        assert!(inferior.installed_breakpoints().is_empty());
+        // removing one that isn't there does nothing
+        inferior.remove_breakpoint(addr).unwrap();
+        assert_eq!(memory(&inferior, addr), original);
+
+        inferior.install_breakpoint(addr).unwrap();
+        assert_eq!(memory(&inferior, addr), arch::BREAKPOINT);
+        assert!(matches!(
+            inferior.continue_exec(false),
+            Ok(Status::Breakpoint(at)) if at == addr
+        ));
+        assert_eq!(inferior.instruction_pointer().unwrap(), addr);
+        inferior.remove_breakpoint(addr).unwrap();
+        assert_eq!(memory(&inferior, addr), original);
+        assert!(matches!(
+            inferior.continue_exec(false),
+            Ok(Status::Exited(0, _))
+        ));
+    }
+
+    #[test]
+    fn installs_breakpoints_in_one_batch() {
+        let (mut inferior, debug_data) = start("samples/function_calls");
+        let func1 = function(&debug_data, "func1");
+        let func3 = function(&debug_data, "func3");
+        let originals = [memory(&inferior, func1), memory(&inferior, func3)];
+        let failed = inferior.install_breakpoints(&[func3, func1, 0x10, func1]);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, 0x10);
+        let installed: Vec<usize> = inferior
+            .installed_breakpoints()
+            .iter()
+            .map(|bp| bp.addr)
+            .collect();
+        // by address, and func3 comes first in the program
+        assert_eq!(installed, [func3, func1]);
+        for (addr, original) in [func1, func3].into_iter().zip(&originals) {
+            assert_eq!(memory(&inferior, addr), arch::BREAKPOINT);
+            assert_eq!(inferior.install_breakpoint(addr).unwrap()[..], original[..]);
+        }
+
+        // main calls func1, which gets to func3 twice
+        for expected in [func1, func3, func3] {
+            assert!(matches!(
+                inferior.continue_exec(false),
+                Ok(Status::Breakpoint(at)) if at == expected
+            ));
+        }
+        assert!(inferior.remove_breakpoints().is_empty());
+        for (addr, original) in [func1, func3].into_iter().zip(&originals) {
+            assert_eq!(&memory(&inferior, addr), original);
+        }
+        assert!(matches!(
+            inferior.continue_exec(false),
+            Ok(Status::Exited(0, _))
+        ));
+    }
+
+    #[test]
+    fn reports_the_programs_own_trap_as_a_signal() {
+        let (mut inferior, debug_data) = start("samples/int3");
+        let after = function(&debug_data, "after");
+        inferior.install_breakpoint(after).unwrap();
+        let pc = match inferior.continue_exec(false) {
+            Ok(Status::Stopped(signal::Signal::SIGTRAP, pc)) => pc,
+            status => panic!("{:?}", status.map(|_| ())),
+        };
+        // left after the int3, in trap
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(inferior.read_memory(pc - 1, 1).unwrap(), [0xcc]);
+        let trap = function(&debug_data, "trap");
+        assert!(trap < pc && pc < after);
+        assert!(matches!(
+            inferior.continue_exec(false),
+            Ok(Status::Breakpoint(at)) if at == after
+        ));
+        assert!(matches!(
+            inferior.continue_exec(false),
+            Ok(Status::Exited(0, _))
+        ));
+    }
+
+    /// `new`'s error starting a file in the temporary directory named after `name` with
+    /// `contents` and permissions `mode`.
+    fn start_file(name: &str, contents: &[u8], mode: u32) -> DeetError {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("deet-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let result = Inferior::new(
+            &path,
+            &Vec::new(),
+            &Redirections::default(),
+            &LaunchOptions::default(),
+        );
+        let _ = std::fs::remove_file(&path);
+        match result {
+            Ok(_) => panic!("{} started", path),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn starting_reports_what_kept_the_program_from_running() {
+        let program = std::fs::read("samples/hello").unwrap();
+        let err = start_file("not-executable", &program, 0o644);
+        assert!(
+            matches!(&err, DeetError::Spawn(_, err) if err.raw_os_error() == Some(libc::EACCES)),
+            "{:?}",
+            err
+        );
+        let err = start_file("not-a-program", b"not a program\n", 0o755);
+        assert!(
+            matches!(&err, DeetError::Spawn(_, err) if err.raw_os_error() == Some(libc::ENOEXEC)),
+            "{:?}",
+            err
+        );
+
+        let redirections = Redirections {
+            stdin: Some("samples/nosuch".to_string()),
+            ..Redirections::default()
+        };
+        let result = Inferior::new(
+            "samples/hello",
+            &Vec::new(),
+            &redirections,
+            &LaunchOptions::default(),
+        );
+        assert!(matches!(result, Err(DeetError::Launch(_))));
+        let result = Inferior::new(
+            "samples/hello",
+            &vec!["nul\0byte".to_string()],
+            &Redirections::default(),
+            &LaunchOptions::default(),
+        );
+        assert!(matches!(result, Err(DeetError::Launch(_))));
     }
 }
@@ -0,0 +1,43 @@
+//! deet, a debugger for Linux programs, as a library: the `deet` binary is a command line over
+//! it, and other tools can embed it.
+//!
+//! `debugger::Debugger` runs commands, typed ones through `execute_line`, and answers questions
+//! about the stopped program with typed results (`backtrace`, `evaluate`, `stop_location`).
+//! What happens meanwhile, such as the program stopping at a breakpoint, is printed as output
+//! and also passed as an `event::Event` to the listeners of `event::subscribe`. `Inferior` and
+//! `DwarfData` are the traced process and its debug info underneath.
+
+// first, so its outln! macro is visible in the other modules
+#[macro_use]
+pub mod output;
+
+pub mod arch;
+mod child_log;
+mod completion;
+mod core_file;
+mod dap;
+pub mod debugger;
+pub mod debugger_command;
+pub mod dwarf_data;
+pub mod error;
+pub mod event;
+pub mod expr;
+mod gimli_wrapper;
+pub mod inferior;
+mod json;
+pub mod location;
+mod mi;
+mod printf;
+mod registers;
+mod remote;
+mod script;
+pub mod serve;
+mod settings;
+pub mod signals;
+pub mod skip;
+mod source;
+pub mod style;
+mod syscalls;
+mod terminal;
+mod tracer;
+mod unwind;
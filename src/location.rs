@@ -0,0 +1,457 @@
+use crate::dwarf_data::DwarfData;
+
+/// A location as the commands that take one (so far `break`) accept it. It is parsed once, so
+/// they all share one grammar:
+///
+/// - `*ADDRESS`, `*SYMBOL`, `*BASE+OFFSET` or `SYMBOL+OFFSET`: an address
+/// - `[FILE:]LINE`: the first address of a source line
+/// - `FUNCTION`: a function, after its prologue
+///
+/// It is resolved against the debug info only when used, since that can change with `file`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    /// Numbers are kept as typed, because bare digits are read in the radix of
+    /// `set address-radix` when resolving
+    Address {
+        base: Base,
+        offset: Option<String>,
+    },
+    Line(Option<String>, usize),
+    Function(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Base {
+    Number(String),
+    Symbol(String),
+}
+
+/// An address a location resolved to, and what it is for display.
+pub struct Resolved {
+    pub address: usize,
+    /// The source file and line; for a function, where it's declared
+    pub line: Option<(String, usize)>,
+    /// The raw name of the function at the address
+    pub function: Option<String>,
+}
+
+impl Location {
+    pub fn parse(text: &str) -> Result<Location, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("Argument required (location).".to_string());
+        }
+        let (expression, starred) = match text.strip_prefix('*') {
+            Some(expression) => (expression.trim(), true),
+            None => (text, false),
+        };
+        if let Some((base, sign, offset)) = split_offset(expression) {
+            if sign == '-' {
+                return Err(format!("Negative offsets aren't supported: \"{}\".", text));
+            }
+            let base = match base.starts_with(|c: char| c.is_ascii_digit()) {
+                true if starred => Base::Number(base.to_string()),
+                true => return Err(format!("A line can't have an offset: \"{}\".", text)),
+                false => Base::Symbol(base.to_string()),
+            };
+            return Ok(Location::Address {
+                base,
+                offset: Some(offset.to_string()),
+            });
+        }
+        if starred {
+            let base = match expression {
+                "" => return Err("Argument required (address).".to_string()),
+                _ if expression.starts_with(|c: char| c.is_ascii_digit()) => {
+                    Base::Number(expression.to_string())
+                }
+                _ => Base::Symbol(expression.to_string()),
+            };
+            return Ok(Location::Address { base, offset: None });
+        }
+        // a C++ name like ns::func has colons too, but no line number after the last
+        match text.rsplit_once(':') {
+            Some((file, line)) if line.parse::<usize>().is_ok() && !file.is_empty() => Ok(
+                Location::Line(Some(file.to_string()), line.parse().unwrap()),
+            ),
+            _ => match text.parse() {
+                Ok(line) => Ok(Location::Line(None, line)),
+                Err(_) => Ok(Location::Function(text.to_string())),
+            },
+        }
+    }
+
+    /// Every address the location could mean. That's more than one for an ambiguous function,
+    /// e.g. a C++ overload set, for the caller to choose from; none is an error. Bare digits in
+    /// numbers are read in `radix`, and said so when that's hex, in case decimal was meant.
+    pub fn resolve(&self, debug_data: &DwarfData, radix: u32) -> Result<Vec<Resolved>, String> {
+        let at = |address: usize| Resolved {
+            address,
+            line: debug_data
+                .get_line_from_addr(address)
+                .map(|line| (line.file, line.number)),
+            function: debug_data.get_function_from_addr(address),
+        };
+        match self {
+            Location::Address { base, offset } => {
+                let number = |text: &str| {
+                    let value = parse_address(text, radix)?;
+                    if radix == 16 && radix_prefix(text).is_none() {
+                        outln!(
+                            "Taking {} as hexadecimal: {:#x}. Use 0d or \"set address-radix 10\" for decimal.",
+                            text,
+                            value
+                        );
+                    }
+                    Ok::<usize, String>(value)
+                };
+                let base_addr = match base {
+                    Base::Number(text) => number(text)?,
+                    Base::Symbol(name) => match debug_data.symbol_addresses(name)[..] {
+                        [] => return Err(format!("No symbol \"{}\" in current program.", name)),
+                        [addr] => addr,
+                        _ => {
+                            return Err(format!(
+                                "\"{}\" is ambiguous; give its address instead.",
+                                name
+                            ))
+                        }
+                    },
+                };
+                let offset = match offset {
+                    Some(offset) => number(offset)?,
+                    None => 0,
+                };
+                let address = base_addr
+                    .checked_add(offset)
+                    .ok_or_else(|| format!("\"{}\" is past the end of the address space.", self))?;
+                Ok(vec![at(address)])
+            }
+            Location::Line(file, line) => {
+                match debug_data.get_addr_for_line(file.as_deref(), *line) {
+                    Some(address) => Ok(vec![at(address)]),
+                    None => Err(match file {
+                        Some(file) => format!("No line {} in file \"{}\".", line, file),
+                        None => format!("No line {} in the current file.", line),
+                    }),
+                }
+            }
+            Location::Function(name) => {
+                let functions = debug_data.get_functions_by_name(name);
+                if functions.is_empty() {
+                    // there's no prologue to skip without line info, so stop at the entry
+                    let addrs = debug_data.function_symbol_addresses(name);
+                    if addrs.is_empty() {
+                        return Err(format!("Function \"{}\" not defined.", name));
+                    }
+                    return Ok(addrs.into_iter().map(at).collect());
+                }
+                Ok(functions
+                    .into_iter()
+                    .map(|(file, func)| Resolved {
+                        address: debug_data.after_prologue(func),
+                        line: Some((file.to_string(), func.line_number)),
+                        function: Some(func.raw_name().to_string()),
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Address { base, offset } => {
+                let (Base::Number(base) | Base::Symbol(base)) = base;
+                write!(f, "*{}", base)?;
+                if let Some(offset) = offset {
+                    write!(f, "+{}", offset)?;
+                }
+                Ok(())
+            }
+            Location::Line(Some(file), line) => write!(f, "{}:{}", file, line),
+            Location::Line(None, line) => write!(f, "{}", line),
+            Location::Function(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Splits `symbol+offset` or `symbol-offset` at the last sign followed by a number, into the
+/// base, the sign and the offset. None if there's no such offset, e.g. in `operator+`.
+fn split_offset(expression: &str) -> Option<(&str, char, &str)> {
+    let at = expression.rfind(['+', '-'])?;
+    let (base, offset) = (expression[..at].trim(), expression[at + 1..].trim());
+    let sign = expression[at..].chars().next()?;
+    (!base.is_empty() && offset.starts_with(|c: char| c.is_ascii_digit()))
+        .then_some((base, sign, offset))
+}
+
+/// The radix an address prefix asks for, 0x for hex or 0d for decimal, and the digits after it.
+fn radix_prefix(addr: &str) -> Option<(u32, &str)> {
+    let prefix = addr.get(..2)?.to_ascii_lowercase();
+    match prefix.as_str() {
+        "0x" => Some((16, &addr[2..])),
+        "0d" => Some((10, &addr[2..])),
+        _ => None,
+    }
+}
+
+/// Parses an address: hex after 0x, decimal after 0d, and bare digits in `radix`. Underscores
+/// between digits are ignored. The error names the character that isn't a digit, or says the
+/// address doesn't fit.
+fn parse_address(addr: &str, radix: u32) -> Result<usize, String> {
+    let addr = addr.trim();
+    let (radix, digits) = radix_prefix(addr).unwrap_or((radix, addr));
+    let name = if radix == 16 {
+        "hexadecimal"
+    } else {
+        "decimal"
+    };
+    let mut value: usize = 0;
+    let mut any = false;
+    for (i, c) in addr.char_indices().skip(addr.len() - digits.len()) {
+        if c == '_' {
+            continue;
+        }
+        let digit = c.to_digit(radix).ok_or_else(|| {
+            format!(
+                "Invalid {} digit '{}' at position {} of address \"{}\".",
+                name,
+                c,
+                i + 1,
+                addr
+            )
+        })?;
+        value = value
+            .checked_mul(radix as usize)
+            .and_then(|value| value.checked_add(digit as usize))
+            .ok_or_else(|| {
+                format!(
+                    "Address \"{}\" is too large for {} bits.",
+                    addr,
+                    usize::BITS
+                )
+            })?;
+        any = true;
+    }
+    if !any {
+        return Err(format!("No digits in address \"{}\".", addr));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(base: Base, offset: Option<&str>) -> Location {
+        Location::Address {
+            base,
+            offset: offset.map(str::to_string),
+        }
+    }
+
+    fn number(text: &str) -> Base {
+        Base::Number(text.to_string())
+    }
+
+    fn symbol(name: &str) -> Base {
+        Base::Symbol(name.to_string())
+    }
+
+    #[test]
+    fn parses_each_form() {
+        let cases = [
+            ("*0x401136", address(number("0x401136"), None)),
+            ("*4198710", address(number("4198710"), None)),
+            ("*main", address(symbol("main"), None)),
+            ("*main+0x1a", address(symbol("main"), Some("0x1a"))),
+            ("*0x401000+16", address(number("0x401000"), Some("16"))),
+            ("main+26", address(symbol("main"), Some("26"))),
+            ("42", Location::Line(None, 42)),
+            ("hello.c:7", Location::Line(Some("hello.c".to_string()), 7)),
+            (
+                "src/dir/hello.c:7",
+                Location::Line(Some("src/dir/hello.c".to_string()), 7),
+            ),
+            ("func2", Location::Function("func2".to_string())),
+            ("  func2  ", Location::Function("func2".to_string())),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Location::parse(text), Ok(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn which_form_wins() {
+        let cases = [
+            // the last sign followed by a number is the offset
+            ("* main + 8", address(symbol("main"), Some("8"))),
+            ("*a+b+4", address(symbol("a+b"), Some("4"))),
+            // a sign that isn't followed by a number is part of the name
+            ("operator+", Location::Function("operator+".to_string())),
+            ("*operator+", address(symbol("operator+"), None)),
+            ("operator+=+4", address(symbol("operator+="), Some("4"))),
+            // colons are a file only with a line number after the last
+            ("ns::func", Location::Function("ns::func".to_string())),
+            (
+                "ns::func:12",
+                Location::Line(Some("ns::func".to_string()), 12),
+            ),
+            (
+                "a.c:ns::func",
+                Location::Function("a.c:ns::func".to_string()),
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Location::parse(text), Ok(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("", "Argument required (location)."),
+            ("   ", "Argument required (location)."),
+            ("*", "Argument required (address)."),
+            ("* ", "Argument required (address)."),
+            ("main-4", "Negative offsets aren't supported: \"main-4\"."),
+            (
+                "*0x1000-4",
+                "Negative offsets aren't supported: \"*0x1000-4\".",
+            ),
+            ("12+4", "A line can't have an offset: \"12+4\"."),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Location::parse(text), Err(expected.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_addresses() {
+        let max = usize::MAX;
+        let cases = [
+            ("0x401136", 16, Ok(0x401136)),
+            ("0x401136", 10, Ok(0x401136)),
+            ("0X40113A", 10, Ok(0x40113a)),
+            ("0x40_11_36", 16, Ok(0x401136)),
+            ("401136", 16, Ok(0x401136)),
+            ("4198710", 10, Ok(4198710)),
+            ("0d4198710", 16, Ok(4198710)),
+            ("0D4_198_710", 16, Ok(4198710)),
+            ("0xffff_ffff_ffff_ffff", 16, Ok(max)),
+            ("18446744073709551615", 10, Ok(max)),
+            (
+                "0x12g4",
+                16,
+                Err("Invalid hexadecimal digit 'g' at position 5 of address \"0x12g4\"."),
+            ),
+            (
+                "12a",
+                10,
+                Err("Invalid decimal digit 'a' at position 3 of address \"12a\"."),
+            ),
+            (
+                "0d12f",
+                16,
+                Err("Invalid decimal digit 'f' at position 5 of address \"0d12f\"."),
+            ),
+            (
+                "0x-1",
+                16,
+                Err("Invalid hexadecimal digit '-' at position 3 of address \"0x-1\"."),
+            ),
+            ("0x", 16, Err("No digits in address \"0x\".")),
+            ("0x__", 16, Err("No digits in address \"0x__\".")),
+            (
+                "0x1_0000_0000_0000_0000",
+                16,
+                Err("Address \"0x1_0000_0000_0000_0000\" is too large for 64 bits."),
+            ),
+            (
+                "18446744073709551616",
+                10,
+                Err("Address \"18446744073709551616\" is too large for 64 bits."),
+            ),
+        ];
+        for (text, radix, expected) in cases {
+            assert_eq!(
+                parse_address(text, radix),
+                expected.map_err(str::to_string),
+                "{} in radix {}",
+                text,
+                radix
+            );
+        }
+    }
+
+    /// The address `text` resolves to in samples/function_calls, and the function there.
+    fn resolve(text: &str, radix: u32) -> Result<(usize, Option<String>), String> {
+        let debug_data = DwarfData::from_file("samples/function_calls").unwrap();
+        let resolved = Location::parse(text)?.resolve(&debug_data, radix)?;
+        assert_eq!(resolved.len(), 1, "{}", text);
+        Ok((resolved[0].address, resolved[0].function.clone()))
+    }
+
+    #[test]
+    fn resolves_each_form() {
+        let debug_data = DwarfData::from_file("samples/function_calls").unwrap();
+        let func2 = debug_data.symbol_addresses("func2")[0];
+        let func2_body = debug_data.get_addr_for_function(None, "func2").unwrap();
+        let line_11 = debug_data.get_addr_for_line(None, 11).unwrap();
+        let in_func2 = |addr: usize| Ok((addr, Some("func2".to_string())));
+        assert_eq!(
+            resolve(&format!("*{:#x}", func2_body), 10),
+            in_func2(func2_body)
+        );
+        assert_eq!(
+            resolve(&format!("*{:X}", func2_body), 16),
+            in_func2(func2_body)
+        );
+        assert_eq!(
+            resolve(&format!("*{}", func2_body), 10),
+            in_func2(func2_body)
+        );
+        assert_eq!(resolve("function_calls.c:11", 16), in_func2(line_11));
+        assert_eq!(resolve("11", 16), in_func2(line_11));
+        assert_eq!(resolve("func2", 16), in_func2(func2_body));
+        assert_eq!(resolve("*func2", 16), in_func2(func2));
+        assert_eq!(resolve("func2+0x4", 10), in_func2(func2 + 4));
+        assert_eq!(resolve("func2+10", 16), in_func2(func2 + 0x10));
+        assert_eq!(resolve("*func2+0d10", 16), in_func2(func2 + 10));
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let cases = [
+            ("*nosuch", "No symbol \"nosuch\" in current program."),
+            ("nosuch+4", "No symbol \"nosuch\" in current program."),
+            ("nosuch", "Function \"nosuch\" not defined."),
+            (
+                "function_calls.c:999",
+                "No line 999 in file \"function_calls.c\".",
+            ),
+            ("nosuch.c:11", "No line 11 in file \"nosuch.c\"."),
+            (
+                "func2+0x2g",
+                "Invalid hexadecimal digit 'g' at position 4 of address \"0x2g\".",
+            ),
+            (
+                "*0xffffffffffffffff+0x1",
+                "\"*0xffffffffffffffff+0x1\" is past the end of the address space.",
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(resolve(text, 16), Err(expected.to_string()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn displays_as_typed() {
+        for text in ["*0x401136", "*main+0x1a", "hello.c:7", "42", "func2"] {
+            assert_eq!(Location::parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(Location::parse("main+26").unwrap().to_string(), "*main+26");
+    }
+}
@@ -1,24 +1,104 @@
-mod debugger;
-mod debugger_command;
-mod inferior;
-mod dwarf_data;
-mod gimli_wrapper;
-
-use crate::debugger::Debugger;
+use deet::debugger::{Debugger, Options};
+use deet::{outln, output, serve, style};
 use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
+    let usage = || -> ! {
+        outln!(
+            "Usage: {} [--nx] [--batch] [--history-file <file>] [--no-color] [--json] [--interpreter=mi] [--dap] [-ex <command>]... [<target program>] [--core <corefile>]",
+            args[0]
+        );
+        outln!(
+            "       {} [<options>...] --args <target program> [<args>...]",
+            args[0]
+        );
+        outln!(
+            "       {} --serve <port> <target program> [<args>...]",
+            args[0]
+        );
         std::process::exit(1);
+    };
+    let mut target = None;
+    let mut core = None;
+    let mut serve = None;
+    let mut program_args = Vec::new();
+    let mut load_init_files = true;
+    let mut commands = Vec::new();
+    let mut options = Options::default();
+    let mut no_color = false;
+    let mut format = output::Format::Text;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--nx" => load_init_files = false,
+            "--batch" => options.batch = true,
+            "--no-color" => no_color = true,
+            "--json" => format = output::Format::Json,
+            "--dap" => format = output::Format::Dap,
+            "--interpreter=mi" | "--interpreter=mi2" | "--interpreter=mi3" => {
+                format = output::Format::Mi
+            }
+            "--interpreter" => match rest.next().map(String::as_str) {
+                Some("mi" | "mi2" | "mi3") => format = output::Format::Mi,
+                _ => usage(),
+            },
+            "--history-file" => {
+                options.history_file = Some(rest.next().unwrap_or_else(|| usage()).into())
+            }
+            "-ex" | "--ex" => commands.push(rest.next().unwrap_or_else(|| usage()).clone()),
+            "--core" => core = Some(rest.next().unwrap_or_else(|| usage())),
+            "--serve" => serve = Some(rest.next().unwrap_or_else(|| usage())),
+            // the program and its arguments, taken as they are, however they look
+            "--args" if target.is_none() => {
+                target = Some(rest.next().unwrap_or_else(|| usage()));
+                program_args.extend(rest.by_ref().cloned());
+            }
+            _ if target.is_none() && !arg.starts_with('-') => {
+                target = Some(arg);
+                // what follows the program is its own arguments when serving it
+                if serve.is_some() {
+                    program_args.extend(rest.by_ref().cloned());
+                }
+            }
+            _ => usage(),
+        }
     }
-    let target = &args[1];
+    // front ends parse the output, so it had better be plain
+    style::init(no_color || matches!(format, output::Format::Mi | output::Format::Dap));
+    output::set_format(format);
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
+    // We hand the terminal to the inferior's process group while it runs, and taking it back from
+    // the background would otherwise stop us
+    unsafe { signal(Signal::SIGTTOU, SigHandler::SigIgn) }
+        .expect("Error disabling SIGTTOU handling");
+
+    if let Some(port) = serve {
+        let target = target.unwrap_or_else(|| usage());
+        std::process::exit(serve::serve(port, target, &program_args));
+    }
 
-    Debugger::new(target).run();
+    options.args = program_args;
+    let mut debugger = match Debugger::new(target.map(String::as_str), options) {
+        Ok(debugger) => debugger,
+        Err(err) => {
+            outln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    if load_init_files {
+        debugger.load_init_files();
+    }
+    for command in commands {
+        debugger.run_at_startup(command);
+    }
+    if let Some(core) = core {
+        debugger.load_core(core);
+    }
+    let status = debugger.run();
+    std::process::exit(status);
 }
@@ -0,0 +1,338 @@
+//! The bits of GDB's machine interface (`--interpreter=mi`) that front ends need to drive deet:
+//! MI commands are translated into deet commands, and their results come back as MI records.
+//!
+//! A command may be answered early, e.g. `^running` before the inferior runs or `^done,bkpt=`
+//! when the breakpoint is set; otherwise it's answered once the next command is read.
+
+use crate::json::quote;
+use crate::output;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The command being answered.
+struct Pending {
+    token: String,
+    answered: bool,
+    /// Why the command failed, if it doesn't produce a result: a default message, and the first
+    /// console line since it started, which is usually the more telling one
+    error: Option<&'static str>,
+    first_console: Option<String>,
+}
+
+static PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+
+/// The variable objects of `-var-create`, as their names and the expressions they stand for.
+/// They're evaluated afresh each time, where the program is stopped then.
+static VARIABLES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// How many variable objects deet has named, for the next name, `var<N>`
+static NAMED: AtomicUsize = AtomicUsize::new(0);
+
+/// Set once `^exit` is out, after which there's nothing more to say
+static EXITED: AtomicBool = AtomicBool::new(false);
+
+/// What to do for an MI command.
+pub enum Request {
+    /// Run this deet command line. Errors with the message if it doesn't produce a result
+    /// (`^done,bkpt=...` or `^done,stack=...`); without one, the command is simply done.
+    Command(String, Option<&'static str>),
+    /// Answer `^done` with these results, if any, without running anything
+    Done(String),
+    /// Evaluate this expression and answer with its value, making it the variable object with
+    /// this name (`-` for deet to pick one) if there is one
+    Evaluate(String, Option<String>),
+    Error(String),
+}
+
+/// Splits `line` into its token and what to do. Lines that aren't MI commands are taken as CLI
+/// commands, as gdb does.
+pub fn translate(line: &str) -> (String, Request) {
+    let line = line.trim();
+    let split = line
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(line.len());
+    let (token, rest) = line.split_at(split);
+    let token = token.to_string();
+    let Some(rest) = rest.strip_prefix('-') else {
+        return (token, Request::Command(rest.to_string(), None));
+    };
+    let words = match split_args(rest) {
+        Ok(words) => words,
+        Err(message) => return (token, Request::Error(message)),
+    };
+    let name = words[0].as_str();
+    let args = &words[1..];
+    let request = match name {
+        "break-insert" => match locations(args, &["-c", "-i", "-p"]).as_slice() {
+            [location] => Request::Command(
+                format!("break {}", location),
+                Some("Could not set the breakpoint."),
+            ),
+            _ => Request::Error("-break-insert: Usage: [OPTION...] LOCATION".to_string()),
+        },
+        "exec-run" => Request::Command("run".to_string(), None),
+        "exec-continue" => Request::Command("continue".to_string(), None),
+        "exec-next" => Request::Command("next".to_string(), None),
+        "exec-step" => Request::Command("step".to_string(), None),
+        "exec-finish" => Request::Command("finish".to_string(), None),
+        "stack-list-frames" => Request::Command("backtrace".to_string(), Some("No stack.")),
+        "gdb-exit" => Request::Command("quit".to_string(), None),
+        "gdb-set" => Request::Command(format!("set {}", args.join(" ")), None),
+        "gdb-show" => Request::Command(format!("show {}", args.join(" ")), None),
+        "environment-cd" => Request::Command(format!("cd {}", args.join(" ")), None),
+        "interpreter-exec" => match args {
+            [interpreter, command] if interpreter == "console" => {
+                Request::Command(command.clone(), None)
+            }
+            _ => Request::Error(
+                "-interpreter-exec: Usage: -interpreter-exec console COMMAND".to_string(),
+            ),
+        },
+        "list-features" => Request::Done("features=[]".to_string()),
+        "var-create" => match skip_options(args, &["--thread", "--frame"]) {
+            [name, _frame, expression @ ..] if !expression.is_empty() => {
+                Request::Evaluate(expression.join(" "), Some(name.clone()))
+            }
+            _ => Request::Error("-var-create: Usage: NAME FRAME EXPRESSION".to_string()),
+        },
+        "var-evaluate-expression" => match locations(args, &["-f"]).as_slice() {
+            [name] => match expression_of(name) {
+                Some(expression) => Request::Evaluate(expression, None),
+                None => Request::Error("Variable object not found".to_string()),
+            },
+            _ => Request::Error("-var-evaluate-expression: Usage: [-f FORMAT] NAME".to_string()),
+        },
+        "var-delete" => match locations(args, &[]).as_slice() {
+            [name] => {
+                let mut variables = VARIABLES.lock().unwrap();
+                let count = variables.len();
+                variables.retain(|(variable, _)| variable != *name);
+                match count - variables.len() {
+                    0 => Request::Error("Variable object not found".to_string()),
+                    deleted => Request::Done(format!("ndeleted=\"{}\"", deleted)),
+                }
+            }
+            _ => Request::Error("-var-delete: Usage: NAME".to_string()),
+        },
+        _ => Request::Error(format!("Undefined MI command: {}", name)),
+    };
+    (token, request)
+}
+
+/// The arguments that aren't options, skipping the values of the options in `with_value`.
+fn locations<'a>(args: &'a [String], with_value: &[&str]) -> Vec<&'a String> {
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if with_value.contains(&arg.as_str()) || arg == "--thread-group" {
+            args.next();
+        } else if !arg.starts_with('-') {
+            rest.push(arg);
+        }
+    }
+    rest
+}
+
+/// `args` after the options at their start, each of which takes a value.
+fn skip_options<'a>(mut args: &'a [String], options: &[&str]) -> &'a [String] {
+    while let [option, _, rest @ ..] = args {
+        if !options.contains(&option.as_str()) {
+            break;
+        }
+        args = rest;
+    }
+    args
+}
+
+/// The expression variable object `name` stands for.
+fn expression_of(name: &str) -> Option<String> {
+    let variables = VARIABLES.lock().unwrap();
+    variables
+        .iter()
+        .find(|(variable, _)| variable == name)
+        .map(|(_, expression)| expression.clone())
+}
+
+/// Answers an `Evaluate` request with the value of its expression, which has type `ty`, and
+/// makes the variable object it asked for.
+pub fn evaluated(variable: Option<String>, expression: &str, value: &str, ty: &str) {
+    let mut results = Tuple::default();
+    if let Some(name) = variable {
+        let name = match name.as_str() {
+            "-" => format!("var{}", NAMED.fetch_add(1, Ordering::Relaxed) + 1),
+            _ => name,
+        };
+        let mut variables = VARIABLES.lock().unwrap();
+        variables.retain(|(variable, _)| *variable != name);
+        variables.push((name.clone(), expression.to_string()));
+        results
+            .string("name", &name)
+            .string("numchild", "0")
+            .string("value", value)
+            .string("type", ty)
+            .string("has_more", "0");
+    } else {
+        results.string("value", value);
+    }
+    result(results.results(), "");
+}
+
+/// Splits an MI command into words, unquoting C strings.
+fn split_args(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        let mut word = String::new();
+        if first == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => word.push('\n'),
+                        Some('t') => word.push('\t'),
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated string in MI command.".to_string()),
+                    },
+                    Some(c) => word.push(c),
+                    None => return Err("Unterminated string in MI command.".to_string()),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+        }
+        words.push(word);
+    }
+    if words.is_empty() {
+        return Err("Empty MI command.".to_string());
+    }
+    Ok(words)
+}
+
+/// Starts answering a command that came with `token`.
+pub fn begin(token: String, error: Option<&'static str>) {
+    *PENDING.lock().unwrap() = Some(Pending {
+        token,
+        answered: false,
+        error,
+        first_console: None,
+    });
+}
+
+/// Answers the command with `^done` and these results. A command can have only one answer, so
+/// further results, like the second of several breakpoints, become notifications.
+pub fn result(results: &str, notification: &str) {
+    let mut pending = PENDING.lock().unwrap();
+    match pending.as_mut().filter(|pending| !pending.answered) {
+        Some(pending) => {
+            pending.answered = true;
+            output::write_record(&format!("{}^done,{}", pending.token, results));
+        }
+        None => output::write_record(&format!("={},{}", notification, results)),
+    }
+}
+
+/// Answers that the inferior is running, before it runs.
+pub fn running() {
+    answer("^running");
+    output::write_record("*running,thread-id=\"all\"");
+    output::write_record("(gdb) ");
+}
+
+pub fn error(message: &str) {
+    answer(&format!("^error,msg={}", quote(message)));
+}
+
+pub fn exit() {
+    answer("^exit");
+    EXITED.store(true, Ordering::Relaxed);
+}
+
+/// Whether `^exit` is out, so the console output of deet shutting down, like killing the
+/// inferior, isn't shown.
+pub fn exited() -> bool {
+    EXITED.load(Ordering::Relaxed)
+}
+
+/// An exec async record, like `*stopped,...`.
+pub fn exec_async(record: &str) {
+    output::write_record(&format!("*{}", record));
+}
+
+fn answer(record: &str) {
+    let mut pending = PENDING.lock().unwrap();
+    let token = match pending.as_mut() {
+        Some(pending) if !pending.answered => {
+            pending.answered = true;
+            pending.token.clone()
+        }
+        _ => String::new(),
+    };
+    output::write_record(&format!("{}{}", token, record));
+}
+
+/// Answers the last command, if it hasn't been answered yet: `^done`, or `^error` if it was
+/// supposed to give results and didn't.
+pub fn finish() {
+    let Some(pending) = PENDING.lock().unwrap().take() else {
+        return;
+    };
+    if pending.answered {
+        return;
+    }
+    let record = match pending.error {
+        Some(default) => {
+            let message = pending.first_console.as_deref().unwrap_or(default);
+            format!("^error,msg={}", quote(message))
+        }
+        None => "^done".to_string(),
+    };
+    output::write_record(&format!("{}{}", pending.token, record));
+}
+
+/// Remembers console output as a possible error message for the command being answered.
+pub fn note_console(line: &str) {
+    if let Some(pending) = PENDING.lock().unwrap().as_mut() {
+        let line = line.trim_end();
+        if !line.is_empty() && pending.first_console.is_none() {
+            pending.first_console = Some(line.to_string());
+        }
+    }
+}
+
+/// A tuple of results, `{name="value",...}`.
+#[derive(Default)]
+pub struct Tuple(String);
+
+impl Tuple {
+    pub fn string(&mut self, name: &str, value: &str) -> &mut Self {
+        let value = quote(value);
+        self.raw(name, &value)
+    }
+
+    /// `value` is already a tuple or list
+    pub fn raw(&mut self, name: &str, value: &str) -> &mut Self {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        self.0.push_str(name);
+        self.0.push('=');
+        self.0.push_str(value);
+        self
+    }
+
+    /// The results without braces, as they follow `^done,`.
+    pub fn results(&self) -> &str {
+        &self.0
+    }
+
+    pub fn braced(&self) -> String {
+        format!("{{{}}}", self.0)
+    }
+}
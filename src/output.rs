@@ -0,0 +1,257 @@
+use crate::terminal;
+use nix::unistd;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+/// Where deet's own output goes: the terminal, and while `set logging` is on, a log file too
+/// (or only, with `set logging redirect`). Everything deet prints goes through `outln!` so the
+/// log sees it. The inferior's output doesn't; see `set logging-child` for that.
+struct Sink {
+    log: Option<LineWriter<File>>,
+    /// Leave the terminal out while logging
+    redirect: bool,
+    /// Also log the commands the user types
+    commands: bool,
+    page: Page,
+}
+
+/// The pager (`set pagination`): the output of a command stops after each screenful for a
+/// `--More--` prompt.
+struct Page {
+    /// Terminal height as of the start of the command, or 0 when not paging
+    rows: usize,
+    columns: usize,
+    /// Rows printed since the last prompt
+    printed: usize,
+    /// The user answered q: drop the rest of the command's output
+    quit: bool,
+}
+
+static SINK: Mutex<Sink> = Mutex::new(Sink {
+    log: None,
+    redirect: false,
+    commands: false,
+    page: Page {
+        rows: 0,
+        columns: 0,
+        printed: 0,
+        quit: false,
+    },
+});
+
+/// How results (see `event`) are printed, which also decides where the rest of the output goes.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    /// One JSON object per line on stdout; everything else goes to stderr
+    Json,
+    /// GDB/MI records on stdout, with everything else as console stream records
+    Mi,
+    /// Debug Adapter Protocol messages on stdout, with everything else as output events
+    Dap,
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Text as u8);
+
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn format() -> Format {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => Format::Json,
+        2 => Format::Mi,
+        3 => Format::Dap,
+        _ => Format::Text,
+    }
+}
+
+/// Like `println!`, but through the sink.
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::output::write_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::write_line(format_args!($($arg)*))
+    };
+}
+
+pub fn write_line(args: fmt::Arguments) {
+    write_text(format!("{}\n", args));
+}
+
+/// Prints `line`, which may be part of a line or several, the way `outln!` prints lines. For
+/// `echo` and `printf`, which only end a line where they're told to.
+pub fn write_text(mut line: String) {
+    if format() == Format::Mi {
+        if crate::mi::exited() {
+            return;
+        }
+        crate::mi::note_console(&line);
+        line = format!("~{}\n", crate::json::quote(&line));
+    }
+    if format() == Format::Dap {
+        crate::dap::console(&line);
+    }
+    let mut sink = SINK.lock().unwrap();
+    if format() != Format::Dap && (sink.log.is_none() || !sink.redirect) {
+        if format() == Format::Json {
+            let _ = std::io::stderr().write_all(line.as_bytes());
+        } else if sink.page.rows > 0 {
+            sink.page.write(&line);
+        } else {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(line.as_bytes());
+            let _ = stdout.flush();
+        }
+    }
+    if let Some(log) = &mut sink.log {
+        let _ = log.write_all(strip_styles(&line).as_bytes());
+    }
+}
+
+/// Starts paging the output of a new command, if `enabled` and both stdin and stdout are the
+/// terminal. Its size is read again each time, in case it was resized.
+pub fn begin_page(enabled: bool) {
+    let interactive = unistd::isatty(0).unwrap_or(false) && format() == Format::Text;
+    let size = terminal::size().filter(|_| enabled && interactive);
+    let page = &mut SINK.lock().unwrap().page;
+    (page.rows, page.columns) = size.unwrap_or((0, 0));
+    page.printed = 0;
+    page.quit = false;
+}
+
+/// Whether the user quit the pager during this command's output, so there's no point making more.
+pub fn page_quit() -> bool {
+    SINK.lock().unwrap().page.quit
+}
+
+impl Page {
+    /// Writes `text` to stdout a row at a time, prompting when the screen is full.
+    fn write(&mut self, text: &str) {
+        if self.quit {
+            return;
+        }
+        let mut stdout = std::io::stdout();
+        for line in text.split_inclusive('\n') {
+            let width = strip_styles(line).trim_end_matches('\n').chars().count();
+            let rows = width.max(1).div_ceil(self.columns);
+            // keep the last row for the prompt
+            if self.printed + rows >= self.rows {
+                let _ = write!(stdout, "--More--");
+                let _ = stdout.flush();
+                let key = terminal::read_key();
+                let _ = write!(stdout, "\r\x1b[K");
+                match key {
+                    // another screenful
+                    Some(b' ') => self.printed = 0,
+                    // another line
+                    Some(b'\n' | b'\r') => self.printed = self.rows.saturating_sub(rows + 1),
+                    _ => {
+                        self.quit = true;
+                        let _ = stdout.flush();
+                        return;
+                    }
+                }
+            }
+            let _ = stdout.write_all(line.as_bytes());
+            self.printed += rows;
+        }
+        let _ = stdout.flush();
+    }
+}
+
+/// Prints a JSON object or MI record on stdout as is, and logs it.
+pub fn write_record(record: &str) {
+    let mut sink = SINK.lock().unwrap();
+    let mut stdout = std::io::stdout();
+    let _ = writeln!(stdout, "{}", record);
+    let _ = stdout.flush();
+    if let Some(log) = &mut sink.log {
+        let _ = writeln!(log, "{}", record);
+    }
+}
+
+/// Writes a protocol message to stdout exactly as it is. Not logged, since it's mostly framing
+/// around what is.
+// the dap tests capture their messages instead
+#[cfg_attr(test, allow(dead_code))]
+pub fn write_raw(text: &str) {
+    let _sink = SINK.lock().unwrap();
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(text.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// Shows output of the inferior that deet captured, on deet's stdout or stderr, or as output
+/// events when speaking DAP.
+pub fn write_inferior(bytes: &[u8], is_stderr: bool) {
+    if format() == Format::Dap {
+        let category = if is_stderr { "stderr" } else { "stdout" };
+        crate::dap::output(category, &String::from_utf8_lossy(bytes));
+    } else if is_stderr {
+        let _ = std::io::stderr().write_all(bytes);
+    } else {
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(bytes).and_then(|_| stdout.flush());
+    }
+}
+
+/// Logs a command the user typed after its prompt, if commands are being logged.
+pub fn log_command(prompt: &str, line: &str) {
+    let mut sink = SINK.lock().unwrap();
+    if !sink.commands {
+        return;
+    }
+    if let Some(log) = &mut sink.log {
+        let _ = writeln!(log, "{}{}", prompt, line);
+    }
+}
+
+/// Starts appending to the log at `path`, closing any previous log.
+pub fn start_log(path: &Path, redirect: bool, commands: bool) -> Result<(), String> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("{}: {}.", path.display(), err))?;
+    let mut sink = SINK.lock().unwrap();
+    if let Some(mut old) = sink.log.take() {
+        let _ = old.flush();
+    }
+    sink.log = Some(LineWriter::new(file));
+    sink.redirect = redirect;
+    sink.commands = commands;
+    Ok(())
+}
+
+/// Flushes and closes the log, if one is open.
+pub fn stop_log() {
+    if let Some(mut log) = SINK.lock().unwrap().log.take() {
+        let _ = log.flush();
+    }
+}
+
+/// `line` without the escape sequences of `style`, which have no business in a file.
+fn strip_styles(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip to the end of the sequence, e.g. "\x1b[1;35m"
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
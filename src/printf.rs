@@ -0,0 +1,290 @@
+//! `echo` and `printf`, for the output of scripts and hooks: the backslash escapes of C strings,
+//! and C's printf with the arguments evaluated as expressions in the stopped program.
+
+use crate::debugger_command::split_expressions;
+use crate::expr::{Context, Expr};
+
+/// The longest string `%s` reads out of the program without a precision
+const MAX_STRING: usize = 4096;
+
+/// A parsed `printf "FORMAT", ARGS...`.
+pub struct Printf {
+    pieces: Vec<Piece>,
+    args: Vec<Expr>,
+}
+
+enum Piece {
+    Text(String),
+    Conversion(Conversion),
+}
+
+/// A `%` of the format, e.g. `%-8.3lx`
+struct Conversion {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    /// With an l, ll, j, z or t length modifier: 64-bit rather than int
+    long: bool,
+    kind: char,
+}
+
+impl Printf {
+    /// Parses the arguments of `printf`: a quoted format, then the expressions its conversions
+    /// take, separated by commas. There must be exactly as many as it has conversions.
+    pub fn parse(line: &str) -> Result<Printf, String> {
+        let line = line.trim();
+        let rest = line
+            .strip_prefix('"')
+            .ok_or("Bad format string: it must be in double quotes.")?;
+        let mut format = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((i, '"')) => break i,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, c)) => {
+                        format.push('\\');
+                        format.push(c);
+                    }
+                    None => return Err("Bad format string, non-terminated '\"'.".to_string()),
+                },
+                Some((_, c)) => format.push(c),
+                None => return Err("Bad format string, non-terminated '\"'.".to_string()),
+            }
+        };
+        let pieces = parse_format(&unescape(&format))?;
+        let rest = rest[end + 1..].trim();
+        let args = match rest.strip_prefix(',') {
+            Some(args) => split_expressions(args)
+                .into_iter()
+                .map(Expr::parse)
+                .collect::<Result<Vec<_>, _>>()?,
+            None if rest.is_empty() => Vec::new(),
+            None => return Err("Invalid argument syntax: expected a comma.".to_string()),
+        };
+        let conversions = pieces
+            .iter()
+            .filter(|piece| matches!(piece, Piece::Conversion(_)))
+            .count();
+        if conversions != args.len() {
+            return Err(format!(
+                "Wrong number of arguments for specified format-string: it takes {}, {} given.",
+                conversions,
+                args.len()
+            ));
+        }
+        Ok(Printf { pieces, args })
+    }
+
+    /// The formatted text, with the arguments evaluated in `context`.
+    pub fn format<C: Context>(&self, context: &C) -> Result<String, String> {
+        let mut text = String::new();
+        let mut args = self.args.iter();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(literal) => text.push_str(literal),
+                Piece::Conversion(conversion) => {
+                    let value = args.next().unwrap().evaluate(context)?.integer(context)?;
+                    text.push_str(&conversion.format(value, context)?);
+                }
+            }
+        }
+        Ok(text)
+    }
+}
+
+impl Conversion {
+    fn format<C: Context>(&self, value: u64, context: &C) -> Result<String, String> {
+        // without a length modifier, C takes an int
+        let (signed, unsigned) = match self.long {
+            true => (value as i64, value),
+            false => (value as i32 as i64, value as u32 as u64),
+        };
+        let (sign, digits) = match self.kind {
+            'd' | 'i' if signed < 0 => ("-", signed.unsigned_abs().to_string()),
+            'd' | 'i' if self.plus => ("+", signed.to_string()),
+            'd' | 'i' if self.space => (" ", signed.to_string()),
+            'd' | 'i' => ("", signed.to_string()),
+            'u' => ("", unsigned.to_string()),
+            'x' if self.alternate && unsigned != 0 => ("0x", format!("{:x}", unsigned)),
+            'x' => ("", format!("{:x}", unsigned)),
+            'X' if self.alternate && unsigned != 0 => ("0X", format!("{:X}", unsigned)),
+            'X' => ("", format!("{:X}", unsigned)),
+            'o' if self.alternate => ("0", format!("{:o}", unsigned)),
+            'o' => ("", format!("{:o}", unsigned)),
+            'p' if value == 0 => return Ok(self.pad("", "(nil)")),
+            'p' => ("0x", format!("{:x}", value)),
+            'c' => return Ok(self.pad("", &char::from(value as u8).to_string())),
+            's' if value == 0 => return Ok(self.pad("", "(null)")),
+            's' => {
+                let limit = self.precision.unwrap_or(MAX_STRING);
+                return Ok(self.pad("", &read_string(context, value as usize, limit)?));
+            }
+            _ => unreachable!("checked by parse_format"),
+        };
+        // a precision is the least number of digits
+        let digits = match self.precision {
+            Some(precision) if digits.len() < precision => {
+                format!("{}{}", "0".repeat(precision - digits.len()), digits)
+            }
+            _ => digits,
+        };
+        Ok(self.pad(sign, &digits))
+    }
+
+    /// `text` after `sign`, padded to the width: with zeros between them for the 0 flag, and
+    /// with spaces on the right for -, or else on the left.
+    fn pad(&self, sign: &str, text: &str) -> String {
+        let len = sign.len() + text.chars().count();
+        let fill = self.width.saturating_sub(len);
+        if self.left {
+            format!("{}{}{}", sign, text, " ".repeat(fill))
+        } else if self.zero && self.precision.is_none() && !matches!(self.kind, 's' | 'c') {
+            format!("{}{}{}", sign, "0".repeat(fill), text)
+        } else {
+            format!("{}{}{}", " ".repeat(fill), sign, text)
+        }
+    }
+}
+
+/// Splits a format into its literal text and its conversions.
+fn parse_format(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            text.push('%');
+            continue;
+        }
+        let mut conversion = Conversion {
+            left: false,
+            zero: false,
+            plus: false,
+            space: false,
+            alternate: false,
+            width: 0,
+            precision: None,
+            long: false,
+            kind: ' ',
+        };
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => conversion.left = true,
+                '0' => conversion.zero = true,
+                '+' => conversion.plus = true,
+                ' ' => conversion.space = true,
+                '#' => conversion.alternate = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        conversion.width = number(&mut chars);
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            conversion.precision = Some(number(&mut chars));
+        }
+        while let Some(&modifier) = chars.peek() {
+            match modifier {
+                'l' | 'j' | 'z' | 't' => conversion.long = true,
+                'h' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        conversion.kind = match chars.next() {
+            Some(kind @ ('d' | 'i' | 'u' | 'x' | 'X' | 'o' | 'c' | 's' | 'p')) => kind,
+            Some(kind) => {
+                return Err(format!(
+                    "Unrecognized format specifier '{}' in printf.",
+                    kind
+                ))
+            }
+            None => return Err("Incomplete format specifier at end of format string.".into()),
+        };
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(Piece::Conversion(conversion));
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// The decimal number at the front of `chars`, or 0.
+fn number(chars: &mut std::iter::Peekable<std::str::Chars>) -> usize {
+    let mut number = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        number = number * 10 + digit as usize;
+        chars.next();
+    }
+    number
+}
+
+/// The NUL-terminated string at `addr` in the program, up to `limit` bytes of it.
+fn read_string<C: Context>(context: &C, addr: usize, limit: usize) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    let mut byte = [0];
+    while bytes.len() < limit {
+        context
+            .read_memory(addr + bytes.len(), &mut byte)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr + bytes.len()))?;
+        if byte[0] == 0 {
+            break;
+        }
+        bytes.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// `text` with its backslash escapes replaced, as in a C string: \n, \t, \", \\, \e for escape
+/// and so on, and octal ones like \033. A backslash before anything else stands for it.
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        let Some(escaped) = chars.next() else {
+            break;
+        };
+        match escaped {
+            'n' => unescaped.push('\n'),
+            't' => unescaped.push('\t'),
+            'r' => unescaped.push('\r'),
+            'a' => unescaped.push('\x07'),
+            'b' => unescaped.push('\x08'),
+            'f' => unescaped.push('\x0c'),
+            'v' => unescaped.push('\x0b'),
+            'e' => unescaped.push('\x1b'),
+            '0'..='7' => {
+                let mut code = escaped.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                unescaped.push(char::from(code as u8));
+            }
+            other => unescaped.push(other),
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,401 @@
+//! The registers as `info registers` shows them: the general ones, and for `info all-registers`
+//! the floating point and vector ones, i.e. the x87 stack with its control and status words, the
+//! SSE registers and mxcsr, and the upper halves of the AVX registers on x86_64; the SIMD
+//! registers with fpsr and fpcr on aarch64.
+//!
+//! The kernel hands the latter out as register sets, one per NT_* note type as in a core file,
+//! read with PTRACE_GETREGSET. Each set is decoded on its own, so a kernel or CPU without one (no
+//! AVX, say) still shows the others.
+
+use crate::arch;
+
+/// A register as `info registers` shows it: its raw value in hex, and decoded.
+pub struct Register {
+    pub name: String,
+    pub hex: String,
+    pub natural: String,
+}
+
+/// The registers of a register set, or why they couldn't be read.
+pub struct RegisterSet {
+    pub name: &'static str,
+    pub registers: Result<Vec<Register>, String>,
+}
+
+pub use machine::REGSETS;
+
+/// The general register called `name`, which can be any `print $name` takes, with the program
+/// counter as a symbol if `symbolize` gives one.
+pub fn general(
+    regs: &arch::Registers,
+    name: &str,
+    symbolize: impl Fn(usize) -> Option<String>,
+) -> Option<Register> {
+    let value = arch::register(regs, name)?;
+    let natural = if name == arch::PC_REGISTER || name == "pc" {
+        match symbolize(value as usize) {
+            Some(symbol) => format!("{:#x} <{}>", value, symbol),
+            None => format!("{:#x}", value),
+        }
+    } else if name == arch::FLAGS_REGISTER {
+        flags(value, arch::FLAGS, &[])
+    } else if arch::is_address_register(name) {
+        format!("{:#x}", value)
+    } else {
+        (value as i64).to_string()
+    };
+    Some(Register {
+        natural,
+        ..unsigned(name, value)
+    })
+}
+
+/// Decodes the register sets, which `regset` reads given a note type and the most bytes wanted.
+pub fn decode(regset: impl Fn(u32, usize) -> Result<Vec<u8>, String>) -> Vec<RegisterSet> {
+    machine::decode(regset)
+}
+
+/// The names of the bits of `value` that are set, of those named, then `fields`, in brackets.
+fn flags(value: u64, names: &[(u32, &str)], fields: &[String]) -> String {
+    let mut set: Vec<&str> = names
+        .iter()
+        .filter(|(bit, _)| value & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    set.extend(fields.iter().map(String::as_str));
+    match set.is_empty() {
+        true => "[ ]".to_string(),
+        false => format!("[ {} ]", set.join(" ")),
+    }
+}
+
+fn unsigned(name: &str, value: u64) -> Register {
+    Register {
+        name: name.to_string(),
+        hex: format!("{:#x}", value),
+        natural: value.to_string(),
+    }
+}
+
+/// A vector register of `bytes` (16 or 32 of them), as a number and as doubles and floats.
+fn vector(name: String, bytes: &[u8]) -> Register {
+    let low = u128::from_le_bytes(bytes[..16].try_into().unwrap());
+    let hex = match bytes.get(16..32) {
+        Some(high) if high.iter().any(|b| *b != 0) => format!(
+            "{:#x}{:032x}",
+            u128::from_le_bytes(high.try_into().unwrap()),
+            low
+        ),
+        _ => format!("{:#x}", low),
+    };
+    let doubles: Vec<String> = bytes
+        .chunks_exact(8)
+        .map(|chunk| format!("{:?}", f64::from_le_bytes(chunk.try_into().unwrap())))
+        .collect();
+    let floats: Vec<String> = bytes
+        .chunks_exact(4)
+        .map(|chunk| format!("{:?}", f32::from_le_bytes(chunk.try_into().unwrap())))
+        .collect();
+    Register {
+        name,
+        hex,
+        natural: format!(
+            "{{v{}_double = {{{}}}, v{}_float = {{{}}}}}",
+            doubles.len(),
+            doubles.join(", "),
+            floats.len(),
+            floats.join(", ")
+        ),
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(target_arch = "x86_64")]
+mod machine {
+    use super::{flags, u16_at, u32_at, unsigned, vector, Register, RegisterSet};
+
+    const NT_PRFPREG: u32 = 2;
+    const NT_X86_XSTATE: u32 = 0x202;
+
+    /// The size of the FXSAVE area: NT_PRFPREG is one, and the XSAVE area starts with one.
+    const FXSAVE_SIZE: usize = 512;
+    /// Room for the whole XSAVE area, which with AMX passes 11 KiB
+    const XSAVE_MAX_SIZE: usize = 16384;
+    /// Where ptrace writes XCR0, the state components enabled, in the FXSAVE area's
+    /// software-available bytes (the first word of xstate_fx_sw_bytes)
+    const XCR0_OFFSET: usize = 464;
+    /// The AVX state component, and where its upper halves of ymm0-15 are in an XSAVE area in
+    /// the standard format the kernel hands out
+    const XFEATURE_AVX: u64 = 1 << 2;
+    const YMM_HIGH_OFFSET: usize = 576;
+
+    /// The register sets decoded, as (note type, most bytes, note name): what `gcore` writes so
+    /// its core files have them, with the kernel's note names
+    pub const REGSETS: &[(u32, usize, &str)] = &[
+        (NT_PRFPREG, FXSAVE_SIZE, "CORE"),
+        (NT_X86_XSTATE, XSAVE_MAX_SIZE, "LINUX"),
+    ];
+
+    const MXCSR_FLAGS: &[(u32, &str)] = &[
+        (0, "IE"),
+        (1, "DE"),
+        (2, "ZE"),
+        (3, "OE"),
+        (4, "UE"),
+        (5, "PE"),
+        (6, "DAZ"),
+        (7, "IM"),
+        (8, "DM"),
+        (9, "ZM"),
+        (10, "OM"),
+        (11, "UM"),
+        (12, "PM"),
+        (15, "FZ"),
+    ];
+    const FSTAT_FLAGS: &[(u32, &str)] = &[
+        (0, "IE"),
+        (1, "DE"),
+        (2, "ZE"),
+        (3, "OE"),
+        (4, "UE"),
+        (5, "PE"),
+        (6, "SF"),
+        (7, "ES"),
+        (8, "C0"),
+        (9, "C1"),
+        (10, "C2"),
+        (14, "C3"),
+        (15, "B"),
+    ];
+    const FCTRL_FLAGS: &[(u32, &str)] = &[
+        (0, "IM"),
+        (1, "DM"),
+        (2, "ZM"),
+        (3, "OM"),
+        (4, "UM"),
+        (5, "PM"),
+    ];
+    /// The rounding modes of the RC field of fctrl and mxcsr
+    const ROUNDING: [&str; 4] = ["nearest", "down", "up", "zero"];
+    /// The precisions of the PC field of fctrl
+    const PRECISION: [&str; 4] = ["single", "reserved", "double", "extended"];
+
+    pub fn decode(regset: impl Fn(u32, usize) -> Result<Vec<u8>, String>) -> Vec<RegisterSet> {
+        let fxsave = regset(NT_PRFPREG, FXSAVE_SIZE).and_then(|area| match area.len() {
+            FXSAVE_SIZE => Ok(area),
+            len => Err(format!("{} bytes where {} were expected", len, FXSAVE_SIZE)),
+        });
+        vec![
+            RegisterSet {
+                name: "x87",
+                registers: fxsave.as_deref().map(x87).map_err(String::clone),
+            },
+            RegisterSet {
+                name: "SSE",
+                registers: fxsave.as_deref().map(sse).map_err(String::clone),
+            },
+            RegisterSet {
+                name: "AVX",
+                registers: regset(NT_X86_XSTATE, XSAVE_MAX_SIZE).and_then(|area| avx(&area)),
+            },
+        ]
+    }
+
+    fn x87(area: &[u8]) -> Vec<Register> {
+        let fctrl = u16_at(area, 0);
+        let fstat = u16_at(area, 2);
+        let top = (fstat >> 11) & 7;
+        // FXSAVE keeps a bit per physical register, set if it's in use, and the registers in
+        // stack order, st0 first
+        let abridged_tag = area[4];
+        let mut ftag = 0;
+        let mut registers = Vec::new();
+        for i in 0..8 {
+            let raw = &area[32 + 16 * i..][..10];
+            let physical = (top as usize + i) % 8;
+            let in_use = abridged_tag & (1 << physical) != 0;
+            ftag |= tag(raw, in_use) << (2 * physical);
+            let mut natural = format!("{:?}", extended(raw));
+            if !in_use {
+                natural.push_str(" (empty)");
+            }
+            let mut value = [0; 16];
+            value[..10].copy_from_slice(raw);
+            registers.push(Register {
+                name: format!("st{}", i),
+                hex: format!("{:#x}", u128::from_le_bytes(value)),
+                natural,
+            });
+        }
+        let control = flags(
+            fctrl.into(),
+            FCTRL_FLAGS,
+            &[
+                format!("PC={}", PRECISION[(fctrl as usize >> 8) & 3]),
+                format!("RC={}", ROUNDING[(fctrl as usize >> 10) & 3]),
+            ],
+        );
+        let status = flags(fstat.into(), FSTAT_FLAGS, &[format!("TOP={}", top)]);
+        registers.push(Register {
+            natural: control,
+            ..unsigned("fctrl", fctrl.into())
+        });
+        registers.push(Register {
+            natural: status,
+            ..unsigned("fstat", fstat.into())
+        });
+        registers.push(unsigned("ftag", ftag));
+        registers.push(unsigned("fop", u16_at(area, 6).into()));
+        registers.push(unsigned("fioff", u64_at(area, 8)));
+        registers.push(unsigned("fooff", u64_at(area, 16)));
+        registers
+    }
+
+    fn sse(area: &[u8]) -> Vec<Register> {
+        let mut registers: Vec<Register> = (0..16)
+            .map(|i| vector(format!("xmm{}", i), &area[160 + 16 * i..][..16]))
+            .collect();
+        let mxcsr = u32_at(area, 24);
+        // the rounding mode is shown only when it isn't the default, nearest
+        let rounding = match (mxcsr as usize >> 13) & 3 {
+            0 => Vec::new(),
+            rounding => vec![format!("RC={}", ROUNDING[rounding])],
+        };
+        registers.push(Register {
+            natural: flags(mxcsr.into(), MXCSR_FLAGS, &rounding),
+            ..unsigned("mxcsr", mxcsr.into())
+        });
+        registers
+    }
+
+    fn avx(area: &[u8]) -> Result<Vec<Register>, String> {
+        if area.len() < YMM_HIGH_OFFSET + 16 * 16 {
+            return Err(format!("the XSAVE area is only {} bytes", area.len()));
+        }
+        if u64_at(area, XCR0_OFFSET) & XFEATURE_AVX == 0 {
+            return Err("the CPU or kernel doesn't have AVX".to_string());
+        }
+        // the upper halves of registers still in their initial state are left as they were,
+        // zeros as the kernel hands the area out
+        Ok((0..16)
+            .map(|i| {
+                let mut bytes = area[160 + 16 * i..][..16].to_vec();
+                bytes.extend_from_slice(&area[YMM_HIGH_OFFSET + 16 * i..][..16]);
+                vector(format!("ymm{}", i), &bytes)
+            })
+            .collect())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// An 80-bit extended precision number as the nearest double.
+    fn extended(raw: &[u8]) -> f64 {
+        let mantissa = u64_at(raw, 0);
+        let sign_exponent = u16_at(raw, 8);
+        let exponent = (sign_exponent & 0x7fff) as i32;
+        let magnitude = match exponent {
+            0x7fff if mantissa << 1 == 0 => f64::INFINITY,
+            0x7fff => f64::NAN,
+            // the mantissa has an explicit integer bit, and denormals the exponent of 1
+            _ => mantissa as f64 / 2f64.powi(63) * 2f64.powi(exponent.max(1) - 16383),
+        };
+        match sign_exponent & 0x8000 {
+            0 => magnitude,
+            _ => -magnitude,
+        }
+    }
+
+    /// The two bits of the full x87 tag word for a register: valid, zero, special or empty.
+    fn tag(raw: &[u8], in_use: bool) -> u64 {
+        let mantissa = u64_at(raw, 0);
+        let exponent = u16_at(raw, 8) & 0x7fff;
+        match (in_use, exponent) {
+            (false, _) => 3,
+            (true, 0) if mantissa == 0 => 1,
+            (true, 0 | 0x7fff) => 2,
+            // an unnormal, without the integer bit
+            (true, _) if mantissa >> 63 == 0 => 2,
+            (true, _) => 0,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod machine {
+    use super::{flags, u32_at, unsigned, vector, Register, RegisterSet};
+
+    const NT_PRFPREG: u32 = 2;
+
+    /// The size of struct user_fpsimd_state: v0-v31, then fpsr and fpcr
+    const FPSIMD_SIZE: usize = 528;
+
+    /// The register sets decoded, as (note type, most bytes, note name): what `gcore` writes so
+    /// its core files have them, with the kernel's note names
+    pub const REGSETS: &[(u32, usize, &str)] = &[(NT_PRFPREG, FPSIMD_SIZE, "CORE")];
+
+    const FPSR_FLAGS: &[(u32, &str)] = &[
+        (0, "IOC"),
+        (1, "DZC"),
+        (2, "OFC"),
+        (3, "UFC"),
+        (4, "IXC"),
+        (7, "IDC"),
+        (27, "QC"),
+    ];
+    const FPCR_FLAGS: &[(u32, &str)] = &[
+        (8, "IOE"),
+        (9, "DZE"),
+        (10, "OFE"),
+        (11, "UFE"),
+        (12, "IXE"),
+        (15, "IDE"),
+        (24, "FZ"),
+        (25, "DN"),
+        (26, "AHP"),
+    ];
+    /// The rounding modes of the RMode field of fpcr
+    const ROUNDING: [&str; 4] = ["nearest", "up", "down", "zero"];
+
+    pub fn decode(regset: impl Fn(u32, usize) -> Result<Vec<u8>, String>) -> Vec<RegisterSet> {
+        let registers = regset(NT_PRFPREG, FPSIMD_SIZE).and_then(|area| {
+            if area.len() < FPSIMD_SIZE {
+                return Err(format!(
+                    "{} bytes where {} were expected",
+                    area.len(),
+                    FPSIMD_SIZE
+                ));
+            }
+            let mut registers: Vec<Register> = (0..32)
+                .map(|i| vector(format!("v{}", i), &area[16 * i..][..16]))
+                .collect();
+            let fpsr = u32_at(&area, 512);
+            let fpcr = u32_at(&area, 516);
+            registers.push(Register {
+                natural: flags(fpsr.into(), FPSR_FLAGS, &[]),
+                ..unsigned("fpsr", fpsr.into())
+            });
+            registers.push(Register {
+                natural: flags(
+                    fpcr.into(),
+                    FPCR_FLAGS,
+                    &[format!("RMode={}", ROUNDING[(fpcr as usize >> 22) & 3])],
+                ),
+                ..unsigned("fpcr", fpcr.into())
+            });
+            Ok(registers)
+        });
+        vec![RegisterSet {
+            name: "SIMD",
+            registers,
+        }]
+    }
+}
@@ -0,0 +1,382 @@
+use crate::arch::{self, Registers};
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+/// One end of a GDB remote serial protocol connection: packets framed as `$data#checksum`, each
+/// acknowledged with `+` (or `-` to ask for it again) until both sides agree to stop doing so.
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Whether packets are still acknowledged
+    ack: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            ack: true,
+        })
+    }
+
+    /// Stops acknowledging packets, once QStartNoAckMode has been agreed on.
+    pub fn stop_acks(&mut self) {
+        self.ack = false;
+    }
+
+    /// Sends a packet, again until it's acknowledged.
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        for &byte in data {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                packet.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let sum = packet[1..]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        packet.extend_from_slice(format!("#{:02x}", sum).as_bytes());
+        loop {
+            self.writer.write_all(&packet)?;
+            if !self.ack {
+                return Ok(());
+            }
+            match self.read_byte()? {
+                b'+' => return Ok(()),
+                b'-' => continue,
+                // stray input before the ack, e.g. a notification; the ack still comes
+                _ => {}
+            }
+        }
+    }
+
+    /// Receives the next packet, unescaped and with runs expanded.
+    pub fn receive(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            if self.read_byte()? != b'$' {
+                // acks of packets we sent without waiting, and noise
+                continue;
+            }
+            let mut raw = Vec::new();
+            self.reader.read_until(b'#', &mut raw)?;
+            if raw.pop() != Some(b'#') {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let sum = raw.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if self.ack {
+                if expected != Some(sum) {
+                    self.writer.write_all(b"-")?;
+                    continue;
+                }
+                self.writer.write_all(b"+")?;
+            }
+            return Ok(unescape(&raw));
+        }
+    }
+
+    /// Sends a packet and returns the reply.
+    pub fn request(&mut self, data: &str) -> io::Result<Vec<u8>> {
+        self.send(data.as_bytes())?;
+        self.receive()
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+/// Undoes `}` escapes and expands `x*n` runs, which repeat x another n - 29 times.
+fn unescape(raw: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(raw.len());
+    let mut bytes = raw.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => data.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            b'*' => {
+                if let (Some(&last), Some(&count)) = (data.last(), bytes.next()) {
+                    data.extend(std::iter::repeat_n(last, count.saturating_sub(29) as usize));
+                }
+            }
+            _ => data.push(byte),
+        }
+    }
+    data
+}
+
+/// Encodes bytes as hex digits, the way packets carry memory and registers.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hex digits into bytes. `xx`, which stands for a register the stub can't read, comes
+/// out as 0.
+pub fn from_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    hex.chunks(2)
+        .map(|pair| match pair {
+            b"xx" => Some(0),
+            _ => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+        })
+        .collect()
+}
+
+/// GDB's numbers for the signals, which the protocol uses instead of the host's.
+const GDB_SIGNALS: &[(u8, Signal)] = &[
+    (1, Signal::SIGHUP),
+    (2, Signal::SIGINT),
+    (3, Signal::SIGQUIT),
+    (4, Signal::SIGILL),
+    (5, Signal::SIGTRAP),
+    (6, Signal::SIGABRT),
+    (8, Signal::SIGFPE),
+    (9, Signal::SIGKILL),
+    (10, Signal::SIGBUS),
+    (11, Signal::SIGSEGV),
+    (12, Signal::SIGSYS),
+    (13, Signal::SIGPIPE),
+    (14, Signal::SIGALRM),
+    (15, Signal::SIGTERM),
+    (16, Signal::SIGURG),
+    (17, Signal::SIGSTOP),
+    (18, Signal::SIGTSTP),
+    (19, Signal::SIGCONT),
+    (20, Signal::SIGCHLD),
+    (21, Signal::SIGTTIN),
+    (22, Signal::SIGTTOU),
+    (23, Signal::SIGIO),
+    (24, Signal::SIGXCPU),
+    (25, Signal::SIGXFSZ),
+    (26, Signal::SIGVTALRM),
+    (27, Signal::SIGPROF),
+    (28, Signal::SIGWINCH),
+    (30, Signal::SIGUSR1),
+    (31, Signal::SIGUSR2),
+    (32, Signal::SIGPWR),
+];
+
+/// The signal with GDB's number `number`.
+pub fn gdb_signal(number: u8) -> Option<Signal> {
+    GDB_SIGNALS
+        .iter()
+        .find(|(gdb, _)| *gdb == number)
+        .map(|(_, signal)| *signal)
+}
+
+/// GDB's number for `signal`, or its "unknown signal" number if it has none.
+pub fn gdb_number(signal: Signal) -> u8 {
+    GDB_SIGNALS
+        .iter()
+        .find(|(_, host)| *host == signal)
+        .map_or(143, |(gdb, _)| *gdb)
+}
+
+/// What a stop reply says happened.
+pub enum Stop {
+    Signal(Signal),
+    Exited(i32),
+    Killed(Signal),
+}
+
+/// A program running under a gdbserver or another GDB stub, e.g. qemu's, on the other end of a
+/// TCP connection. `Inferior` talks to it in place of ptrace when debugging with `target
+/// remote`.
+pub struct Remote {
+    /// Behind a RefCell since even reading registers or memory is a round trip, and those
+    /// only borrow the inferior
+    connection: RefCell<Connection>,
+    pid: Pid,
+    /// Whether the stub understands vCont, the newer way to resume
+    vcont: bool,
+    /// Whether the stub knows about processes, so kill can name one
+    multiprocess: bool,
+}
+
+impl Remote {
+    /// Connects to the stub at `address`, HOST:PORT or just :PORT for this machine, and asks it
+    /// what it supports and where the program is stopped.
+    pub fn connect(address: &str) -> Result<Remote, String> {
+        let target = match address.strip_prefix(':') {
+            Some(port) => format!("localhost:{}", port),
+            None => address.to_string(),
+        };
+        let error = |err: io::Error| format!("{}: {}.", address, err);
+        let stream = TcpStream::connect(&target).map_err(error)?;
+        let mut connection = Connection::new(stream).map_err(error)?;
+        let features = connection
+            .request("qSupported:multiprocess+;swbreak+;vContSupported+")
+            .map_err(error)?;
+        let features = String::from_utf8_lossy(&features).into_owned();
+        let supports = |feature: &str| features.split(';').any(|f| f == feature);
+        if supports("QStartNoAckMode+")
+            && connection.request("QStartNoAckMode").map_err(error)? == b"OK"
+        {
+            connection.stop_acks();
+        }
+        let vcont = String::from_utf8_lossy(&connection.request("vCont?").map_err(error)?)
+            .split(';')
+            .filter(|action| matches!(*action, "c" | "s"))
+            .count()
+            == 2;
+        let stop = connection.request("?").map_err(error)?;
+        if !matches!(stop.first(), Some(b'S' | b'T')) {
+            return Err(format!(
+                "{}: the remote target isn't stopped in a program ({}).",
+                address,
+                String::from_utf8_lossy(&stop)
+            ));
+        }
+        // QCp<pid>.<tid> with multiprocess, QC<tid> without
+        let current = connection.request("qC").map_err(error)?;
+        let current = String::from_utf8_lossy(&current);
+        let id = current.strip_prefix("QC").unwrap_or("");
+        let id = id.strip_prefix('p').unwrap_or(id);
+        let pid = id.split('.').next().unwrap_or("");
+        let pid = i32::from_str_radix(pid, 16).unwrap_or(1);
+        Ok(Remote {
+            connection: RefCell::new(connection),
+            pid: Pid::from_raw(pid),
+            vcont,
+            multiprocess: supports("multiprocess+"),
+        })
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Sends a packet, failing with EIO if the stub can't do what it asks.
+    fn request(&self, data: &str) -> Result<Vec<u8>, nix::Error> {
+        let reply = self
+            .connection
+            .borrow_mut()
+            .request(data)
+            .map_err(io_error)?;
+        match reply.first() {
+            Some(b'E') if reply.len() == 3 => Err(Errno::EIO),
+            // an empty reply means the stub doesn't know the packet
+            None => Err(Errno::ENOSYS),
+            _ => Ok(reply),
+        }
+    }
+
+    pub fn registers(&self) -> Result<Registers, nix::Error> {
+        let bytes = from_hex(&self.request("g")?).ok_or(Errno::EPROTO)?;
+        let mut regs: Registers = unsafe { std::mem::zeroed() };
+        let mut offset = 0;
+        for (name, size) in arch::REMOTE_REGISTERS {
+            let field = bytes.get(offset..offset + size).ok_or(Errno::EPROTO)?;
+            let mut value = [0; 8];
+            value[..*size].copy_from_slice(field);
+            if let Some(register) = arch::register_mut(&mut regs, name) {
+                *register = u64::from_le_bytes(value);
+            }
+            offset += size;
+        }
+        Ok(regs)
+    }
+
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let reply = self.request(&format!("m{:x},{:x}", addr, len))?;
+        match from_hex(&reply) {
+            Some(bytes) if bytes.len() == len => Ok(bytes),
+            // a short read stops at memory that isn't mapped
+            Some(_) => Err(Errno::EIO),
+            None => Err(Errno::EPROTO),
+        }
+    }
+
+    /// Has the stub plant a breakpoint at `addr`, so that it also takes care of hiding it from
+    /// memory reads.
+    pub fn insert_breakpoint(&self, addr: usize) -> Result<(), nix::Error> {
+        self.breakpoint_request('Z', addr)
+    }
+
+    pub fn remove_breakpoint(&self, addr: usize) -> Result<(), nix::Error> {
+        self.breakpoint_request('z', addr)
+    }
+
+    fn breakpoint_request(&self, packet: char, addr: usize) -> Result<(), nix::Error> {
+        let kind = arch::BREAKPOINT.len();
+        match &self.request(&format!("{}0,{:x},{}", packet, addr, kind))?[..] {
+            b"OK" => Ok(()),
+            _ => Err(Errno::EPROTO),
+        }
+    }
+
+    /// Resumes the program, for one instruction if `step`, and waits for it to stop. Output
+    /// packets it sends meanwhile are shown as the program's output.
+    pub fn resume(&self, step: bool) -> Result<Stop, nix::Error> {
+        let packet = match (self.vcont, step) {
+            (true, true) => "vCont;s",
+            (true, false) => "vCont;c",
+            (false, true) => "s",
+            (false, false) => "c",
+        };
+        let mut connection = self.connection.borrow_mut();
+        connection.send(packet.as_bytes()).map_err(io_error)?;
+        loop {
+            let reply = connection.receive().map_err(io_error)?;
+            let number = || {
+                let hex = std::str::from_utf8(reply.get(1..3)?).ok()?;
+                u8::from_str_radix(hex, 16).ok()
+            };
+            match reply.first() {
+                Some(b'O') if reply.len() > 1 => {
+                    let text = from_hex(&reply[1..]).ok_or(Errno::EPROTO)?;
+                    crate::output::write_inferior(&text, false);
+                }
+                Some(b'S' | b'T') => {
+                    let signal = number().ok_or(Errno::EPROTO)?;
+                    return Ok(Stop::Signal(gdb_signal(signal).unwrap_or(Signal::SIGTRAP)));
+                }
+                Some(b'W') => return Ok(Stop::Exited(number().ok_or(Errno::EPROTO)? as i32)),
+                Some(b'X') => {
+                    let signal = number().ok_or(Errno::EPROTO)?;
+                    return Ok(Stop::Killed(gdb_signal(signal).unwrap_or(Signal::SIGKILL)));
+                }
+                _ => return Err(Errno::EPROTO),
+            }
+        }
+    }
+
+    /// Kills the program. Stubs may hang up rather than reply, and plain `k` has no reply at
+    /// all, so none is needed.
+    pub fn kill(&self) -> Result<(), nix::Error> {
+        let mut connection = self.connection.borrow_mut();
+        if self.multiprocess {
+            let packet = format!("vKill;{:x}", self.pid.as_raw());
+            connection.send(packet.as_bytes()).map_err(io_error)?;
+            let _ = connection.receive();
+            Ok(())
+        } else {
+            connection.send(b"k").map_err(io_error)
+        }
+    }
+
+    /// Lets the program go on without us.
+    pub fn detach(&self) -> Result<(), nix::Error> {
+        self.request("D")?;
+        Ok(())
+    }
+}
+
+fn io_error(err: io::Error) -> nix::Error {
+    match err.raw_os_error() {
+        Some(errno) => Errno::from_i32(errno),
+        None => Errno::EIO,
+    }
+}
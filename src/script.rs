@@ -0,0 +1,270 @@
+//! The scripting language of `script` and `eval`, for what the command language can't express:
+//! variables, conditions, loops and functions around the debugger's operations. Scripts see
+//! the debugger only through the library API of `Debugger`, the same one programs embedding
+//! deet use, so the two stay in step.
+//!
+//! The language is rhai, embedded with its standard library and the built-in functions below.
+
+use crate::debugger::Debugger;
+use crate::event::{self, Event, Location, StopReason};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, Scope, AST, INT};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+/// How many times a program has stopped, counted from the events, so on_stop hooks know when
+/// to run
+static STOPS: AtomicUsize = AtomicUsize::new(0);
+
+static COUNT_STOPS: Once = Once::new();
+
+/// How deeply script functions may call each other, so runaway recursion errors out
+const MAX_CALL_DEPTH: usize = 64;
+
+thread_local! {
+    /// The debugger the built-in functions work on, while a script runs. rhai's functions have
+    /// to be 'static, so they can't borrow it.
+    static DEBUGGER: Cell<*mut Debugger> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+type Result<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// The on_stop hooks, shared with the built-in functions that add and call them.
+#[derive(Default)]
+struct Hooks {
+    functions: Vec<FnPtr>,
+    /// The value of STOPS when they last ran
+    stops_seen: usize,
+    /// Set while they run, so one that continues the program doesn't set them off again
+    running: bool,
+}
+
+/// The state scripts share: global variables, functions and hooks outlive the script that made
+/// them, so `eval` can look at what a script left behind.
+pub struct Interpreter {
+    engine: Engine,
+    globals: Scope<'static>,
+    /// The functions the scripts so far have defined
+    functions: AST,
+    hooks: Rc<RefCell<Hooks>>,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        COUNT_STOPS.call_once(|| {
+            event::subscribe(|event| match event {
+                Event::Stopped {
+                    reason: StopReason::Core(_) | StopReason::Switched(_),
+                    ..
+                } => {}
+                Event::Stopped { .. } => {
+                    STOPS.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => {}
+            })
+        });
+        let hooks = Rc::new(RefCell::new(Hooks {
+            stops_seen: STOPS.load(Ordering::Relaxed),
+            ..Hooks::default()
+        }));
+        let mut engine = Engine::new();
+        engine.set_max_call_levels(MAX_CALL_DEPTH);
+        engine.on_print(|text| outln!("{}", text));
+        engine.on_debug(|text, _, _| outln!("{}", text));
+        register_builtins(&mut engine, &hooks);
+        Interpreter {
+            engine,
+            globals: Scope::new(),
+            functions: AST::empty(),
+            hooks,
+        }
+    }
+
+    /// Runs `code`, and returns the value of its last statement if it has one. Errors say
+    /// where they happened, in `origin` (a file name or "eval").
+    pub fn run(
+        &mut self,
+        debugger: &mut Debugger,
+        code: &str,
+        origin: &str,
+    ) -> std::result::Result<Option<String>, String> {
+        let ast = self
+            .engine
+            .compile_with_scope(&self.globals, code)
+            .map_err(|err| match err.1.line() {
+                Some(line) => format!("{}:{}: {}", origin, line, err.0),
+                None => format!("{}: {}", origin, err.0),
+            })?;
+        let ast = self.functions.merge(&ast);
+        let result = with_debugger_set(debugger, || {
+            self.engine
+                .eval_ast_with_scope::<Dynamic>(&mut self.globals, &ast)
+        });
+        self.functions = ast.clone_functions_only();
+        match result {
+            Ok(value) if value.is_unit() => Ok(None),
+            Ok(value) => Ok(Some(value.to_string())),
+            Err(err) => Err(located(origin, *err)),
+        }
+    }
+
+    /// Calls the on_stop hooks if the program has stopped since they last ran, e.g. after a
+    /// command typed at the prompt. Errors in them are printed.
+    pub fn after_command(&mut self, debugger: &mut Debugger) {
+        let result = with_debugger_set(debugger, || {
+            run_hooks(&self.hooks, |hook| {
+                hook.call::<Dynamic>(&self.engine, &self.functions, ())
+            })
+        });
+        if let Err(err) = result {
+            outln!("Error in on_stop hook: {}", located("on_stop", *err));
+        }
+    }
+}
+
+/// Runs `f` with `debugger` as the one the built-in functions work on.
+fn with_debugger_set<T>(debugger: &mut Debugger, f: impl FnOnce() -> T) -> T {
+    let outer = DEBUGGER.replace(debugger);
+    let result = f();
+    DEBUGGER.set(outer);
+    result
+}
+
+/// Calls `f` with the debugger of the script running.
+fn with_debugger<T>(f: impl FnOnce(&mut Debugger) -> T) -> T {
+    let debugger = DEBUGGER.get();
+    assert!(!debugger.is_null(), "no script is running");
+    // SAFETY: it was set from the `&mut Debugger` of the `Interpreter` method running the
+    // script, which doesn't use it until the script is done, and the functions that get it
+    // here let go of it before calling back into the script
+    f(unsafe { &mut *debugger })
+}
+
+/// Calls the hooks with `call` if the program has stopped since they last ran.
+fn run_hooks(hooks: &RefCell<Hooks>, call: impl Fn(&FnPtr) -> Result<Dynamic>) -> Result<()> {
+    let functions = {
+        let mut hooks = hooks.borrow_mut();
+        let stops = STOPS.load(Ordering::Relaxed);
+        if stops == hooks.stops_seen || hooks.running {
+            return Ok(());
+        }
+        hooks.stops_seen = stops;
+        hooks.running = true;
+        hooks.functions.clone()
+    };
+    let result = functions.iter().try_for_each(|hook| call(hook).map(drop));
+    hooks.borrow_mut().running = false;
+    result
+}
+
+/// The functions that make up the scripting API, each of them a call into `Debugger`'s.
+fn register_builtins(engine: &mut Engine, hooks: &Rc<RefCell<Hooks>>) {
+    let command = {
+        let hooks = hooks.clone();
+        move |context: &NativeCallContext, line: &str| -> Result<()> {
+            if let Some(status) = with_debugger(|debugger| debugger.execute_line(line)) {
+                // the command was quit, which has already killed or detached from the programs
+                std::process::exit(status);
+            }
+            run_hooks(&hooks, |hook| hook.call_within_context(context, ()))
+        }
+    };
+    let run = command.clone();
+    engine.register_fn("command", move |context: NativeCallContext, line: &str| {
+        run(&context, line)
+    });
+    let run = command.clone();
+    engine.register_fn(
+        "set_breakpoint",
+        move |context: NativeCallContext, location: &str| {
+            run(&context, &format!("break {}", location))
+        },
+    );
+    engine.register_fn("cont", move |context: NativeCallContext| -> Result<bool> {
+        command(&context, "continue")?;
+        Ok(with_debugger(|debugger| debugger.inferior().is_some()))
+    });
+    engine.register_fn("alive", || {
+        with_debugger(|debugger| debugger.inferior().is_some())
+    });
+    engine.register_fn("reg", |register: &str| -> Result<INT> {
+        let value = with_debugger(|debugger| debugger.register(register))?;
+        Ok(value as INT)
+    });
+    engine.register_fn("read_mem", |addr: INT, len: INT| -> Result<Array> {
+        let len = usize::try_from(len).map_err(|_| "Negative length.".to_string())?;
+        let bytes = with_debugger(|debugger| debugger.read_memory(addr as usize, len))?;
+        Ok(bytes
+            .into_iter()
+            .map(|byte| Dynamic::from(byte as INT))
+            .collect())
+    });
+    engine.register_fn("frames", || -> Result<Array> {
+        let frames = with_debugger(|debugger| debugger.backtrace())?;
+        Ok(frames
+            .iter()
+            .map(|frame| Dynamic::from(frame_text(frame)))
+            .collect())
+    });
+    engine.register_fn("print_var", |expression: &str| -> Result<String> {
+        with_debugger(|debugger| {
+            let value = debugger.evaluate(expression)?;
+            debugger.format_value(&value)
+        })
+        .map_err(Into::into)
+    });
+    engine.register_fn("value", |expression: &str| -> Result<INT> {
+        let text = with_debugger(|debugger| {
+            let value = debugger.evaluate(expression)?;
+            debugger.format_value(&value)
+        })?;
+        // chars show as "65 'A'", pointers as "(int *) 0x7ffc..."
+        text.split(' ')
+            .find_map(parse_int)
+            .ok_or_else(|| format!("{} isn't a number: {}", expression, text).into())
+    });
+    let added = hooks.clone();
+    engine.register_fn("on_stop", move |hook: FnPtr| {
+        added.borrow_mut().functions.push(hook)
+    });
+    let added = hooks.clone();
+    engine.register_fn("on_stop", move |name: &str| -> Result<()> {
+        added.borrow_mut().functions.push(FnPtr::new(name)?);
+        Ok(())
+    });
+    engine.register_fn("write_file", |path: &str, text: Dynamic| -> Result<()> {
+        std::fs::write(path, text.to_string()).map_err(|err| format!("{}: {}", path, err).into())
+    });
+}
+
+/// An error of a script from `origin`, with the line it happened on if it's known.
+fn located(origin: &str, mut err: EvalAltResult) -> String {
+    let position = err.take_position();
+    let message = match err {
+        // what a built-in function failed with
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        err => err.to_string(),
+    };
+    match position.line() {
+        Some(line) => format!("{}:{}: {}", origin, line, message),
+        None => format!("{}: {}", origin, message),
+    }
+}
+
+/// An integer as `print` shows it, in decimal or hex.
+fn parse_int(text: &str) -> Option<INT> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|value| value as INT),
+        None => text.parse().ok(),
+    }
+}
+
+/// A frame of `frames()`: "func (file:line)", "func (0x401136)" or "0x401136".
+fn frame_text(frame: &Location) -> String {
+    match (&frame.function, &frame.file, frame.line) {
+        (Some(function), Some(file), Some(line)) => format!("{} ({}:{})", function, file, line),
+        (Some(function), _, _) => format!("{} ({:#x})", function, frame.address),
+        (None, _, _) => format!("{:#x}", frame.address),
+    }
+}
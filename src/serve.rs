@@ -0,0 +1,416 @@
+//! `deet --serve`: a GDB stub. deet starts the program and traces it as usual, but takes its
+//! orders over the GDB remote serial protocol from a gdb (or a deet with `target remote`) that
+//! connects from elsewhere, instead of from a command line.
+//!
+//! It's the all-stop flavor of the protocol: the client resumes the program and gets a stop
+//! reply once it stops, with nothing in between except for an interrupt (a 0x03 byte) to stop it
+//! early. The program is one thread as far as the client knows, the one that reported the stop.
+
+use crate::arch::{self, Registers};
+use crate::inferior::{Inferior, LaunchOptions, Redirections, Status};
+use crate::remote::{from_hex, gdb_number, gdb_signal, to_hex, Connection};
+use crate::signals;
+use nix::sys::signal::{self, Signal};
+use std::io::{self, Read};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often a running program's interrupt watcher checks whether the program has stopped
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Starts `target` with `args`, waits for a client on `port` and serves it until it detaches,
+/// kills the program or hangs up, or the program exits. Returns deet's exit status.
+pub fn serve(port: &str, target: &str, args: &[String]) -> i32 {
+    let listener = match TcpListener::bind(format!("0.0.0.0:{}", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            outln!("Can't listen on port {}: {}.", port, err);
+            return 1;
+        }
+    };
+    let mut inferior = match Inferior::new(
+        target,
+        &args.to_vec(),
+        &Redirections::default(),
+        &LaunchOptions::default(),
+    ) {
+        Ok(inferior) => inferior,
+        Err(err) => {
+            outln!("Error starting inferior {}: {}", target, err);
+            return 1;
+        }
+    };
+    outln!("Process {} created; pid = {}", target, inferior.pid());
+    outln!("Listening on port {}", port);
+    let connection = listener.accept().and_then(|(stream, client)| {
+        outln!("Remote debugging from host {}", client.ip());
+        Ok((Connection::new(stream.try_clone()?)?, stream))
+    });
+    let (connection, stream) = match connection {
+        Ok(connection) => connection,
+        Err(err) => {
+            outln!("Can't accept a connection on port {}: {}.", port, err);
+            let _ = inferior.kill();
+            return 1;
+        }
+    };
+    let mut server = Server {
+        connection,
+        stream,
+        inferior: Some(inferior),
+        stop: "S05".to_string(),
+    };
+    if let Err(err) = server.run() {
+        outln!("Remote connection lost: {}.", err);
+    }
+    if let Some(mut inferior) = server.inferior.take() {
+        let _ = inferior.kill();
+    }
+    0
+}
+
+struct Server {
+    connection: Connection,
+    /// The connection's socket, for watching for interrupts while the program runs
+    stream: TcpStream,
+    /// None once the program is gone
+    inferior: Option<Inferior>,
+    /// The stop reply for the last stop, which `?` asks for again
+    stop: String,
+}
+
+/// Whether the session goes on after a packet.
+enum Next {
+    Reply(String),
+    /// Reply, then end the session: the program exited or was killed or detached from
+    LastReply(String),
+    /// End it without a reply, for `k`
+    End,
+}
+
+impl Server {
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let packet = match self.connection.receive() {
+                Ok(packet) => packet,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    outln!("The client hung up.");
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            };
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            match self.handle(&packet) {
+                Next::Reply(reply) => {
+                    self.connection.send(reply.as_bytes())?;
+                    if packet == "QStartNoAckMode" {
+                        self.connection.stop_acks();
+                    }
+                }
+                Next::LastReply(reply) => return self.connection.send(reply.as_bytes()),
+                Next::End => return Ok(()),
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> Next {
+        let Some(inferior) = &mut self.inferior else {
+            return Next::LastReply(self.stop.clone());
+        };
+        let (name, rest) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        match name {
+            "q" | "Q" => Next::Reply(self.query(packet)),
+            "?" => Next::Reply(self.stop.clone()),
+            // there's one thread to choose from
+            "H" | "T" => Next::Reply("OK".to_string()),
+            "g" | "G" | "p" | "P" | "m" | "M" => Next::Reply(or_error(self.access(name, rest))),
+            // software breakpoints only, as the breakpoint instruction of the debugger's own
+            "Z" | "z" if rest.starts_with("0,") => {
+                let result = address_and_length(&rest[2..]).and_then(|(addr, _)| match name {
+                    "Z" => inferior.install_breakpoint(addr).map(drop),
+                    _ => inferior.remove_breakpoint(addr),
+                });
+                Next::Reply(or_error(result.map(|_| "OK".to_string())))
+            }
+            "c" => self.resume(false, None),
+            "s" => self.resume(true, None),
+            "C" | "S" => match signal_number(rest) {
+                Some(signal) => self.resume(name == "S", signal),
+                None => Next::Reply(format!("E{:02x}", nix::Error::EINVAL as i32)),
+            },
+            "v" => self.v_packet(packet),
+            "k" => {
+                let _ = inferior.kill();
+                self.inferior = None;
+                Next::End
+            }
+            "D" => {
+                outln!("Detaching from process {}", inferior.pid());
+                let failed = inferior.remove_breakpoints();
+                for (addr, err) in &failed {
+                    outln!("Cannot remove breakpoint at {:#x}: {}", addr, err);
+                }
+                let inferior = self.inferior.take().unwrap();
+                let result = match failed.first() {
+                    Some((_, err)) => Err(*err),
+                    None => inferior.detach(),
+                };
+                Next::LastReply(or_error(result.map(|_| "OK".to_string())))
+            }
+            _ => Next::Reply(String::new()),
+        }
+    }
+
+    /// The register and memory packets.
+    fn access(&mut self, name: &str, rest: &str) -> Result<String, nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        match name {
+            "g" => {
+                let regs = inferior.registers()?;
+                Ok(arch::REMOTE_REGISTERS
+                    .iter()
+                    .map(|(name, size)| register_hex(&regs, name, *size))
+                    .collect())
+            }
+            "G" => {
+                let bytes = from_hex(rest.as_bytes()).ok_or(nix::Error::EINVAL)?;
+                let mut regs = inferior.registers()?;
+                let mut fields = bytes.as_slice();
+                for (name, size) in arch::REMOTE_REGISTERS {
+                    let Some((field, after)) = fields.split_at_checked(*size) else {
+                        break;
+                    };
+                    set_register(&mut regs, name, field);
+                    fields = after;
+                }
+                inferior.set_registers(regs)?;
+                Ok("OK".to_string())
+            }
+            "p" => {
+                let (name, size) = register_number(rest)?;
+                Ok(register_hex(&inferior.registers()?, name, size))
+            }
+            "P" => {
+                let (number, value) = rest.split_once('=').ok_or(nix::Error::EINVAL)?;
+                let (name, _) = register_number(number)?;
+                let value = from_hex(value.as_bytes()).ok_or(nix::Error::EINVAL)?;
+                let mut regs = inferior.registers()?;
+                set_register(&mut regs, name, &value);
+                inferior.set_registers(regs)?;
+                Ok("OK".to_string())
+            }
+            "m" => {
+                let (addr, len) = address_and_length(rest)?;
+                Ok(to_hex(&inferior.read_memory(addr, len)?))
+            }
+            _ => {
+                let (range, data) = rest.split_once(':').ok_or(nix::Error::EINVAL)?;
+                let (addr, _) = address_and_length(range)?;
+                let bytes = from_hex(data.as_bytes()).ok_or(nix::Error::EINVAL)?;
+                inferior.write_memory(addr, &bytes)?;
+                Ok("OK".to_string())
+            }
+        }
+    }
+
+    /// The general queries and settings that the client asks about while connecting.
+    fn query(&self, packet: &str) -> String {
+        let tid = self.inferior.as_ref().unwrap().tid().as_raw();
+        match packet.split(':').next().unwrap_or("") {
+            "qSupported" => "PacketSize=4000;QStartNoAckMode+;vContSupported+".to_string(),
+            "QStartNoAckMode" => "OK".to_string(),
+            "qC" => format!("QC{:x}", tid),
+            "qfThreadInfo" => format!("m{:x}", tid),
+            "qsThreadInfo" => "l".to_string(),
+            // deet started it, so it's killed rather than detached from when gdb quits
+            "qAttached" => "0".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// vCont and the other multi-letter packets starting with v.
+    fn v_packet(&mut self, packet: &str) -> Next {
+        if packet == "vCont?" {
+            return Next::Reply("vCont;c;C;s;S".to_string());
+        }
+        if let Some(actions) = packet.strip_prefix("vCont;") {
+            // every action is for the one thread, so the first one decides
+            let action = actions.split(';').next().unwrap_or("");
+            let action = action.split(':').next().unwrap_or("");
+            return match action.split_at(action.len().min(1)) {
+                ("c", "") => self.resume(false, None),
+                ("s", "") => self.resume(true, None),
+                (kind @ ("C" | "S"), number) => match signal_number(number) {
+                    Some(signal) => self.resume(kind == "S", signal),
+                    None => Next::Reply(format!("E{:02x}", nix::Error::EINVAL as i32)),
+                },
+                _ => Next::Reply(String::new()),
+            };
+        }
+        if packet.starts_with("vKill") {
+            let mut inferior = self.inferior.take().unwrap();
+            return Next::LastReply(or_error(inferior.kill().map(|_| "OK".to_string())));
+        }
+        Next::Reply(String::new())
+    }
+
+    /// Resumes the program and waits for it to stop, watching for interrupts from the client
+    /// meanwhile.
+    fn resume(&mut self, step: bool, signal: Option<Signal>) -> Next {
+        let inferior = self.inferior.as_mut().unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let watcher = match self.stream.try_clone() {
+            Ok(stream) => Some(watch_interrupts(stream, inferior.pid(), running.clone())),
+            Err(err) => {
+                outln!("Can't watch for interrupts: {}", err);
+                None
+            }
+        };
+        let mut status = inferior.resume(step, signal);
+        // the child of a fork isn't traced on the client's behalf
+        while let Ok(Status::Forked(child, vfork)) = status {
+            let detached = Inferior::from_forked(child, inferior).and_then(|mut child| match child
+                .remove_breakpoints()
+                .first()
+            {
+                Some((_, err)) => Err(*err),
+                None => child.detach(),
+            });
+            if let Err(err) = detached {
+                outln!("Error detaching from forked child {}: {}", child, err);
+            }
+            // they came out of the parent's memory too
+            if vfork {
+                inferior.restore_breakpoints_after_vfork();
+            }
+            status = inferior.resume(step, signal);
+        }
+        running.store(false, Ordering::Relaxed);
+        if let Some(watcher) = watcher {
+            let _ = watcher.join();
+        }
+        let tid = inferior.tid().as_raw();
+        self.stop = match status {
+            Ok(Status::Exited(code, _)) => {
+                outln!("Child exited with status {}", code);
+                self.inferior = None;
+                format!("W{:02x}", code & 0xff)
+            }
+            Ok(Status::Signaled(signal, ..)) => {
+                outln!("Child terminated with signal = {}", signals::name(signal));
+                self.inferior = None;
+                format!("X{:02x}", gdb_number(signal))
+            }
+            Ok(Status::Stopped(signal, _)) => {
+                format!("T{:02x}thread:{:x};", gdb_number(signal), tid)
+            }
+            // already rewound to the breakpoint, as GDB expects of a stub
+            Ok(Status::Breakpoint(_)) => {
+                format!("T{:02x}thread:{:x};", gdb_number(Signal::SIGTRAP), tid)
+            }
+            // the client sets no deadline
+            Ok(Status::TimedOut(_)) => {
+                format!("T{:02x}thread:{:x};", gdb_number(Signal::SIGSTOP), tid)
+            }
+            Ok(Status::Stepped(_) | Status::HardwareTrap(_)) => {
+                format!("T{:02x}thread:{:x};", gdb_number(Signal::SIGTRAP), tid)
+            }
+            // neither runs with the client: it can't follow an exec, and syscalls aren't traced
+            Ok(Status::Execed | Status::Syscall(_) | Status::Forked(..)) => {
+                format!("T{:02x}thread:{:x};", gdb_number(Signal::SIGTRAP), tid)
+            }
+            Err(err) => return Next::Reply(or_error(Err(err))),
+        };
+        match self.inferior {
+            Some(_) => Next::Reply(self.stop.clone()),
+            None => Next::LastReply(self.stop.clone()),
+        }
+    }
+}
+
+/// The reply, or an error reply. The protocol leaves error numbers up to the stub; these are
+/// errnos.
+fn or_error(result: Result<String, nix::Error>) -> String {
+    result.unwrap_or_else(|err| format!("E{:02x}", err as i32 & 0xff))
+}
+
+/// Watches the socket on another thread while the program runs, and interrupts the program with
+/// a SIGINT when the client sends 0x03. Anything else is left for after the stop. It checks
+/// `running` between reads and stops once it's cleared.
+fn watch_interrupts(
+    mut stream: TcpStream,
+    pid: nix::unistd::Pid,
+    running: Arc<AtomicBool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        if stream
+            .set_read_timeout(Some(INTERRUPT_POLL_INTERVAL))
+            .is_err()
+        {
+            return;
+        }
+        let mut byte = [0];
+        while running.load(Ordering::Relaxed) {
+            match stream.peek(&mut byte) {
+                Ok(1) if byte[0] == 0x03 => {
+                    let _ = stream.read(&mut byte);
+                    let _ = signal::kill(pid, Signal::SIGINT);
+                }
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            // a SIGCHLD for the tracer thread
+                            | io::ErrorKind::Interrupted
+                    ) => {}
+                _ => break,
+            }
+        }
+        // the timeout is the socket's, which the main thread shares
+        let _ = stream.set_read_timeout(None);
+    })
+}
+
+/// The hex digits of register `name`, `size` bytes of it, in target byte order.
+fn register_hex(regs: &Registers, name: &str, size: usize) -> String {
+    let value = arch::register(regs, name).unwrap_or(0);
+    to_hex(&value.to_le_bytes()[..size])
+}
+
+fn set_register(regs: &mut Registers, name: &str, bytes: &[u8]) {
+    let mut value = [0; 8];
+    let len = bytes.len().min(8);
+    value[..len].copy_from_slice(&bytes[..len]);
+    if let Some(register) = arch::register_mut(regs, name) {
+        *register = u64::from_le_bytes(value);
+    }
+}
+
+/// The register with number `number` (hex) in the `g` packet's order.
+fn register_number(number: &str) -> Result<(&'static str, usize), nix::Error> {
+    let number = usize::from_str_radix(number, 16).map_err(|_| nix::Error::EINVAL)?;
+    arch::REMOTE_REGISTERS
+        .get(number)
+        .copied()
+        .ok_or(nix::Error::EINVAL)
+}
+
+/// Parses the `ADDR,LENGTH` of a memory or breakpoint packet, both in hex.
+fn address_and_length(text: &str) -> Result<(usize, usize), nix::Error> {
+    let (addr, len) = text.split_once(',').ok_or(nix::Error::EINVAL)?;
+    let parse = |hex: &str| usize::from_str_radix(hex, 16).map_err(|_| nix::Error::EINVAL);
+    Ok((parse(addr)?, parse(len)?))
+}
+
+/// The signal of a `C` or `S` packet, which can be followed by an address to resume at (which
+/// isn't supported and ignored), or None if it's not a number. GDB's 0 means no signal.
+fn signal_number(text: &str) -> Option<Option<Signal>> {
+    let number = text.split(';').next()?;
+    match u8::from_str_radix(number, 16).ok()? {
+        0 => Some(None),
+        number => Some(gdb_signal(number)),
+    }
+}
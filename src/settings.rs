@@ -0,0 +1,648 @@
+use crate::debugger_command;
+use crate::style::{self, Style};
+use crate::syscalls::SyscallTracer;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What kind of value a setting holds, which decides how it's parsed and shown.
+pub enum Kind {
+    /// on or off
+    Bool,
+    /// A count, or "unlimited"
+    Int,
+    /// One of these words
+    Enum(&'static [&'static str]),
+    String,
+    /// A file, or none if the value is left out
+    Path,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(usize),
+    Enum(&'static str),
+    String(String),
+    Path(Option<PathBuf>),
+}
+
+/// A setting of `set` and `show`: how to parse it, its default, and how to document it.
+pub struct SettingSpec {
+    /// One or more words, e.g. "print mangled"
+    pub name: &'static str,
+    kind: Kind,
+    /// The value before any `set`, in the syntax `set` takes
+    default: &'static str,
+    /// One line for `help set`
+    pub help: &'static str,
+    /// What `show` says the value is the value of, e.g. "Killing inferiors on quit"
+    description: &'static str,
+    /// Extra validation of the value given to `set`
+    check: Option<Check>,
+}
+
+type Check = fn(&str) -> Result<(), String>;
+
+/// Only the substitutions `Debugger::prompt` knows.
+fn check_prompt(text: &str) -> Result<(), String> {
+    style::expand(text, Style::Prompt, |c| "pfs".contains(c).then(String::new)).map(|_| ())
+}
+
+/// Arguments `run` can split.
+fn check_args(text: &str) -> Result<(), String> {
+    debugger_command::parse_run_args(text).map(|_| ())
+}
+
+/// The settings `set` knows, with their values where there's a fixed set of them.
+pub const SETTINGS: &[SettingSpec] = &[
+    SettingSpec {
+        name: "print mangled",
+        kind: Kind::Bool,
+        default: "off",
+        help: "show C++ names mangled",
+        description: "Printing of C++ names in mangled form",
+        check: None,
+    },
+    SettingSpec {
+        name: "print symbol",
+        kind: Kind::Bool,
+        default: "on",
+        help: "show code addresses with the function they're in, as 0x401196 <main+0x26>",
+        description: "Printing of symbol names when printing code addresses",
+        check: None,
+    },
+    SettingSpec {
+        name: "args",
+        kind: Kind::String,
+        default: "",
+        help: "the arguments and redirections of run without any, as typed",
+        description: "Argument list to give program being debugged when it is started",
+        check: Some(check_args),
+    },
+    SettingSpec {
+        name: "auto-reload-symbols",
+        kind: Kind::Bool,
+        default: "on",
+        help: "reload the program's symbols before a run if it's been rebuilt",
+        description: "Reloading of a rebuilt program's symbols",
+        check: None,
+    },
+    SettingSpec {
+        name: "follow-fork-mode",
+        kind: Kind::Enum(&["parent", "child"]),
+        default: "parent",
+        help: "which process to keep debugging after a fork",
+        description: "The process followed after a fork",
+        check: None,
+    },
+    SettingSpec {
+        name: "detach-on-fork",
+        kind: Kind::Bool,
+        default: "on",
+        help: "off keeps both processes as inferiors",
+        description: "Detaching the other process after a fork",
+        check: None,
+    },
+    SettingSpec {
+        name: "disable-randomization",
+        kind: Kind::Bool,
+        default: "on",
+        help: "turn off address space randomization",
+        description: "Disabling randomization of debuggee's virtual address space",
+        check: None,
+    },
+    SettingSpec {
+        name: "trace-syscalls",
+        kind: Kind::String,
+        default: "off",
+        help: "print the program's syscalls: on, off, or NAME,NAME...",
+        description: "Syscall tracing",
+        check: Some(check_syscall_names),
+    },
+    SettingSpec {
+        name: "inferior-tty",
+        kind: Kind::Path,
+        default: "",
+        help: "run the program on another terminal (deet's if left out)",
+        description: "Terminal for future runs of program being debugged",
+        check: None,
+    },
+    SettingSpec {
+        name: "new-console-input",
+        kind: Kind::Enum(&["inherit", "pipe"]),
+        default: "inherit",
+        help: "give the program deet's stdin, or a pipe that \"input\" writes to",
+        description: "Where future runs of the program being debugged take their input from",
+        check: None,
+    },
+    SettingSpec {
+        name: "logging-child",
+        kind: Kind::Path,
+        default: "",
+        help: "copy the program's output into a file (none if left out)",
+        description: "The file the program's output is logged to",
+        check: None,
+    },
+    SettingSpec {
+        name: "kill-on-quit",
+        kind: Kind::Bool,
+        default: "on",
+        help: "off detaches from the program on quit instead",
+        description: "Killing inferiors on quit",
+        check: None,
+    },
+    SettingSpec {
+        name: "confirm",
+        kind: Kind::Bool,
+        default: "on",
+        help: "off skips the questions before killing the program or deleting breakpoints",
+        description: "Whether to confirm potentially dangerous operations",
+        check: None,
+    },
+    SettingSpec {
+        name: "print-statistics",
+        kind: Kind::Bool,
+        default: "off",
+        help: "print run time and resource usage on exit",
+        description: "Printing of run statistics",
+        check: None,
+    },
+    SettingSpec {
+        name: "run-timeout",
+        kind: Kind::Int,
+        default: "unlimited",
+        help: "seconds a run or continue may go before the program is stopped; 0 for no limit",
+        description: "The time limit of runs and continues",
+        check: None,
+    },
+    SettingSpec {
+        name: "breakpoint-timing",
+        kind: Kind::Bool,
+        default: "off",
+        help: "time the program's runs up to each breakpoint, for info breakpoints",
+        description: "Timing of the runs between breakpoint hits",
+        check: None,
+    },
+    SettingSpec {
+        name: "count-instructions",
+        kind: Kind::Bool,
+        default: "off",
+        help: "count the instructions the program runs, and show the count at each stop",
+        description: "Counting of the instructions executed",
+        check: None,
+    },
+    SettingSpec {
+        name: "exit-with-inferior-status",
+        kind: Kind::Bool,
+        default: "off",
+        help: "exit deet with the program's exit status",
+        description: "Exiting with the inferior's status",
+        check: None,
+    },
+    SettingSpec {
+        name: "pagination",
+        kind: Kind::Bool,
+        default: "on",
+        help: "stop for --More-- after each screenful of a command's output",
+        description: "State of pagination",
+        check: None,
+    },
+    SettingSpec {
+        name: "prompt",
+        kind: Kind::String,
+        default: "(deet)",
+        help: "the prompt; %p pid, %f function, %s state, %{STYLE}...%{} colors",
+        description: "Deet's prompt",
+        check: Some(check_prompt),
+    },
+    SettingSpec {
+        name: "verbose",
+        kind: Kind::Bool,
+        default: "off",
+        help: "print the commands of scripts as they run; list each real-time signal",
+        description: "Verbosity",
+        check: None,
+    },
+    SettingSpec {
+        name: "auto-load-safe-path",
+        kind: Kind::String,
+        default: "",
+        help: "DIR:DIR... whose world-writable .deetinit files load anyway",
+        description: "The directories safe for loading init files",
+        check: None,
+    },
+    SettingSpec {
+        name: "history save",
+        kind: Kind::Bool,
+        default: "on",
+        help: "save the command history as commands are entered",
+        description: "Saving of the command history",
+        check: None,
+    },
+    SettingSpec {
+        name: "history size",
+        kind: Kind::Int,
+        default: "256",
+        help: "how many commands the history keeps",
+        description: "The size of the command history",
+        check: None,
+    },
+    SettingSpec {
+        name: "history hints",
+        kind: Kind::Bool,
+        default: "on",
+        help: "show the rest of the last command starting like the line, taken with right arrow",
+        description: "Hinting from the command history",
+        check: None,
+    },
+    SettingSpec {
+        name: "editing-mode",
+        kind: Kind::Enum(&["emacs", "vi"]),
+        default: "emacs",
+        help: "the key bindings of the command line",
+        description: "Editing of command lines",
+        check: None,
+    },
+    SettingSpec {
+        name: "bell-style",
+        kind: Kind::Enum(&["audible", "visible", "none"]),
+        default: "audible",
+        help: "what the command line does when there's nothing to complete",
+        description: "The bell style",
+        check: None,
+    },
+    SettingSpec {
+        name: "completion-type",
+        kind: Kind::Enum(&["list", "circular"]),
+        default: "list",
+        help: "list completes the common prefix and lists the rest; circular cycles through them",
+        description: "The completion of command lines",
+        check: None,
+    },
+    SettingSpec {
+        name: "history filename",
+        kind: Kind::Path,
+        default: "",
+        help: "where to save the command history",
+        description: "The filename in which to record the command history",
+        check: None,
+    },
+    SettingSpec {
+        name: "logging",
+        kind: Kind::Bool,
+        default: "off",
+        help: "copy deet's output into the logging file (\"set logging on FILE\" names it)",
+        description: "Logging",
+        check: None,
+    },
+    SettingSpec {
+        name: "logging file",
+        kind: Kind::Path,
+        default: "deet.txt",
+        help: "the file that logging appends to",
+        description: "The current logfile",
+        check: None,
+    },
+    SettingSpec {
+        name: "logging redirect",
+        kind: Kind::Bool,
+        default: "off",
+        help: "while logging, leave the terminal out",
+        description: "Redirecting the output to the logfile only",
+        check: None,
+    },
+    SettingSpec {
+        name: "logging commands",
+        kind: Kind::Bool,
+        default: "off",
+        help: "while logging, also log the commands typed",
+        description: "Logging of typed commands",
+        check: None,
+    },
+    SettingSpec {
+        name: "output-format",
+        kind: Kind::Enum(&["text", "json"]),
+        default: "text",
+        help: "json prints results as one JSON object per line on stdout",
+        description: "The format of command results",
+        check: None,
+    },
+    SettingSpec {
+        name: "address-radix",
+        kind: Kind::Enum(&["16", "10"]),
+        default: "16",
+        help: "the radix of addresses like *1234 without a 0x or 0d prefix",
+        description: "The radix of addresses without a prefix",
+        check: None,
+    },
+    SettingSpec {
+        name: "style enabled",
+        kind: Kind::Bool,
+        default: "on",
+        help: "color the output",
+        description: "CLI output styling",
+        check: None,
+    },
+];
+
+fn check_syscall_names(value: &str) -> Result<(), String> {
+    if matches!(value, "on" | "off") {
+        return Ok(());
+    }
+    match value
+        .split(',')
+        .find(|name| !name.is_empty() && !SyscallTracer::is_known(name))
+    {
+        Some(name) => Err(format!("Unknown syscall \"{}\".", name)),
+        None => Ok(()),
+    }
+}
+
+impl Kind {
+    fn parse(&self, text: &str) -> Result<Value, String> {
+        match self {
+            Kind::Bool => match text {
+                "on" | "1" | "yes" | "enable" => Ok(Value::Bool(true)),
+                "off" | "0" | "no" | "disable" => Ok(Value::Bool(false)),
+                _ => Err("\"on\" or \"off\" expected.".to_string()),
+            },
+            Kind::Int => match text {
+                "unlimited" => Ok(Value::Int(usize::MAX)),
+                _ => text
+                    .parse()
+                    .map(Value::Int)
+                    .map_err(|_| "integer or \"unlimited\" expected.".to_string()),
+            },
+            Kind::Enum(choices) => match choices.iter().find(|choice| **choice == text) {
+                Some(choice) => Ok(Value::Enum(choice)),
+                None => Err(format!("Expected one of: {}.", choices.join(", "))),
+            },
+            Kind::String => Ok(Value::String(text.to_string())),
+            Kind::Path => Ok(Value::Path((!text.is_empty()).then(|| PathBuf::from(text)))),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(true) => write!(f, "on"),
+            Value::Bool(false) => write!(f, "off"),
+            Value::Int(usize::MAX) => write!(f, "unlimited"),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Enum(choice) => write!(f, "{}", choice),
+            Value::String(text) => write!(f, "\"{}\"", text),
+            Value::Path(Some(path)) => write!(f, "\"{}\"", path.display()),
+            Value::Path(None) => write!(f, "unset"),
+        }
+    }
+}
+
+/// The current values of all SETTINGS. Feature code reads them through the typed accessors,
+/// which panic if asked for a setting that doesn't exist or has another kind.
+pub struct Settings {
+    values: BTreeMap<&'static str, Value>,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        let values = SETTINGS
+            .iter()
+            .map(|spec| {
+                let value = spec.kind.parse(spec.default).expect("invalid default");
+                (spec.name, value)
+            })
+            .collect();
+        Settings { values }
+    }
+
+    /// `set NAME VALUE`. Returns the name of the setting that changed.
+    pub fn set(&mut self, args: &[&str]) -> Result<&'static str, String> {
+        let (spec, rest) = find(args).ok_or_else(|| unknown("set", args))?;
+        let text = rest.join(" ");
+        let value = spec.kind.parse(&text)?;
+        if let Some(check) = spec.check {
+            check(&text)?;
+        }
+        self.values.insert(spec.name, value);
+        Ok(spec.name)
+    }
+
+    /// Sets a value from the program rather than the user.
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        let spec = SETTINGS.iter().find(|spec| spec.name == name).unwrap();
+        self.values.insert(spec.name, value);
+    }
+
+    /// `show NAME`, or every setting without a name.
+    pub fn show(&self, args: &[&str]) -> Result<(), String> {
+        if args.is_empty() {
+            for spec in SETTINGS {
+                self.show_one(spec);
+            }
+            return Ok(());
+        }
+        if let Some((spec, [])) = find(args) {
+            self.show_one(spec);
+            return Ok(());
+        }
+        // a first word like "history" shows the settings it starts
+        let group = format!("{} ", args.join(" "));
+        let mut members = SETTINGS
+            .iter()
+            .filter(|spec| spec.name.starts_with(&group))
+            .peekable();
+        if members.peek().is_none() {
+            return Err(unknown("show", args));
+        }
+        for spec in members {
+            self.show_one(spec);
+        }
+        Ok(())
+    }
+
+    fn show_one(&self, spec: &SettingSpec) {
+        outln!("{} is {}.", spec.description, self.values[spec.name]);
+    }
+
+    fn get(&self, name: &str) -> &Value {
+        self.values
+            .get(name)
+            .unwrap_or_else(|| panic!("no setting {}", name))
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        match self.get(name) {
+            Value::Bool(on) => *on,
+            other => panic!("setting {} is {:?}, not a bool", name, other),
+        }
+    }
+
+    pub fn int(&self, name: &str) -> usize {
+        match self.get(name) {
+            Value::Int(n) => *n,
+            other => panic!("setting {} is {:?}, not an int", name, other),
+        }
+    }
+
+    pub fn choice(&self, name: &str) -> &'static str {
+        match self.get(name) {
+            Value::Enum(choice) => choice,
+            other => panic!("setting {} is {:?}, not an enum", name, other),
+        }
+    }
+
+    pub fn string(&self, name: &str) -> &str {
+        match self.get(name) {
+            Value::String(text) => text,
+            other => panic!("setting {} is {:?}, not a string", name, other),
+        }
+    }
+
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        match self.get(name) {
+            Value::Path(path) => path.as_deref(),
+            other => panic!("setting {} is {:?}, not a path", name, other),
+        }
+    }
+}
+
+/// The lines of `help set`.
+pub fn print_help() {
+    outln!("\nSettings:");
+    for spec in SETTINGS {
+        let value = match spec.kind {
+            Kind::Bool => "on|off".to_string(),
+            Kind::Int => "N".to_string(),
+            Kind::Enum(choices) => choices.join("|"),
+            Kind::String => "VALUE".to_string(),
+            Kind::Path => "[FILE]".to_string(),
+        };
+        let usage = format!("{} {}", spec.name, value);
+        outln!("  set {:<33} {}", usage, spec.help);
+    }
+}
+
+/// The settings whose name, help or description `pattern` matches, as (name, description).
+pub fn matching(pattern: &Regex) -> Vec<(&'static str, &'static str)> {
+    SETTINGS
+        .iter()
+        .filter(|spec| {
+            [spec.name, spec.help, spec.description]
+                .iter()
+                .any(|text| pattern.is_match(text))
+        })
+        .map(|spec| (spec.name, spec.description))
+        .collect()
+}
+
+/// The names of the settings as lists of words, for completion. With `values`, also each name
+/// followed by each of its values, for settings with a fixed set of them.
+pub fn phrases(values: bool) -> Vec<Vec<&'static str>> {
+    let mut phrases = Vec::new();
+    for spec in SETTINGS {
+        let name: Vec<&'static str> = spec.name.split(' ').collect();
+        let choices: &[&'static str] = match spec.kind {
+            Kind::Bool if values => &["on", "off"],
+            Kind::Enum(choices) if values => choices,
+            _ => &[],
+        };
+        for choice in choices {
+            let mut phrase = name.clone();
+            phrase.push(choice);
+            phrases.push(phrase);
+        }
+        phrases.push(name);
+    }
+    phrases
+}
+
+/// The setting whose name starts `args`, and the words after its name.
+fn find<'a, 'b>(args: &'a [&'b str]) -> Option<(&'static SettingSpec, &'a [&'b str])> {
+    SETTINGS
+        .iter()
+        .filter_map(|spec| {
+            let words = spec.name.split(' ').count();
+            let matches = args.len() >= words
+                && spec
+                    .name
+                    .split(' ')
+                    .zip(args)
+                    .all(|(word, arg)| word == *arg);
+            matches.then(|| (spec, &args[words..]))
+        })
+        .max_by_key(|(spec, _)| spec.name.len())
+}
+
+/// The error for a setting name that isn't known, suggesting the closest one.
+fn unknown(command: &str, args: &[&str]) -> String {
+    let name = args.first().copied().unwrap_or("");
+    let mut suggestions: Vec<(usize, &str)> = SETTINGS
+        .iter()
+        .filter_map(|spec| {
+            // compare with as many words as the setting has
+            let words = spec.name.split(' ').count();
+            let given = args[..words.min(args.len())].join(" ");
+            let distance = edit_distance(&given, spec.name);
+            is_misspelling(&given, spec.name, distance).then_some((distance, spec.name))
+        })
+        .collect();
+    suggestions.sort();
+    let names: Vec<&str> = suggestions.iter().map(|(_, name)| *name).collect();
+    match did_you_mean(&names) {
+        Some(hint) => format!("Unknown setting \"{}\". {}", name, hint),
+        None => format!("Unknown setting \"{}\". Try \"help {}\".", name, command),
+    }
+}
+
+/// Up to three of `known` that `given` could be a misspelling of, closest first.
+pub fn suggestions<'a>(given: &str, known: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut scored: Vec<(usize, &str)> = known
+        .into_iter()
+        .filter_map(|name| {
+            let distance = edit_distance(given, name);
+            is_misspelling(given, name, distance).then_some((distance, name))
+        })
+        .collect();
+    scored.sort();
+    scored.dedup_by_key(|(_, name)| *name);
+    scored.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+/// Whether `given` is a few edits from `name`, or starts it. The edits must leave part of
+/// `given`, or one letter would be a misspelling of every other short name.
+fn is_misspelling(given: &str, name: &str, distance: usize) -> bool {
+    (distance <= (name.len() / 3).max(1) && distance < given.len())
+        || (!given.is_empty() && name.starts_with(given))
+}
+
+/// `Did you mean "a", "b" or "c"?`, or None without suggestions.
+pub fn did_you_mean(suggestions: &[&str]) -> Option<String> {
+    let quoted: Vec<String> = suggestions
+        .iter()
+        .take(3)
+        .map(|name| format!("\"{}\"", name))
+        .collect();
+    match quoted.as_slice() {
+        [] => None,
+        [one] => Some(format!("Did you mean {}?", one)),
+        [rest @ .., last] => Some(format!("Did you mean {} or {}?", rest.join(", "), last)),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(previous + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
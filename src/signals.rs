@@ -0,0 +1,86 @@
+//! What deet calls signals when it shows them: their names and, as GDB words them, what they
+//! mean, for stops, exits, core files and everything else that mentions one.
+
+use nix::sys::signal::Signal;
+
+/// Every signal the host knows, with what it means
+const SIGNALS: &[(Signal, &str)] = &[
+    (Signal::SIGHUP, "Hangup"),
+    (Signal::SIGINT, "Interrupt"),
+    (Signal::SIGQUIT, "Quit"),
+    (Signal::SIGILL, "Illegal instruction"),
+    (Signal::SIGTRAP, "Trace/breakpoint trap"),
+    (Signal::SIGABRT, "Aborted"),
+    (Signal::SIGBUS, "Bus error"),
+    (Signal::SIGFPE, "Arithmetic exception"),
+    (Signal::SIGKILL, "Killed"),
+    (Signal::SIGUSR1, "User defined signal 1"),
+    (Signal::SIGSEGV, "Segmentation fault"),
+    (Signal::SIGUSR2, "User defined signal 2"),
+    (Signal::SIGPIPE, "Broken pipe"),
+    (Signal::SIGALRM, "Alarm clock"),
+    (Signal::SIGTERM, "Terminated"),
+    #[cfg(not(target_arch = "aarch64"))]
+    (Signal::SIGSTKFLT, "Stack fault"),
+    (Signal::SIGCHLD, "Child status changed"),
+    (Signal::SIGCONT, "Continued"),
+    (Signal::SIGSTOP, "Stopped (signal)"),
+    (Signal::SIGTSTP, "Stopped (user)"),
+    (Signal::SIGTTIN, "Stopped (tty input)"),
+    (Signal::SIGTTOU, "Stopped (tty output)"),
+    (Signal::SIGURG, "Urgent I/O condition"),
+    (Signal::SIGXCPU, "CPU time limit exceeded"),
+    (Signal::SIGXFSZ, "File size limit exceeded"),
+    (Signal::SIGVTALRM, "Virtual timer expired"),
+    (Signal::SIGPROF, "Profiling timer expired"),
+    (Signal::SIGWINCH, "Window size changed"),
+    (Signal::SIGIO, "I/O possible"),
+    (Signal::SIGPWR, "Power fail/restart"),
+    (Signal::SIGSYS, "Bad system call"),
+];
+
+/// The first real-time signal, as the kernel numbers them; glibc keeps the first two for itself
+pub const FIRST_REALTIME: i32 = 32;
+
+/// The last signal number, of the last real-time signal
+pub const LAST_SIGNAL: i32 = 64;
+
+/// "SIGSEGV"
+pub fn name(signal: Signal) -> &'static str {
+    signal.as_str()
+}
+
+/// "Segmentation fault"
+pub fn meaning(signal: Signal) -> &'static str {
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == signal)
+        .map_or("Unknown signal", |(_, meaning)| meaning)
+}
+
+/// The name of signal `number`, or as GDB calls one that isn't in the table, e.g. "SIG40".
+pub fn name_of(number: i32) -> String {
+    match Signal::try_from(number) {
+        Ok(signal) => name(signal).to_string(),
+        Err(_) => format!("SIG{}", number),
+    }
+}
+
+/// What signal `number` means, e.g. "Real-time event 40".
+pub fn meaning_of(number: i32) -> String {
+    match Signal::try_from(number) {
+        Ok(signal) => meaning(signal).to_string(),
+        Err(_) if number >= FIRST_REALTIME => format!("Real-time event {}", number),
+        Err(_) => "Unknown signal".to_string(),
+    }
+}
+
+/// What deet does when the program gets signal `number`: whether it stops, whether it says so,
+/// and whether continuing passes the signal on. Every signal stops the program and goes no
+/// further, except SIGKILL, which a tracer never sees.
+pub fn handling(number: i32) -> (bool, bool, bool) {
+    match number == libc::SIGKILL {
+        true => (false, false, true),
+        false => (true, true, false),
+    }
+}
@@ -0,0 +1,176 @@
+//! The skip list of `skip`: functions and source files that stepping should go over rather than
+//! into, like logging helpers and library internals. Stepping into a call to one of them is
+//! meant to step over it, as `next` would.
+
+use regex::Regex;
+
+/// The entries of `skip`, in the order they were added.
+pub struct SkipList {
+    entries: Vec<Skip>,
+    /// The number the next entry gets. Numbers start at 1 and aren't reused after a delete.
+    next_number: usize,
+}
+
+pub struct Skip {
+    pub number: usize,
+    pub enabled: bool,
+    /// The name, regular expression or glob, as typed
+    pub text: String,
+    matcher: Matcher,
+}
+
+enum Matcher {
+    /// A function by its demangled name, with or without the parameter list
+    Function(String),
+    /// Functions whose demangled names match a regular expression anywhere
+    FunctionRegex(Regex),
+    /// Source files matching a glob: just their base names for a glob without a `/`, else all
+    /// of their paths or the end of them after a `/`
+    File(Regex, bool),
+}
+
+impl Skip {
+    /// "function", "file"
+    pub fn kind(&self) -> &'static str {
+        match self.matcher {
+            Matcher::Function(_) | Matcher::FunctionRegex(_) => "function",
+            Matcher::File(..) => "file",
+        }
+    }
+
+    fn is_match(&self, function: Option<&str>, file: Option<&str>) -> bool {
+        match (&self.matcher, function, file) {
+            (Matcher::Function(name), Some(function), _) => {
+                function == name
+                    || function
+                        .strip_prefix(name.as_str())
+                        .is_some_and(|params| params.starts_with('('))
+            }
+            (Matcher::FunctionRegex(pattern), Some(function), _) => pattern.is_match(function),
+            (Matcher::File(pattern, false), _, Some(file)) => {
+                pattern.is_match(file.rsplit('/').next().unwrap_or(file))
+            }
+            (Matcher::File(pattern, true), _, Some(file)) => {
+                pattern.is_match(file)
+                    || file
+                        .match_indices('/')
+                        .any(|(i, _)| pattern.is_match(&file[i + 1..]))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Default for SkipList {
+    fn default() -> SkipList {
+        SkipList::new()
+    }
+}
+
+impl SkipList {
+    pub fn new() -> SkipList {
+        SkipList {
+            entries: Vec::new(),
+            next_number: 1,
+        }
+    }
+
+    pub fn entries(&self) -> &[Skip] {
+        &self.entries
+    }
+
+    /// Skips functions named `text`, or matching it if it has any of the special characters
+    /// of a regular expression.
+    pub fn add_function(&mut self, text: &str) -> Result<&Skip, String> {
+        let matcher = if text.contains(['.', '*', '+', '?', '[', ']', '^', '$', '|', '\\']) {
+            Matcher::FunctionRegex(Regex::new(text).map_err(|err| err.to_string())?)
+        } else {
+            Matcher::Function(text.to_string())
+        };
+        Ok(self.add(text, matcher))
+    }
+
+    /// Skips the source files matching the glob `text`.
+    pub fn add_file(&mut self, text: &str) -> Result<&Skip, String> {
+        let matcher = Matcher::File(glob(text)?, text.contains('/'));
+        Ok(self.add(text, matcher))
+    }
+
+    fn add(&mut self, text: &str, matcher: Matcher) -> &Skip {
+        self.entries.push(Skip {
+            number: self.next_number,
+            enabled: true,
+            text: text.to_string(),
+            matcher,
+        });
+        self.next_number += 1;
+        self.entries.last().unwrap()
+    }
+
+    /// The entries numbered `numbers`, or all of them given none. Fails naming the first
+    /// number that isn't an entry's.
+    pub fn select(&mut self, numbers: &[usize]) -> Result<Vec<&mut Skip>, usize> {
+        if let Some(&missing) = numbers
+            .iter()
+            .find(|&&number| !self.entries.iter().any(|skip| skip.number == number))
+        {
+            return Err(missing);
+        }
+        Ok(self
+            .entries
+            .iter_mut()
+            .filter(|skip| numbers.is_empty() || numbers.contains(&skip.number))
+            .collect())
+    }
+
+    /// Deletes the entries numbered `numbers`, or all of them given none, failing like `select`.
+    pub fn delete(&mut self, numbers: &[usize]) -> Result<(), usize> {
+        self.select(numbers)?;
+        self.entries
+            .retain(|skip| !numbers.is_empty() && !numbers.contains(&skip.number));
+        Ok(())
+    }
+
+    /// Whether an enabled entry matches a function, by its demangled name, or the source file
+    /// it's in. Either can be unknown.
+    pub fn is_skipped(&self, function: Option<&str>, file: Option<&str>) -> bool {
+        self.entries
+            .iter()
+            .any(|skip| skip.enabled && skip.is_match(function, file))
+    }
+}
+
+/// A shell glob as a regular expression matching all of the text: `*` for any run of characters
+/// but `/`, `?` for any one but `/`, and classes like `[ch]` and `[!ch]`.
+fn glob(glob: &str) -> Result<Regex, String> {
+    let mut regex = String::from("^");
+    let mut in_class = false;
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            _ if in_class => {
+                in_class = c != ']';
+                if c == '\\' || c == '[' {
+                    regex.push('\\');
+                }
+                regex.push(c);
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                in_class = true;
+                regex.push(c);
+                if chars.next_if_eq(&'!').is_some() {
+                    regex.push('^');
+                }
+                // a ] right after the [ is a member, not the end
+                if chars.next_if_eq(&']').is_some() {
+                    regex.push_str("\\]");
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|err| err.to_string())
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reads and caches the source files named in the debug info, so stop messages can show the text
+/// of the current line.
+pub struct SourceFiles {
+    /// Directories to look in when the path recorded in the debug info doesn't exist (e.g. the
+    /// binary was built on another machine)
+    search_dirs: Vec<PathBuf>,
+    /// Lines of each file we've tried to read, keyed by the path from the debug info. None if the
+    /// file couldn't be found.
+    files: HashMap<String, Option<Vec<String>>>,
+}
+
+impl SourceFiles {
+    pub fn new(target: &str) -> SourceFiles {
+        let mut search_dirs = vec![PathBuf::from(".")];
+        if let Some(dir) = Path::new(target).parent() {
+            search_dirs.push(dir.to_path_buf());
+        }
+        SourceFiles {
+            search_dirs,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Returns the text of line `number` (1-based) of `path`.
+    pub fn get_line(&mut self, path: &str, number: usize) -> Option<&str> {
+        let lines = self.get_file(path)?;
+        lines.get(number.checked_sub(1)?).map(|line| line.as_str())
+    }
+
+    /// Returns all lines of `path`, reading it on first use.
+    pub fn get_file(&mut self, path: &str) -> Option<&Vec<String>> {
+        if !self.files.contains_key(path) {
+            let contents = self
+                .find(path)
+                .and_then(|found| fs::read_to_string(found).ok());
+            let lines = contents.map(|contents| contents.lines().map(String::from).collect());
+            self.files.insert(path.to_string(), lines);
+        }
+        self.files.get(path)?.as_ref()
+    }
+
+    /// Where the file at `path` in the debug info is, if it's there or in one of the search
+    /// directories.
+    pub fn find(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        let file_name = path.file_name()?;
+        self.search_dirs
+            .iter()
+            .map(|dir| dir.join(file_name))
+            .find(|candidate| candidate.is_file())
+    }
+}
@@ -0,0 +1,125 @@
+use nix::unistd;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether output is styled. Global since output is printed from all over the place.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What a piece of output is, which decides how it looks.
+#[derive(Clone, Copy)]
+pub enum Style {
+    /// Headers of stops: breakpoint hits and signals
+    Stop,
+    Function,
+    /// file:line and addresses
+    Location,
+    Error,
+    Prompt,
+    /// The rest of a command from the history, after what's typed
+    Hint,
+}
+
+impl Style {
+    /// The style called `name` in templates like `set prompt`'s.
+    pub fn by_name(name: &str) -> Option<Style> {
+        match name {
+            "stop" => Some(Style::Stop),
+            "function" => Some(Style::Function),
+            "location" => Some(Style::Location),
+            "error" => Some(Style::Error),
+            "prompt" => Some(Style::Prompt),
+            "hint" => Some(Style::Hint),
+            _ => None,
+        }
+    }
+
+    fn sgr(self) -> &'static str {
+        match self {
+            Style::Stop => "1;35",
+            Style::Function => "33",
+            Style::Location => "32",
+            Style::Error => "31",
+            Style::Prompt => "1",
+            Style::Hint => "2",
+        }
+    }
+}
+
+/// Turns styling on unless asked not to by `--no-color` or NO_COLOR, or stdout isn't a terminal
+/// (so piped output stays plain).
+pub fn init(no_color: bool) {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let tty = unistd::isatty(1).unwrap_or(false);
+    set_enabled(!no_color && !no_color_env && tty);
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// `text` in `style`, if styling is on.
+pub fn paint<T: fmt::Display>(style: Style, text: T) -> Painted<T> {
+    Painted(style, text)
+}
+
+pub struct Painted<T>(Style, T);
+
+impl<T: fmt::Display> fmt::Display for Painted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            write!(f, "\x1b[{}m{}\x1b[0m", self.0.sgr(), self.1)
+        } else {
+            self.1.fmt(f)
+        }
+    }
+}
+
+/// Expands `template`, in which `%{NAME}` starts text in the style NAME (see `Style::by_name`)
+/// and `%{}` goes back to `base`, `%%` is a %, and other `%X` are replaced by `substitute(X)`.
+/// Gives the text plain, and styled if styling is on.
+pub fn expand(
+    template: &str,
+    base: Style,
+    substitute: impl Fn(char) -> Option<String>,
+) -> Result<(String, String), String> {
+    let mut plain = String::new();
+    let mut styled = String::new();
+    let mut current = base;
+    let mut span = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            span.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => span.push('%'),
+            Some('{') => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let next = match name.as_str() {
+                    "" => base,
+                    name => Style::by_name(name)
+                        .ok_or_else(|| format!("Unknown style \"{}\".", name))?,
+                };
+                if !span.is_empty() {
+                    plain.push_str(&span);
+                    styled.push_str(&paint(current, &span).to_string());
+                    span.clear();
+                }
+                current = next;
+            }
+            Some(c) => match substitute(c) {
+                Some(text) => span.push_str(&text),
+                None => return Err(format!("Unknown substitution %{}.", c)),
+            },
+            None => span.push('%'),
+        }
+    }
+    plain.push_str(&span);
+    styled.push_str(&paint(current, &span).to_string());
+    Ok((plain, styled))
+}
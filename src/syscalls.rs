@@ -0,0 +1,253 @@
+use crate::arch;
+use crate::inferior::Inferior;
+use nix::errno::Errno;
+use nix::unistd::Pid;
+use std::collections::{HashMap, HashSet};
+
+/// How to show a syscall argument.
+#[derive(Clone, Copy)]
+enum Arg {
+    Int,
+    Hex,
+    Octal,
+    /// A file descriptor, or AT_FDCWD
+    Fd,
+    /// A NUL-terminated string in the inferior's memory
+    Path,
+    /// A pointer to a buffer whose contents we don't decode
+    Ptr,
+}
+
+use Arg::*;
+
+/// Common x86_64 syscalls: number, name and arguments. Others are shown by number.
+#[cfg(target_arch = "x86_64")]
+const SYSCALLS: &[(u64, &str, &[Arg])] = &[
+    (0, "read", &[Fd, Ptr, Int]),
+    (1, "write", &[Fd, Ptr, Int]),
+    (2, "open", &[Path, Hex, Octal]),
+    (3, "close", &[Fd]),
+    (4, "stat", &[Path, Ptr]),
+    (5, "fstat", &[Fd, Ptr]),
+    (6, "lstat", &[Path, Ptr]),
+    (7, "poll", &[Ptr, Int, Int]),
+    (8, "lseek", &[Fd, Int, Int]),
+    (9, "mmap", &[Ptr, Int, Hex, Hex, Fd, Hex]),
+    (10, "mprotect", &[Ptr, Int, Hex]),
+    (11, "munmap", &[Ptr, Int]),
+    (12, "brk", &[Ptr]),
+    (13, "rt_sigaction", &[Int, Ptr, Ptr, Int]),
+    (14, "rt_sigprocmask", &[Int, Ptr, Ptr, Int]),
+    (16, "ioctl", &[Fd, Hex, Ptr]),
+    (17, "pread64", &[Fd, Ptr, Int, Int]),
+    (18, "pwrite64", &[Fd, Ptr, Int, Int]),
+    (19, "readv", &[Fd, Ptr, Int]),
+    (20, "writev", &[Fd, Ptr, Int]),
+    (21, "access", &[Path, Octal]),
+    (22, "pipe", &[Ptr]),
+    (32, "dup", &[Fd]),
+    (33, "dup2", &[Fd, Fd]),
+    (35, "nanosleep", &[Ptr, Ptr]),
+    (39, "getpid", &[]),
+    (41, "socket", &[Int, Int, Int]),
+    (42, "connect", &[Fd, Ptr, Int]),
+    (56, "clone", &[Hex, Ptr, Ptr, Ptr, Hex]),
+    (57, "fork", &[]),
+    (58, "vfork", &[]),
+    (59, "execve", &[Path, Ptr, Ptr]),
+    (60, "exit", &[Int]),
+    (61, "wait4", &[Int, Ptr, Hex, Ptr]),
+    (62, "kill", &[Int, Int]),
+    (72, "fcntl", &[Fd, Int, Hex]),
+    (79, "getcwd", &[Ptr, Int]),
+    (80, "chdir", &[Path]),
+    (82, "rename", &[Path, Path]),
+    (83, "mkdir", &[Path, Octal]),
+    (84, "rmdir", &[Path]),
+    (87, "unlink", &[Path]),
+    (89, "readlink", &[Path, Ptr, Int]),
+    (102, "getuid", &[]),
+    (158, "arch_prctl", &[Hex, Ptr]),
+    (202, "futex", &[Ptr, Int, Int, Ptr]),
+    (217, "getdents64", &[Fd, Ptr, Int]),
+    (218, "set_tid_address", &[Ptr]),
+    (228, "clock_gettime", &[Int, Ptr]),
+    (230, "clock_nanosleep", &[Int, Int, Ptr, Ptr]),
+    (231, "exit_group", &[Int]),
+    (257, "openat", &[Fd, Path, Hex, Octal]),
+    (262, "newfstatat", &[Fd, Path, Ptr, Hex]),
+    (263, "unlinkat", &[Fd, Path, Hex]),
+    (273, "set_robust_list", &[Ptr, Int]),
+    (292, "dup3", &[Fd, Fd, Hex]),
+    (293, "pipe2", &[Ptr, Hex]),
+    (302, "prlimit64", &[Int, Int, Ptr, Ptr]),
+    (318, "getrandom", &[Ptr, Int, Hex]),
+    (334, "rseq", &[Ptr, Int, Hex, Hex]),
+    (435, "clone3", &[Ptr, Int]),
+    (439, "faccessat2", &[Fd, Path, Octal, Hex]),
+];
+
+/// The same for aarch64, which has the generic numbering and lacks the older calls like open.
+#[cfg(target_arch = "aarch64")]
+const SYSCALLS: &[(u64, &str, &[Arg])] = &[
+    (17, "getcwd", &[Ptr, Int]),
+    (23, "dup", &[Fd]),
+    (24, "dup3", &[Fd, Fd, Hex]),
+    (25, "fcntl", &[Fd, Int, Hex]),
+    (29, "ioctl", &[Fd, Hex, Ptr]),
+    (35, "unlinkat", &[Fd, Path, Hex]),
+    (49, "chdir", &[Path]),
+    (56, "openat", &[Fd, Path, Hex, Octal]),
+    (57, "close", &[Fd]),
+    (59, "pipe2", &[Ptr, Hex]),
+    (61, "getdents64", &[Fd, Ptr, Int]),
+    (62, "lseek", &[Fd, Int, Int]),
+    (63, "read", &[Fd, Ptr, Int]),
+    (64, "write", &[Fd, Ptr, Int]),
+    (65, "readv", &[Fd, Ptr, Int]),
+    (66, "writev", &[Fd, Ptr, Int]),
+    (67, "pread64", &[Fd, Ptr, Int, Int]),
+    (68, "pwrite64", &[Fd, Ptr, Int, Int]),
+    (79, "newfstatat", &[Fd, Path, Ptr, Hex]),
+    (80, "fstat", &[Fd, Ptr]),
+    (93, "exit", &[Int]),
+    (94, "exit_group", &[Int]),
+    (96, "set_tid_address", &[Ptr]),
+    (98, "futex", &[Ptr, Int, Int, Ptr]),
+    (99, "set_robust_list", &[Ptr, Int]),
+    (101, "nanosleep", &[Ptr, Ptr]),
+    (113, "clock_gettime", &[Int, Ptr]),
+    (115, "clock_nanosleep", &[Int, Int, Ptr, Ptr]),
+    (129, "kill", &[Int, Int]),
+    (134, "rt_sigaction", &[Int, Ptr, Ptr, Int]),
+    (135, "rt_sigprocmask", &[Int, Ptr, Ptr, Int]),
+    (172, "getpid", &[]),
+    (174, "getuid", &[]),
+    (198, "socket", &[Int, Int, Int]),
+    (203, "connect", &[Fd, Ptr, Int]),
+    (214, "brk", &[Ptr]),
+    (215, "munmap", &[Ptr, Int]),
+    (220, "clone", &[Hex, Ptr, Ptr, Ptr, Hex]),
+    (221, "execve", &[Path, Ptr, Ptr]),
+    (222, "mmap", &[Ptr, Int, Hex, Hex, Fd, Hex]),
+    (226, "mprotect", &[Ptr, Int, Hex]),
+    (260, "wait4", &[Int, Ptr, Hex, Ptr]),
+    (261, "prlimit64", &[Int, Int, Ptr, Ptr]),
+    (278, "getrandom", &[Ptr, Int, Hex]),
+    (293, "rseq", &[Ptr, Int, Hex, Hex]),
+    (435, "clone3", &[Ptr, Int]),
+    (439, "faccessat2", &[Fd, Path, Octal, Hex]),
+];
+
+/// Syscalls that return an address rather than a count or fd.
+const RETURNS_POINTER: &[&str] = &["mmap", "brk"];
+
+/// Syscalls that never return, so they're printed at entry.
+const NO_RETURN: &[&str] = &["exit", "exit_group"];
+
+const AT_FDCWD: i32 = -100;
+const MAX_STRING: usize = 64;
+
+/// Prints the inferior's syscalls, strace style, while `continue` runs it with PTRACE_SYSCALL.
+pub struct SyscallTracer {
+    /// Names of the syscalls to show; empty shows all
+    filter: HashSet<String>,
+    /// Formatted call of each process's syscall in progress, printed once it returns
+    pending: HashMap<Pid, String>,
+}
+
+impl SyscallTracer {
+    pub fn new(filter: HashSet<String>) -> SyscallTracer {
+        SyscallTracer {
+            filter,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Returns whether `name` is a syscall we know, for validating filters.
+    pub fn is_known(name: &str) -> bool {
+        SYSCALLS.iter().any(|(_, known, _)| *known == name)
+    }
+
+    pub fn on_entry(&mut self, inferior: &Inferior) {
+        let regs = match inferior.registers() {
+            Ok(regs) => regs,
+            Err(_) => return,
+        };
+        let (number, values) = arch::syscall(&regs);
+        let (name, arg_kinds) = lookup(number);
+        if !self.filter.is_empty() && !self.filter.contains(&name) {
+            return;
+        }
+        let args: Vec<String> = arg_kinds
+            .iter()
+            .zip(values)
+            .map(|(kind, value)| format_arg(inferior, *kind, value))
+            .collect();
+        let call = format!("{}({})", name, args.join(", "));
+        if NO_RETURN.contains(&name.as_str()) {
+            outln!("{} = ?", call);
+        } else {
+            self.pending.insert(inferior.tid(), call);
+        }
+    }
+
+    pub fn on_exit(&mut self, inferior: &Inferior) {
+        let call = match self.pending.remove(&inferior.tid()) {
+            Some(call) => call,
+            None => return,
+        };
+        let regs = match inferior.registers() {
+            Ok(regs) => regs,
+            Err(_) => return,
+        };
+        let ret = arch::syscall_return(&regs) as i64;
+        let (name, _) = lookup(arch::syscall(&regs).0);
+        if (-4095..0).contains(&ret) {
+            let errno = Errno::from_i32(-ret as i32);
+            outln!("{} = -1 {:?} ({})", call, errno, errno.desc());
+        } else if RETURNS_POINTER.contains(&name.as_str()) {
+            outln!("{} = {:#x}", call, ret);
+        } else {
+            outln!("{} = {}", call, ret);
+        }
+    }
+}
+
+fn lookup(number: u64) -> (String, &'static [Arg]) {
+    match SYSCALLS.iter().find(|(nr, _, _)| *nr == number) {
+        Some((_, name, args)) => (name.to_string(), args),
+        None => (format!("syscall_{}", number), &[Hex, Hex, Hex]),
+    }
+}
+
+fn format_arg(inferior: &Inferior, kind: Arg, value: u64) -> String {
+    match kind {
+        Int => (value as i64).to_string(),
+        Hex => format!("{:#x}", value),
+        Octal => format!("{:#o}", value),
+        Fd if value as i32 == AT_FDCWD => "AT_FDCWD".to_string(),
+        Fd => (value as i32).to_string(),
+        Ptr | Path if value == 0 => "NULL".to_string(),
+        Ptr => format!("{:#x}", value),
+        Path => match read_string(inferior, value as usize) {
+            Some(string) => format!("{:?}", string),
+            None => format!("{:#x}", value),
+        },
+    }
+}
+
+/// Reads a NUL-terminated string from the inferior, truncated to MAX_STRING bytes.
+fn read_string(inferior: &Inferior, addr: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_STRING {
+        let word = inferior.read_word(addr + bytes.len()).ok()?;
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return Some(String::from_utf8_lossy(&bytes).into_owned());
+            }
+            bytes.push(byte);
+        }
+    }
+    Some(format!("{}...", String::from_utf8_lossy(&bytes)))
+}
@@ -0,0 +1,152 @@
+use nix::sys::signal::{self, SigHandler, Signal};
+use nix::sys::termios::{self, SetArg, Termios};
+use nix::unistd::{self, Pid};
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const STDIN: RawFd = 0;
+
+/// Hands deet's terminal back and forth between deet and the inferior. While the inferior runs,
+/// its process group owns the terminal (so it can read stdin and gets ctrl+c) with its own
+/// terminal modes; whenever it stops, deet takes the terminal back and restores the modes
+/// rustyline expects.
+///
+/// Does nothing if deet's stdin isn't a terminal.
+pub struct Terminal {
+    /// deet's own process group and terminal modes, or None if stdin isn't a terminal
+    ours: Option<(Pid, Termios)>,
+    /// The inferior's terminal modes as of its last stop, so that e.g. a curses program gets
+    /// back the raw mode it set
+    inferior_modes: Option<Termios>,
+}
+
+impl Terminal {
+    pub fn new() -> Terminal {
+        let ours = if unistd::isatty(STDIN).unwrap_or(false) {
+            termios::tcgetattr(STDIN)
+                .ok()
+                .map(|modes| (unistd::getpgrp(), modes))
+        } else {
+            None
+        };
+        Terminal {
+            ours,
+            inferior_modes: None,
+        }
+    }
+
+    /// Whether there is a terminal to manage, i.e. whether the inferior should be started in its
+    /// own process group.
+    pub fn is_managed(&self) -> bool {
+        self.ours.is_some()
+    }
+
+    /// Forgets the modes of the previous inferior, so a new one starts with deet's.
+    pub fn reset_inferior_modes(&mut self) {
+        self.inferior_modes = None;
+    }
+
+    /// Gives the terminal to the process group of `pid` before resuming it.
+    pub fn give_to_inferior(&mut self, pid: Pid) {
+        if self.ours.is_none() {
+            return;
+        }
+        if let Some(modes) = &self.inferior_modes {
+            let _ = termios::tcsetattr(STDIN, SetArg::TCSADRAIN, modes);
+        }
+        if let Ok(pgid) = unistd::getpgid(Some(pid)) {
+            // deet ignores SIGTTOU, otherwise doing this from the background would stop us
+            let _ = unistd::tcsetpgrp(STDIN, pgid);
+        }
+    }
+
+    /// Takes the terminal back after the inferior stopped or exited, remembering the modes it
+    /// left the terminal in if it's still around.
+    pub fn take_back(&mut self, inferior_alive: bool) {
+        let (pgrp, modes) = match &self.ours {
+            Some(ours) => ours,
+            None => return,
+        };
+        self.inferior_modes = if inferior_alive {
+            termios::tcgetattr(STDIN).ok()
+        } else {
+            None
+        };
+        let _ = unistd::tcsetpgrp(STDIN, *pgrp);
+        let _ = termios::tcsetattr(STDIN, SetArg::TCSADRAIN, modes);
+    }
+
+    /// Puts deet's terminal modes back after something else, like a shell command, may have
+    /// changed them.
+    pub fn restore_ours(&self) {
+        if let Some((pgrp, modes)) = &self.ours {
+            let _ = unistd::tcsetpgrp(STDIN, *pgrp);
+            let _ = termios::tcsetattr(STDIN, SetArg::TCSADRAIN, modes);
+        }
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn note_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Lets ctrl+c interrupt deet's own work, e.g. a long backtrace, while it lives. The rest of the
+/// time deet ignores SIGINT, which is for the inferior.
+pub struct Interrupts(());
+
+impl Interrupts {
+    pub fn catch() -> Interrupts {
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        let _ = unsafe { signal::signal(Signal::SIGINT, SigHandler::Handler(note_interrupt)) };
+        Interrupts(())
+    }
+
+    /// Whether ctrl+c has been pressed since `catch`.
+    pub fn occurred(&self) -> bool {
+        INTERRUPTED.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Interrupts {
+    fn drop(&mut self) {
+        let _ = unsafe { signal::signal(Signal::SIGINT, SigHandler::SigIgn) };
+    }
+}
+
+/// The size of the terminal on stdout, as rows and columns, if it is one.
+pub fn size() -> Option<(usize, usize)> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let ok = unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_row > 0 && size.ws_col > 0).then_some((size.ws_row.into(), size.ws_col.into()))
+}
+
+/// Reads a single key press from the terminal on stdin, without waiting for a newline or
+/// echoing it. None at the end of input.
+pub fn read_key() -> Option<u8> {
+    let saved = termios::tcgetattr(STDIN).ok()?;
+    let mut raw = saved.clone();
+    raw.local_flags
+        .remove(termios::LocalFlags::ICANON | termios::LocalFlags::ECHO);
+    raw.control_chars[termios::SpecialCharacterIndices::VMIN as usize] = 1;
+    raw.control_chars[termios::SpecialCharacterIndices::VTIME as usize] = 0;
+    let _ = termios::tcsetattr(STDIN, SetArg::TCSANOW, &raw);
+    let mut key = [0u8];
+    let count = loop {
+        match unistd::read(STDIN, &mut key) {
+            Err(nix::errno::Errno::EINTR) => continue,
+            result => break result,
+        }
+    };
+    let _ = termios::tcsetattr(STDIN, SetArg::TCSANOW, &saved);
+    match count {
+        Ok(1) => Some(key[0]),
+        _ => None,
+    }
+}
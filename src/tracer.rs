@@ -0,0 +1,321 @@
+//! The thread that traces the programs deet debugs. Only the thread that started tracing a
+//! process may make ptrace requests on it, so one thread of deet's own starts them all, makes
+//! every ptrace call, and waits for what their threads report. The rest of deet hands it
+//! closures with `run`, which waits for their results, and gets the events of the threads it
+//! `watch`es over channels.
+//!
+//! Between requests it sleeps on a pipe, written to by `run` and by a SIGCHLD handler, so it
+//! wakes up for either and only looks for events when a child has reported something.
+
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::{self, Pid};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::OnceLock;
+
+/// What wait4 reported for a thread, with the resources it used
+pub type Event = (Pid, WaitStatus, libc::rusage);
+
+/// An event, or the thread whose wait failed with why
+pub type WaitResult = Result<Event, (Pid, nix::Error)>;
+
+enum Request {
+    /// A closure to run, which sends its result back itself
+    Run(Box<dyn FnOnce() + Send>),
+    /// Starts sending a thread's events down a channel, until it's gone
+    Watch(Pid, Sender<WaitResult>),
+}
+
+/// Where the tracer thread takes its requests from, once it's started
+static TRACER: OnceLock<Sender<Request>> = OnceLock::new();
+
+/// The write end of the pipe the tracer thread sleeps on
+static WAKE: AtomicI32 = AtomicI32::new(-1);
+
+/// Set by the SIGCHLD handler, so the tracer thread knows to look for events
+static CHILD_CHANGED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static ON_TRACER: Cell<bool> = const { Cell::new(false) };
+}
+
+fn tracer() -> &'static Sender<Request> {
+    TRACER.get_or_init(|| {
+        let (wake_read, wake_write) =
+            unistd::pipe2(OFlag::O_CLOEXEC).expect("no pipe for the tracer");
+        // a full pipe wakes it up just the same
+        unsafe {
+            let flags = libc::fcntl(wake_write, libc::F_GETFL);
+            libc::fcntl(wake_write, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        WAKE.store(wake_write, Ordering::SeqCst);
+        let handler = SigAction::new(
+            SigHandler::Handler(child_changed),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        unsafe { signal::sigaction(Signal::SIGCHLD, &handler) }.expect("no SIGCHLD handler");
+        let (sender, requests) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("tracer".to_string())
+            .spawn(move || serve(requests, wake_read))
+            .expect("no tracer thread");
+        sender
+    })
+}
+
+extern "C" fn child_changed(_: libc::c_int) {
+    CHILD_CHANGED.store(true, Ordering::SeqCst);
+    wake();
+}
+
+/// Wakes the tracer thread up, from anywhere, a signal handler included.
+fn wake() {
+    let errno = unsafe { *libc::__errno_location() };
+    let _ = unistd::write(WAKE.load(Ordering::SeqCst), &[0]);
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// The tracer thread: runs the requests, and sends the events of the threads being watched
+/// to whoever watches them.
+fn serve(requests: Receiver<Request>, wake_read: RawFd) {
+    ON_TRACER.with(|on_tracer| on_tracer.set(true));
+    let mut watched = HashMap::new();
+    loop {
+        let mut reap = CHILD_CHANGED.swap(false, Ordering::SeqCst);
+        for request in requests.try_iter() {
+            match request {
+                Request::Run(job) => job(),
+                // its first events may have come before it was watched
+                Request::Watch(tid, sender) => {
+                    watched.insert(tid, sender);
+                    reap = true;
+                }
+            }
+        }
+        if reap {
+            reap_events(&mut watched);
+        }
+        // whatever came since the looks above has written to the pipe
+        let mut bytes = [0; 64];
+        match unistd::read(wake_read, &mut bytes) {
+            Ok(_) | Err(Errno::EINTR) => {}
+            Err(err) => panic!("the tracer's pipe broke: {}", err),
+        }
+    }
+}
+
+/// Sends everything the watched threads have to report, and stops watching those that are
+/// gone. A thread whose watcher has gone away is still waited for, so it doesn't linger as a
+/// zombie.
+fn reap_events(watched: &mut HashMap<Pid, Sender<WaitResult>>) {
+    watched.retain(|&tid, sender| loop {
+        let event = match wait4(tid, WaitPidFlag::__WALL | WaitPidFlag::WNOHANG) {
+            Ok(Some(event)) => Ok(event),
+            Ok(None) => return true,
+            Err(Errno::EINTR) => continue,
+            Err(err) => Err((tid, err)),
+        };
+        let stopped = matches!(
+            event,
+            Ok((
+                _,
+                WaitStatus::Stopped(..)
+                    | WaitStatus::PtraceEvent(..)
+                    | WaitStatus::PtraceSyscall(..),
+                _
+            ))
+        );
+        let _ = sender.send(event);
+        if !stopped {
+            return false;
+        }
+    });
+}
+
+/// wait4 on one thread, for the resource usage that waitpid doesn't give. Returns None if
+/// WNOHANG is given and it has nothing to report.
+fn wait4(tid: Pid, flags: WaitPidFlag) -> Result<Option<Event>, nix::Error> {
+    let mut raw_status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let pid = unsafe { libc::wait4(tid.as_raw(), &mut raw_status, flags.bits(), &mut usage) };
+    match nix::Error::result(pid)? {
+        0 => Ok(None),
+        pid => {
+            let pid = Pid::from_raw(pid);
+            Ok(Some((pid, WaitStatus::from_raw(pid, raw_status)?, usage)))
+        }
+    }
+}
+
+/// Runs `f` on the tracer thread and returns what it returns, or panics with its panic.
+pub fn run<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    if ON_TRACER.with(Cell::get) {
+        return f();
+    }
+    let (sender, result) = mpsc::sync_channel(1);
+    let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+        let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+    });
+    // the job has run, or been dropped unrun, by the time its result or the closed channel
+    // comes back, so nothing it borrows is used after this returns
+    let job: Box<dyn FnOnce() + Send + 'static> = unsafe { std::mem::transmute(job) };
+    send(Request::Run(job));
+    match result.recv().expect("the tracer thread is gone") {
+        Ok(value) => value,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+/// Has the events of thread `tid` sent down `sender` from now on, until it's gone or its wait
+/// fails. Each thread must be watched by one receiver only.
+pub fn watch(tid: Pid, sender: Sender<WaitResult>) {
+    send(Request::Watch(tid, sender));
+}
+
+fn send(request: Request) {
+    tracer()
+        .send(request)
+        .unwrap_or_else(|_| panic!("the tracer thread is gone"));
+    wake();
+}
+
+/// nix's ptrace requests, made on the tracer thread. The addresses and data that nix takes as
+/// pointers are passed on as numbers, so that the requests can be sent there.
+pub mod ptrace {
+    use super::run;
+    use nix::errno::Errno;
+    use nix::sys::ptrace;
+    use nix::sys::signal::Signal;
+    use nix::unistd::Pid;
+
+    pub use nix::sys::ptrace::{AddressType, Event, Options};
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn getregs(pid: Pid) -> nix::Result<libc::user_regs_struct> {
+        run(move || ptrace::getregs(pid))
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn setregs(pid: Pid, regs: libc::user_regs_struct) -> nix::Result<()> {
+        run(move || ptrace::setregs(pid, regs))
+    }
+
+    pub fn read_user(pid: Pid, offset: AddressType) -> nix::Result<libc::c_long> {
+        let offset = offset as usize;
+        run(move || ptrace::read_user(pid, offset as AddressType))
+    }
+
+    /// # Safety
+    ///
+    /// As for nix's `write_user`.
+    pub unsafe fn write_user(
+        pid: Pid,
+        offset: AddressType,
+        data: *mut libc::c_void,
+    ) -> nix::Result<()> {
+        let (offset, data) = (offset as usize, data as usize);
+        run(move || unsafe {
+            ptrace::write_user(pid, offset as AddressType, data as *mut libc::c_void)
+        })
+    }
+
+    pub fn read(pid: Pid, addr: AddressType) -> nix::Result<libc::c_long> {
+        let addr = addr as usize;
+        run(move || ptrace::read(pid, addr as AddressType))
+    }
+
+    /// # Safety
+    ///
+    /// As for nix's `write`.
+    pub unsafe fn write(pid: Pid, addr: AddressType, data: *mut libc::c_void) -> nix::Result<()> {
+        let (addr, data) = (addr as usize, data as usize);
+        run(move || unsafe { ptrace::write(pid, addr as AddressType, data as *mut libc::c_void) })
+    }
+
+    pub fn setoptions(pid: Pid, options: Options) -> nix::Result<()> {
+        run(move || ptrace::setoptions(pid, options))
+    }
+
+    pub fn getsiginfo(pid: Pid) -> nix::Result<libc::siginfo_t> {
+        run(move || ptrace::getsiginfo(pid))
+    }
+
+    pub fn getevent(pid: Pid) -> nix::Result<libc::c_long> {
+        run(move || ptrace::getevent(pid))
+    }
+
+    pub fn cont<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> nix::Result<()> {
+        let sig = sig.into();
+        run(move || ptrace::cont(pid, sig))
+    }
+
+    pub fn step<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> nix::Result<()> {
+        let sig = sig.into();
+        run(move || ptrace::step(pid, sig))
+    }
+
+    pub fn syscall<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> nix::Result<()> {
+        let sig = sig.into();
+        run(move || ptrace::syscall(pid, sig))
+    }
+
+    pub fn detach<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> nix::Result<()> {
+        let sig = sig.into();
+        run(move || ptrace::detach(pid, sig))
+    }
+
+    /// A request nix has no function for, such as PTRACE_GETREGSET, as libc::ptrace makes it.
+    ///
+    /// # Safety
+    ///
+    /// `addr` and `data` must be what `request` expects, and valid until it returns.
+    pub unsafe fn request(
+        request: libc::c_uint,
+        pid: Pid,
+        addr: *mut libc::c_void,
+        data: *mut libc::c_void,
+    ) -> nix::Result<libc::c_long> {
+        let (addr, data) = (addr as usize, data as usize);
+        run(move || {
+            let res = unsafe {
+                libc::ptrace(
+                    request as _,
+                    pid.as_raw(),
+                    addr as *mut libc::c_void,
+                    data as *mut libc::c_void,
+                )
+            };
+            Errno::result(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_closures_on_the_tracer_thread() {
+        let mut borrowed = vec![1];
+        let name = run(|| {
+            borrowed.push(2);
+            // from the tracer thread itself, directly
+            run(|| std::thread::current().name().map(str::to_string))
+        });
+        assert_eq!(name.as_deref(), Some("tracer"));
+        assert_eq!(borrowed, [1, 2]);
+
+        let panic = panic::catch_unwind(|| run(|| panic!("in the tracer")));
+        assert_eq!(panic.unwrap_err().downcast_ref(), Some(&"in the tracer"));
+        // and it's still there
+        assert_eq!(run(|| 1 + 1), 2);
+    }
+}
@@ -0,0 +1,367 @@
+//! Unwinding with call frame information: the .eh_frame and .debug_frame tables that say, for
+//! every instruction of a function, where its caller's registers and return address were saved.
+//! Unlike the frame pointer chain, this works for code built without frame pointers, as most
+//! optimized code is.
+
+use crate::arch::{self, Registers};
+use crate::inferior::{page_size, MemoryMapping};
+use gimli::{CfaRule, CieOrFde, UnwindSection};
+use object::{Object, ObjectSection, ObjectSegment};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::rc::Rc;
+
+type Reader = gimli::EndianRcSlice<gimli::RunTimeEndian>;
+
+/// The call frame information of a program, indexed by address.
+pub struct CallFrameInfo {
+    eh_frame: Option<gimli::EhFrame<Reader>>,
+    debug_frame: Option<gimli::DebugFrame<Reader>>,
+    bases: gimli::BaseAddresses,
+    /// Sorted by start address, and only one for each
+    fdes: Vec<Fde>,
+    context: RefCell<gimli::UnwindContext<Reader>>,
+}
+
+struct Fde {
+    start: usize,
+    end: usize,
+    /// In .eh_frame rather than .debug_frame
+    in_eh_frame: bool,
+    entry: gimli::FrameDescriptionEntry<Reader>,
+}
+
+/// How to find a caller's frame from a frame at some address: its CFA (where the stack pointer
+/// was before the call) as a register plus an offset, and where the registers it saved are.
+/// Registers without a rule weren't changed, but for the return address, which is undefined.
+pub struct Row {
+    cfa: (u16, i64),
+    rules: Vec<(u16, Rule)>,
+    return_address: u16,
+}
+
+enum Rule {
+    Undefined,
+    SameValue,
+    /// Saved at this offset from the CFA
+    Offset(i64),
+    /// The CFA plus this offset
+    ValOffset(i64),
+    /// In this other register
+    Register(u16),
+}
+
+impl CallFrameInfo {
+    /// None at all, for when there's no program or it's one whose frames we don't unwind this way
+    pub fn empty() -> CallFrameInfo {
+        CallFrameInfo {
+            eh_frame: None,
+            debug_frame: None,
+            bases: gimli::BaseAddresses::default(),
+            fdes: Vec::new(),
+            context: RefCell::new(gimli::UnwindContext::new()),
+        }
+    }
+
+    /// The tables of `object`. FDEs that can't be parsed are left out, as are all of a 32-bit
+    /// program's, since its registers are numbered differently; their frames are unwound with
+    /// the frame pointer instead.
+    pub fn load(object: &object::File, endian: gimli::RunTimeEndian) -> CallFrameInfo {
+        let mut info = CallFrameInfo::empty();
+        if !object.is_64() {
+            return info;
+        }
+        let section = |name: &str| {
+            let section = object.section_by_name(name)?;
+            let data = section.uncompressed_data().ok()?;
+            Some((section.address(), Reader::new(Rc::from(&*data), endian)))
+        };
+        let address = |name: &str| {
+            object
+                .section_by_name(name)
+                .map(|section| section.address())
+        };
+        if let Some(address) = address(".eh_frame_hdr") {
+            info.bases = info.bases.set_eh_frame_hdr(address);
+        }
+        if let Some(address) = address(".text") {
+            info.bases = info.bases.set_text(address);
+        }
+        if let Some(address) = address(".got") {
+            info.bases = info.bases.set_got(address);
+        }
+        if let Some((address, data)) = section(".eh_frame") {
+            info.bases = info.bases.set_eh_frame(address);
+            let eh_frame = gimli::EhFrame::from(data);
+            info.fdes.extend(fdes(&eh_frame, &info.bases, true));
+            info.eh_frame = Some(eh_frame);
+        }
+        if let Some((_, data)) = section(".debug_frame") {
+            let debug_frame = gimli::DebugFrame::from(data);
+            info.fdes.extend(fdes(&debug_frame, &info.bases, false));
+            info.debug_frame = Some(debug_frame);
+        }
+        // .eh_frame's first, and kept where both describe a function
+        info.fdes.sort_by_key(|fde| fde.start);
+        info.fdes.dedup_by_key(|fde| fde.start);
+        info
+    }
+
+    /// How to unwind a frame at `pc`, if an FDE covers it and its CFA is a register plus an
+    /// offset, as it is outside of PLT stubs and hand-written assembly.
+    pub fn row(&self, pc: usize) -> Option<Row> {
+        let index = self.fdes.partition_point(|fde| fde.start <= pc);
+        let fde = self.fdes[..index].last().filter(|fde| pc < fde.end)?;
+        let mut context = self.context.borrow_mut();
+        let row = if fde.in_eh_frame {
+            let eh_frame = self.eh_frame.as_ref()?;
+            fde.entry
+                .unwind_info_for_address(eh_frame, &self.bases, &mut context, pc as u64)
+        } else {
+            let debug_frame = self.debug_frame.as_ref()?;
+            fde.entry
+                .unwind_info_for_address(debug_frame, &self.bases, &mut context, pc as u64)
+        }
+        .ok()?;
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => (register.0, *offset),
+            CfaRule::Expression(_) => return None,
+        };
+        let rules = row
+            .registers()
+            .map(|(register, rule)| {
+                let rule = match rule {
+                    gimli::RegisterRule::SameValue => Rule::SameValue,
+                    gimli::RegisterRule::Offset(offset) => Rule::Offset(*offset),
+                    gimli::RegisterRule::ValOffset(offset) => Rule::ValOffset(*offset),
+                    gimli::RegisterRule::Register(other) => Rule::Register(other.0),
+                    // expressions aren't evaluated, so what they compute is as good as lost
+                    _ => Rule::Undefined,
+                };
+                (register.0, rule)
+            })
+            .collect();
+        Some(Row {
+            cfa,
+            rules,
+            return_address: fde.entry.cie().return_address_register().0,
+        })
+    }
+}
+
+/// The call frame information of the ELF objects mapped into a process, at the addresses they're
+/// loaded at: the shared libraries and the dynamic loader, whose frames the program's own tables
+/// don't describe. An object's tables are loaded when it's first seen mapped.
+#[derive(Default)]
+pub struct MappedFrameInfo {
+    /// Sorted by start address
+    objects: Vec<MappedObject>,
+}
+
+struct MappedObject {
+    path: String,
+    /// From the start of its first mapping to the end of its last
+    start: usize,
+    end: usize,
+    /// What its addresses in the process are offset by from those in the file
+    bias: usize,
+    info: Rc<CallFrameInfo>,
+}
+
+impl MappedFrameInfo {
+    /// Brings the objects in line with the process's `mappings`: those no longer mapped there
+    /// are dropped, and those newly mapped loaded. Files that aren't ELF objects get no tables.
+    pub fn update(&mut self, mappings: &[MemoryMapping]) {
+        let mut spans: Vec<(String, usize, usize)> = Vec::new();
+        for mapping in mappings
+            .iter()
+            .filter(|mapping| mapping.name.starts_with('/'))
+        {
+            match spans.iter_mut().find(|(path, ..)| *path == mapping.name) {
+                Some((_, start, end)) => {
+                    *start = (*start).min(mapping.start);
+                    *end = (*end).max(mapping.end);
+                }
+                None => spans.push((mapping.name.clone(), mapping.start, mapping.end)),
+            }
+        }
+        let mut loaded = std::mem::take(&mut self.objects);
+        for (path, start, end) in spans {
+            let known = loaded
+                .iter()
+                .position(|object| object.path == path && object.start == start);
+            let object = match known {
+                Some(index) => MappedObject {
+                    end,
+                    ..loaded.swap_remove(index)
+                },
+                None => {
+                    let (bias, info) = load_mapped(&path, start);
+                    MappedObject {
+                        path,
+                        start,
+                        end,
+                        bias,
+                        info: Rc::new(info),
+                    }
+                }
+            };
+            self.objects.push(object);
+        }
+        self.objects.sort_by_key(|object| object.start);
+    }
+
+    /// How to unwind a frame at `pc`, as `CallFrameInfo::row` says, from the tables of the object
+    /// mapped there.
+    pub fn row(&self, pc: usize) -> Option<Row> {
+        let index = self.objects.partition_point(|object| object.start <= pc);
+        let object = self.objects[..index]
+            .last()
+            .filter(|object| pc < object.end)?;
+        object.info.row(pc.wrapping_sub(object.bias))
+    }
+}
+
+/// The load bias and call frame information of the object at `path`, whose first mapping
+/// starts at `start`: that's the page of its lowest segment. None at all if it can't be read.
+fn load_mapped(path: &str, start: usize) -> (usize, CallFrameInfo) {
+    let load = || {
+        let file = File::open(path).ok()?;
+        let mmap = unsafe { memmap2::Mmap::map(&file) }.ok()?;
+        let object = object::File::parse(&*mmap).ok()?;
+        let first = object.segments().map(|segment| segment.address()).min()?;
+        let bias = start.wrapping_sub(first as usize & !(page_size() - 1));
+        let endian = match object.is_little_endian() {
+            true => gimli::RunTimeEndian::Little,
+            false => gimli::RunTimeEndian::Big,
+        };
+        Some((bias, CallFrameInfo::load(&object, endian)))
+    };
+    load().unwrap_or_else(|| (0, CallFrameInfo::empty()))
+}
+
+/// The FDEs of `section`, up to the first entry that can't be parsed.
+fn fdes<S: UnwindSection<Reader>>(
+    section: &S,
+    bases: &gimli::BaseAddresses,
+    in_eh_frame: bool,
+) -> Vec<Fde> {
+    let mut fdes = Vec::new();
+    let mut entries = section.entries(bases);
+    while let Ok(Some(entry)) = entries.next() {
+        let CieOrFde::Fde(partial) = entry else {
+            continue;
+        };
+        if let Ok(entry) =
+            partial.parse(|section, bases, offset| section.cie_from_offset(bases, offset))
+        {
+            let start = entry.initial_address() as usize;
+            fdes.push(Fde {
+                start,
+                end: start + entry.len() as usize,
+                in_eh_frame,
+                entry,
+            });
+        }
+    }
+    fdes
+}
+
+/// What the unwinder knows of a frame's registers: its pc, and the others by DWARF number.
+/// Those of the innermost frame are all known; a caller's are only as far as the rules say.
+#[derive(Clone)]
+pub struct Frame {
+    pub pc: usize,
+    registers: BTreeMap<u16, usize>,
+}
+
+impl Frame {
+    pub fn new(regs: &Registers) -> Frame {
+        let registers = arch::DWARF_REGISTERS
+            .iter()
+            .enumerate()
+            .filter_map(|(number, name)| {
+                Some((number as u16, arch::register(regs, name)? as usize))
+            })
+            .collect();
+        Frame {
+            pc: arch::pc(regs),
+            registers,
+        }
+    }
+
+    pub fn sp(&self) -> Option<usize> {
+        self.registers.get(&arch::DWARF_SP).copied()
+    }
+
+    pub fn fp(&self) -> Option<usize> {
+        self.registers.get(&arch::DWARF_FP).copied()
+    }
+
+    /// Where the stack pointer was before the call to this frame's function, per `row`. None if
+    /// the register it's relative to isn't known here.
+    pub fn cfa(&self, row: &Row) -> Option<usize> {
+        let (register, offset) = row.cfa;
+        Some(
+            self.registers
+                .get(&register)?
+                .wrapping_add_signed(offset as isize),
+        )
+    }
+
+    /// The caller's frame, per `row` and the frame's `cfa`, reading saved registers with
+    /// `read_word`. None if this is the outermost frame, whose return address is undefined.
+    pub fn caller(
+        &self,
+        row: &Row,
+        cfa: usize,
+        read_word: impl Fn(usize) -> Result<usize, nix::Error>,
+    ) -> Result<Option<Frame>, nix::Error> {
+        let mut caller = self.clone();
+        caller.registers.insert(arch::DWARF_SP, cfa);
+        caller.registers.remove(&row.return_address);
+        for (register, rule) in &row.rules {
+            let value = match rule {
+                Rule::Undefined => None,
+                Rule::SameValue => continue,
+                Rule::Offset(offset) => Some(read_word(cfa.wrapping_add_signed(*offset as isize))?),
+                Rule::ValOffset(offset) => Some(cfa.wrapping_add_signed(*offset as isize)),
+                Rule::Register(other) => self.registers.get(other).copied(),
+            };
+            match value {
+                Some(value) => caller.registers.insert(*register, value),
+                None => caller.registers.remove(register),
+            };
+        }
+        match caller.registers.get(&row.return_address) {
+            Some(&pc) if pc != 0 => {
+                caller.pc = pc;
+                Ok(Some(caller))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The caller's frame, going by the frame pointer: the caller's frame pointer is saved at
+    /// the frame pointer, with the return address above it. None if the frame pointer is 0,
+    /// as the outermost frame leaves it, or unknown.
+    pub fn caller_by_frame_pointer(
+        &self,
+        word_size: usize,
+        read_word: impl Fn(usize) -> Result<usize, nix::Error>,
+    ) -> Result<Option<Frame>, nix::Error> {
+        let fp = match self.fp() {
+            Some(0) | None => return Ok(None),
+            Some(fp) => fp,
+        };
+        let mut caller = self.clone();
+        caller.registers.insert(arch::DWARF_FP, read_word(fp)?);
+        caller.pc = read_word(fp + word_size)?;
+        match arch::caller_sp(fp, word_size) {
+            Some(sp) => caller.registers.insert(arch::DWARF_SP, sp),
+            None => caller.registers.remove(&arch::DWARF_SP),
+        };
+        Ok(Some(caller))
+    }
+}